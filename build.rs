@@ -0,0 +1,68 @@
+//! Empaqueta los programas de usuario en la imagen del kernel.
+//!
+//! Cada `user/<nombre>.elf` queda en la tabla de `initrd` como `<nombre>`,
+//! con `include_bytes!`. `user/autorun` lista, uno por línea, los programas
+//! que el kernel arranca al bootear; las líneas vacías y las que empiezan con
+//! `#` se saltean. Sin directorio `user/` la tabla queda vacía.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const USER_DIR: &str = "user";
+const MANIFEST: &str = "autorun";
+
+fn main() {
+    let user = Path::new(env!("CARGO_MANIFEST_DIR")).join(USER_DIR);
+    println!("cargo:rerun-if-changed={}", user.display());
+
+    let images = images(&user);
+    let autorun = autorun(&user.join(MANIFEST));
+    for name in &autorun {
+        if !images.iter().any(|(image, _)| image == name) {
+            panic!("{}/{}: no hay ningún {}/{}.elf", USER_DIR, MANIFEST, USER_DIR, name);
+        }
+    }
+
+    let mut out = String::new();
+    out.push_str("pub(crate) static BUILTIN: &[(&str, &[u8])] = &[\n");
+    for (name, path) in &images {
+        println!("cargo:rerun-if-changed={}", path.display());
+        writeln!(out, "    ({:?}, include_bytes!({:?})),", name, path.display().to_string()).unwrap();
+    }
+    out.push_str("];\n\npub(crate) static AUTORUN: &[&str] = &[\n");
+    for name in &autorun {
+        writeln!(out, "    {:?},", name).unwrap();
+    }
+    out.push_str("];\n");
+
+    let dest = PathBuf::from(env::var("OUT_DIR").unwrap()).join("initrd_images.rs");
+    fs::write(dest, out).unwrap();
+}
+
+/// `(nombre, ruta)` de cada `.elf` del directorio, ordenados por nombre.
+fn images(dir: &Path) -> Vec<(String, PathBuf)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut images: Vec<_> = entries
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "elf"))
+        .map(|path| (path.file_stem().unwrap().to_str().expect("nombre de programa no UTF-8").to_string(), path))
+        .collect();
+    images.sort();
+    images
+}
+
+fn autorun(manifest: &Path) -> Vec<String> {
+    println!("cargo:rerun-if-changed={}", manifest.display());
+    let Ok(text) = fs::read_to_string(manifest) else {
+        return Vec::new();
+    };
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
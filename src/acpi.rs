@@ -0,0 +1,308 @@
+//! Lectura de tablas ACPI.
+//!
+//! Alcanza para encontrar la MADT: se busca el RSDP en la EBDA y en el área
+//! de la BIOS, se sigue al RSDT/XSDT y se recorren sus entradas. Las tablas
+//! están en RAM (regiones ACPI reclaimable/NVS), así que se leen a través del
+//! mapeo de memoria física del bootloader con `memory::phys_to_virt`.
+//! No hay intérprete de AML: sólo tablas estáticas. `discover` agrega al
+//! inventario de `device` lo que describen la MADT y la tabla del HPET.
+
+use x86_64::PhysAddr;
+
+use crate::device::{self, Bus, Device, Resource};
+use crate::memory;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcpiError {
+    RsdpNotFound,
+    BadChecksum([u8; 4]),
+    TableNotFound([u8; 4]),
+}
+
+/// Encabezado común de todas las tablas del sistema (SDT).
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+pub struct SdtHeader {
+    pub signature: [u8; 4],
+    pub length: u32,
+    pub revision: u8,
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+    pub oem_revision: u32,
+    pub creator_id: u32,
+    pub creator_revision: u32,
+}
+
+const SDT_HEADER_LEN: usize = core::mem::size_of::<SdtHeader>();
+
+/// Lee un valor sin alinear de memoria física.
+unsafe fn read_phys<T: Copy>(addr: PhysAddr) -> T {
+    let ptr: *const T = memory::phys_to_virt(addr).as_ptr();
+    unsafe { ptr.read_unaligned() }
+}
+
+/// Bytes en `[addr, addr + len)` de memoria física.
+unsafe fn phys_bytes(addr: PhysAddr, len: usize) -> &'static [u8] {
+    let ptr: *const u8 = memory::phys_to_virt(addr).as_ptr();
+    unsafe { core::slice::from_raw_parts(ptr, len) }
+}
+
+/// La suma de todos los bytes de una estructura ACPI tiene que dar 0.
+pub fn checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) == 0
+}
+
+// ----------------- RSDP -----------------
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+/// Largo de la parte ACPI 1.0 del RSDP, que es la que cubre el checksum base.
+const RSDP_V1_LEN: usize = 20;
+
+/// Tabla raíz: RSDT (punteros de 32 bits) o XSDT (de 64, ACPI 2.0+).
+#[derive(Debug, Clone, Copy)]
+pub enum RootTable {
+    Rsdt(PhysAddr),
+    Xsdt(PhysAddr),
+}
+
+fn scan_for_rsdp(start: u64, len: u64) -> Option<PhysAddr> {
+    (start..start + len).step_by(16).map(PhysAddr::new).find(|&addr| {
+        let bytes = unsafe { phys_bytes(addr, RSDP_V1_LEN) };
+        &bytes[..8] == RSDP_SIGNATURE && checksum_ok(bytes)
+    })
+}
+
+/// Busca el RSDP en el primer KiB de la EBDA y en `0xE0000..0x100000`.
+pub fn find_root_table() -> Result<RootTable, AcpiError> {
+    // El segmento de la EBDA está en la BDA, en 0x40E.
+    let ebda = u64::from(unsafe { read_phys::<u16>(PhysAddr::new(0x40E)) }) << 4;
+    let rsdp = (ebda != 0)
+        .then(|| scan_for_rsdp(ebda, 1024))
+        .flatten()
+        .or_else(|| scan_for_rsdp(0xE0000, 0x20000))
+        .ok_or(AcpiError::RsdpNotFound)?;
+
+    let revision: u8 = unsafe { read_phys(rsdp + 15u64) };
+    if revision >= 2 {
+        let xsdt: u64 = unsafe { read_phys(rsdp + 24u64) };
+        if xsdt != 0 {
+            return Ok(RootTable::Xsdt(PhysAddr::new(xsdt)));
+        }
+    }
+    let rsdt: u32 = unsafe { read_phys(rsdp + 16u64) };
+    Ok(RootTable::Rsdt(PhysAddr::new(u64::from(rsdt))))
+}
+
+// ----------------- TABLAS -----------------
+
+/// Encabezado de la tabla en `addr`, validando su checksum.
+pub fn read_header(addr: PhysAddr) -> Result<SdtHeader, AcpiError> {
+    let header: SdtHeader = unsafe { read_phys(addr) };
+    let bytes = unsafe { phys_bytes(addr, header.length as usize) };
+    if !checksum_ok(bytes) {
+        return Err(AcpiError::BadChecksum(header.signature));
+    }
+    Ok(header)
+}
+
+/// Dirección física de la primera tabla con la firma dada.
+pub fn find_table(signature: &[u8; 4]) -> Result<PhysAddr, AcpiError> {
+    let (root, entry_size) = match find_root_table()? {
+        RootTable::Rsdt(addr) => (addr, 4),
+        RootTable::Xsdt(addr) => (addr, 8),
+    };
+    let header = read_header(root)?;
+    let entries = (header.length as usize - SDT_HEADER_LEN) / entry_size;
+
+    for i in 0..entries {
+        let entry = root + (SDT_HEADER_LEN + i * entry_size) as u64;
+        let table = if entry_size == 4 {
+            u64::from(unsafe { read_phys::<u32>(entry) })
+        } else {
+            unsafe { read_phys::<u64>(entry) }
+        };
+        let table = PhysAddr::new(table);
+        let candidate: SdtHeader = unsafe { read_phys(table) };
+        if &candidate.signature == signature {
+            read_header(table)?;
+            return Ok(table);
+        }
+    }
+    Err(AcpiError::TableNotFound(*signature))
+}
+
+// ----------------- MADT -----------------
+
+pub const MAX_IO_APICS: usize = 4;
+pub const MAX_OVERRIDES: usize = 16;
+pub const MAX_LOCAL_APICS: usize = 64;
+
+/// Un procesador lógico según la MADT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalApicInfo {
+    pub processor_uid: u32,
+    pub apic_id: u32,
+    /// Si el firmware lo habilitó; uno deshabilitado puede ser hot-plug.
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoApicInfo {
+    pub id: u8,
+    pub address: PhysAddr,
+    /// Primera interrupción global (GSI) que atiende.
+    pub gsi_base: u32,
+}
+
+/// Reasignación de una IRQ ISA a otra GSI, con su polaridad y disparo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterruptOverride {
+    pub isa_irq: u8,
+    pub gsi: u32,
+    /// Campo `MPS INTI flags`: bits 0-1 polaridad, bits 2-3 modo de disparo.
+    pub flags: u16,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Madt {
+    pub local_apic_address: PhysAddr,
+    pub io_apics: [Option<IoApicInfo>; MAX_IO_APICS],
+    pub overrides: [Option<InterruptOverride>; MAX_OVERRIDES],
+    pub local_apics: [Option<LocalApicInfo>; MAX_LOCAL_APICS],
+}
+
+impl Madt {
+    /// Procesadores lógicos habilitados.
+    pub fn enabled_cpus(&self) -> usize {
+        self.local_apics.iter().flatten().filter(|cpu| cpu.enabled).count()
+    }
+}
+
+const MADT_LOCAL_APIC: u8 = 0;
+const MADT_IO_APIC: u8 = 1;
+const MADT_INTERRUPT_OVERRIDE: u8 = 2;
+const MADT_LOCAL_APIC_OVERRIDE: u8 = 5;
+const MADT_LOCAL_X2APIC: u8 = 9;
+
+/// Lee la MADT ("APIC"): direcciones del APIC local y de los IO-APICs, y la
+/// lista de procesadores.
+pub fn madt() -> Result<Madt, AcpiError> {
+    let table = find_table(b"APIC")?;
+    let header = read_header(table)?;
+
+    let local: u32 = unsafe { read_phys(table + SDT_HEADER_LEN as u64) };
+    let mut madt = Madt {
+        local_apic_address: PhysAddr::new(u64::from(local)),
+        io_apics: [None; MAX_IO_APICS],
+        overrides: [None; MAX_OVERRIDES],
+        local_apics: [None; MAX_LOCAL_APICS],
+    };
+
+    // Después del encabezado vienen la dirección del APIC local y los flags.
+    let mut offset = SDT_HEADER_LEN + 8;
+    while offset + 2 <= header.length as usize {
+        let entry = table + offset as u64;
+        let kind: u8 = unsafe { read_phys(entry) };
+        let len: u8 = unsafe { read_phys(entry + 1u64) };
+        if len < 2 {
+            break;
+        }
+
+        match kind {
+            MADT_LOCAL_APIC | MADT_LOCAL_X2APIC => {
+                let info = if kind == MADT_LOCAL_APIC {
+                    LocalApicInfo {
+                        processor_uid: u32::from(unsafe { read_phys::<u8>(entry + 2u64) }),
+                        apic_id: u32::from(unsafe { read_phys::<u8>(entry + 3u64) }),
+                        enabled: unsafe { read_phys::<u32>(entry + 4u64) } & 1 != 0,
+                    }
+                } else {
+                    LocalApicInfo {
+                        apic_id: unsafe { read_phys(entry + 4u64) },
+                        enabled: unsafe { read_phys::<u32>(entry + 8u64) } & 1 != 0,
+                        processor_uid: unsafe { read_phys(entry + 12u64) },
+                    }
+                };
+                if let Some(slot) = madt.local_apics.iter_mut().find(|s| s.is_none()) {
+                    *slot = Some(info);
+                }
+            }
+            MADT_IO_APIC => {
+                let info = IoApicInfo {
+                    id: unsafe { read_phys(entry + 2u64) },
+                    address: PhysAddr::new(u64::from(unsafe { read_phys::<u32>(entry + 4u64) })),
+                    gsi_base: unsafe { read_phys(entry + 8u64) },
+                };
+                if let Some(slot) = madt.io_apics.iter_mut().find(|s| s.is_none()) {
+                    *slot = Some(info);
+                }
+            }
+            MADT_INTERRUPT_OVERRIDE => {
+                let info = InterruptOverride {
+                    isa_irq: unsafe { read_phys(entry + 3u64) },
+                    gsi: unsafe { read_phys(entry + 4u64) },
+                    flags: unsafe { read_phys(entry + 8u64) },
+                };
+                if let Some(slot) = madt.overrides.iter_mut().find(|s| s.is_none()) {
+                    *slot = Some(info);
+                }
+            }
+            MADT_LOCAL_APIC_OVERRIDE => {
+                let address: u64 = unsafe { read_phys(entry + 4u64) };
+                madt.local_apic_address = PhysAddr::new(address);
+            }
+            _ => {}
+        }
+        offset += len as usize;
+    }
+
+    Ok(madt)
+}
+
+// ----------------- HPET -----------------
+
+/// Dirección física de los registros del HPET, de la tabla "HPET". Está en
+/// una Generic Address Structure después del ID del bloque de timers.
+pub fn hpet_address() -> Result<PhysAddr, AcpiError> {
+    let table = find_table(b"HPET")?;
+    let address: u64 = unsafe { read_phys(table + (SDT_HEADER_LEN + 8) as u64) };
+    Ok(PhysAddr::new(address))
+}
+
+// ----------------- DESCUBRIMIENTO -----------------
+
+/// Tamaño de la ventana de registros de un IO-APIC (IOREGSEL e IOWIN).
+const IO_APIC_WINDOW: u64 = 0x20;
+/// Tamaño del bloque de registros del HPET.
+const HPET_WINDOW: u64 = 0x400;
+
+/// Agrega los IO-APICs y el HPET al inventario. Se llama una vez, después de
+/// `memory::init`; una tabla que falta se salta.
+pub fn discover() {
+    match madt() {
+        Ok(madt) => {
+            for io_apic in madt.io_apics.iter().flatten() {
+                device::add(Device::new(Bus::Acpi, "ACPI0009", "IO-APIC").with(Resource::Mmio {
+                    start: io_apic.address.as_u64(),
+                    len: IO_APIC_WINDOW,
+                }));
+            }
+        }
+        Err(err) => crate::log_warn!("ACPI: sin MADT: {:?}", err),
+    }
+    if let Ok(address) = hpet_address() {
+        device::add(Device::new(Bus::Acpi, "PNP0103", "HPET").with(Resource::Mmio {
+            start: address.as_u64(),
+            len: HPET_WINDOW,
+        }));
+    }
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_checksum() {
+    assert!(checksum_ok(&[0x10, 0xF0]));
+    assert!(!checksum_ok(&[0x10, 0xEF]));
+}
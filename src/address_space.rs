@@ -0,0 +1,261 @@
+//! Espacios de direcciones de usuario.
+//!
+//! Cada `AddressSpace` tiene su propia tabla de nivel 4. La mitad del kernel
+//! (todas las entradas fuera de `USER_START..USER_END`) se copia de la tabla
+//! del kernel al crearlo, así que apunta a las mismas tablas de nivel 3 y el
+//! kernel se ve igual desde cualquier espacio. La parte de usuario es propia:
+//! sus tablas y sus marcos se liberan con el espacio.
+//!
+//! Se modifica a través de la memoria física mapeada por el bootloader, sin
+//! necesidad de que esté cargado en CR3.
+
+use x86_64::structures::paging::mapper::{MapToError, TranslateResult};
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags, PageTableIndex, PhysFrame, Size4KiB,
+    Translate,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::error::{KernelError, KernelResult};
+use crate::memory::{self, USER_END, USER_START};
+
+/// Entradas del nivel 4 que son de usuario.
+const USER_ENTRIES: core::ops::Range<usize> = (USER_START >> 39) as usize..(USER_END >> 39) as usize;
+
+/// Le pide marcos a `memory::allocate_frame`, para las tablas que crea
+/// `map_to`.
+struct Frames;
+
+unsafe impl FrameAllocator<Size4KiB> for Frames {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        memory::allocate_frame().ok()
+    }
+}
+
+pub struct AddressSpace {
+    page_table: PhysFrame,
+}
+
+impl AddressSpace {
+    /// Un espacio sin nada mapeado en la parte de usuario.
+    pub fn new() -> KernelResult<AddressSpace> {
+        let page_table = memory::allocate_frame()?;
+        let kernel = unsafe { &*table(memory::kernel_page_table()) };
+        let table = unsafe { &mut *table(page_table) };
+        for (index, entry) in table.iter_mut().enumerate() {
+            if USER_ENTRIES.contains(&index) {
+                entry.set_unused();
+            } else {
+                *entry = kernel[index].clone();
+            }
+        }
+        Ok(AddressSpace { page_table })
+    }
+
+    /// El marco de la tabla de nivel 4, lo que va en CR3.
+    pub fn page_table(&self) -> PhysFrame {
+        self.page_table
+    }
+
+    fn mapper(&mut self) -> OffsetPageTable<'_> {
+        let offset = memory::physical_memory_offset();
+        unsafe { OffsetPageTable::new(&mut *table(self.page_table), offset) }
+    }
+
+    /// Mapea `page` a un marco nuevo en cero, con `flags` más los de
+    /// presente y usuario, y lo devuelve.
+    pub fn map(&mut self, page: Page, flags: PageTableFlags) -> KernelResult<PhysFrame> {
+        check_user(page)?;
+        let frame = memory::allocate_frame()?;
+        unsafe { (*table(frame)).zero() };
+        let flags = flags | PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+        // Las tablas nuevas quedan con los permisos más amplios: los que
+        // importan son los de la última.
+        let parent_flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+        let result = unsafe { self.mapper().map_to_with_table_flags(page, frame, flags, parent_flags, &mut Frames) };
+        match result {
+            // Una página que no estaba mapeada no puede estar en el TLB.
+            Ok(flush) => {
+                flush.ignore();
+                Ok(frame)
+            }
+            Err(err) => {
+                unsafe { memory::deallocate_frame(frame) };
+                Err(match err {
+                    MapToError::PageAlreadyMapped(_) => KernelError::AlreadyMapped,
+                    err => err.into(),
+                })
+            }
+        }
+    }
+
+    /// Le agrega `flags` a los permisos de `page`, que tiene que estar
+    /// mapeada. Los que ya tenía se mantienen.
+    pub fn add_flags(&mut self, page: Page, flags: PageTableFlags) -> KernelResult<()> {
+        check_user(page)?;
+        let (_, current) = self.translate(page.start_address()).ok_or(KernelError::NotMapped)?;
+        let flush = unsafe { self.mapper().update_flags(page, current | flags) }.map_err(|_| KernelError::NotMapped)?;
+        // Si es el espacio activo, el TLB puede tener los permisos viejos.
+        flush.flush();
+        Ok(())
+    }
+
+    /// Desmapea `page` y libera su marco.
+    pub fn unmap(&mut self, page: Page) -> KernelResult<()> {
+        check_user(page)?;
+        let (frame, flush) = self.mapper().unmap(page).map_err(|_| KernelError::NotMapped)?;
+        // Si es el espacio activo, el TLB puede tener la página.
+        flush.flush();
+        unsafe { memory::deallocate_frame(frame) };
+        Ok(())
+    }
+
+    /// La dirección física de `addr` y los permisos de su página.
+    pub fn translate(&mut self, addr: VirtAddr) -> Option<(PhysAddr, PageTableFlags)> {
+        match self.mapper().translate(addr) {
+            TranslateResult::Mapped { frame, offset, flags } => Some((frame.start_address() + offset, flags)),
+            _ => None,
+        }
+    }
+
+    /// Copia `bytes` a partir de `addr`, que tiene que estar mapeada.
+    pub fn write(&mut self, addr: VirtAddr, bytes: &[u8]) -> KernelResult<()> {
+        let mut done = 0;
+        while done < bytes.len() {
+            let at = addr + done as u64;
+            let (phys, _) = self.translate(at).ok_or(KernelError::NotMapped)?;
+            let len = (4096 - usize::from(at.page_offset())).min(bytes.len() - done);
+            let dst = (memory::physical_memory_offset() + phys.as_u64()).as_mut_ptr::<u8>();
+            unsafe { core::ptr::copy_nonoverlapping(bytes[done..].as_ptr(), dst, len) };
+            done += len;
+        }
+        Ok(())
+    }
+
+    /// Llena `buffer` con lo que hay a partir de `addr`. Todo tiene que estar
+    /// mapeado con acceso de usuario: sirve para leer lo que pasa un
+    /// proceso en una syscall.
+    pub fn read_user(&mut self, addr: VirtAddr, buffer: &mut [u8]) -> KernelResult<()> {
+        let mut done = 0;
+        while done < buffer.len() {
+            let at = addr + done as u64;
+            check_user(Page::containing_address(at)).map_err(|_| KernelError::NotMapped)?;
+            let (phys, flags) = self.translate(at).ok_or(KernelError::NotMapped)?;
+            if !flags.contains(PageTableFlags::USER_ACCESSIBLE) {
+                return Err(KernelError::NotMapped);
+            }
+            let len = (4096 - usize::from(at.page_offset())).min(buffer.len() - done);
+            let src = (memory::physical_memory_offset() + phys.as_u64()).as_ptr::<u8>();
+            unsafe { core::ptr::copy_nonoverlapping(src, buffer[done..].as_mut_ptr(), len) };
+            done += len;
+        }
+        Ok(())
+    }
+
+    /// Un espacio nuevo con una copia de cada página de usuario de este, con
+    /// los mismos permisos.
+    pub fn try_clone(&self) -> KernelResult<AddressSpace> {
+        let mut copy = AddressSpace::new()?;
+        let mut result = Ok(());
+        for_each_user_page(self.page_table, &mut |page, frame, flags| {
+            if result.is_err() {
+                return;
+            }
+            let flags = flags - (PageTableFlags::ACCESSED | PageTableFlags::DIRTY);
+            result = copy.map(page, flags).map(|new| unsafe {
+                core::ptr::copy_nonoverlapping(table(frame) as *const u8, table(new) as *mut u8, 4096);
+            });
+        });
+        // Si falló, soltar la copia libera lo que alcanzó a mapear.
+        result.map(|()| copy)
+    }
+
+    /// Libera todo lo de usuario: marcos y tablas. El espacio queda como
+    /// recién creado.
+    pub fn clear(&mut self) {
+        let top = unsafe { &mut *table(self.page_table) };
+        for entry in top.iter_mut().take(USER_ENTRIES.end).skip(USER_ENTRIES.start) {
+            if let Ok(frame) = entry.frame() {
+                unsafe { free_table(frame, 3) };
+            }
+            entry.set_unused();
+        }
+        // Si está cargado en CR3, que no quede nada viejo en el TLB.
+        if x86_64::registers::control::Cr3::read().0 == self.page_table {
+            x86_64::instructions::tlb::flush_all();
+        }
+    }
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        assert!(
+            x86_64::registers::control::Cr3::read().0 != self.page_table,
+            "se soltó el espacio de direcciones activo"
+        );
+        self.clear();
+        unsafe { memory::deallocate_frame(self.page_table) };
+    }
+}
+
+fn check_user(page: Page) -> KernelResult<()> {
+    let addr = page.start_address().as_u64();
+    if (USER_START..USER_END).contains(&addr) {
+        Ok(())
+    } else {
+        Err(KernelError::InvalidArgument)
+    }
+}
+
+/// La tabla de páginas en `frame`, a través del mapeo de la memoria física.
+fn table(frame: PhysFrame) -> *mut PageTable {
+    (memory::physical_memory_offset() + frame.start_address().as_u64()).as_mut_ptr()
+}
+
+/// Llama a `f` con cada página de usuario mapeada en la tabla de nivel 4
+/// `page_table`, su marco y sus permisos.
+fn for_each_user_page(page_table: PhysFrame, f: &mut impl FnMut(Page, PhysFrame, PageTableFlags)) {
+    fn walk(
+        frame: PhysFrame,
+        level: u8,
+        indices: &mut [u16; 4],
+        f: &mut impl FnMut(Page, PhysFrame, PageTableFlags),
+    ) {
+        let range = if level == 4 { USER_ENTRIES } else { 0..512 };
+        let table = unsafe { &*table(frame) };
+        for index in range {
+            let entry = &table[index];
+            let Ok(child) = entry.frame() else {
+                continue;
+            };
+            indices[4 - level as usize] = index as u16;
+            if level > 1 {
+                walk(child, level - 1, indices, f);
+            } else {
+                let [p4, p3, p2, p1] = indices.map(PageTableIndex::new);
+                f(Page::from_page_table_indices(p4, p3, p2, p1), child, entry.flags());
+            }
+        }
+    }
+    walk(page_table, 4, &mut [0; 4], f);
+}
+
+/// Libera la tabla de nivel `level` en `frame`, lo que cuelga de ella y los
+/// marcos que mapea.
+///
+/// # Safety
+///
+/// La tabla tiene que ser de usuario y no estar en uso.
+unsafe fn free_table(frame: PhysFrame, level: u8) {
+    for entry in unsafe { (*table(frame)).iter() } {
+        let Ok(child) = entry.frame() else {
+            continue;
+        };
+        if level > 1 {
+            unsafe { free_table(child, level - 1) };
+        } else {
+            unsafe { memory::deallocate_frame(child) };
+        }
+    }
+    unsafe { memory::deallocate_frame(frame) };
+}
@@ -1,6 +1,8 @@
 #![allow(unsafe_op_in_unsafe_fn)]
 
 use alloc::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+use linked_list_allocator::Heap;
 use spin::Mutex;
 use x86_64::instructions::interrupts;
 
@@ -8,6 +10,10 @@ use crate::slab::SlabAllocator;
 
 pub use crate::buddy::PAGE_SIZE;
 
+crate::register_counter!(HEAP_ALLOC, "heap.alloc");
+crate::register_counter!(HEAP_DEALLOC, "heap.dealloc");
+crate::register_counter!(HEAP_GROW, "heap.grow");
+
 pub const HEAP_SIZE: usize = 128 * 1024;
 pub const HEAP_START: usize = 0x_4444_4442_0000;
 
@@ -38,49 +44,162 @@ unsafe impl GlobalAlloc for LockedSlabAllocator {
                 let block_size = size.next_power_of_two().max(crate::buddy::PAGE_SIZE);
                 
                 let current_end = allocator.start() + allocator.size();
-                
-                let start_page = Page::containing_address(VirtAddr::new(current_end as u64));
-                let end_addr = current_end + block_size;
-                let end_page = Page::containing_address(VirtAddr::new(end_addr as u64 - 1));
-                
-                let page_range = Page::range_inclusive(start_page, end_page);
-                
-                let mut mapping_success = true;
-                for page in page_range {
-                    if crate::memory::map_page(page).is_err() {
-                        mapping_success = false;
-                        break;
-                    }
-                }
-                
-                if mapping_success {
+                if map_heap_pages(current_end, block_size).is_ok() {
+                    HEAP_GROW.inc();
                     allocator.add_memory(current_end, block_size);
                     ptr = allocator.allocate(layout.size(), layout.align());
                 }
             }
-            
+
+            if !ptr.is_null() {
+                HEAP_ALLOC.inc();
+                track(ptr, layout, true);
+            }
             ptr
         })
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         interrupts::without_interrupts(|| {
+            HEAP_DEALLOC.inc();
+            track(ptr, layout, false);
             self.inner.lock().deallocate(ptr, layout.size(), layout.align())
         })
     }
 }
 
+/// Allocator first-fit sobre una lista enlazada de bloques libres. Más simple
+/// y más lento que buddy+slab; está para comparar ambos con la feature
+/// `linked-list-allocator`.
+pub struct LockedLinkedListAllocator {
+    inner: Mutex<Heap>,
+}
+
+impl LockedLinkedListAllocator {
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::new(Heap::empty()),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `[heap_start, heap_start + heap_size)` tiene que estar mapeado y sin usar.
+    pub unsafe fn init(&self, heap_start: usize, heap_size: usize) {
+        self.inner.lock().init(heap_start, heap_size);
+    }
+}
+
+impl Default for LockedLinkedListAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl GlobalAlloc for LockedLinkedListAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        interrupts::without_interrupts(|| {
+            let mut heap = self.inner.lock();
+            if let Ok(ptr) = heap.allocate_first_fit(layout) {
+                HEAP_ALLOC.inc();
+                track(ptr.as_ptr(), layout, true);
+                return ptr.as_ptr();
+            }
+
+            // Crece al menos lo pedido más el peor caso de alineación.
+            let grow = (layout.size() + layout.align()).next_power_of_two().max(PAGE_SIZE);
+            let top = heap.top();
+            if map_heap_pages(top, grow).is_err() {
+                return ptr::null_mut();
+            }
+            heap.extend(grow);
+            HEAP_GROW.inc();
+            match heap.allocate_first_fit(layout) {
+                Ok(ptr) => {
+                    HEAP_ALLOC.inc();
+                    track(ptr.as_ptr(), layout, true);
+                    ptr.as_ptr()
+                }
+                Err(()) => ptr::null_mut(),
+            }
+        })
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        interrupts::without_interrupts(|| {
+            HEAP_DEALLOC.inc();
+            track(ptr, layout, false);
+            self.inner.lock().deallocate(NonNull::new_unchecked(ptr), layout)
+        })
+    }
+}
+
+/// Con la feature `kasan`, anota la alocación o liberación en el mapa de
+/// sombra (`kasan::shadow`).
+#[inline]
+fn track(ptr: *mut u8, layout: Layout, allocated: bool) {
+    #[cfg(feature = "kasan")]
+    crate::kasan::shadow::mark(ptr as usize, layout.size(), allocated);
+    #[cfg(not(feature = "kasan"))]
+    let _ = (ptr, layout, allocated);
+}
+
+/// Mapea las páginas de `[start, start + size)` al final del heap.
+fn map_heap_pages(start: usize, size: usize) -> KernelResult<()> {
+    let start_page = Page::containing_address(VirtAddr::new(start as u64));
+    let end_page = Page::containing_address(VirtAddr::new((start + size) as u64 - 1));
+
+    Page::range_inclusive(start_page, end_page).try_for_each(crate::memory::map_page)
+}
+
+#[cfg(not(feature = "linked-list-allocator"))]
 #[global_allocator]
 static ALLOCATOR: LockedSlabAllocator = LockedSlabAllocator::new();
 
-use x86_64::{
-    structures::paging::{
-        mapper::MapToError, Page, Size4KiB,
-    },
-    VirtAddr,
+#[cfg(feature = "linked-list-allocator")]
+#[global_allocator]
+static ALLOCATOR: LockedLinkedListAllocator = LockedLinkedListAllocator::new();
+
+/// Nombre del allocator compilado, para etiquetar resultados de benchmarks.
+pub const NAME: &str = if cfg!(feature = "linked-list-allocator") {
+    "linked-list"
+} else {
+    "buddy+slab"
 };
 
-pub fn init_heap() -> Result<(), MapToError<Size4KiB>> {
+/// Para diagnósticos: indica si alguien tiene tomado el allocator.
+pub fn is_locked() -> bool {
+    ALLOCATOR.inner.is_locked()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Bytes que maneja el allocator, incluido lo que creció.
+    pub size: usize,
+    /// Bytes libres según el allocator compilado (en buddy+slab, sólo los
+    /// del buddy).
+    pub free: usize,
+    /// Alocaciones todavía no liberadas.
+    pub live: u64,
+}
+
+pub fn stats() -> HeapStats {
+    let (size, free) = interrupts::without_interrupts(|| {
+        let inner = ALLOCATOR.inner.lock();
+        #[cfg(not(feature = "linked-list-allocator"))]
+        return (inner.size(), inner.free_bytes());
+        #[cfg(feature = "linked-list-allocator")]
+        return (inner.size(), inner.free());
+    });
+    let live = HEAP_ALLOC.get().saturating_sub(HEAP_DEALLOC.get());
+    HeapStats { size, free, live }
+}
+
+use x86_64::{structures::paging::Page, VirtAddr};
+
+use crate::error::KernelResult;
+
+pub fn init_heap() -> KernelResult<()> {
     let page_range = {
         let heap_start = VirtAddr::new(HEAP_START as u64);
         let heap_end = heap_start + HEAP_SIZE - 1u64;
@@ -0,0 +1,163 @@
+//! APIC local de la CPU.
+//!
+//! La dirección de sus registros sale del MSR `IA32_APIC_BASE` y se mapea sin
+//! caché con `memory::map_mmio`, así que `init` requiere `memory::init`.
+//! Habilitarlo no desactiva el PIC 8259: sus IRQs siguen entrando por LINT0,
+//! que el firmware deja configurado como ExtINT.
+//!
+//! Si la CPU soporta x2APIC, se usa ese modo: los mismos registros se acceden
+//! por MSRs (0x800 + offset / 16) en vez de por MMIO y no hace falta mapear
+//! nada. `Lapic` esconde la diferencia, así que el resto del código sigue
+//! usando las constantes `Register` de acá.
+
+use conquer_once::spin::OnceCell;
+use core::arch::x86_64::__cpuid;
+use x86_64::registers::model_specific::Msr;
+use x86_64::PhysAddr;
+
+use crate::error::KernelError;
+use crate::mmio::{Field, MmioRegion, ReadOnly, ReadWrite, Readable, Register, Writable, WriteOnly};
+
+pub mod timer;
+
+const IA32_APIC_BASE: u32 = 0x1B;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const APIC_BASE_X2APIC: u64 = 1 << 10;
+const APIC_BASE_ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+const MMIO_LEN: usize = 0x1000;
+const X2APIC_MSR_BASE: u32 = 0x800;
+/// Bit de ECX en CPUID hoja 1.
+const CPUID_X2APIC: u32 = 1 << 21;
+
+pub(crate) const ID: Register<u32, ReadOnly> = Register::new(0x20);
+pub(crate) const EOI: Register<u32, WriteOnly> = Register::new(0xB0);
+pub(crate) const SPURIOUS: Register<u32, ReadWrite> = Register::new(0xF0);
+pub(crate) const LVT_TIMER: Register<u32, ReadWrite> = Register::new(0x320);
+pub(crate) const TIMER_INITIAL: Register<u32, ReadWrite> = Register::new(0x380);
+pub(crate) const TIMER_CURRENT: Register<u32, ReadOnly> = Register::new(0x390);
+pub(crate) const TIMER_DIVIDE: Register<u32, ReadWrite> = Register::new(0x3E0);
+
+const SPURIOUS_VECTOR_FIELD: Field<u32> = Field::new(0, 8);
+const SPURIOUS_ENABLE: Field<u32> = Field::bit(8);
+
+/// Vector de las interrupciones espurias del APIC; no llevan EOI.
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApicError {
+    /// No se pudieron mapear sus registros.
+    Map(KernelError),
+}
+
+/// Acceso a los registros del APIC local, en el modo que se haya habilitado.
+#[derive(Debug)]
+pub(crate) enum Lapic {
+    XApic(MmioRegion),
+    X2Apic,
+}
+
+/// MSR que corresponde a un registro en modo x2APIC.
+const fn x2apic_msr<A>(reg: Register<u32, A>) -> u32 {
+    X2APIC_MSR_BASE + (reg.offset() as u32 >> 4)
+}
+
+impl Lapic {
+    pub(crate) fn read<A: Readable>(&self, reg: Register<u32, A>) -> u32 {
+        match self {
+            Lapic::XApic(regs) => regs.read(reg),
+            // Los registros de 32 bits ocupan la parte baja del MSR.
+            Lapic::X2Apic => unsafe { Msr::new(x2apic_msr(reg)).read() as u32 },
+        }
+    }
+
+    pub(crate) fn write<A: Writable>(&self, reg: Register<u32, A>, value: u32) {
+        match self {
+            Lapic::XApic(regs) => regs.write(reg, value),
+            Lapic::X2Apic => unsafe { Msr::new(x2apic_msr(reg)).write(u64::from(value)) },
+        }
+    }
+
+    pub(crate) fn modify(&self, reg: Register<u32, ReadWrite>, f: impl FnOnce(u32) -> u32) {
+        let value = self.read(reg);
+        self.write(reg, f(value));
+    }
+}
+
+static LAPIC: OnceCell<Lapic> = OnceCell::uninit();
+
+pub(crate) fn regs() -> &'static Lapic {
+    LAPIC.try_get().expect("APIC local no inicializado")
+}
+
+/// Si la CPU soporta el modo x2APIC.
+pub fn x2apic_supported() -> bool {
+    unsafe { __cpuid(1).ecx & CPUID_X2APIC != 0 }
+}
+
+/// Habilita el APIC local, en modo x2APIC si está disponible y si no
+/// mapeando sus registros.
+pub fn init() -> Result<(), ApicError> {
+    let mut base_msr = Msr::new(IA32_APIC_BASE);
+    let base = unsafe { base_msr.read() };
+
+    let lapic = if x2apic_supported() {
+        // EXTD sin EN es una combinación inválida, así que se prenden juntos.
+        unsafe { base_msr.write(base | APIC_BASE_ENABLE | APIC_BASE_X2APIC) };
+        Lapic::X2Apic
+    } else {
+        let phys = PhysAddr::new(base & APIC_BASE_ADDRESS_MASK);
+        let virt =
+            crate::memory::map_mmio(phys, MMIO_LEN as u64).map_err(ApicError::Map)?;
+        unsafe { base_msr.write(base | APIC_BASE_ENABLE) };
+        Lapic::XApic(unsafe { MmioRegion::new(virt, MMIO_LEN) })
+    };
+
+    lapic.modify(SPURIOUS, |value| {
+        let value = SPURIOUS_VECTOR_FIELD.set(value, u32::from(SPURIOUS_VECTOR));
+        SPURIOUS_ENABLE.set(value, 1)
+    });
+    LAPIC.init_once(|| lapic);
+    Ok(())
+}
+
+pub fn is_initialized() -> bool {
+    LAPIC.is_initialized()
+}
+
+/// Si `init` habilitó el modo x2APIC.
+pub fn is_x2apic() -> bool {
+    matches!(LAPIC.try_get(), Ok(Lapic::X2Apic))
+}
+
+/// ID del APIC local de la CPU actual.
+///
+/// En x2APIC el ID ocupa el registro entero; se trunca a 8 bits porque es lo
+/// que entra en el destino de un MSI o de una entrada del IO-APIC.
+pub fn id() -> u8 {
+    match regs() {
+        Lapic::XApic(_) => (regs().read(ID) >> 24) as u8,
+        Lapic::X2Apic => regs().read(ID) as u8,
+    }
+}
+
+/// Fin de interrupción para vectores entregados por el APIC local.
+pub fn end_of_interrupt() {
+    regs().write(EOI, 0);
+}
+
+/// Si hay algún vector en servicio (entregado y todavía sin EOI). Los 256
+/// bits del ISR están en 8 registros de 32, cada 0x10 desde 0x100.
+pub fn any_in_service() -> bool {
+    (0..8).any(|i| regs().read(Register::<u32, ReadOnly>::new(0x100 + i * 0x10)) != 0)
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_x2apic_msr_numbers() {
+    assert_eq!(x2apic_msr(ID), 0x802);
+    assert_eq!(x2apic_msr(EOI), 0x80B);
+    assert_eq!(x2apic_msr(LVT_TIMER), 0x832);
+    assert_eq!(x2apic_msr(TIMER_DIVIDE), 0x83E);
+}
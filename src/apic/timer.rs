@@ -0,0 +1,93 @@
+//! Timer del APIC local, calibrado con el PIT.
+//!
+//! El timer cuenta a la frecuencia del bus dividida por `DIVIDER`, que varía
+//! entre máquinas. `calibrate` la mide dejando que cuente durante 10 ms de la
+//! cuenta regresiva del PIT (`pit::wait_one_shot`), cuya frecuencia sí es fija.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::instructions::interrupts;
+
+use super::{regs, LVT_TIMER, TIMER_CURRENT, TIMER_DIVIDE, TIMER_INITIAL};
+use crate::mmio::Field;
+
+/// Vector de la interrupción del timer del APIC.
+pub const VECTOR: u8 = 0x30;
+
+const CALIBRATION_MS: u64 = 10;
+
+/// Valor de TIMER_DIVIDE para dividir por 16.
+const DIVIDE_BY_16: u32 = 0b0011;
+
+const LVT_VECTOR: Field<u32> = Field::new(0, 8);
+const LVT_MASKED: Field<u32> = Field::bit(16);
+const LVT_MODE: Field<u32> = Field::new(17, 2);
+const MODE_ONE_SHOT: u32 = 0b00;
+const MODE_PERIODIC: u32 = 0b01;
+
+/// Cuentas del timer por segundo, con el divisor por 16. 0 = sin calibrar.
+static FREQUENCY: AtomicU64 = AtomicU64::new(0);
+static FIRED: AtomicU64 = AtomicU64::new(0);
+
+/// Mide la frecuencia del timer con el PIT y la guarda. Devuelve cuentas por segundo.
+pub fn calibrate() -> u64 {
+    let counted = interrupts::without_interrupts(|| {
+        let regs = regs();
+        regs.write(TIMER_DIVIDE, DIVIDE_BY_16);
+        regs.write(LVT_TIMER, LVT_MASKED.set(0, 1));
+        regs.write(TIMER_INITIAL, u32::MAX);
+
+        crate::pit::wait_one_shot((CALIBRATION_MS * 1000) as u32);
+
+        let counted = u32::MAX - regs.read(TIMER_CURRENT);
+        regs.write(TIMER_INITIAL, 0);
+        counted
+    });
+
+    let frequency = u64::from(counted) * 1000 / CALIBRATION_MS;
+    FREQUENCY.store(frequency, Ordering::Relaxed);
+    frequency
+}
+
+/// Cuentas por segundo medidas por `calibrate`, calibrando si hace falta.
+pub fn frequency() -> u64 {
+    match FREQUENCY.load(Ordering::Relaxed) {
+        0 => calibrate(),
+        frequency => frequency,
+    }
+}
+
+fn start(mode: u32, count: u64) {
+    let count = count.clamp(1, u64::from(u32::MAX)) as u32;
+    let regs = regs();
+    regs.write(TIMER_DIVIDE, DIVIDE_BY_16);
+    let lvt = LVT_VECTOR.set(0, u32::from(VECTOR));
+    regs.write(LVT_TIMER, LVT_MODE.set(lvt, mode));
+    regs.write(TIMER_INITIAL, count);
+}
+
+/// Interrumpe `hz` veces por segundo en `VECTOR`.
+pub fn set_periodic(hz: u32) {
+    assert!(hz > 0, "frecuencia nula");
+    start(MODE_PERIODIC, frequency() / u64::from(hz));
+}
+
+/// Una sola interrupción dentro de `micros` microsegundos.
+pub fn one_shot(micros: u64) {
+    start(MODE_ONE_SHOT, frequency() * micros / 1_000_000);
+}
+
+pub fn stop() {
+    let regs = regs();
+    regs.write(LVT_TIMER, LVT_MASKED.set(0, 1));
+    regs.write(TIMER_INITIAL, 0);
+}
+
+/// Interrupciones del timer del APIC desde el arranque.
+pub fn fired() -> u64 {
+    FIRED.load(Ordering::Relaxed)
+}
+
+/// Lo llama el handler de `VECTOR`.
+pub(crate) fn on_interrupt() {
+    FIRED.fetch_add(1, Ordering::Relaxed);
+}
@@ -0,0 +1,71 @@
+//! Backtraces siguiendo la cadena de RBP.
+//!
+//! El target compila con `"frame-pointer": "always"`, así que cada frame
+//! empieza con el RBP del anterior y, encima, la dirección de retorno. No hay
+//! símbolos: se imprimen direcciones, que se resuelven con `addr2line` sobre
+//! el binario del kernel.
+
+use core::arch::asm;
+use core::fmt::Write;
+
+pub const MAX_FRAMES: usize = 16;
+/// Salto máximo entre dos frames consecutivos; más es una cadena rota.
+const MAX_FRAME_SIZE: u64 = 1 << 20;
+
+/// Direcciones de retorno capturadas, de la más reciente a la más vieja.
+#[derive(Debug, Clone, Copy)]
+pub struct Backtrace {
+    frames: [u64; MAX_FRAMES],
+    len: usize,
+}
+
+impl Backtrace {
+    /// Captura los frames del que llama. Se corta en el primer RBP que no
+    /// parezca parte de la misma pila.
+    #[inline(never)]
+    pub fn capture() -> Backtrace {
+        let mut backtrace = Backtrace { frames: [0; MAX_FRAMES], len: 0 };
+        let mut rbp: u64;
+        unsafe { asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags)) };
+
+        while backtrace.len < MAX_FRAMES && rbp != 0 && rbp.is_multiple_of(8) {
+            let (next, ret) = unsafe {
+                let frame = rbp as *const u64;
+                (frame.read(), frame.add(1).read())
+            };
+            if ret == 0 {
+                break;
+            }
+            backtrace.frames[backtrace.len] = ret;
+            backtrace.len += 1;
+            if next <= rbp || next - rbp > MAX_FRAME_SIZE {
+                break;
+            }
+            rbp = next;
+        }
+        backtrace
+    }
+
+    pub fn frames(&self) -> &[u64] {
+        &self.frames[..self.len]
+    }
+
+    pub fn write(&self, out: &mut impl Write) -> core::fmt::Result {
+        for (i, ret) in self.frames().iter().enumerate() {
+            writeln!(out, "  #{:<2} {:#018x}", i, ret)?;
+        }
+        Ok(())
+    }
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_capture_sees_callers() {
+    #[inline(never)]
+    fn nested(depth: u32) -> Backtrace {
+        if depth == 0 { Backtrace::capture() } else { nested(depth - 1) }
+    }
+    let backtrace = nested(3);
+    assert!(backtrace.frames().len() >= 4, "{:?}", backtrace.frames());
+}
@@ -0,0 +1,115 @@
+//! Microbenchmarks de la CPU medidos con el TSC.
+//!
+//! Los resultados están en ciclos del TSC; `time::tsc_frequency` da la
+//! frecuencia para pasarlos a tiempo. Al no haber shell se llaman como funciones
+//! (`bench::run_all()` imprime todo). Faltan las mediciones de syscall y de
+//! cambio de contexto: el kernel no tiene ni syscalls ni threads.
+
+use alloc::vec::Vec;
+use core::arch::x86_64::{_mm_lfence, _rdtsc};
+
+use crate::rng::SimpleRng;
+
+/// Lectura del TSC serializada: `lfence` evita que se adelanten o atrasen
+/// instrucciones alrededor de la medición.
+pub fn rdtsc() -> u64 {
+    unsafe {
+        _mm_lfence();
+        let tsc = _rdtsc();
+        _mm_lfence();
+        tsc
+    }
+}
+
+/// Mínimo, mediana y máximo de una serie de mediciones, en ciclos.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    pub min: u64,
+    pub median: u64,
+    pub max: u64,
+}
+
+impl Sample {
+    fn from_runs(runs: &mut [u64]) -> Sample {
+        runs.sort_unstable();
+        Sample {
+            min: runs[0],
+            median: runs[runs.len() / 2],
+            max: runs[runs.len() - 1],
+        }
+    }
+}
+
+/// Mide `f` `runs` veces, cada una repitiéndola `iterations` veces, y devuelve
+/// el costo por iteración.
+pub fn measure(runs: usize, iterations: u64, mut f: impl FnMut()) -> Sample {
+    assert!(runs > 0 && iterations > 0);
+    let mut results = Vec::with_capacity(runs);
+    for _ in 0..runs {
+        let start = rdtsc();
+        for _ in 0..iterations {
+            f();
+        }
+        results.push((rdtsc() - start) / iterations);
+    }
+    Sample::from_runs(&mut results)
+}
+
+// ----------------- LATENCIA DE MEMORIA -----------------
+
+/// Latencia promedio de una carga dependiente sobre un buffer de `bytes`.
+///
+/// Se recorre una permutación aleatoria como lista enlazada (pointer chasing):
+/// cada carga depende de la anterior y el prefetcher no puede anticiparla.
+/// Con buffers que entran en L1, L2 o sólo en RAM se ve cada nivel.
+pub fn memory_latency(bytes: usize) -> Sample {
+    let len = (bytes / core::mem::size_of::<usize>()).max(2);
+
+    // Ciclo único que pasa por todos los elementos (algoritmo de Sattolo).
+    let mut next: Vec<usize> = (0..len).collect();
+    let mut rng = SimpleRng::new(0x5eed);
+    for i in (1..len).rev() {
+        let j = rng.next_range(0, i as u64) as usize;
+        next.swap(i, j);
+    }
+
+    let mut index = 0;
+    let sample = measure(5, len as u64, || {
+        index = unsafe { core::ptr::read_volatile(&next[index]) };
+    });
+    core::hint::black_box(index);
+    sample
+}
+
+// ----------------- INTERRUPCIONES -----------------
+
+/// Vector con un handler vacío, usado para medir la ida y vuelta de una interrupción.
+pub const BENCH_VECTOR: u8 = 0xF0;
+
+/// Costo de `int` + `iretq` hasta un handler vacío.
+pub fn interrupt_round_trip() -> Sample {
+    measure(5, 1000, || unsafe {
+        core::arch::asm!("int {vector}", vector = const BENCH_VECTOR);
+    })
+}
+
+/// Corre todos los benchmarks e imprime los resultados. Necesita el heap.
+pub fn run_all() {
+    crate::println!("benchmark                mín   mediana      máx  (ciclos)");
+    // El buddy no entrega bloques de más de 2 MiB, así que el buffer más grande es de 1 MiB.
+    for (name, bytes) in [
+        ("memoria 16 KiB", 16 << 10),
+        ("memoria 256 KiB", 256 << 10),
+        ("memoria 1 MiB", 1 << 20),
+    ] {
+        print_sample(name, memory_latency(bytes));
+    }
+    print_sample("interrupción (int)", interrupt_round_trip());
+}
+
+fn print_sample(name: &str, sample: Sample) {
+    crate::println!(
+        "{:<20} {:>8} {:>9} {:>8}",
+        name, sample.min, sample.median, sample.max
+    );
+}
@@ -16,6 +16,8 @@ pub struct BuddyAllocator {
     heap_start: usize,
     heap_size: usize,
     free_lists: [Option<ptr::NonNull<FreeBlock>>; NUM_ORDERS],
+    /// Bytes en bloques entregados por `allocate` y todavía no liberados.
+    allocated: usize,
 }
 
 impl BuddyAllocator {
@@ -24,9 +26,24 @@ impl BuddyAllocator {
             heap_start: 0,
             heap_size: 0,
             free_lists: [None; NUM_ORDERS],
+            allocated: 0,
         }
     }
 
+    /// Crea un allocator sobre una región prestada, por ejemplo un array estático.
+    /// La región se recorta a páginas completas.
+    pub fn from_region(region: &'static mut [u8]) -> Self {
+        let start = region.as_mut_ptr() as usize;
+        let aligned_start = (start + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        let end = (start + region.len()) & !(PAGE_SIZE - 1);
+
+        let mut allocator = Self::new();
+        if end > aligned_start {
+            unsafe { allocator.init(aligned_start, end - aligned_start) };
+        }
+        allocator
+    }
+
     pub fn start(&self) -> usize {
         self.heap_start
     }
@@ -96,6 +113,7 @@ impl BuddyAllocator {
                 unsafe {
                     self.free_lists[list_index] = (*block.as_ptr()).next;
                     self.split_block(block.as_ptr() as usize, current_order, order);
+                    self.allocated += Self::order_to_size(order);
                     return block.as_ptr() as *mut u8;
                 }
             }
@@ -104,10 +122,41 @@ impl BuddyAllocator {
         ptr::null_mut()
     }
 
-    pub unsafe fn deallocate(&mut self, ptr: *mut u8, size: usize) {
+    /// Devuelve un bloque de `allocate`. Un bloque que no puede ser de este
+    /// allocator (fuera de la región, desalineado, de un orden inválido o que
+    /// ya está libre) se rechaza y devuelve `false`, sin tocar las listas.
+    pub unsafe fn deallocate(&mut self, ptr: *mut u8, size: usize) -> bool {
         let size = size.max(PAGE_SIZE);
         let order = self.size_to_order(size);
-        self.free_block(ptr as usize, order);
+        let addr = ptr as usize;
+        let block_size = Self::order_to_size(order);
+
+        let valid = order <= MAX_ORDER
+            && addr >= self.heap_start
+            && addr.checked_add(block_size).is_some_and(|end| end <= self.heap_start + self.heap_size)
+            && (addr - self.heap_start).is_multiple_of(block_size)
+            && !self.is_free(addr);
+        let Some(allocated) = self.allocated.checked_sub(block_size).filter(|_| valid) else {
+            return false;
+        };
+        self.allocated = allocated;
+        self.free_block(addr, order);
+        true
+    }
+
+    /// Si `addr` cae dentro de un bloque libre, de cualquier orden.
+    unsafe fn is_free(&self, addr: usize) -> bool {
+        (MIN_ORDER..=MAX_ORDER).any(|order| {
+            let mut current = self.free_lists[order - MIN_ORDER];
+            while let Some(block) = current {
+                let start = block.as_ptr() as usize;
+                if (start..start + Self::order_to_size(order)).contains(&addr) {
+                    return true;
+                }
+                current = (*block.as_ptr()).next;
+            }
+            false
+        })
     }
 
     unsafe fn split_block(&mut self, addr: usize, current_order: usize, target_order: usize) {
@@ -181,6 +230,121 @@ impl BuddyAllocator {
     pub fn order_to_size(order: usize) -> usize {
         1 << order
     }
+
+    /// Cantidad de bloques libres de un orden dado.
+    pub fn free_blocks(&self, order: usize) -> usize {
+        let mut count = 0;
+        let mut current = self.free_lists[order - MIN_ORDER];
+        while let Some(block) = current {
+            count += 1;
+            current = unsafe { (*block.as_ptr()).next };
+        }
+        count
+    }
+
+    /// Bytes entregados por `allocate` que todavía no se liberaron.
+    pub fn allocated_bytes(&self) -> usize {
+        self.allocated
+    }
+
+    /// Bytes en bloques libres, de todos los órdenes.
+    pub fn free_bytes(&self) -> usize {
+        (MIN_ORDER..=MAX_ORDER)
+            .map(|order| self.free_blocks(order) * Self::order_to_size(order))
+            .sum()
+    }
 }
 
 unsafe impl Send for BuddyAllocator {}
+
+// ----------------- TESTS -----------------
+
+/// Región estática distinta para cada call site. Se alinea a 64 KiB para que
+/// el buddy la tome como bloques del mayor orden posible.
+#[cfg(test)]
+macro_rules! test_region {
+    ($size:expr) => {{
+        #[repr(align(65536))]
+        struct Region([u8; $size]);
+        static mut REGION: Region = Region([0; $size]);
+        unsafe { &mut (*&raw mut REGION).0 as &mut [u8] }
+    }};
+}
+
+#[cfg(test)]
+pub(crate) use test_region;
+
+#[test_case]
+fn test_buddy_from_region() {
+    let region = test_region!(64 * 1024);
+    let start = region.as_ptr() as usize;
+    let buddy = BuddyAllocator::from_region(region);
+
+    assert_eq!(buddy.start(), start);
+    assert_eq!(buddy.size(), 64 * 1024);
+    assert_eq!(buddy.free_blocks(16), 1);
+}
+
+#[test_case]
+fn test_buddy_split_and_coalesce() {
+    let mut buddy = BuddyAllocator::from_region(test_region!(64 * 1024));
+
+    let a = buddy.allocate(PAGE_SIZE);
+    let b = buddy.allocate(PAGE_SIZE);
+    assert!(!a.is_null() && !b.is_null());
+    assert_eq!(a as usize % PAGE_SIZE, 0);
+    assert_eq!((a as usize) ^ (b as usize), PAGE_SIZE);
+    assert_eq!(buddy.free_blocks(16), 0);
+
+    unsafe {
+        buddy.deallocate(a, PAGE_SIZE);
+        buddy.deallocate(b, PAGE_SIZE);
+    }
+    assert_eq!(buddy.free_blocks(16), 1);
+    assert_eq!(buddy.free_blocks(12), 0);
+}
+
+#[test_case]
+fn test_buddy_rounds_up_to_power_of_two() {
+    let mut buddy = BuddyAllocator::from_region(test_region!(64 * 1024));
+
+    let block = buddy.allocate(3 * PAGE_SIZE);
+    assert_eq!(block as usize % (4 * PAGE_SIZE), 0);
+    assert_eq!(buddy.free_blocks(14), 1);
+    assert_eq!(buddy.free_blocks(15), 1);
+
+    unsafe { buddy.deallocate(block, 3 * PAGE_SIZE) };
+    assert_eq!(buddy.free_blocks(16), 1);
+}
+
+#[test_case]
+fn test_buddy_exhaustion() {
+    let mut buddy = BuddyAllocator::from_region(test_region!(16 * 1024));
+
+    for _ in 0..4 {
+        assert!(!buddy.allocate(PAGE_SIZE).is_null());
+    }
+    assert!(buddy.allocate(PAGE_SIZE).is_null());
+    assert!(buddy.allocate(1 << (MAX_ORDER + 1)).is_null());
+}
+
+#[test_case]
+fn test_buddy_rejects_bad_frees() {
+    let mut buddy = BuddyAllocator::from_region(test_region!(16 * 1024));
+    let block = buddy.allocate(PAGE_SIZE);
+    assert_eq!(buddy.allocated_bytes(), PAGE_SIZE);
+
+    unsafe {
+        // Desalineado, fuera de la región y de un orden que no existe.
+        assert!(!buddy.deallocate(block.wrapping_add(8), PAGE_SIZE));
+        assert!(!buddy.deallocate(block.wrapping_add(64 * 1024), PAGE_SIZE));
+        assert!(!buddy.deallocate(block, 1 << (MAX_ORDER + 1)));
+
+        assert!(buddy.deallocate(block, PAGE_SIZE));
+        // Liberarlo de nuevo no puede agregar otra vez el bloque.
+        assert!(!buddy.deallocate(block, PAGE_SIZE));
+    }
+    assert_eq!(buddy.allocated_bytes(), 0);
+    assert_eq!(buddy.free_blocks(14), 1);
+    assert_eq!(buddy.free_blocks(12), 0);
+}
@@ -0,0 +1,235 @@
+//! Fuentes de reloj, al estilo de los clocksources de Linux.
+//!
+//! Cada fuente es un contador monótono con su frecuencia; `select` elige la
+//! disponible con mayor `rating` y `now_ns` la lee. Todo el que necesita la
+//! hora (`time::now_ns`, `Instant`, `uptime`) pasa por acá y no por un
+//! dispositivo en particular.
+//!
+//! | fuente | resolución | rating |
+//! |--------|------------|--------|
+//! | `tsc`  | ciclo de CPU | 300 si es invariante, 120 si no |
+//! | `hpet` | ~70 ns en QEMU | 250 |
+//! | `pit`  | un tick (~55 ms por defecto) | 100 |
+//!
+//! Al arrancar la fuente es el PIT; se vuelve a elegir cuando se calibra el
+//! TSC y cuando se inicializa el HPET. `now_ns` sigue contando desde donde
+//! estaba al cambiar de fuente.
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::time::cycles_to_ns;
+
+pub trait ClockSource: Sync {
+    fn name(&self) -> &'static str;
+    /// Preferencia entre las disponibles: gana la mayor.
+    fn rating(&self) -> u32;
+    fn available(&self) -> bool;
+    /// Cuentas por segundo de `read`.
+    fn frequency(&self) -> u64;
+    fn read(&self) -> u64;
+}
+
+pub struct Pit;
+pub struct Tsc;
+pub struct Hpet;
+
+impl ClockSource for Pit {
+    fn name(&self) -> &'static str {
+        "pit"
+    }
+
+    fn rating(&self) -> u32 {
+        100
+    }
+
+    fn available(&self) -> bool {
+        true
+    }
+
+    fn frequency(&self) -> u64 {
+        u64::from(crate::pit::frequency())
+    }
+
+    fn read(&self) -> u64 {
+        crate::interrupts::ticks()
+    }
+}
+
+impl ClockSource for Tsc {
+    fn name(&self) -> &'static str {
+        "tsc"
+    }
+
+    fn rating(&self) -> u32 {
+        if crate::time::tsc_invariant() { 300 } else { 120 }
+    }
+
+    fn available(&self) -> bool {
+        crate::time::tsc_frequency() != 0
+    }
+
+    fn frequency(&self) -> u64 {
+        crate::time::tsc_frequency()
+    }
+
+    fn read(&self) -> u64 {
+        crate::bench::rdtsc()
+    }
+}
+
+impl ClockSource for Hpet {
+    fn name(&self) -> &'static str {
+        "hpet"
+    }
+
+    fn rating(&self) -> u32 {
+        250
+    }
+
+    fn available(&self) -> bool {
+        crate::hpet::is_present()
+    }
+
+    fn frequency(&self) -> u64 {
+        crate::hpet::frequency()
+    }
+
+    fn read(&self) -> u64 {
+        crate::hpet::counter()
+    }
+}
+
+pub static SOURCES: [&dyn ClockSource; 3] = [&Tsc, &Hpet, &Pit];
+
+struct Current {
+    source: &'static dyn ClockSource,
+    hz: u64,
+    /// Lectura de `source` al elegirla.
+    base: u64,
+    /// `now_ns` al elegirla.
+    offset_ns: u64,
+}
+
+impl Current {
+    fn now_ns(&self) -> u64 {
+        if self.hz == 0 {
+            // Todavía no se eligió nada: el PIT desde el arranque.
+            return cycles_to_ns(Pit.read(), Pit.frequency());
+        }
+        self.offset_ns + cycles_to_ns(self.source.read().saturating_sub(self.base), self.hz)
+    }
+
+    /// Pasa a `source` siguiendo desde donde iba la anterior, también en el
+    /// primer cambio, así `now_ns` no vuelve atrás.
+    fn switch(&mut self, source: &'static dyn ClockSource) {
+        let offset_ns = self.now_ns();
+        *self = Current { source, hz: source.frequency(), base: source.read(), offset_ns };
+    }
+}
+
+static CURRENT: Mutex<Current> = Mutex::new(Current { source: &Pit, hz: 0, base: 0, offset_ns: 0 });
+
+/// La disponible con mayor rating; entre iguales, la primera.
+fn best(sources: &[&'static dyn ClockSource]) -> Option<&'static dyn ClockSource> {
+    sources
+        .iter()
+        .copied()
+        .filter(|source| source.available())
+        .reduce(|best, source| if source.rating() > best.rating() { source } else { best })
+}
+
+/// Cambia a la mejor fuente disponible. Devuelve su nombre.
+pub fn select() -> &'static str {
+    let source = best(&SOURCES).unwrap_or(&Pit);
+    let changed = interrupts::without_interrupts(|| {
+        let mut current = CURRENT.lock();
+        if current.hz != 0 && core::ptr::addr_eq(current.source, source) {
+            return false;
+        }
+        current.switch(source);
+        true
+    });
+    if changed {
+        crate::log_info!("clocksource: {} ({} Hz)", source.name(), source.frequency());
+    }
+    source.name()
+}
+
+/// La fuente vigente.
+pub fn current() -> &'static dyn ClockSource {
+    interrupts::without_interrupts(|| CURRENT.lock().source)
+}
+
+/// Nanosegundos desde el arranque, con la resolución de la fuente vigente.
+pub fn now_ns() -> u64 {
+    interrupts::without_interrupts(|| CURRENT.lock().now_ns())
+}
+
+pub fn print_clocksources() {
+    let current = current();
+    crate::println!("FUENTE RATING  HZ           ");
+    for source in SOURCES {
+        if source.available() {
+            let mark = if core::ptr::addr_eq(source, current) { "*" } else { "" };
+            crate::println!("{:<6} {:<7} {:<12} {}", source.name(), source.rating(), source.frequency(), mark);
+        }
+    }
+}
+
+// ----------------- TESTS -----------------
+
+#[cfg(test)]
+struct Fake(&'static str, u32, bool);
+
+#[cfg(test)]
+impl ClockSource for Fake {
+    fn name(&self) -> &'static str {
+        self.0
+    }
+
+    fn rating(&self) -> u32 {
+        self.1
+    }
+
+    fn available(&self) -> bool {
+        self.2
+    }
+
+    fn frequency(&self) -> u64 {
+        1
+    }
+
+    fn read(&self) -> u64 {
+        0
+    }
+}
+
+#[test_case]
+fn test_best_available_wins() {
+    static SLOW: Fake = Fake("slow", 100, true);
+    static FAST: Fake = Fake("fast", 300, true);
+    static MISSING: Fake = Fake("missing", 500, false);
+    static TIE: Fake = Fake("tie", 300, true);
+
+    assert_eq!(best(&[&SLOW, &FAST, &MISSING]).unwrap().name(), "fast");
+    assert_eq!(best(&[&FAST, &TIE]).unwrap().name(), "fast");
+    assert!(best(&[&MISSING]).is_none());
+}
+
+#[test_case]
+fn test_now_ns_survives_switch() {
+    let before = now_ns();
+    select();
+    assert!(now_ns() >= before);
+}
+
+#[test_case]
+fn test_first_switch_continues_from_pit() {
+    static FROZEN: Fake = Fake("frozen", 100, true);
+
+    let mut current = Current { source: &Pit, hz: 0, base: 0, offset_ns: 0 };
+    let before = current.now_ns();
+    current.switch(&FROZEN);
+    assert!(current.now_ns() >= before);
+}
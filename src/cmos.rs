@@ -0,0 +1,64 @@
+//! NVRAM del CMOS (128 bytes detrás del RTC, puertos 0x70/0x71).
+//!
+//! Se escribe el número de registro en el puerto índice y se lee o escribe el
+//! valor en el puerto de datos. Los primeros 14 bytes son del RTC; el resto
+//! lo usa el firmware salvo por un rango libre que toma `config`.
+
+use conquer_once::spin::OnceCell;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::driver::{Driver, Stage};
+use crate::portio::{self, PortRegion};
+
+/// Cantidad de registros direccionables.
+pub const SIZE: u8 = 128;
+
+const INDEX: u16 = 0;
+const DATA: u16 = 1;
+
+/// El bit 7 del índice apaga las NMI; se deja siempre en 0.
+const INDEX_MASK: u8 = 0x7f;
+
+static PORTS: OnceCell<Mutex<PortRegion>> = OnceCell::uninit();
+
+fn init() {
+    let ports = portio::claim("cmos", 0x70, 2).expect("puertos del CMOS ocupados");
+    PORTS.init_once(|| Mutex::new(ports));
+}
+
+crate::register_driver!(CMOS_DRIVER, Driver {
+    name: "cmos",
+    stage: Stage::Early,
+    depends_on: &[],
+    device: Some("PNP0B00"),
+    probe: Driver::always,
+    init,
+});
+
+fn ports() -> &'static Mutex<PortRegion> {
+    PORTS.try_get().expect("CMOS no inicializado")
+}
+
+pub fn read(register: u8) -> u8 {
+    assert!(register < SIZE, "registro CMOS {:#x} inexistente", register);
+    // El par índice/dato no puede interrumpirse a la mitad.
+    interrupts::without_interrupts(|| {
+        let ports = ports().lock();
+        unsafe {
+            ports.write_only::<u8>(INDEX).write(register & INDEX_MASK);
+            ports.port::<u8>(DATA).read()
+        }
+    })
+}
+
+pub fn write(register: u8, value: u8) {
+    assert!(register < SIZE, "registro CMOS {:#x} inexistente", register);
+    interrupts::without_interrupts(|| {
+        let ports = ports().lock();
+        unsafe {
+            ports.write_only::<u8>(INDEX).write(register & INDEX_MASK);
+            ports.port::<u8>(DATA).write(value);
+        }
+    })
+}
@@ -0,0 +1,144 @@
+//! Configuración persistente del kernel.
+//!
+//! Unos pocos ajustes (consolas activas, nivel de log, distribución de
+//! teclado) se guardan en la NVRAM del CMOS para que sobrevivan a un reinicio
+//! sin recompilar. Todavía no hay sistema de archivos, así que no hay
+//! `/boot/kur.cfg`: el CMOS es el único almacenamiento disponible.
+//!
+//! El bloque lleva un byte mágico y un checksum; si no coinciden (primer
+//! arranque, batería agotada) se usan los valores por defecto.
+
+use crate::driver::{Driver, Stage};
+use crate::log::{self, Level};
+use crate::task::keyboard::{self, Layout};
+use crate::cmos;
+
+/// Primer registro del bloque. QEMU y SeaBIOS usan registros hasta 0x5f.
+const BASE: u8 = 0x70;
+const MAGIC: u8 = 0x4b; // 'K'
+const LEN: usize = 5;
+
+/// Consolas que quedan registradas al arrancar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ConsoleSetting {
+    All = 0,
+    Vga,
+    Serial,
+}
+
+impl ConsoleSetting {
+    fn from_u8(value: u8) -> Option<ConsoleSetting> {
+        Some(match value {
+            0 => ConsoleSetting::All,
+            1 => ConsoleSetting::Vga,
+            2 => ConsoleSetting::Serial,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub console: ConsoleSetting,
+    pub log_level: Level,
+    pub keyboard_layout: Layout,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            console: ConsoleSetting::All,
+            log_level: Level::Info,
+            keyboard_layout: Layout::Us104,
+        }
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) ^ 0xff
+}
+
+fn encode(config: &Config) -> [u8; LEN] {
+    let mut bytes = [
+        MAGIC,
+        config.console as u8,
+        config.log_level as u8,
+        config.keyboard_layout as u8,
+        0,
+    ];
+    bytes[LEN - 1] = checksum(&bytes[..LEN - 1]);
+    bytes
+}
+
+fn decode(bytes: &[u8; LEN]) -> Option<Config> {
+    if bytes[0] != MAGIC || bytes[LEN - 1] != checksum(&bytes[..LEN - 1]) {
+        return None;
+    }
+    Some(Config {
+        console: ConsoleSetting::from_u8(bytes[1])?,
+        log_level: Level::from_u8(bytes[2])?,
+        keyboard_layout: Layout::from_u8(bytes[3])?,
+    })
+}
+
+/// Lee la configuración guardada. `None` si el bloque no es válido.
+pub fn load() -> Option<Config> {
+    let mut bytes = [0; LEN];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = cmos::read(BASE + i as u8);
+    }
+    decode(&bytes)
+}
+
+pub fn save(config: &Config) {
+    for (i, byte) in encode(config).iter().enumerate() {
+        cmos::write(BASE + i as u8, *byte);
+    }
+}
+
+/// Aplica la configuración al sistema en marcha.
+pub fn apply(config: &Config) {
+    log::set_level(config.log_level);
+    keyboard::set_layout(config.keyboard_layout);
+    match config.console {
+        ConsoleSetting::All => {}
+        ConsoleSetting::Vga => crate::console::unregister("serial"),
+        ConsoleSetting::Serial => crate::console::unregister("vga"),
+    }
+}
+
+fn init() {
+    match load() {
+        Some(config) => {
+            apply(&config);
+            crate::log_info!("configuración cargada del CMOS: {:?}", config);
+        }
+        None => crate::log_debug!("sin configuración válida en el CMOS; usando valores por defecto"),
+    }
+}
+
+crate::register_driver!(CONFIG_DRIVER, Driver {
+    name: "config",
+    stage: Stage::Services,
+    depends_on: &["cmos"],
+    device: None,
+    probe: Driver::always,
+    init,
+});
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_encode_decode_roundtrip() {
+    let config = Config {
+        console: ConsoleSetting::Serial,
+        log_level: Level::Debug,
+        keyboard_layout: Layout::De105,
+    };
+    let mut bytes = encode(&config);
+    assert_eq!(decode(&bytes), Some(config));
+
+    bytes[2] ^= 1;
+    assert_eq!(decode(&bytes), None);
+}
@@ -0,0 +1,200 @@
+//! Consolas de texto.
+//!
+//! `print!`/`println!` escriben en todas las consolas activas, en orden, y
+//! `read_byte` toma la primera entrada disponible de cualquiera de ellas.
+//! `serial_print!` sigue yendo sólo al puerto serie: lo usa el framework de
+//! tests para reportar resultados al host.
+
+use core::fmt;
+use spin::Mutex;
+
+const MAX_CONSOLES: usize = 4;
+
+pub trait Console: Sync {
+    fn name(&self) -> &'static str;
+    fn write_str(&self, s: &str);
+    /// Lectura no bloqueante de un byte de entrada.
+    fn read_byte(&self) -> Option<u8>;
+    /// Tamaño en caracteres, como `(columnas, filas)`.
+    fn size(&self) -> (usize, usize);
+    /// Posición del cursor, como `(columna, fila)`.
+    fn cursor(&self) -> (usize, usize);
+}
+
+// ----------------- VGA -----------------
+
+pub struct VgaConsole;
+
+impl Console for VgaConsole {
+    fn name(&self) -> &'static str {
+        "vga"
+    }
+
+    fn write_str(&self, s: &str) {
+        crate::vga_buffer::WRITER.lock().write_string(s);
+    }
+
+    fn read_byte(&self) -> Option<u8> {
+        // La entrada de la pantalla es el teclado, que decodifica `task::keyboard`.
+        crate::task::keyboard::read_byte()
+    }
+
+    fn size(&self) -> (usize, usize) {
+        use crate::vga_buffer::{BUFFER_HEIGHT, BUFFER_WIDTH};
+        (BUFFER_WIDTH, BUFFER_HEIGHT)
+    }
+
+    fn cursor(&self) -> (usize, usize) {
+        crate::vga_buffer::WRITER.lock().cursor()
+    }
+}
+
+// ----------------- SERIAL -----------------
+
+/// El cursor de la terminal del host no se puede consultar: se lleva la
+/// cuenta de lo que se escribe, suponiendo que la terminal salta de línea al
+/// llegar al borde y hace scroll en la última fila.
+pub struct SerialConsole {
+    position: Mutex<(usize, usize)>,
+}
+
+const SERIAL_COLUMNS: usize = 80;
+const SERIAL_ROWS: usize = 24;
+
+/// Posición `(columna, fila)` después de escribir `s` desde `(column, row)`.
+fn advance((mut column, mut row): (usize, usize), s: &str) -> (usize, usize) {
+    for character in s.chars() {
+        match character {
+            '\n' => {
+                column = 0;
+                row += 1;
+            }
+            '\r' => column = 0,
+            '\x08' => column = column.saturating_sub(1),
+            '\t' => column = (column / 8 + 1) * 8,
+            c if c.is_control() => {}
+            _ => column += 1,
+        }
+        if column >= SERIAL_COLUMNS {
+            column = 0;
+            row += 1;
+        }
+    }
+    (column, row.min(SERIAL_ROWS - 1))
+}
+
+impl Console for SerialConsole {
+    fn name(&self) -> &'static str {
+        "serial"
+    }
+
+    fn write_str(&self, s: &str) {
+        crate::serial::write_str(s);
+
+        let mut position = self.position.lock();
+        *position = advance(*position, s);
+    }
+
+    fn read_byte(&self) -> Option<u8> {
+        crate::serial::SERIAL1.lock().try_receive().ok()
+    }
+
+    fn size(&self) -> (usize, usize) {
+        // No hay forma de consultar la terminal del host; se asume 80x24.
+        (SERIAL_COLUMNS, SERIAL_ROWS)
+    }
+
+    fn cursor(&self) -> (usize, usize) {
+        // `write_str` la actualiza desde `_print`, que puede correr en una IRQ.
+        x86_64::instructions::interrupts::without_interrupts(|| *self.position.lock())
+    }
+}
+
+pub static VGA: VgaConsole = VgaConsole;
+pub static SERIAL: SerialConsole = SerialConsole {
+    position: Mutex::new((0, 0)),
+};
+
+// ----------------- REGISTRO -----------------
+
+static CONSOLES: Mutex<[Option<&'static dyn Console>; MAX_CONSOLES]> =
+    Mutex::new([Some(&VGA), Some(&SERIAL), None, None]);
+
+/// Agrega una consola al final de la lista de consolas activas.
+pub fn register(console: &'static dyn Console) {
+    let mut consoles = CONSOLES.lock();
+    let slot = consoles
+        .iter_mut()
+        .find(|c| c.is_none())
+        .expect("demasiadas consolas registradas");
+    *slot = Some(console);
+}
+
+/// Saca de la lista la consola con el nombre dado.
+pub fn unregister(name: &str) {
+    let mut consoles = CONSOLES.lock();
+    for slot in consoles.iter_mut() {
+        if slot.is_some_and(|c| c.name() == name) {
+            *slot = None;
+        }
+    }
+}
+
+/// Para diagnósticos: indica si alguien tiene tomada la lista de consolas.
+pub fn is_locked() -> bool {
+    CONSOLES.is_locked()
+}
+
+/// Primer byte de entrada disponible en cualquiera de las consolas.
+pub fn read_byte() -> Option<u8> {
+    use x86_64::instructions::interrupts;
+
+    interrupts::without_interrupts(|| {
+        CONSOLES.lock().iter().flatten().find_map(|c| c.read_byte())
+    })
+}
+
+struct ConsoleWriter(&'static dyn Console);
+
+impl fmt::Write for ConsoleWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write_str(s);
+        Ok(())
+    }
+}
+
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::console::_print(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    use core::fmt::Write;
+    use x86_64::instructions::interrupts;
+
+    interrupts::without_interrupts(|| {
+        for console in CONSOLES.lock().iter().flatten() {
+            ConsoleWriter(*console).write_fmt(args).unwrap();
+        }
+    });
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_serial_cursor_tracks_rows() {
+    assert_eq!(advance((0, 0), "hola"), (4, 0));
+    assert_eq!(advance((4, 0), "\nmundo\n"), (0, 2));
+    assert_eq!(advance((3, 1), "ab\rc"), (1, 1));
+    // Salta al llegar al borde y no pasa de la última fila.
+    assert_eq!(advance((SERIAL_COLUMNS - 1, 0), "xy"), (1, 1));
+    assert_eq!(advance((0, SERIAL_ROWS - 1), "\n\n"), (0, SERIAL_ROWS - 1));
+    assert_eq!(advance((0, 0), "ñ"), (1, 0));
+}
@@ -0,0 +1,119 @@
+//! Tiempo ocioso de la CPU y utilización.
+//!
+//! La CPU está ociosa mientras espera en `hlt`. Los lugares donde el kernel
+//! se queda sin trabajo (el executor sin tareas listas, el hilo idle) esperan
+//! con `enable_and_hlt` de este módulo, que anota el TSC al dormir; la
+//! interrupción que la despierta cierra el intervalo al entrar al handler, así
+//! que lo que hace el handler (incluido cambiar a otro hilo) ya cuenta como
+//! trabajo.
+//!
+//! Cada `WINDOW_MS` milisegundos el hook del timer calcula qué parte de la
+//! ventana no fue ociosa; `utilization` promedia las últimas `WINDOWS`. Sin
+//! `hlt` (girando en un loop) la utilización queda en 100% aunque no haya
+//! nada que hacer.
+
+use core::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+
+use crate::bench::rdtsc;
+
+pub const WINDOW_MS: u64 = 100;
+/// Ventanas que promedia `utilization`.
+pub const WINDOWS: usize = 10;
+
+/// TSC al entrar en `hlt`; 0 si no se está esperando.
+static IDLE_SINCE: AtomicU64 = AtomicU64::new(0);
+static IDLE_CYCLES: AtomicU64 = AtomicU64::new(0);
+
+/// Porcentaje de trabajo de cada ventana, como ring.
+static BUSY: [AtomicU8; WINDOWS] = [const { AtomicU8::new(0) }; WINDOWS];
+static NEXT_WINDOW: AtomicUsize = AtomicUsize::new(0);
+static FILLED: AtomicUsize = AtomicUsize::new(0);
+/// TSC y ciclos ociosos al comenzar la ventana en curso.
+static WINDOW_TSC: AtomicU64 = AtomicU64::new(0);
+static WINDOW_IDLE: AtomicU64 = AtomicU64::new(0);
+
+/// Habilita las interrupciones y espera la próxima, contando la espera como
+/// tiempo ocioso. Se llama con las interrupciones deshabilitadas, como
+/// `interrupts::enable_and_hlt`.
+pub fn enable_and_hlt() {
+    IDLE_SINCE.store(rdtsc(), Ordering::Relaxed);
+    x86_64::instructions::interrupts::enable_and_hlt();
+    // Por si la despertó algo que no pasa por `on_interrupt`.
+    end_idle();
+}
+
+fn end_idle() {
+    let since = IDLE_SINCE.swap(0, Ordering::Relaxed);
+    if since != 0 {
+        IDLE_CYCLES.fetch_add(rdtsc().saturating_sub(since), Ordering::Relaxed);
+    }
+}
+
+/// Lo llaman los handlers de interrupción al entrar.
+#[inline]
+pub(crate) fn on_interrupt() {
+    if IDLE_SINCE.load(Ordering::Relaxed) != 0 {
+        end_idle();
+    }
+}
+
+/// Lo llama `time::on_tick`.
+pub(crate) fn on_timer_tick(now: u64) {
+    let window = crate::time::ms_to_ticks(WINDOW_MS).max(1);
+    if !now.is_multiple_of(window) {
+        return;
+    }
+    let tsc = rdtsc();
+    let idle = IDLE_CYCLES.load(Ordering::Relaxed);
+    let elapsed = tsc.saturating_sub(WINDOW_TSC.swap(tsc, Ordering::Relaxed));
+    let idle = idle.saturating_sub(WINDOW_IDLE.swap(idle, Ordering::Relaxed));
+    // La primera ventana arranca en el tick en que se llama por primera vez.
+    if elapsed == 0 || elapsed == tsc {
+        return;
+    }
+    let busy = 100 - (idle.min(elapsed) * 100 / elapsed);
+    let index = NEXT_WINDOW.load(Ordering::Relaxed);
+    BUSY[index].store(busy as u8, Ordering::Relaxed);
+    NEXT_WINDOW.store((index + 1) % WINDOWS, Ordering::Relaxed);
+    if FILLED.load(Ordering::Relaxed) < WINDOWS {
+        FILLED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Ciclos del TSC pasados en `hlt` desde el arranque.
+pub fn idle_cycles() -> u64 {
+    IDLE_CYCLES.load(Ordering::Relaxed)
+}
+
+/// Porcentaje de tiempo sin esperar en `hlt`, promediado sobre las últimas
+/// ventanas. `None` hasta que termina la primera.
+pub fn utilization() -> Option<u8> {
+    let filled = FILLED.load(Ordering::Relaxed);
+    if filled == 0 {
+        return None;
+    }
+    let total: usize = BUSY.iter().take(filled).map(|busy| busy.load(Ordering::Relaxed) as usize).sum();
+    Some((total / filled) as u8)
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_hlt_counts_as_idle() {
+    use x86_64::instructions::interrupts;
+
+    let before = idle_cycles();
+    interrupts::disable();
+    enable_and_hlt();
+    assert!(idle_cycles() > before);
+    assert_eq!(IDLE_SINCE.load(Ordering::Relaxed), 0);
+
+    // Esperando hasta completar una ventana entera ya hay utilización.
+    let window = crate::time::ms_to_ticks(WINDOW_MS).max(1);
+    let deadline = crate::interrupts::ticks() + 2 * window + 1;
+    while crate::interrupts::ticks() < deadline {
+        interrupts::disable();
+        enable_and_hlt();
+    }
+    assert!(utilization().is_some_and(|busy| busy <= 100));
+}
@@ -0,0 +1,326 @@
+//! Qué CPU tenemos: identificación, topología, caches y frecuencia.
+//!
+//! Junta CPUID con la MADT. La topología sale de la hoja 0xB (hilos por core
+//! y procesadores lógicos por paquete) o, si no está, de las hojas 1 y 4; la
+//! cantidad de sockets es la de procesadores habilitados en la MADT dividida
+//! por los lógicos de cada paquete. La frecuencia es la del TSC que calibró
+//! `time`, que en CPUs modernas coincide con la nominal.
+//!
+//! `print_cpuinfo` lo muestra con el formato de `/proc/cpuinfo`.
+
+use core::arch::x86_64::{__cpuid, __cpuid_count};
+
+pub const MAX_CACHES: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheKind {
+    Data,
+    Instruction,
+    Unified,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cache {
+    pub level: u8,
+    pub kind: CacheKind,
+    pub size: u32,
+    pub line_size: u32,
+    pub ways: u32,
+    /// Procesadores lógicos que la comparten.
+    pub shared_by: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Topology {
+    pub threads_per_core: u32,
+    pub cores_per_package: u32,
+    /// `None` si no hay MADT para contar procesadores.
+    pub packages: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CpuInfo {
+    pub vendor: [u8; 12],
+    pub brand: [u8; 48],
+    pub family: u32,
+    pub model: u32,
+    pub stepping: u32,
+    pub topology: Topology,
+    pub caches: [Option<Cache>; MAX_CACHES],
+    /// Frecuencia del TSC, o 0 si todavía no se calibró.
+    pub tsc_hz: u64,
+    /// CPUID.1: features en EDX (bits 0-31) y ECX (bits 32-63).
+    pub features: u64,
+}
+
+impl CpuInfo {
+    pub fn vendor(&self) -> &str {
+        core::str::from_utf8(&self.vendor).unwrap_or("?")
+    }
+
+    /// Nombre comercial, o "" si la CPU no lo informa.
+    pub fn brand(&self) -> &str {
+        let end = self.brand.iter().position(|&b| b == 0).unwrap_or(self.brand.len());
+        core::str::from_utf8(&self.brand[..end]).unwrap_or("?").trim()
+    }
+
+    pub fn has_feature(&self, name: &str) -> bool {
+        FEATURES
+            .iter()
+            .any(|&(feature, bit)| feature == name && self.features & (1 << bit) != 0)
+    }
+
+    pub fn logical_per_package(&self) -> u32 {
+        self.topology.threads_per_core * self.topology.cores_per_package
+    }
+}
+
+/// Features de CPUID.1 que se listan, con su bit en `CpuInfo::features`.
+const FEATURES: &[(&str, u32)] = &[
+    ("fpu", 0),
+    ("tsc", 4),
+    ("msr", 5),
+    ("pae", 6),
+    ("mce", 7),
+    ("apic", 9),
+    ("mtrr", 12),
+    ("pge", 13),
+    ("mca", 14),
+    ("pat", 16),
+    ("clflush", 19),
+    ("mmx", 23),
+    ("fxsr", 24),
+    ("sse", 25),
+    ("sse2", 26),
+    ("ht", 28),
+    ("sse3", 32),
+    ("pclmulqdq", 33),
+    ("ssse3", 41),
+    ("fma", 44),
+    ("cx16", 45),
+    ("sse4_1", 51),
+    ("sse4_2", 52),
+    ("x2apic", 53),
+    ("popcnt", 55),
+    ("tsc_deadline_timer", 56),
+    ("aes", 57),
+    ("xsave", 58),
+    ("avx", 60),
+    ("rdrand", 62),
+    ("hypervisor", 63),
+];
+
+fn bits(value: u32, low: u32, high: u32) -> u32 {
+    (value >> low) & ((1 << (high - low + 1)) - 1)
+}
+
+fn max_leaf() -> u32 {
+    unsafe { __cpuid(0).eax }
+}
+
+fn max_extended_leaf() -> u32 {
+    unsafe { __cpuid(0x8000_0000).eax }
+}
+
+fn vendor() -> [u8; 12] {
+    let leaf = unsafe { __cpuid(0) };
+    let mut vendor = [0; 12];
+    vendor[..4].copy_from_slice(&leaf.ebx.to_le_bytes());
+    vendor[4..8].copy_from_slice(&leaf.edx.to_le_bytes());
+    vendor[8..].copy_from_slice(&leaf.ecx.to_le_bytes());
+    vendor
+}
+
+fn brand() -> [u8; 48] {
+    let mut brand = [0; 48];
+    if max_extended_leaf() < 0x8000_0004 {
+        return brand;
+    }
+    for (i, leaf) in (0x8000_0002..=0x8000_0004).enumerate() {
+        let regs = unsafe { __cpuid(leaf) };
+        for (j, reg) in [regs.eax, regs.ebx, regs.ecx, regs.edx].into_iter().enumerate() {
+            let at = i * 16 + j * 4;
+            brand[at..at + 4].copy_from_slice(&reg.to_le_bytes());
+        }
+    }
+    brand
+}
+
+/// Familia, modelo y stepping de CPUID.1 EAX, con los campos extendidos.
+fn signature(eax: u32) -> (u32, u32, u32) {
+    let base_family = bits(eax, 8, 11);
+    let mut family = base_family;
+    let mut model = bits(eax, 4, 7);
+    if base_family == 0xF {
+        family += bits(eax, 20, 27);
+    }
+    if base_family == 0x6 || base_family == 0xF {
+        model |= bits(eax, 16, 19) << 4;
+    }
+    (family, model, bits(eax, 0, 3))
+}
+
+/// Un subleaf de la hoja 4 (o de 0x8000_001D en AMD, con el mismo formato).
+/// `None` cuando se terminó la lista.
+fn decode_cache(eax: u32, ebx: u32, ecx: u32) -> Option<Cache> {
+    let kind = match bits(eax, 0, 4) {
+        1 => CacheKind::Data,
+        2 => CacheKind::Instruction,
+        3 => CacheKind::Unified,
+        _ => return None,
+    };
+    let line_size = bits(ebx, 0, 11) + 1;
+    let partitions = bits(ebx, 12, 21) + 1;
+    let ways = bits(ebx, 22, 31) + 1;
+    let sets = ecx + 1;
+    Some(Cache {
+        level: bits(eax, 5, 7) as u8,
+        kind,
+        size: ways * partitions * line_size * sets,
+        line_size,
+        ways,
+        shared_by: bits(eax, 14, 25) + 1,
+    })
+}
+
+fn caches(amd: bool) -> [Option<Cache>; MAX_CACHES] {
+    let mut caches = [None; MAX_CACHES];
+    let leaf = if amd { 0x8000_001D } else { 4 };
+    let supported = if amd { max_extended_leaf() >= leaf } else { max_leaf() >= leaf };
+    if !supported {
+        return caches;
+    }
+    for (subleaf, slot) in caches.iter_mut().enumerate() {
+        let regs = unsafe { __cpuid_count(leaf, subleaf as u32) };
+        match decode_cache(regs.eax, regs.ebx, regs.ecx) {
+            Some(cache) => *slot = Some(cache),
+            None => break,
+        }
+    }
+    caches
+}
+
+/// Hilos por core y procesadores lógicos por paquete.
+fn threads_and_logical(has_ht: bool, amd: bool) -> (u32, u32) {
+    if max_leaf() >= 0xB {
+        let (mut threads, mut logical) = (0, 0);
+        for subleaf in 0.. {
+            let regs = unsafe { __cpuid_count(0xB, subleaf) };
+            match bits(regs.ecx, 8, 15) {
+                1 => threads = bits(regs.ebx, 0, 15),
+                2 => logical = bits(regs.ebx, 0, 15),
+                _ => break,
+            }
+        }
+        if threads > 0 && logical > 0 {
+            return (threads, logical);
+        }
+    }
+
+    let leaf1_logical = if has_ht { bits(unsafe { __cpuid(1).ebx }, 16, 23).max(1) } else { 1 };
+    let cores = if amd && max_extended_leaf() >= 0x8000_0008 {
+        bits(unsafe { __cpuid(0x8000_0008).ecx }, 0, 7) + 1
+    } else if !amd && max_leaf() >= 4 {
+        bits(unsafe { __cpuid_count(4, 0).eax }, 26, 31) + 1
+    } else {
+        1
+    };
+    (leaf1_logical.div_ceil(cores).max(1), leaf1_logical.max(cores))
+}
+
+fn packages(logical_per_package: u32) -> Option<u32> {
+    let cpus = crate::acpi::madt().ok()?.enabled_cpus() as u32;
+    Some(cpus.div_ceil(logical_per_package).max(1))
+}
+
+/// Junta todo lo que se sabe de la CPU. Necesita la memoria inicializada para
+/// leer la MADT.
+pub fn cpuinfo() -> CpuInfo {
+    let leaf1 = unsafe { __cpuid(1) };
+    let (family, model, stepping) = signature(leaf1.eax);
+    let features = u64::from(leaf1.edx) | u64::from(leaf1.ecx) << 32;
+    let vendor = vendor();
+    let amd = &vendor == b"AuthenticAMD";
+
+    let (threads_per_core, logical) = threads_and_logical(features & (1 << 28) != 0, amd);
+    let topology = Topology {
+        threads_per_core,
+        cores_per_package: (logical / threads_per_core).max(1),
+        packages: packages(logical),
+    };
+
+    CpuInfo {
+        vendor,
+        brand: brand(),
+        family,
+        model,
+        stepping,
+        topology,
+        caches: caches(amd),
+        tsc_hz: crate::time::tsc_frequency(),
+        features,
+    }
+}
+
+/// Listado estilo `/proc/cpuinfo`.
+pub fn print_cpuinfo() {
+    let info = cpuinfo();
+    crate::println!("vendor_id       : {}", info.vendor());
+    crate::println!("cpu family      : {}", info.family);
+    crate::println!("model           : {}", info.model);
+    crate::println!("model name      : {}", info.brand());
+    crate::println!("stepping        : {}", info.stepping);
+    if info.tsc_hz != 0 {
+        crate::println!("cpu MHz         : {}.{:03}", info.tsc_hz / 1_000_000, info.tsc_hz / 1000 % 1000);
+    }
+    match info.topology.packages {
+        Some(packages) => crate::println!("sockets         : {}", packages),
+        None => crate::println!("sockets         : ? (sin MADT)"),
+    }
+    crate::println!("cores/socket    : {}", info.topology.cores_per_package);
+    crate::println!("threads/core    : {}", info.topology.threads_per_core);
+    for cache in info.caches.iter().flatten() {
+        let kind = match cache.kind {
+            CacheKind::Data => "d",
+            CacheKind::Instruction => "i",
+            CacheKind::Unified => "",
+        };
+        crate::println!(
+            "cache L{}{:<2}      : {} KB, {} vías, línea de {} B, {} CPUs",
+            cache.level,
+            kind,
+            cache.size / 1024,
+            cache.ways,
+            cache.line_size,
+            cache.shared_by
+        );
+    }
+    crate::print!("flags           :");
+    for &(name, bit) in FEATURES {
+        if info.features & (1 << bit) != 0 {
+            crate::print!(" {}", name);
+        }
+    }
+    crate::println!();
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_decode_cache() {
+    // L1d de 32 KiB: 8 vías, 64 sets, línea de 64, compartida por 2 hilos.
+    let cache = decode_cache(1 | 1 << 5 | 1 << 14, 63 | 7 << 22, 63).unwrap();
+    assert_eq!(cache.kind, CacheKind::Data);
+    assert_eq!(cache.level, 1);
+    assert_eq!(cache.size, 32 * 1024);
+    assert_eq!(cache.shared_by, 2);
+    assert_eq!(decode_cache(0, 0, 0), None);
+}
+
+#[test_case]
+fn test_signature() {
+    // Intel Skylake: familia 6, modelo extendido 0x5E.
+    assert_eq!(signature(0x000506E3), (6, 0x5E, 3));
+    // AMD Zen 2: familia 0xF + 8.
+    assert_eq!(signature(0x00870F10), (0x17, 0x71, 0));
+}
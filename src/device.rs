@@ -0,0 +1,197 @@
+//! Inventario de hardware.
+//!
+//! Los dispositivos se descubren una vez al arrancar y quedan en una tabla fija
+//! (no hay heap todavía cuando corre `driver::init_all`). Los legacy los agrega
+//! `discover`, los del bus PCI el driver `pci` al enumerarlo y los de las
+//! tablas ACPI `acpi::discover`, que necesita la memoria física mapeada. Un
+//! driver se asocia a un dispositivo por su ID PNP a través de `bind`.
+
+use spin::Mutex;
+use crate::portio::{self, PortRegion};
+
+const MAX_DEVICES: usize = 64;
+const MAX_RESOURCES: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bus {
+    Isa,
+    Ps2,
+    Pci,
+    Acpi,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    IoPorts { start: u16, len: u16 },
+    Irq(u8),
+    Mmio { start: u64, len: u64 },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Device {
+    pub bus: Bus,
+    /// ID PNP (por ejemplo `PNP0303` para un teclado PS/2).
+    pub id: &'static str,
+    pub name: &'static str,
+    pub resources: [Option<Resource>; MAX_RESOURCES],
+    pub driver: Option<&'static str>,
+}
+
+impl Device {
+    pub const fn new(bus: Bus, id: &'static str, name: &'static str) -> Self {
+        Device {
+            bus,
+            id,
+            name,
+            resources: [None; MAX_RESOURCES],
+            driver: None,
+        }
+    }
+
+    pub const fn with(mut self, resource: Resource) -> Self {
+        let mut i = 0;
+        while i < MAX_RESOURCES {
+            if self.resources[i].is_none() {
+                self.resources[i] = Some(resource);
+                return self;
+            }
+            i += 1;
+        }
+        panic!("demasiados recursos para un dispositivo");
+    }
+
+    pub fn resources(&self) -> impl Iterator<Item = &Resource> {
+        self.resources.iter().flatten()
+    }
+}
+
+struct DeviceTable {
+    devices: [Option<Device>; MAX_DEVICES],
+    len: usize,
+}
+
+static DEVICES: Mutex<DeviceTable> = Mutex::new(DeviceTable {
+    devices: [None; MAX_DEVICES],
+    len: 0,
+});
+
+/// Agrega un dispositivo al inventario. Si la tabla está llena se avisa y
+/// se lo descarta: el bus PCI puede tener más funciones de las que entran.
+pub fn add(device: Device) {
+    let mut table = DEVICES.lock();
+    let len = table.len;
+    if len == MAX_DEVICES {
+        drop(table);
+        crate::log_warn!("tabla de dispositivos llena; se descarta {} ({})", device.name, device.id);
+        return;
+    }
+    table.devices[len] = Some(device);
+    table.len += 1;
+}
+
+/// Asocia `driver` al primer dispositivo libre con el ID dado.
+/// Devuelve una copia del dispositivo asociado.
+pub fn bind(driver: &'static str, id: &str) -> Option<Device> {
+    let mut table = DEVICES.lock();
+    let len = table.len;
+    table.devices[..len]
+        .iter_mut()
+        .flatten()
+        .find(|d| d.id == id && d.driver.is_none())
+        .map(|d| {
+            d.driver = Some(driver);
+            *d
+        })
+}
+
+pub fn for_each(mut f: impl FnMut(&Device)) {
+    let table = DEVICES.lock();
+    for device in table.devices[..table.len].iter().flatten() {
+        f(device);
+    }
+}
+
+/// Listado estilo `lsdev`.
+pub fn print_devices() {
+    crate::println!("BUS   ID       DRIVER        NOMBRE");
+    for_each(|d| {
+        crate::println!(
+            "{:<5} {:<8} {:<13} {}",
+            bus_name(d.bus),
+            d.id,
+            d.driver.unwrap_or("-"),
+            d.name
+        );
+        for resource in d.resources() {
+            match *resource {
+                Resource::IoPorts { start, len } => {
+                    crate::println!("        io   {:#06x}-{:#06x}", start, start + len - 1)
+                }
+                Resource::Irq(irq) => crate::println!("        irq  {}", irq),
+                Resource::Mmio { start, len } => {
+                    crate::println!("        mmio {:#x}-{:#x}", start, start + len - 1)
+                }
+            }
+        }
+    });
+}
+
+fn bus_name(bus: Bus) -> &'static str {
+    match bus {
+        Bus::Isa => "isa",
+        Bus::Ps2 => "ps2",
+        Bus::Pci => "pci",
+        Bus::Acpi => "acpi",
+    }
+}
+
+// ----------------- DESCUBRIMIENTO -----------------
+
+/// Detecta los dispositivos legacy de la PC. Lo llama `driver::init_all`.
+pub fn discover() {
+    add(Device::new(Bus::Isa, "PNP0000", "PIC 8259 encadenado")
+        .with(Resource::IoPorts { start: 0x20, len: 2 })
+        .with(Resource::IoPorts { start: 0xA0, len: 2 }));
+
+    add(Device::new(Bus::Isa, "PNP0100", "PIT 8253/8254")
+        .with(Resource::IoPorts { start: 0x40, len: 4 })
+        .with(Resource::Irq(0)));
+
+    add(Device::new(Bus::Isa, "PNP0B00", "RTC/CMOS")
+        .with(Resource::IoPorts { start: 0x70, len: 2 })
+        .with(Resource::Irq(8)));
+
+    add(Device::new(Bus::Isa, "PNP0900", "VGA modo texto")
+        .with(Resource::Mmio { start: 0xb8000, len: 0x8000 }));
+
+    if com1_present() {
+        add(Device::new(Bus::Isa, "PNP0501", "UART 16550 (COM1)")
+            .with(Resource::IoPorts { start: 0x3F8, len: 8 })
+            .with(Resource::Irq(4)));
+    }
+
+    // Los dispositivos PS/2 los agrega el driver `i8042` después de
+    // inicializar el controlador e identificar qué hay en cada puerto.
+}
+
+/// El registro scratch del UART devuelve lo que se escribe si el chip existe.
+fn com1_present() -> bool {
+    probe_ports(0x3F8, 8, |ports| unsafe {
+        let mut scratch = ports.port::<u8>(7);
+        scratch.write(0xAE);
+        scratch.read() == 0xAE
+    })
+}
+
+/// Reclama los puertos sólo mientras dura el sondeo; después son del driver.
+/// Si ya tienen dueño, el dispositivo está claramente presente.
+fn probe_ports(start: u16, len: u16, probe: impl FnOnce(&PortRegion) -> bool) -> bool {
+    match portio::claim("probe", start, len) {
+        Ok(ports) => {
+            let present = probe(&ports);
+            portio::release(ports);
+            present
+        }
+        Err(_) => true,
+    }
+}
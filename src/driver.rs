@@ -0,0 +1,230 @@
+//! Registro de drivers estilo "módulo de kernel".
+//!
+//! Cada driver se declara con `register_driver!`, que coloca un `Driver` en la
+//! sección `kur_drivers` del binario. El linker genera los símbolos
+//! `__start_kur_drivers` / `__stop_kur_drivers`, así que `init_all()` puede
+//! recorrer todos los drivers sin una lista mantenida a mano.
+//!
+//! Los drivers se agrupan en etapas (`Stage`) que corren en orden; dentro de
+//! cada etapa se ordenan por dependencias. Compilando con
+//! `KUR_INIT_STOP_AFTER=<etapa>` el arranque se detiene al terminar esa etapa,
+//! para depurar el boot de a partes.
+
+/// Cantidad máxima de drivers que soporta el ordenamiento (no hay heap todavía).
+const MAX_DRIVERS: usize = 32;
+
+/// Etapas de arranque, en el orden en que se ejecutan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    /// Lo mínimo para reportar errores: GDT, puerto serie, CMOS.
+    Early,
+    /// Reservada: `memory::init` necesita el `BootInfo` y todavía se llama
+    /// desde el entry point, después de `kur_os::init`.
+    Memory,
+    /// IDT y controladores de interrupciones.
+    Interrupts,
+    /// Dispositivos que generan IRQs.
+    Drivers,
+    /// Servicios que usan a los anteriores.
+    Services,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 5] = [
+        Stage::Early,
+        Stage::Memory,
+        Stage::Interrupts,
+        Stage::Drivers,
+        Stage::Services,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Early => "early",
+            Stage::Memory => "memory",
+            Stage::Interrupts => "interrupts",
+            Stage::Drivers => "drivers",
+            Stage::Services => "services",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Stage> {
+        Stage::ALL.into_iter().find(|stage| stage.name() == name)
+    }
+}
+
+/// Etapa después de la cual se detiene el arranque, fijada al compilar.
+fn stop_after() -> Option<Stage> {
+    let name = option_env!("KUR_INIT_STOP_AFTER")?;
+    match Stage::from_name(name) {
+        Some(stage) => Some(stage),
+        None => panic!("KUR_INIT_STOP_AFTER: etapa '{}' desconocida", name),
+    }
+}
+
+pub struct Driver {
+    /// Nombre único, usado también para declarar dependencias.
+    pub name: &'static str,
+    /// Etapa en la que se inicializa.
+    pub stage: Stage,
+    /// Drivers que tienen que estar inicializados antes que este.
+    pub depends_on: &'static [&'static str],
+    /// ID PNP del dispositivo que maneja; se asocia con `device::bind`.
+    pub device: Option<&'static str>,
+    /// Criterio de match: devuelve `false` si el hardware no está presente.
+    pub probe: fn() -> bool,
+    pub init: fn(),
+}
+
+impl Driver {
+    /// Probe por defecto para drivers que no dependen de hardware detectable.
+    pub fn always() -> bool {
+        true
+    }
+}
+
+/// Registra un driver en la sección `kur_drivers`.
+///
+/// ```ignore
+/// register_driver!(GDT_DRIVER, Driver {
+///     name: "gdt",
+///     stage: Stage::Early,
+///     depends_on: &[],
+///     device: None,
+///     probe: Driver::always,
+///     init: gdt::init,
+/// });
+/// ```
+#[macro_export]
+macro_rules! register_driver {
+    ($ident:ident, $driver:expr) => {
+        #[used]
+        #[unsafe(link_section = "kur_drivers")]
+        static $ident: $crate::driver::Driver = $driver;
+    };
+}
+
+unsafe extern "C" {
+    static __start_kur_drivers: u8;
+    static __stop_kur_drivers: u8;
+}
+
+/// Todos los drivers registrados, en el orden en que los dejó el linker.
+pub fn drivers() -> &'static [Driver] {
+    unsafe {
+        let start = (&raw const __start_kur_drivers).cast::<Driver>();
+        let stop = (&raw const __stop_kur_drivers).cast::<Driver>();
+        let len = stop.offset_from(start) as usize;
+        core::slice::from_raw_parts(start, len)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Pending,
+    Ready,
+    Absent,
+}
+
+/// Inicializa todos los drivers, etapa por etapa, respetando sus dependencias.
+///
+/// Antes se descubren los dispositivos. Un driver cuyo `probe` falla, o cuyo
+/// dispositivo no aparece, queda ausente, y también los que dependen de él;
+/// al final de cada etapa se informan los ausentes. Entra en pánico si una
+/// dependencia no existe, está en una etapa posterior o si hay un ciclo.
+pub fn init_all() {
+    crate::device::discover();
+
+    let drivers = drivers();
+    assert!(drivers.len() <= MAX_DRIVERS, "demasiados drivers registrados");
+
+    for driver in drivers {
+        for dep in driver.depends_on {
+            let Some(index) = index_of(drivers, dep) else {
+                panic!("driver '{}' depende de '{}', que no existe", driver.name, dep);
+            };
+            if drivers[index].stage > driver.stage {
+                panic!(
+                    "driver '{}' (etapa {}) depende de '{}', de la etapa posterior {}",
+                    driver.name,
+                    driver.stage.name(),
+                    dep,
+                    drivers[index].stage.name()
+                );
+            }
+        }
+    }
+
+    let mut states = [State::Pending; MAX_DRIVERS];
+    let stop_after = stop_after();
+
+    for stage in Stage::ALL {
+        init_stage(drivers, &mut states, stage);
+        report_absent(drivers, &states, stage);
+
+        if stop_after == Some(stage) {
+            crate::println!("init: detenido después de la etapa '{}'", stage.name());
+            crate::hlt_loop();
+        }
+    }
+}
+
+fn init_stage(drivers: &[Driver], states: &mut [State], stage: Stage) {
+    let mut remaining = drivers.iter().filter(|d| d.stage == stage).count();
+
+    while remaining > 0 {
+        let mut progress = false;
+
+        for (i, driver) in drivers.iter().enumerate() {
+            if driver.stage != stage || states[i] != State::Pending {
+                continue;
+            }
+
+            let mut deps_ready = true;
+            let mut deps_absent = false;
+            for dep in driver.depends_on {
+                match states[index_of(drivers, dep).unwrap()] {
+                    State::Pending => deps_ready = false,
+                    State::Absent => deps_absent = true,
+                    State::Ready => {}
+                }
+            }
+            if !deps_ready {
+                continue;
+            }
+
+            // El dispositivo se toma sólo si el driver va a arrancar: uno que
+            // falla el probe o le falta una dependencia no lo deja tomado.
+            let bind = || match driver.device {
+                Some(id) => crate::device::bind(driver.name, id).is_some(),
+                None => true,
+            };
+
+            states[i] = if !deps_absent && (driver.probe)() && bind() {
+                (driver.init)();
+                crate::event::publish(crate::event::Event::DeviceAdded(driver.name));
+                State::Ready
+            } else {
+                State::Absent
+            };
+            remaining -= 1;
+            progress = true;
+        }
+
+        if !progress {
+            panic!("ciclo de dependencias entre drivers de la etapa '{}'", stage.name());
+        }
+    }
+}
+
+fn report_absent(drivers: &[Driver], states: &[State], stage: Stage) {
+    for (i, driver) in drivers.iter().enumerate() {
+        if driver.stage == stage && states[i] == State::Absent {
+            crate::log_warn!("init [{}]: driver '{}' ausente", stage.name(), driver.name);
+        }
+    }
+}
+
+fn index_of(drivers: &[Driver], name: &str) -> Option<usize> {
+    drivers.iter().position(|d| d.name == name)
+}
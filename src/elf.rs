@@ -0,0 +1,203 @@
+//! Lectura de ejecutables ELF64 para x86_64.
+//!
+//! Alcanza para cargar programas estáticos: se valida el encabezado y se
+//! recorren los segmentos `PT_LOAD`. No hay relocaciones, ni intérprete, ni
+//! bibliotecas dinámicas. Nada aloca: las vistas apuntan a la imagen.
+
+use core::fmt;
+
+use crate::error::KernelError;
+
+const MAGIC: [u8; 4] = *b"\x7FELF";
+const CLASS_64: u8 = 2;
+const LITTLE_ENDIAN: u8 = 1;
+const TYPE_EXEC: u16 = 2;
+const MACHINE_X86_64: u16 = 0x3E;
+const HEADER_LEN: usize = 64;
+const PROGRAM_HEADER_LEN: usize = 56;
+const PT_LOAD: u32 = 1;
+
+/// Permisos de un segmento (`p_flags`).
+pub const PF_X: u32 = 1;
+pub const PF_W: u32 = 2;
+pub const PF_R: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElfError {
+    /// No empieza con `\x7FELF` o es más corto que los encabezados.
+    NotElf,
+    /// Es ELF pero no un ejecutable de 64 bits little-endian para x86_64.
+    Unsupported,
+    /// Un segmento se sale del archivo o tiene tamaños incoherentes.
+    BadSegment(usize),
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ElfError::NotElf => write!(f, "no es un ELF"),
+            ElfError::Unsupported => write!(f, "ELF no soportado"),
+            ElfError::BadSegment(index) => write!(f, "segmento {} inválido", index),
+        }
+    }
+}
+
+impl From<ElfError> for KernelError {
+    fn from(err: ElfError) -> Self {
+        match err {
+            ElfError::NotElf | ElfError::BadSegment(_) => KernelError::InvalidArgument,
+            ElfError::Unsupported => KernelError::Unsupported,
+        }
+    }
+}
+
+/// Un segmento `PT_LOAD`: `data` va en `vaddr` y el resto hasta `mem_size`
+/// se llena con ceros.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment<'a> {
+    pub vaddr: u64,
+    pub mem_size: u64,
+    pub flags: u32,
+    pub data: &'a [u8],
+}
+
+impl Segment<'_> {
+    pub fn is_writable(&self) -> bool {
+        self.flags & PF_W != 0
+    }
+}
+
+pub struct Elf<'a> {
+    image: &'a [u8],
+    entry: u64,
+    ph_offset: usize,
+    ph_count: usize,
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+impl<'a> Elf<'a> {
+    /// Valida el encabezado y todos los segmentos.
+    pub fn parse(image: &'a [u8]) -> Result<Elf<'a>, ElfError> {
+        if image.len() < HEADER_LEN || image[..4] != MAGIC {
+            return Err(ElfError::NotElf);
+        }
+        if image[4] != CLASS_64
+            || image[5] != LITTLE_ENDIAN
+            || u16_at(image, 16) != TYPE_EXEC
+            || u16_at(image, 18) != MACHINE_X86_64
+            || usize::from(u16_at(image, 54)) != PROGRAM_HEADER_LEN
+        {
+            return Err(ElfError::Unsupported);
+        }
+        let ph_offset = usize::try_from(u64_at(image, 32)).map_err(|_| ElfError::NotElf)?;
+        let ph_count = usize::from(u16_at(image, 56));
+        let end = ph_count.checked_mul(PROGRAM_HEADER_LEN).and_then(|len| len.checked_add(ph_offset));
+        if end.is_none_or(|end| end > image.len()) {
+            return Err(ElfError::NotElf);
+        }
+        let elf = Elf { image, entry: u64_at(image, 24), ph_offset, ph_count };
+        for index in 0..ph_count {
+            elf.segment(index)?;
+        }
+        Ok(elf)
+    }
+
+    pub fn entry(&self) -> u64 {
+        self.entry
+    }
+
+    /// Los segmentos `PT_LOAD`, en el orden del archivo.
+    pub fn segments(&self) -> impl Iterator<Item = Segment<'a>> + '_ {
+        // `parse` ya los validó.
+        (0..self.ph_count).filter_map(|index| self.segment(index).ok().flatten())
+    }
+
+    fn segment(&self, index: usize) -> Result<Option<Segment<'a>>, ElfError> {
+        let header = &self.image[self.ph_offset + index * PROGRAM_HEADER_LEN..][..PROGRAM_HEADER_LEN];
+        if u32_at(header, 0) != PT_LOAD {
+            return Ok(None);
+        }
+        let offset = u64_at(header, 8);
+        let vaddr = u64_at(header, 16);
+        let file_size = u64_at(header, 32);
+        let mem_size = u64_at(header, 40);
+        let data = offset
+            .checked_add(file_size)
+            .filter(|&end| end <= self.image.len() as u64 && file_size <= mem_size)
+            .filter(|_| vaddr.checked_add(mem_size).is_some())
+            .map(|end| &self.image[offset as usize..end as usize])
+            .ok_or(ElfError::BadSegment(index))?;
+        Ok(Some(Segment { vaddr, mem_size, flags: u32_at(header, 4), data }))
+    }
+}
+
+// ----------------- TESTS -----------------
+
+#[cfg(test)]
+const TEST_IMAGE_LEN: usize = HEADER_LEN + 2 * PROGRAM_HEADER_LEN + 4;
+
+/// Un ejecutable con un segmento `PT_LOAD` de 4 bytes y un `PT_NOTE`.
+#[cfg(test)]
+fn test_image() -> [u8; TEST_IMAGE_LEN] {
+    let mut image = [0u8; TEST_IMAGE_LEN];
+    image[..4].copy_from_slice(&MAGIC);
+    image[4] = CLASS_64;
+    image[5] = LITTLE_ENDIAN;
+    image[16..18].copy_from_slice(&TYPE_EXEC.to_le_bytes());
+    image[18..20].copy_from_slice(&MACHINE_X86_64.to_le_bytes());
+    image[24..32].copy_from_slice(&0x40_1000u64.to_le_bytes());
+    image[32..40].copy_from_slice(&(HEADER_LEN as u64).to_le_bytes());
+    image[54..56].copy_from_slice(&(PROGRAM_HEADER_LEN as u16).to_le_bytes());
+    image[56..58].copy_from_slice(&2u16.to_le_bytes());
+
+    let load = &mut image[HEADER_LEN..][..PROGRAM_HEADER_LEN];
+    load[0..4].copy_from_slice(&PT_LOAD.to_le_bytes());
+    load[4..8].copy_from_slice(&(PF_R | PF_X).to_le_bytes());
+    load[8..16].copy_from_slice(&((TEST_IMAGE_LEN - 4) as u64).to_le_bytes());
+    load[16..24].copy_from_slice(&0x40_1000u64.to_le_bytes());
+    load[32..40].copy_from_slice(&4u64.to_le_bytes());
+    load[40..48].copy_from_slice(&0x2000u64.to_le_bytes());
+    // El segundo queda como `PT_NOTE` (4).
+    image[HEADER_LEN + PROGRAM_HEADER_LEN] = 4;
+    image[TEST_IMAGE_LEN - 4..].copy_from_slice(&[0xDE, 0xAD, 0xBE, 0xEF]);
+    image
+}
+
+#[test_case]
+fn test_parse_load_segments() {
+    let image = test_image();
+    let elf = Elf::parse(&image).unwrap();
+    assert_eq!(elf.entry(), 0x40_1000);
+    let mut segments = elf.segments();
+    let segment = segments.next().unwrap();
+    assert_eq!(segment.vaddr, 0x40_1000);
+    assert_eq!(segment.mem_size, 0x2000);
+    assert_eq!(segment.data, [0xDE, 0xAD, 0xBE, 0xEF]);
+    assert!(!segment.is_writable());
+    assert!(segments.next().is_none());
+}
+
+#[test_case]
+fn test_parse_rejects_bad_images() {
+    assert_eq!(Elf::parse(b"hola").err(), Some(ElfError::NotElf));
+
+    let mut image = test_image();
+    image[18] = 0x28; // ARM
+    assert_eq!(Elf::parse(&image).err(), Some(ElfError::Unsupported));
+
+    let mut image = test_image();
+    // El segmento ocupa más en el archivo que en memoria.
+    image[HEADER_LEN + 40..HEADER_LEN + 48].copy_from_slice(&2u64.to_le_bytes());
+    assert_eq!(Elf::parse(&image).err(), Some(ElfError::BadSegment(0)));
+}
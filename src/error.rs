@@ -0,0 +1,182 @@
+//! Error común de los subsistemas del kernel.
+//!
+//! Las APIs de `memory` y `allocator` devuelven `KernelResult`, y cada
+//! subsistema con su propio error (`AcpiError`, `Ps2Error`, ...) lo convierte
+//! a `KernelError` con `From`, así un `?` compone llamadas a varios
+//! subsistemas sin un `map_err` por cada uno. El error propio sigue siendo el
+//! que devuelve cada driver: es el que tiene el detalle para diagnosticar.
+
+use core::alloc::AllocError;
+use core::fmt;
+use x86_64::structures::paging::mapper::{MapToError, UnmapError};
+use x86_64::structures::paging::Size4KiB;
+
+use crate::acpi::AcpiError;
+use crate::apic::ApicError;
+use crate::hpet::HpetError;
+use crate::ioapic::IoApicError;
+use crate::kprobe::KprobeError;
+use crate::msi::MsiError;
+use crate::ps2::Ps2Error;
+use crate::task::keyboard::LedError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KernelError {
+    /// No quedan marcos físicos, heap o espacio virtual.
+    OutOfMemory,
+    /// La dirección no está mapeada.
+    NotMapped,
+    AlreadyMapped,
+    /// El subsistema todavía no se inicializó.
+    NotInitialized,
+    /// El dispositivo falló o respondió algo inesperado; lleva su nombre.
+    DeviceError(&'static str),
+    NotFound,
+    InvalidArgument,
+    /// El recurso está tomado y esperar podría colgar al que llama (por
+    /// ejemplo, desde un handler de interrupción).
+    WouldBlock,
+    TimedOut,
+    Unsupported,
+}
+
+pub type KernelResult<T> = Result<T, KernelError>;
+
+impl fmt::Display for KernelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KernelError::OutOfMemory => write!(f, "sin memoria"),
+            KernelError::NotMapped => write!(f, "dirección no mapeada"),
+            KernelError::AlreadyMapped => write!(f, "página ya mapeada"),
+            KernelError::NotInitialized => write!(f, "subsistema sin inicializar"),
+            KernelError::DeviceError(device) => write!(f, "error del dispositivo {}", device),
+            KernelError::NotFound => write!(f, "no encontrado"),
+            KernelError::InvalidArgument => write!(f, "argumento inválido"),
+            KernelError::WouldBlock => write!(f, "recurso ocupado"),
+            KernelError::TimedOut => write!(f, "tiempo de espera agotado"),
+            KernelError::Unsupported => write!(f, "no soportado"),
+        }
+    }
+}
+
+impl From<MapToError<Size4KiB>> for KernelError {
+    fn from(err: MapToError<Size4KiB>) -> Self {
+        match err {
+            MapToError::FrameAllocationFailed => KernelError::OutOfMemory,
+            MapToError::PageAlreadyMapped(_) => KernelError::AlreadyMapped,
+            MapToError::ParentEntryHugePage => KernelError::Unsupported,
+        }
+    }
+}
+
+impl From<UnmapError> for KernelError {
+    fn from(err: UnmapError) -> Self {
+        match err {
+            UnmapError::PageNotMapped => KernelError::NotMapped,
+            UnmapError::ParentEntryHugePage => KernelError::Unsupported,
+            UnmapError::InvalidFrameAddress(_) => KernelError::InvalidArgument,
+        }
+    }
+}
+
+impl From<AllocError> for KernelError {
+    fn from(_: AllocError) -> Self {
+        KernelError::OutOfMemory
+    }
+}
+
+impl From<AcpiError> for KernelError {
+    fn from(err: AcpiError) -> Self {
+        match err {
+            AcpiError::RsdpNotFound | AcpiError::TableNotFound(_) => KernelError::NotFound,
+            AcpiError::BadChecksum(_) => KernelError::DeviceError("acpi"),
+        }
+    }
+}
+
+impl From<HpetError> for KernelError {
+    fn from(err: HpetError) -> Self {
+        match err {
+            HpetError::Acpi(err) => err.into(),
+            HpetError::Map(err) => err,
+            HpetError::BadPeriod(_) => KernelError::DeviceError("hpet"),
+            HpetError::Counter32 => KernelError::Unsupported,
+        }
+    }
+}
+
+impl From<IoApicError> for KernelError {
+    fn from(err: IoApicError) -> Self {
+        match err {
+            IoApicError::Acpi(err) => err.into(),
+            IoApicError::Map(err) => err,
+            IoApicError::NoSuchGsi(_) | IoApicError::NoSuchEntry(_) => KernelError::InvalidArgument,
+        }
+    }
+}
+
+impl From<ApicError> for KernelError {
+    fn from(err: ApicError) -> Self {
+        match err {
+            ApicError::Map(err) => err,
+        }
+    }
+}
+
+impl From<MsiError> for KernelError {
+    fn from(err: MsiError) -> Self {
+        match err {
+            MsiError::NotSupported => KernelError::Unsupported,
+            MsiError::NoSuchEntry(_) => KernelError::InvalidArgument,
+            MsiError::BadBar(_) => KernelError::DeviceError("msi"),
+            MsiError::Map(err) => err,
+        }
+    }
+}
+
+impl From<Ps2Error> for KernelError {
+    fn from(err: Ps2Error) -> Self {
+        match err {
+            Ps2Error::NotInitialized => KernelError::NotInitialized,
+            Ps2Error::Timeout => KernelError::TimedOut,
+            Ps2Error::SelfTestFailed(_) | Ps2Error::PortTestFailed(..) | Ps2Error::NoAck(_) => {
+                KernelError::DeviceError("i8042")
+            }
+        }
+    }
+}
+
+impl From<LedError> for KernelError {
+    fn from(err: LedError) -> Self {
+        match err {
+            LedError::NotInitialized => KernelError::NotInitialized,
+            LedError::Timeout => KernelError::TimedOut,
+            LedError::NoAck(_) => KernelError::DeviceError("teclado"),
+        }
+    }
+}
+
+impl From<KprobeError> for KernelError {
+    fn from(err: KprobeError) -> Self {
+        match err {
+            KprobeError::AlreadyProbed => KernelError::InvalidArgument,
+            KprobeError::TableFull => KernelError::OutOfMemory,
+            KprobeError::NotFound => KernelError::NotFound,
+        }
+    }
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_subsystem_errors_convert() {
+    fn hpet() -> KernelResult<()> {
+        Err(HpetError::Acpi(AcpiError::TableNotFound(*b"HPET")))?
+    }
+
+    assert_eq!(hpet(), Err(KernelError::NotFound));
+    assert_eq!(KernelError::from(MapToError::<Size4KiB>::FrameAllocationFailed), KernelError::OutOfMemory);
+    assert_eq!(KernelError::from(UnmapError::PageNotMapped), KernelError::NotMapped);
+    assert_eq!(KernelError::from(Ps2Error::Timeout), KernelError::TimedOut);
+    assert_eq!(KernelError::from(IoApicError::Map(KernelError::OutOfMemory)), KernelError::OutOfMemory);
+}
@@ -0,0 +1,116 @@
+//! Bus de eventos del kernel.
+//!
+//! Los drivers publican lo que pasa (un dispositivo nuevo, una tecla) con
+//! `publish` y los servicios se suscriben con `subscribe`, que devuelve un
+//! `Stream` para consumirlos desde una tarea async. Cada suscriptor tiene su
+//! propia cola: uno lento no frena a los demás, pero pierde los eventos que
+//! no le entran (se cuentan en `Subscription::dropped`).
+//!
+//! `publish` no aloca ni bloquea, así que se puede llamar desde un handler de
+//! interrupción y antes de que exista el heap. `subscribe` sí necesita el heap.
+
+use alloc::sync::Arc;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::task::{Context, Poll};
+use crossbeam_queue::ArrayQueue;
+use futures_util::stream::Stream;
+use futures_util::task::AtomicWaker;
+use pc_keyboard::DecodedKey;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+pub const MAX_SUBSCRIBERS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// Un driver terminó de inicializar su dispositivo.
+    DeviceAdded(&'static str),
+    /// Una interfaz de red tiene enlace.
+    LinkUp(&'static str),
+    KeyPressed(DecodedKey),
+}
+
+struct Subscriber {
+    queue: ArrayQueue<Event>,
+    waker: AtomicWaker,
+    dropped: AtomicU64,
+}
+
+crate::register_counter!(PUBLISHED, "event.published");
+
+static SUBSCRIBERS: Mutex<[Option<Arc<Subscriber>>; MAX_SUBSCRIBERS]> =
+    Mutex::new([const { None }; MAX_SUBSCRIBERS]);
+
+/// Entrega `event` a todos los suscriptores.
+pub fn publish(event: Event) {
+    PUBLISHED.inc();
+    interrupts::without_interrupts(|| {
+        for subscriber in SUBSCRIBERS.lock().iter().flatten() {
+            if subscriber.queue.push(event).is_ok() {
+                subscriber.waker.wake();
+            } else {
+                subscriber.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    });
+}
+
+/// Se suscribe a todos los eventos publicados a partir de ahora, con una cola
+/// de `capacity` eventos. `None` si ya hay `MAX_SUBSCRIBERS` suscriptores.
+pub fn subscribe(capacity: usize) -> Option<Subscription> {
+    let subscriber = Arc::new(Subscriber {
+        queue: ArrayQueue::new(capacity),
+        waker: AtomicWaker::new(),
+        dropped: AtomicU64::new(0),
+    });
+    interrupts::without_interrupts(|| {
+        let mut subscribers = SUBSCRIBERS.lock();
+        let index = subscribers.iter().position(|slot| slot.is_none())?;
+        subscribers[index] = Some(subscriber.clone());
+        Some(Subscription { index, subscriber })
+    })
+}
+
+/// Extremo de lectura de una suscripción; al soltarlo se libera el lugar.
+pub struct Subscription {
+    index: usize,
+    subscriber: Arc<Subscriber>,
+}
+
+impl Subscription {
+    /// El próximo evento, sin esperar.
+    pub fn try_next(&self) -> Option<Event> {
+        self.subscriber.queue.pop()
+    }
+
+    /// Eventos perdidos por tener la cola llena.
+    pub fn dropped(&self) -> u64 {
+        self.subscriber.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Stream for Subscription {
+    type Item = Event;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Event>> {
+        if let Some(event) = self.subscriber.queue.pop() {
+            return Poll::Ready(Some(event));
+        }
+
+        self.subscriber.waker.register(cx.waker());
+        match self.subscriber.queue.pop() {
+            Some(event) => {
+                self.subscriber.waker.take();
+                Poll::Ready(Some(event))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        interrupts::without_interrupts(|| SUBSCRIBERS.lock()[self.index] = None);
+    }
+}
@@ -4,6 +4,11 @@ use x86_64::structures::tss::TaskStateSegment;
 use x86_64::structures::gdt::{GlobalDescriptorTable, Descriptor, SegmentSelector};
 use lazy_static::lazy_static;
 use x86_64::VirtAddr;
+use x86_64::instructions::interrupts;
+use core::cell::UnsafeCell;
+use core::mem::size_of;
+use spin::Mutex;
+use crate::driver::{Driver, Stage};
 
 
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
@@ -11,37 +16,112 @@ pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
 
 pub const BREAKPOINT_IST_INDEX: u16 = 1;
 
+/// NMI, #MC y #DB pueden llegar entre el `syscall` y el cambio de stack de
+/// `syscall::entry`, ya en ring 0 pero con el RSP del usuario: sin un stack
+/// propio, la CPU apilaría el frame donde el usuario eligió.
+pub const NMI_IST_INDEX: u16 = 2;
+pub const MACHINE_CHECK_IST_INDEX: u16 = 3;
+pub const DEBUG_IST_INDEX: u16 = 4;
+
+pub const IST_STACK_SIZE: usize = 4096 * 5; // 20 KB
+
+const IST_INDICES: [u16; 5] =
+    [DOUBLE_FAULT_IST_INDEX, BREAKPOINT_IST_INDEX, NMI_IST_INDEX, MACHINE_CHECK_IST_INDEX, DEBUG_IST_INDEX];
+
+/// Tamaño del stack al que salta la CPU cuando una interrupción llega desde
+/// ring 3 (`RSP0` del TSS).
+pub const PRIVILEGE_STACK_SIZE: usize = 4096 * 5;
+
+/// Un bit por puerto de E/S (65536 puertos).
+const IO_BITMAP_BYTES: usize = 65536 / 8;
+
+/// Bitmap de permisos de E/S para ring 3: un bit en 1 niega el puerto.
+/// La CPU lo lee directamente de la memoria del TSS.
+#[repr(transparent)]
+struct IoBitmap(UnsafeCell<[u8; IO_BITMAP_BYTES + 1]>);
+
+// Las escrituras se serializan con `IO_BITMAP_LOCK`.
+unsafe impl Sync for IoBitmap {}
+
+/// El TSS en sí. Después de cargarlo sólo cambia `RSP0`, con
+/// `set_privilege_stack`.
+#[repr(transparent)]
+struct Tss(UnsafeCell<TaskStateSegment>);
+
+// La única escritura, la de `set_privilege_stack`, es de una palabra y con
+// las interrupciones deshabilitadas.
+unsafe impl Sync for Tss {}
+
+impl Tss {
+    fn get(&self) -> &TaskStateSegment {
+        unsafe { &*self.0.get() }
+    }
+}
+
+/// TSS seguido de su bitmap de E/S, como lo espera la CPU: `iomap_base` es el
+/// offset del bitmap desde el comienzo del TSS, y el byte extra al final
+/// (siempre 0xFF) cubre los accesos de varios bytes que cruzan el último.
+#[repr(C)]
+struct TssWithIoBitmap {
+    tss: Tss,
+    io_bitmap: IoBitmap,
+}
+
+static IO_BITMAP_LOCK: Mutex<()> = Mutex::new(());
+
+/// El tope de un stack IST nuevo: cada uso tiene su propio `static`.
+macro_rules! ist_stack {
+    () => {{
+        #[repr(align(16))]
+        #[allow(dead_code)]
+        struct AlignedStack([u8; IST_STACK_SIZE]);
+        static mut STACK: AlignedStack = AlignedStack([0; IST_STACK_SIZE]);
+
+        VirtAddr::from_ptr(&raw const STACK) + IST_STACK_SIZE as u64
+    }};
+}
+
 lazy_static! {
 
-    static ref TSS: TaskStateSegment = {
+    static ref TSS: TssWithIoBitmap = {
         let mut tss = TaskStateSegment::new();
+        tss.iomap_base = size_of::<TaskStateSegment>() as u16;
         
-        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = 4096 * 5; // 20 KB
-            #[repr(align(16))]
-            #[allow(dead_code)]
-            struct AlignedStack([u8; 4096 * 5]);
-            static mut STACK: AlignedStack = AlignedStack([0; 4096 * 5]);
-
-            let stack_start = VirtAddr::from_ptr(&raw const STACK);
-            stack_start + STACK_SIZE as u64
-        };
-        
-        tss.interrupt_stack_table[BREAKPOINT_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = 4096 * 5;
-            #[repr(align(16))]
-            #[allow(dead_code)]
-            struct AlignedStack([u8; 4096 * 5]);
-            static mut STACK: AlignedStack = AlignedStack([0; 4096 * 5]);
-
-            let stack_start = VirtAddr::from_ptr(&raw const STACK);
-            stack_start + STACK_SIZE as u64
-        };
+        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = ist_stack!();
+        tss.interrupt_stack_table[BREAKPOINT_IST_INDEX as usize] = ist_stack!();
+        tss.interrupt_stack_table[NMI_IST_INDEX as usize] = ist_stack!();
+        tss.interrupt_stack_table[MACHINE_CHECK_IST_INDEX as usize] = ist_stack!();
+        tss.interrupt_stack_table[DEBUG_IST_INDEX as usize] = ist_stack!();
+
+        // Los handlers sin IST corren acá cuando interrumpen a ring 3.
+        tss.privilege_stack_table[0] = privilege_stack_bounds().1;
         
-        tss
+        TssWithIoBitmap {
+            tss: Tss(UnsafeCell::new(tss)),
+            io_bitmap: IoBitmap(UnsafeCell::new([0xFF; IO_BITMAP_BYTES + 1])),
+        }
     };
 }
 
+/// Descriptor de TSS cuyo límite incluye el bitmap de E/S;
+/// `Descriptor::tss_segment` sólo cubre los 104 bytes del TSS.
+fn tss_descriptor(tss: &'static TssWithIoBitmap) -> Descriptor {
+    let base = tss as *const TssWithIoBitmap as u64;
+    let limit = (size_of::<TssWithIoBitmap>() - 1) as u64;
+    debug_assert!(limit <= 0xFFFF);
+
+    let present = 1 << 47;
+    let available_64bit_tss = 0b1001 << 40;
+    let low = present
+        | available_64bit_tss
+        | limit
+        | (base & 0xFF_FFFF) << 16
+        | ((base >> 24) & 0xFF) << 56;
+    let high = base >> 32;
+
+    Descriptor::SystemSegment(low, high)
+}
+
 lazy_static! {
 
     static ref GDT: (GlobalDescriptorTable, Selectors) = {
@@ -50,10 +130,16 @@ lazy_static! {
         let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
         
         let data_selector = gdt.add_entry(Descriptor::kernel_data_segment()); 
+
+        // Datos antes que código: `sysret` toma los dos de posiciones fijas
+        // a partir de un mismo selector base.
+        let user_data_selector = gdt.add_entry(Descriptor::user_data_segment());
+
+        let user_code_selector = gdt.add_entry(Descriptor::user_code_segment());
         
-        let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
+        let tss_selector = gdt.add_entry(tss_descriptor(&TSS));
         
-        (gdt, Selectors { code_selector, data_selector, tss_selector })
+        (gdt, Selectors { code_selector, data_selector, user_data_selector, user_code_selector, tss_selector })
     };
 }
 
@@ -61,9 +147,97 @@ lazy_static! {
 struct Selectors {
     code_selector: SegmentSelector,
     data_selector: SegmentSelector,
+    user_data_selector: SegmentSelector,
+    user_code_selector: SegmentSelector,
     tss_selector: SegmentSelector,
 }
 
+/// Selector del segmento de código del kernel.
+pub fn kernel_code_selector() -> SegmentSelector {
+    GDT.1.code_selector
+}
+
+/// Selector del segmento de datos del kernel.
+pub fn kernel_data_selector() -> SegmentSelector {
+    GDT.1.data_selector
+}
+
+/// Selector del segmento de código de ring 3, con RPL 3: el `CS` que se
+/// pone en el frame de `iretq` para bajar a usuario.
+pub fn user_code_selector() -> SegmentSelector {
+    GDT.1.user_code_selector
+}
+
+/// Selector del segmento de datos de ring 3, con RPL 3: el `SS` de usuario.
+pub fn user_data_selector() -> SegmentSelector {
+    GDT.1.user_data_selector
+}
+
+
+/// Rango `(base, tope)` del stack IST con el índice dado.
+pub fn ist_stack_bounds(index: u16) -> (VirtAddr, VirtAddr) {
+    let top = TSS.tss.get().interrupt_stack_table[index as usize];
+    (top - IST_STACK_SIZE as u64, top)
+}
+
+/// Rango `(base, tope)` del stack de `RSP0` con el que arranca el TSS, el
+/// que se usa mientras ring 3 no es de un proceso.
+pub fn privilege_stack_bounds() -> (VirtAddr, VirtAddr) {
+    #[repr(align(16))]
+    #[allow(dead_code)]
+    struct AlignedStack([u8; PRIVILEGE_STACK_SIZE]);
+    static mut STACK: AlignedStack = AlignedStack([0; PRIVILEGE_STACK_SIZE]);
+
+    let base = VirtAddr::from_ptr(&raw const STACK);
+    (base, base + PRIVILEGE_STACK_SIZE as u64)
+}
+
+/// Cambia el stack al que salta la CPU cuando una interrupción llega desde
+/// ring 3. El scheduler pone el del proceso al que cambia.
+pub fn set_privilege_stack(top: VirtAddr) {
+    interrupts::without_interrupts(|| unsafe {
+        (*TSS.tss.0.get()).privilege_stack_table[0] = top;
+    });
+}
+
+/// Índice del stack IST que contiene `addr`, si hay alguno.
+pub fn ist_index_of(addr: VirtAddr) -> Option<u16> {
+    IST_INDICES.iter().copied().find(|&index| {
+        let (base, top) = ist_stack_bounds(index);
+        addr >= base && addr < top
+    })
+}
+
+/// Permite (o vuelve a negar) el acceso desde ring 3 a `len` puertos desde `start`.
+///
+/// Hay un único TSS, así que el permiso vale para todo el código de usuario.
+/// Sólo aplica con IOPL 0; con IOPL 3 la CPU ni consulta el bitmap.
+pub fn set_io_permission(start: u16, len: u16, allowed: bool) {
+    let end = start as usize + len as usize;
+    assert!(end <= 65536, "rango de puertos fuera del espacio de E/S");
+
+    interrupts::without_interrupts(|| {
+        let _guard = IO_BITMAP_LOCK.lock();
+        let bitmap = unsafe { &mut *TSS.io_bitmap.0.get() };
+        for port in start as usize..end {
+            let mask = 1 << (port % 8);
+            if allowed {
+                bitmap[port / 8] &= !mask;
+            } else {
+                bitmap[port / 8] |= mask;
+            }
+        }
+    });
+}
+
+/// Indica si ring 3 puede acceder a `port`.
+pub fn io_port_allowed(port: u16) -> bool {
+    interrupts::without_interrupts(|| {
+        let _guard = IO_BITMAP_LOCK.lock();
+        let bitmap = unsafe { &*TSS.io_bitmap.0.get() };
+        bitmap[port as usize / 8] & (1 << (port % 8)) == 0
+    })
+}
 
 pub fn init() {
     use x86_64::registers::segmentation::{CS, Segment, SS};
@@ -75,4 +249,49 @@ pub fn init() {
         SS::set_reg(GDT.1.data_selector);
         load_tss(GDT.1.tss_selector);
     }
-}
\ No newline at end of file
+}
+
+crate::register_driver!(GDT_DRIVER, Driver {
+    name: "gdt",
+    stage: Stage::Early,
+    depends_on: &[],
+    device: None,
+    probe: Driver::always,
+    init,
+});
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_io_permission_bitmap() {
+    assert!(!io_port_allowed(0x3D4));
+    set_io_permission(0x3D4, 2, true);
+    assert!(io_port_allowed(0x3D4) && io_port_allowed(0x3D5));
+    assert!(!io_port_allowed(0x3D6));
+
+    set_io_permission(0x3D4, 2, false);
+    assert!(!io_port_allowed(0x3D4));
+}
+
+#[test_case]
+fn test_user_selectors() {
+    use x86_64::PrivilegeLevel;
+
+    let (code, data) = (user_code_selector(), user_data_selector());
+    assert_eq!(code.rpl(), PrivilegeLevel::Ring3);
+    assert_eq!(data.rpl(), PrivilegeLevel::Ring3);
+    assert_eq!(code.index(), data.index() + 1);
+    assert_eq!(GDT.1.code_selector.rpl(), PrivilegeLevel::Ring0);
+}
+
+#[test_case]
+fn test_ist_stacks_are_distinct() {
+    for (i, &index) in IST_INDICES.iter().enumerate() {
+        let (base, top) = ist_stack_bounds(index);
+        assert_eq!(ist_index_of(top - 8u64), Some(index));
+        for &other in &IST_INDICES[i + 1..] {
+            let (other_base, other_top) = ist_stack_bounds(other);
+            assert!(top <= other_base || other_top <= base);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! Tareas periódicas del kernel.
+//!
+//! Un subsistema que necesita hacer algo cada tanto (vaciar estadísticas,
+//! recuperar memoria) registra una función con su período en ticks, en vez
+//! de tener su propio loop. Cada función tiene un timer en `timer_wheel` que
+//! la marca como vencida y despierta a `run`, una tarea async: las funciones
+//! corren en contexto de tarea y pueden alocar o imprimir.
+
+use core::future::poll_fn;
+use core::task::Poll;
+use futures_util::task::AtomicWaker;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::timer_wheel::{self, TimerId};
+
+pub const MAX_JOBS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JobId(usize);
+
+#[derive(Clone, Copy)]
+struct Job {
+    name: &'static str,
+    period: u64,
+    next_due: u64,
+    /// El timer de `next_due`.
+    timer: TimerId,
+    /// Venció y `run` todavía no la corrió.
+    due: bool,
+    runs: u64,
+    work: fn(),
+}
+
+static JOBS: Mutex<[Option<Job>; MAX_JOBS]> = Mutex::new([None; MAX_JOBS]);
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+fn arm(index: usize, deadline: u64) -> TimerId {
+    timer_wheel::add_timer(deadline, expired, index).expect("sin timers para una tarea periódica")
+}
+
+/// El timer de la función `index`: la marca como vencida y programa el
+/// próximo plazo.
+fn expired(index: usize) {
+    interrupts::without_interrupts(|| {
+        let mut jobs = JOBS.lock();
+        let Some(job) = jobs[index].as_mut() else {
+            return;
+        };
+        // Si se atrasó varios períodos corre una sola vez.
+        let now = crate::time::ticks();
+        while job.next_due <= now {
+            job.next_due += job.period;
+        }
+        job.due = true;
+        job.timer = arm(index, job.next_due);
+    });
+    WAKER.wake();
+}
+
+/// Corre `work` cada `period` ticks, empezando dentro de `period` ticks.
+pub fn register(name: &'static str, period: u64, work: fn()) -> JobId {
+    assert!(period > 0, "período nulo para '{}'", name);
+    interrupts::without_interrupts(|| {
+        let mut jobs = JOBS.lock();
+        let index = jobs
+            .iter()
+            .position(|job| job.is_none())
+            .expect("demasiadas tareas periódicas");
+        let next_due = crate::time::ticks() + period;
+        let timer = arm(index, next_due);
+        jobs[index] = Some(Job { name, period, next_due, timer, due: false, runs: 0, work });
+        JobId(index)
+    })
+}
+
+pub fn unregister(id: JobId) {
+    interrupts::without_interrupts(|| {
+        if let Some(job) = JOBS.lock()[id.0].take() {
+            timer_wheel::cancel(job.timer);
+        }
+    });
+}
+
+/// Veces que corrió la función.
+pub fn runs(id: JobId) -> u64 {
+    interrupts::without_interrupts(|| JOBS.lock()[id.0].map_or(0, |job| job.runs))
+}
+
+/// Próxima función vencida, marcándola como corrida.
+fn take_due() -> Option<fn()> {
+    interrupts::without_interrupts(|| {
+        let mut jobs = JOBS.lock();
+        let job = jobs.iter_mut().flatten().find(|job| job.due)?;
+        job.due = false;
+        job.runs += 1;
+        Some(job.work)
+    })
+}
+
+fn any_due() -> bool {
+    interrupts::without_interrupts(|| JOBS.lock().iter().flatten().any(|job| job.due))
+}
+
+/// Corre las funciones registradas a medida que vencen. Nunca termina.
+pub async fn run() {
+    loop {
+        poll_fn(|cx| {
+            WAKER.register(cx.waker());
+            if any_due() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+
+        while let Some(work) = take_due() {
+            work();
+        }
+    }
+}
+
+pub fn print_jobs() {
+    crate::println!("NOMBRE           PERÍODO  CORRIDAS");
+    let jobs = interrupts::without_interrupts(|| *JOBS.lock());
+    for job in jobs.iter().flatten() {
+        crate::println!("{:<16} {:<8} {}", job.name, job.period, job.runs);
+    }
+}
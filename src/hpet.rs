@@ -0,0 +1,88 @@
+//! HPET (High Precision Event Timer): un contador de 64 bits a frecuencia
+//! fija, mapeado en memoria.
+//!
+//! Sólo se usa el contador principal, como fuente de reloj (`clocksource`);
+//! los comparadores quedan apagados. La dirección sale de la tabla ACPI
+//! "HPET", así que `init` se llama después de `memory::init`.
+
+use conquer_once::spin::OnceCell;
+use x86_64::instructions::interrupts;
+
+use crate::error::KernelError;
+use crate::acpi::{self, AcpiError};
+use crate::mmio::{Field, MmioRegion, ReadOnly, ReadWrite, Register};
+
+const MMIO_LEN: u64 = 0x400;
+
+const CAPABILITIES: Register<u64, ReadOnly> = Register::new(0x00);
+const CONFIG: Register<u64, ReadWrite> = Register::new(0x10);
+const MAIN_COUNTER: Register<u64, ReadWrite> = Register::new(0xF0);
+
+/// Período del contador en femtosegundos.
+const PERIOD: Field<u64> = Field::new(32, 32);
+/// El contador es de 64 bits.
+const COUNT_SIZE_64: Field<u64> = Field::bit(13);
+const ENABLE: Field<u64> = Field::bit(0);
+
+/// La especificación pide un período de a lo sumo 100 ns.
+const MAX_PERIOD_FS: u64 = 100_000_000;
+const FS_PER_SECOND: u64 = 1_000_000_000_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HpetError {
+    Acpi(AcpiError),
+    /// No se pudieron mapear sus registros.
+    Map(KernelError),
+    BadPeriod(u64),
+    /// Un contador de 32 bits da la vuelta en minutos: no sirve de reloj.
+    Counter32,
+}
+
+struct Hpet {
+    regs: MmioRegion,
+    hz: u64,
+}
+
+static HPET: OnceCell<Hpet> = OnceCell::uninit();
+
+/// Encuentra el HPET, pone en cero y arranca su contador. Devuelve la
+/// frecuencia en Hz.
+pub fn init() -> Result<u64, HpetError> {
+    let address = acpi::hpet_address().map_err(HpetError::Acpi)?;
+    let base = crate::memory::map_mmio(address, MMIO_LEN).map_err(HpetError::Map)?;
+    let regs = unsafe { MmioRegion::new(base, MMIO_LEN as usize) };
+
+    let capabilities = regs.read(CAPABILITIES);
+    let period = PERIOD.get(capabilities);
+    if period == 0 || period > MAX_PERIOD_FS {
+        return Err(HpetError::BadPeriod(period));
+    }
+    if !COUNT_SIZE_64.is_set(capabilities) {
+        return Err(HpetError::Counter32);
+    }
+
+    interrupts::without_interrupts(|| {
+        regs.write_field(CONFIG, ENABLE, 0);
+        regs.write(MAIN_COUNTER, 0);
+        regs.write_field(CONFIG, ENABLE, 1);
+    });
+
+    let hz = FS_PER_SECOND / period;
+    HPET.init_once(|| Hpet { regs, hz });
+    crate::log_info!("HPET en {:#x}: {} Hz", address.as_u64(), hz);
+    Ok(hz)
+}
+
+pub fn is_present() -> bool {
+    HPET.is_initialized()
+}
+
+/// Frecuencia del contador, o 0 si no hay HPET.
+pub fn frequency() -> u64 {
+    HPET.try_get().map_or(0, |hpet| hpet.hz)
+}
+
+/// Valor del contador principal, o 0 si no hay HPET.
+pub fn counter() -> u64 {
+    HPET.try_get().map_or(0, |hpet| hpet.regs.read(MAIN_COUNTER))
+}
@@ -0,0 +1,95 @@
+//! Imágenes de programas de usuario, por nombre.
+//!
+//! Hace las veces de initrd mientras no haya VFS: el bootloader no carga
+//! uno, así que los programas van dentro de la imagen del kernel. Los de
+//! `user/` los empaqueta `build.rs` y se registran en `init`; el kernel
+//! también puede registrar otros con `register`. `process::exec` y
+//! `process::spawn_program` buscan acá, y `autorun` dice cuáles arrancar al
+//! bootear.
+
+use core::fmt;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::error::KernelError;
+
+pub const MAX_IMAGES: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitrdError {
+    AlreadyRegistered,
+    TableFull,
+}
+
+impl fmt::Display for InitrdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InitrdError::AlreadyRegistered => write!(f, "imagen ya registrada"),
+            InitrdError::TableFull => write!(f, "sin lugar para más imágenes"),
+        }
+    }
+}
+
+impl From<InitrdError> for KernelError {
+    fn from(err: InitrdError) -> Self {
+        match err {
+            InitrdError::AlreadyRegistered => KernelError::InvalidArgument,
+            InitrdError::TableFull => KernelError::OutOfMemory,
+        }
+    }
+}
+
+type Image = (&'static str, &'static [u8]);
+
+static IMAGES: Mutex<[Option<Image>; MAX_IMAGES]> = Mutex::new([None; MAX_IMAGES]);
+
+// `BUILTIN` y `AUTORUN`, generados por `build.rs`.
+include!(concat!(env!("OUT_DIR"), "/initrd_images.rs"));
+
+/// Registra los programas empaquetados. Lo llama `crate::init`.
+pub fn init() {
+    for &(name, image) in BUILTIN {
+        if let Err(err) = register(name, image) {
+            crate::log_warn!("initrd: no se pudo registrar '{}': {}", name, err);
+        }
+    }
+}
+
+/// Programas que el kernel arranca al bootear, en orden.
+pub fn autorun() -> &'static [&'static str] {
+    AUTORUN
+}
+
+pub fn register(name: &'static str, image: &'static [u8]) -> Result<(), InitrdError> {
+    interrupts::without_interrupts(|| {
+        let mut images = IMAGES.lock();
+        if images.iter().flatten().any(|&(other, _)| other == name) {
+            return Err(InitrdError::AlreadyRegistered);
+        }
+        let slot = images.iter_mut().find(|slot| slot.is_none()).ok_or(InitrdError::TableFull)?;
+        *slot = Some((name, image));
+        Ok(())
+    })
+}
+
+/// La imagen registrada como `name`, con el nombre en `'static`.
+pub fn find(name: &str) -> Option<Image> {
+    interrupts::without_interrupts(|| IMAGES.lock().iter().flatten().copied().find(|&(other, _)| other == name))
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_register_and_find() {
+    register("test-initrd", b"\x7FELF").unwrap();
+    assert_eq!(register("test-initrd", b""), Err(InitrdError::AlreadyRegistered));
+    assert_eq!(find("test-initrd"), Some(("test-initrd", &b"\x7FELF"[..])));
+    assert_eq!(find("test-initrd-no"), None);
+}
+
+#[test_case]
+fn test_autorun_programs_are_registered() {
+    for name in autorun() {
+        assert!(find(name).is_some(), "'{}' no está registrado", name);
+    }
+}
@@ -1,9 +1,20 @@
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+use x86_64::VirtAddr;
+use core::arch::naked_asm;
+use core::fmt::Write;
 use lazy_static::lazy_static;
 use pic8259::ChainedPics;
 use spin;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use crate::driver::{Driver, Stage};
+use crate::portio::{self, PortRegion};
+use conquer_once::spin::OnceCell;
 
 pub const PIC_1_OFFSET: u8 = 32;
+
+crate::register_counter!(pub(crate) IRQ_TIMER, "irq.timer");
+crate::register_counter!(IRQ_KEYBOARD, "irq.keyboard");
+
 pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 pub static PICS: spin::Mutex<ChainedPics> =
     spin::Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
@@ -13,13 +24,25 @@ lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
         unsafe {
+            idt.debug
+                .set_handler_fn(debug_handler)
+                .set_stack_index(crate::gdt::DEBUG_IST_INDEX);
+
             idt.breakpoint
-                .set_handler_fn(breakpoint_handler)
+                .set_handler_addr(VirtAddr::from_ptr(breakpoint_entry as *const ()))
                 .set_stack_index(crate::gdt::BREAKPOINT_IST_INDEX);
             
             idt.double_fault
                 .set_handler_fn(double_fault_handler)
                 .set_stack_index(crate::gdt::DOUBLE_FAULT_IST_INDEX);
+
+            idt.non_maskable_interrupt
+                .set_handler_fn(nmi_handler)
+                .set_stack_index(crate::gdt::NMI_IST_INDEX);
+
+            idt.machine_check
+                .set_handler_fn(machine_check_handler)
+                .set_stack_index(crate::gdt::MACHINE_CHECK_IST_INDEX);
         }
 
         idt[InterruptIndex::Temporizador.as_usize()]
@@ -28,8 +51,28 @@ lazy_static! {
         idt[InterruptIndex::Teclado.as_usize()]
             .set_handler_fn(keyboard_interrupt_handler);
 
+        idt[usize::from(PIC_1_OFFSET + 7)].set_handler_fn(pic1_spurious_handler);
+        idt[usize::from(PIC_2_OFFSET + 7)].set_handler_fn(pic2_spurious_handler);
+
         idt.page_fault.set_handler_fn(page_fault_handler);
 
+        idt.divide_error.set_handler_fn(divide_error_handler);
+        idt.overflow.set_handler_fn(overflow_handler);
+        idt.invalid_opcode.set_handler_fn(invalid_opcode_handler);
+        idt.segment_not_present.set_handler_fn(segment_not_present_handler);
+        idt.stack_segment_fault.set_handler_fn(stack_segment_fault_handler);
+        idt.general_protection_fault.set_handler_fn(general_protection_fault_handler);
+        idt.alignment_check.set_handler_fn(alignment_check_handler);
+
+        idt[crate::bench::BENCH_VECTOR as usize].set_handler_fn(bench_handler);
+
+        idt[crate::apic::timer::VECTOR as usize].set_handler_fn(apic_timer_handler);
+        idt[crate::apic::SPURIOUS_VECTOR as usize].set_handler_fn(apic_spurious_handler);
+
+        for (i, stub) in DYNAMIC_STUBS.iter().enumerate() {
+            idt[DYNAMIC_VECTOR_BASE as usize + i].set_handler_fn(*stub);
+        }
+
         idt
     };
 }
@@ -37,13 +80,144 @@ lazy_static! {
 
 pub fn init_idt() {
     IDT.load();
+    enable_machine_check();
+}
+
+/// `pic8259` accede a los puertos por su cuenta; el registro sólo deja
+/// constancia de que son suyos.
+static PIC_PORTS: OnceCell<(PortRegion, PortRegion)> = OnceCell::uninit();
+
+fn init_pic() {
+    let ports = (
+        portio::claim("pic1", 0x20, 2).expect("puertos del PIC maestro ocupados"),
+        portio::claim("pic2", 0xA0, 2).expect("puertos del PIC esclavo ocupados"),
+    );
+    PIC_PORTS.init_once(|| ports);
+    unsafe { PICS.lock().initialize() };
+    PIC_READY.store(true, Ordering::Release);
+}
+
+static PIC_READY: AtomicBool = AtomicBool::new(false);
+
+/// Habilita las interrupciones de hardware (`sti`).
+///
+/// Sin el PIC remapeado, IRQ0 llegaría en el vector 8 y se confundiría con un
+/// double fault, así que esto entra en pánico si el driver `pic` no corrió.
+pub fn enable_hardware() {
+    assert!(
+        PIC_READY.load(Ordering::Acquire),
+        "enable_hardware antes de inicializar el PIC"
+    );
+    x86_64::instructions::interrupts::enable();
+}
+
+/// Avisa al PIC que terminó de atenderse la IRQ de `index`.
+fn end_of_interrupt(index: InterruptIndex) {
+    unsafe {
+        PICS.lock().notify_end_of_interrupt(index.as_u8());
+    }
+}
+
+crate::register_driver!(IDT_DRIVER, Driver {
+    name: "idt",
+    stage: Stage::Interrupts,
+    depends_on: &["gdt"],
+    device: None,
+    probe: Driver::always,
+    init: init_idt,
+});
+
+crate::register_driver!(PIC_DRIVER, Driver {
+    name: "pic",
+    stage: Stage::Interrupts,
+    depends_on: &["idt"],
+    device: Some("PNP0000"),
+    probe: Driver::always,
+    init: init_pic,
+});
+
+
+/// Si la excepción vino de ring 3 y hay un proceso corriendo, la culpa es
+/// suya: se informa y termina sólo él, con `process::FAULT_EXIT_CODE`. Si no,
+/// vuelve y el handler sigue como con un fallo del kernel.
+fn exit_if_user_fault(stack_frame: &InterruptStackFrame, what: core::fmt::Arguments) {
+    if stack_frame.code_segment & 3 != 3 {
+        return;
+    }
+    let Some(pid) = crate::process::current() else {
+        return;
+    };
+    crate::log_warn!(
+        "proceso {}: {} en RIP {:#x}; se lo termina",
+        pid.as_u64(),
+        what,
+        stack_frame.instruction_pointer.as_u64()
+    );
+    crate::process::exit(crate::process::FAULT_EXIT_CODE);
+}
+
+extern "x86-interrupt" fn debug_handler(mut stack_frame: InterruptStackFrame) {
+    if crate::kprobe::handle_debug(&mut stack_frame) {
+        return;
+    }
+    // Un proceso puede prender TF con `popf`: volver repetiría el #DB para siempre.
+    exit_if_user_fault(&stack_frame, format_args!("excepción de debug"));
+    crate::serial_println!("--- EXCEPCION: DEBUG ---");
+    crate::serial_println!("Stack Frame: {:#?}", stack_frame);
 }
 
+/// La entrada del breakpoint. No usa el ABI `x86-interrupt` porque los
+/// kprobes necesitan los registros generales: se apilan todos sobre el frame
+/// de la CPU y se pasan como `Registers`.
+#[unsafe(naked)]
+unsafe extern "C" fn breakpoint_entry() -> ! {
+    naked_asm!(
+        "push rax",
+        "push rbx",
+        "push rcx",
+        "push rdx",
+        "push rsi",
+        "push rdi",
+        "push rbp",
+        "push r8",
+        "push r9",
+        "push r10",
+        "push r11",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        // 15 registros más las 5 palabras del frame: alineado a 16.
+        "mov rdi, rsp",
+        "call {handler}",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop r11",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rbp",
+        "pop rdi",
+        "pop rsi",
+        "pop rdx",
+        "pop rcx",
+        "pop rbx",
+        "pop rax",
+        "iretq",
+        handler = sym breakpoint_handler,
+    );
+}
 
-extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
+extern "C" fn breakpoint_handler(registers: &mut crate::kprobe::Registers) {
+    if crate::kprobe::handle_breakpoint(registers) {
+        return;
+    }
+    exit_if_user_fault(&registers.frame, format_args!("breakpoint"));
     crate::println!("--- EXCEPCION: BREAKPOINT ---");
     crate::serial_println!("--- EXCEPCION: BREAKPOINT ---");
-    crate::serial_println!("Stack Frame: {:#?}", stack_frame);
+    crate::serial_println!("Stack Frame: {:#?}", registers.frame);
 
 }
 
@@ -51,9 +225,99 @@ extern "x86-interrupt" fn double_fault_handler(
     stack_frame: InterruptStackFrame, 
     _error_code: u64
 ) -> ! {
+    // Un doble fallo suele venir de un stack desbordado en medio de un print,
+    // así que el reporte va por serie sin tomar locks.
+    let mut out = unsafe { crate::serial::emergency_writer() };
+    report_double_fault(&mut out, &stack_frame);
     panic!("EXCEPCIÓN: DOBLE FALLO\n{:#?}", stack_frame);
 }
 
+fn report_double_fault(out: &mut impl Write, stack_frame: &InterruptStackFrame) {
+    use x86_64::registers::control::Cr2;
+
+    let _ = writeln!(out, "EXCEPCIÓN: DOBLE FALLO");
+
+    let rsp: u64;
+    unsafe {
+        core::arch::asm!("mov {}, rsp", out(reg) rsp, options(nomem, nostack, preserves_flags));
+    }
+    match crate::gdt::ist_index_of(VirtAddr::new(rsp)) {
+        Some(index) => {
+            let (base, top) = crate::gdt::ist_stack_bounds(index);
+            let _ = writeln!(
+                out,
+                "Stack del handler: IST {} ({:#x}..{:#x}), {} bytes libres",
+                index, base.as_u64(), top.as_u64(), rsp - base.as_u64()
+            );
+        }
+        None => {
+            let _ = writeln!(out, "Stack del handler: {:#x} (fuera de los stacks IST)", rsp);
+        }
+    }
+
+    let sp = stack_frame.stack_pointer.as_u64();
+    let cr2 = Cr2::read_raw();
+    let _ = writeln!(out, "Stack pointer al fallar: {:#x}", sp);
+    let _ = writeln!(out, "CR2: {:#x}", cr2);
+
+    // Si el fallo fue al escribir justo debajo del stack pointer, o la página
+    // donde iría el próximo push no está mapeada, el stack se desbordó.
+    let next_push = VirtAddr::new(sp.wrapping_sub(8));
+    let cr2_near_sp = cr2 <= sp && sp - cr2 <= crate::allocator::PAGE_SIZE as u64;
+    let thread = crate::stack::overflowed_thread(cr2)
+        .or_else(|| crate::stack::overflowed_thread(next_push.as_u64()));
+    if let Some(thread) = thread {
+        let _ = writeln!(out, "Diagnóstico: desbordamiento del stack del hilo {}", thread);
+        write_recent_log(out);
+        return;
+    }
+    let diagnosis = match (crate::gdt::ist_index_of(next_push), crate::memory::is_mapped(next_push)) {
+        (Some(index), _) => {
+            let (base, _) = crate::gdt::ist_stack_bounds(index);
+            if next_push.as_u64() - base.as_u64() < crate::allocator::PAGE_SIZE as u64 {
+                "stack IST casi agotado; probable desbordamiento dentro de otro handler"
+            } else {
+                "el fallo ocurrió dentro de un handler con stack IST"
+            }
+        }
+        (None, Ok(false)) => "probable desbordamiento del stack del kernel (página no mapeada bajo el stack pointer)",
+        _ if cr2_near_sp => "probable desbordamiento del stack del kernel (CR2 justo debajo del stack pointer)",
+        (None, Ok(true)) => "el stack pointer apunta a memoria mapeada; no parece un desbordamiento",
+        (None, Err(_)) => "no se pudieron consultar las tablas de páginas",
+    };
+    let _ = writeln!(out, "Diagnóstico: {}", diagnosis);
+
+    write_recent_log(out);
+}
+
+fn write_recent_log(out: &mut impl Write) {
+    let _ = writeln!(out, "Últimos mensajes del log:");
+    if !crate::log::recent(|line| {
+        let _ = writeln!(out, "  {}", line);
+    }) {
+        let _ = writeln!(out, "  <historial bloqueado>");
+    }
+}
+
+/// Una NMI no se puede enmascarar, así que llega aunque el kernel esté colgado
+/// con las interrupciones deshabilitadas: desde el monitor de QEMU, `nmi`
+/// muestra dónde estaba. Después de reportar, el kernel sigue.
+extern "x86-interrupt" fn nmi_handler(stack_frame: InterruptStackFrame) {
+    // Si el cuelgue fue con el lock del puerto serie tomado, se escribe igual:
+    // algún byte intercalado importa menos que no ver el reporte.
+    match crate::serial::SERIAL1.try_lock() {
+        Some(mut serial) => report_nmi(&mut *serial, &stack_frame),
+        None => report_nmi(&mut unsafe { crate::serial::emergency_writer() }, &stack_frame),
+    }
+}
+
+fn report_nmi(out: &mut impl Write, stack_frame: &InterruptStackFrame) {
+    let _ = writeln!(out, "--- NMI ---");
+    let _ = writeln!(out, "Ticks: {}", ticks());
+    let _ = writeln!(out, "Stack Frame: {:#?}", stack_frame);
+    write_recent_log(out);
+}
+
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum InterruptIndex {
@@ -71,34 +335,260 @@ impl InterruptIndex {
     }
 }
 
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Cantidad de interrupciones del timer desde el arranque.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Corre el cuerpo de un handler anotándolo en `trace` y cerrando la espera
+/// ociosa que interrumpió (`cpu_usage`). Con la feature `irq-latency`
+/// registra cuántos ciclos tardó en `latency`.
+#[inline(always)]
+fn measured(vector: u8, body: impl FnOnce()) {
+    crate::cpu_usage::on_interrupt();
+    #[cfg(feature = "irq-latency")]
+    let start = crate::bench::rdtsc();
+    crate::trace::begin(crate::trace::Point::Irq(vector));
+    body();
+    crate::trace::end(crate::trace::Point::Irq(vector));
+    #[cfg(feature = "irq-latency")]
+    crate::latency::record(vector, crate::bench::rdtsc() - start);
+}
+
 extern "x86-interrupt" fn timer_interrupt_handler(
-    _stack_frame: InterruptStackFrame)
+    stack_frame: InterruptStackFrame)
 {
-    print!(".");
+    measured(InterruptIndex::Temporizador.as_u8(), || {
+        let now = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+        IRQ_TIMER.inc();
+        // Con el reloj virtual, los hooks los corre `time::advance`.
+        if !crate::time::is_deterministic() {
+            crate::time::on_tick(now, stack_frame.instruction_pointer);
+        }
+        end_of_interrupt(InterruptIndex::Temporizador);
+    });
+    // Fuera de `measured`: si cambia de hilo, esto vuelve recién cuando al
+    // interrumpido le toca de nuevo.
+    crate::scheduler::on_timer_tick(crate::time::ticks());
+}
+
+
+extern "x86-interrupt" fn apic_timer_handler(_stack_frame: InterruptStackFrame) {
+    measured(crate::apic::timer::VECTOR, || {
+        crate::apic::timer::on_interrupt();
+        crate::apic::end_of_interrupt();
+    });
+}
+
+// ----------------- INTERRUPCIONES ESPURIAS -----------------
+//
+// El 8259 entrega IRQ7 (o IRQ15 en el esclavo) cuando una línea baja antes de
+// que la CPU confirme la interrupción. Se distinguen de una IRQ real leyendo
+// el ISR: si el bit no está prendido, fue espuria y no lleva EOI al PIC que
+// la generó. Una espuria del esclavo sí necesita EOI en el maestro, que vio
+// una IRQ2 legítima.
+
+static SPURIOUS_PIC1: AtomicU64 = AtomicU64::new(0);
+static SPURIOUS_PIC2: AtomicU64 = AtomicU64::new(0);
+static SPURIOUS_APIC: AtomicU64 = AtomicU64::new(0);
+
+/// Contadores de interrupciones espurias desde el arranque.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterruptStats {
+    pub spurious_pic1: u64,
+    pub spurious_pic2: u64,
+    pub spurious_apic: u64,
+}
+
+pub fn stats() -> InterruptStats {
+    InterruptStats {
+        spurious_pic1: SPURIOUS_PIC1.load(Ordering::Relaxed),
+        spurious_pic2: SPURIOUS_PIC2.load(Ordering::Relaxed),
+        spurious_apic: SPURIOUS_APIC.load(Ordering::Relaxed),
+    }
+}
+
+/// Comando OCW3 para que la próxima lectura del puerto de comando devuelva el ISR.
+const OCW3_READ_ISR: u8 = 0x0B;
+
+/// Si la IRQ7 del maestro o, con `slave`, la del esclavo (IRQ15) está en servicio.
+fn pic_irq7_in_service(slave: bool) -> bool {
+    let Ok((pic1, pic2)) = PIC_PORTS.try_get() else {
+        return true;
+    };
+    let region = if slave { pic2 } else { pic1 };
+    // El lock de PICS serializa los comandos con `notify_end_of_interrupt`.
+    let _pics = PICS.lock();
+    let mut command = region.port::<u8>(0);
     unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Temporizador.as_u8());
+        command.write(OCW3_READ_ISR);
+        command.read() & (1 << 7) != 0
     }
 }
 
+/// Si lo que corre está dentro de un handler del que todavía se tiene que
+/// volver: una IRQ sin EOI en el PIC o en el APIC, o un handler con stack
+/// IST (NMI, #MC, #DB, breakpoint, doble fallo). Con el lock de los PICs
+/// tomado no se puede saber, y se asume que sí.
+pub fn in_interrupt_context() -> bool {
+    let rsp: u64;
+    unsafe {
+        core::arch::asm!("mov {}, rsp", out(reg) rsp, options(nomem, nostack, preserves_flags));
+    }
+    if crate::gdt::ist_index_of(VirtAddr::new(rsp)).is_some() {
+        return true;
+    }
+    if crate::apic::is_initialized() && crate::apic::any_in_service() {
+        return true;
+    }
+    let Ok((pic1, pic2)) = PIC_PORTS.try_get() else {
+        return false;
+    };
+    let Some(_pics) = PICS.try_lock() else {
+        return true;
+    };
+    [pic1, pic2].iter().any(|region| {
+        let mut command = region.port::<u8>(0);
+        unsafe {
+            command.write(OCW3_READ_ISR);
+            command.read() != 0
+        }
+    })
+}
+
+extern "x86-interrupt" fn pic1_spurious_handler(_stack_frame: InterruptStackFrame) {
+    if !pic_irq7_in_service(false) {
+        SPURIOUS_PIC1.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    // Nadie usa la IRQ7 real todavía.
+    unsafe { PICS.lock().notify_end_of_interrupt(PIC_1_OFFSET + 7) };
+}
+
+extern "x86-interrupt" fn pic2_spurious_handler(_stack_frame: InterruptStackFrame) {
+    if !pic_irq7_in_service(true) {
+        SPURIOUS_PIC2.fetch_add(1, Ordering::Relaxed);
+        // Un vector del maestro manda EOI sólo al maestro.
+        unsafe { PICS.lock().notify_end_of_interrupt(PIC_1_OFFSET) };
+        return;
+    }
+    unsafe { PICS.lock().notify_end_of_interrupt(PIC_2_OFFSET + 7) };
+}
+
+/// Las interrupciones espurias del APIC no llevan EOI.
+extern "x86-interrupt" fn apic_spurious_handler(_stack_frame: InterruptStackFrame) {
+    SPURIOUS_APIC.fetch_add(1, Ordering::Relaxed);
+}
+
+// ----------------- VECTORES DINÁMICOS -----------------
+
+/// Primer vector que se reparte con `allocate_vector` (MSI, MSI-X).
+pub const DYNAMIC_VECTOR_BASE: u8 = 0x50;
+pub const DYNAMIC_VECTORS: usize = 16;
+
+type DynamicTable = [Option<fn()>; DYNAMIC_VECTORS];
+
+static DYNAMIC: spin::Mutex<DynamicTable> = spin::Mutex::new([None; DYNAMIC_VECTORS]);
+
+/// Reserva un vector libre y le asocia `handler`. El EOI va al APIC local,
+/// así que sólo sirve para interrupciones entregadas por él.
+pub fn allocate_vector(handler: fn()) -> Option<u8> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut table = DYNAMIC.lock();
+        let index = table.iter().position(|slot| slot.is_none())?;
+        table[index] = Some(handler);
+        Some(DYNAMIC_VECTOR_BASE + index as u8)
+    })
+}
+
+/// Devuelve un vector obtenido con `allocate_vector`.
+pub fn free_vector(vector: u8) {
+    let index = vector.wrapping_sub(DYNAMIC_VECTOR_BASE) as usize;
+    assert!(index < DYNAMIC_VECTORS, "vector {:#x} no es dinámico", vector);
+    x86_64::instructions::interrupts::without_interrupts(|| DYNAMIC.lock()[index] = None);
+}
+
+fn dispatch_dynamic(index: usize) {
+    measured(DYNAMIC_VECTOR_BASE + index as u8, || {
+        // Se copia el handler para no llamarlo con el lock tomado.
+        let handler = DYNAMIC.lock()[index];
+        if let Some(handler) = handler {
+            handler();
+        }
+        crate::apic::end_of_interrupt();
+    });
+}
+
+/// Un handler por vector dinámico: la IDT no dice a qué vector se entró.
+macro_rules! dynamic_stubs {
+    ($($index:literal),*) => {
+        [$({
+            extern "x86-interrupt" fn stub(_stack_frame: InterruptStackFrame) {
+                dispatch_dynamic($index);
+            }
+            stub as Handler
+        }),*]
+    };
+}
+
+type Handler = extern "x86-interrupt" fn(InterruptStackFrame);
+
+static DYNAMIC_STUBS: [Handler; DYNAMIC_VECTORS] =
+    dynamic_stubs!(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15);
+
+/// Vacío a propósito: `bench::interrupt_round_trip` mide sólo la entrada y la salida.
+/// Con `irq-latency`, lo que registra es el costo de la medición misma.
+extern "x86-interrupt" fn bench_handler(_stack_frame: InterruptStackFrame) {
+    measured(crate::bench::BENCH_VECTOR, || {});
+}
 
 extern "x86-interrupt" fn keyboard_interrupt_handler(
     _stack_frame: InterruptStackFrame)
 {
-    use x86_64::instructions::port::Port;
-
-    let mut port = Port::new(0x60);
-    let scancode: u8 = unsafe { port.read() };
-    crate::task::keyboard::add_scancode(scancode);
+    measured(InterruptIndex::Teclado.as_u8(), || {
+        IRQ_KEYBOARD.inc();
+        if let Some(scancode) = crate::task::keyboard::read_scancode() {
+            crate::task::keyboard::add_scancode(scancode);
+        }
 
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Teclado.as_u8());
-    }
+        end_of_interrupt(InterruptIndex::Teclado);
+    });
 }
 
 use x86_64::structures::idt::PageFaultErrorCode;
-use crate::hlt_loop;
+
+/// Decodificación legible del código de error de un fallo de página.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PageFaultCause {
+    cause: &'static str,
+    access: &'static str,
+    mode: &'static str,
+}
+
+fn describe_page_fault(error_code: PageFaultErrorCode) -> PageFaultCause {
+    let cause = if error_code.contains(PageFaultErrorCode::MALFORMED_TABLE) {
+        "bit reservado en una tabla de páginas"
+    } else if error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
+        "violación de protección"
+    } else {
+        "página no presente"
+    };
+    let access = if error_code.contains(PageFaultErrorCode::INSTRUCTION_FETCH) {
+        "ejecución"
+    } else if error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
+        "escritura"
+    } else {
+        "lectura"
+    };
+    let mode = if error_code.contains(PageFaultErrorCode::USER_MODE) {
+        "usuario"
+    } else {
+        "kernel"
+    };
+    PageFaultCause { cause, access, mode }
+}
 
 extern "x86-interrupt" fn page_fault_handler(
     stack_frame: InterruptStackFrame,
@@ -106,9 +596,243 @@ extern "x86-interrupt" fn page_fault_handler(
 ) {
     use x86_64::registers::control::Cr2;
 
+    let address = Cr2::read();
+    let fault = describe_page_fault(error_code);
+
+    exit_if_user_fault(
+        &stack_frame,
+        format_args!("fallo de página, {} de {:#x}: {}", fault.access, address.as_u64(), fault.cause),
+    );
+
+    if let Some(thread) = crate::stack::overflowed_thread(address.as_u64()) {
+        panic!(
+            "desbordamiento del stack del hilo {}: {} de {:#x} en RIP {:#x}",
+            thread,
+            fault.access,
+            address.as_u64(),
+            stack_frame.instruction_pointer.as_u64()
+        );
+    }
+
     println!("EXCEPCIÓN: FALLO DE PÁGINA");
-    println!("Dirección Accedida: {:?}", Cr2::read());
+    println!("Dirección Accedida: {:?}", address);
+    println!("Causa: {} ({} en modo {})", fault.cause, fault.access, fault.mode);
     println!("Código de Error: {:?}", error_code);
     println!("{:#?}", stack_frame);
-    hlt_loop();
-}
\ No newline at end of file
+    panic!(
+        "fallo de página: {} de {:#x} en RIP {:#x}: {}",
+        fault.access,
+        address.as_u64(),
+        stack_frame.instruction_pointer.as_u64(),
+        fault.cause
+    );
+}
+
+// ----------------- EXCEPCIONES FATALES -----------------
+//
+// En el kernel ninguna de estas se puede recuperar todavía: se informa qué
+// pasó y dónde. Desde ring 3 terminan al proceso. En las que traen código de
+// error, para #NP, #SS y #GP es el selector de segmento involucrado (0 si el
+// fallo no vino de cargar un selector).
+
+extern "x86-interrupt" fn divide_error_handler(stack_frame: InterruptStackFrame) {
+    exit_if_user_fault(&stack_frame, format_args!("división por cero"));
+    panic!("EXCEPCIÓN: DIVISIÓN POR CERO\n{:#?}", stack_frame);
+}
+
+extern "x86-interrupt" fn overflow_handler(stack_frame: InterruptStackFrame) {
+    exit_if_user_fault(&stack_frame, format_args!("overflow (INTO)"));
+    panic!("EXCEPCIÓN: OVERFLOW (INTO)\n{:#?}", stack_frame);
+}
+
+extern "x86-interrupt" fn invalid_opcode_handler(stack_frame: InterruptStackFrame) {
+    exit_if_user_fault(&stack_frame, format_args!("opcode inválido"));
+    panic!("EXCEPCIÓN: OPCODE INVÁLIDO\n{:#?}", stack_frame);
+}
+
+extern "x86-interrupt" fn segment_not_present_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) {
+    exit_if_user_fault(&stack_frame, format_args!("segmento no presente ({:#x})", error_code));
+    panic!(
+        "EXCEPCIÓN: SEGMENTO NO PRESENTE (selector {:#x})\n{:#?}",
+        error_code, stack_frame
+    );
+}
+
+extern "x86-interrupt" fn stack_segment_fault_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) {
+    exit_if_user_fault(&stack_frame, format_args!("fallo del segmento de stack ({:#x})", error_code));
+    panic!(
+        "EXCEPCIÓN: FALLO DEL SEGMENTO DE STACK (selector {:#x})\n{:#?}",
+        error_code, stack_frame
+    );
+}
+
+extern "x86-interrupt" fn general_protection_fault_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) {
+    exit_if_user_fault(&stack_frame, format_args!("fallo de protección general ({:#x})", error_code));
+    panic!(
+        "EXCEPCIÓN: FALLO DE PROTECCIÓN GENERAL (selector {:#x})\n{:#?}",
+        error_code, stack_frame
+    );
+}
+
+extern "x86-interrupt" fn alignment_check_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) {
+    exit_if_user_fault(&stack_frame, format_args!("chequeo de alineación ({:#x})", error_code));
+    panic!(
+        "EXCEPCIÓN: CHEQUEO DE ALINEACIÓN (código {:#x})\n{:#?}",
+        error_code, stack_frame
+    );
+}
+
+// ----------------- MACHINE CHECK -----------------
+//
+// Sin CR4.MCE, un error de hardware apaga la CPU (en QEMU, un triple fault
+// sin explicación). Con él llega #MC y se puede leer qué banco lo reportó.
+
+const CPUID_MCE: u32 = 1 << 7;
+const CPUID_MCA: u32 = 1 << 14;
+
+const IA32_MCG_CAP: u32 = 0x179;
+const IA32_MCG_STATUS: u32 = 0x17A;
+const IA32_MC0_STATUS: u32 = 0x401;
+
+const MC_STATUS_VALID: u64 = 1 << 63;
+const MC_STATUS_OVERFLOW: u64 = 1 << 62;
+const MC_STATUS_UNCORRECTED: u64 = 1 << 61;
+const MC_STATUS_ADDR_VALID: u64 = 1 << 58;
+const MC_STATUS_CONTEXT_CORRUPT: u64 = 1 << 57;
+
+fn cpu_features() -> u32 {
+    unsafe { core::arch::x86_64::__cpuid(1).edx }
+}
+
+fn enable_machine_check() {
+    use x86_64::registers::control::{Cr4, Cr4Flags};
+
+    if cpu_features() & CPUID_MCE != 0 {
+        unsafe { Cr4::update(|flags| flags.insert(Cr4Flags::MACHINE_CHECK_EXCEPTION)) };
+    }
+}
+
+/// Decodificación de un registro IA32_MCi_STATUS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct MachineCheckStatus {
+    uncorrected: bool,
+    overflow: bool,
+    context_corrupt: bool,
+    address_valid: bool,
+    /// Código de error de la arquitectura (MCA), bits 0..16.
+    mca_code: u16,
+}
+
+/// `None` si el banco no tiene un error registrado.
+fn decode_mc_status(status: u64) -> Option<MachineCheckStatus> {
+    if status & MC_STATUS_VALID == 0 {
+        return None;
+    }
+    Some(MachineCheckStatus {
+        uncorrected: status & MC_STATUS_UNCORRECTED != 0,
+        overflow: status & MC_STATUS_OVERFLOW != 0,
+        context_corrupt: status & MC_STATUS_CONTEXT_CORRUPT != 0,
+        address_valid: status & MC_STATUS_ADDR_VALID != 0,
+        mca_code: status as u16,
+    })
+}
+
+extern "x86-interrupt" fn machine_check_handler(stack_frame: InterruptStackFrame) -> ! {
+    // Como en el doble fallo, el reporte va por serie sin tomar locks.
+    let mut out = unsafe { crate::serial::emergency_writer() };
+    report_machine_check(&mut out);
+    panic!("EXCEPCIÓN: MACHINE CHECK\n{:#?}", stack_frame);
+}
+
+fn report_machine_check(out: &mut impl Write) {
+    use x86_64::registers::model_specific::Msr;
+
+    let _ = writeln!(out, "EXCEPCIÓN: MACHINE CHECK");
+    if cpu_features() & CPUID_MCA == 0 {
+        let _ = writeln!(out, "La CPU no tiene bancos MCA; no hay más detalle");
+        return;
+    }
+
+    let cap = unsafe { Msr::new(IA32_MCG_CAP).read() };
+    let mcg_status = unsafe { Msr::new(IA32_MCG_STATUS).read() };
+    let _ = writeln!(out, "MCG_STATUS: {:#x}", mcg_status);
+
+    let banks = (cap & 0xFF) as u32;
+    let mut reported = false;
+    for bank in 0..banks {
+        let base = IA32_MC0_STATUS + 4 * bank;
+        let status = unsafe { Msr::new(base).read() };
+        let Some(decoded) = decode_mc_status(status) else {
+            continue;
+        };
+        reported = true;
+        let _ = writeln!(out, "Banco {}: status {:#x} {:?}", bank, status, decoded);
+        if decoded.address_valid {
+            // MCi_ADDR sigue a MCi_STATUS.
+            let address = unsafe { Msr::new(base + 1).read() };
+            let _ = writeln!(out, "  dirección: {:#x}", address);
+        }
+    }
+    if !reported {
+        let _ = writeln!(out, "Ningún banco tiene un error válido ({} bancos)", banks);
+    }
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_allocate_and_free_vector() {
+    fn handler() {}
+    let vector = allocate_vector(handler).expect("sin vectores dinámicos libres");
+    assert!((DYNAMIC_VECTOR_BASE..DYNAMIC_VECTOR_BASE + DYNAMIC_VECTORS as u8).contains(&vector));
+    let other = allocate_vector(handler).unwrap();
+    assert_ne!(vector, other);
+    free_vector(vector);
+    free_vector(other);
+    assert_eq!(allocate_vector(handler), Some(vector));
+    free_vector(vector);
+}
+
+#[test_case]
+fn test_decode_mc_status() {
+    assert_eq!(decode_mc_status(0x1234), None);
+
+    let status = MC_STATUS_VALID | MC_STATUS_UNCORRECTED | MC_STATUS_ADDR_VALID | 0x0150;
+    assert_eq!(
+        decode_mc_status(status),
+        Some(MachineCheckStatus {
+            uncorrected: true,
+            overflow: false,
+            context_corrupt: false,
+            address_valid: true,
+            mca_code: 0x0150,
+        })
+    );
+}
+
+#[test_case]
+fn test_describe_page_fault() {
+    let write = PageFaultErrorCode::PROTECTION_VIOLATION | PageFaultErrorCode::CAUSED_BY_WRITE;
+    assert_eq!(
+        describe_page_fault(write),
+        PageFaultCause { cause: "violación de protección", access: "escritura", mode: "kernel" }
+    );
+
+    let fetch = PageFaultErrorCode::INSTRUCTION_FETCH | PageFaultErrorCode::USER_MODE;
+    assert_eq!(
+        describe_page_fault(fetch),
+        PageFaultCause { cause: "página no presente", access: "ejecución", mode: "usuario" }
+    );
+}
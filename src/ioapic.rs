@@ -0,0 +1,268 @@
+//! IO-APIC: ruteo de interrupciones de dispositivos.
+//!
+//! Cada IO-APIC atiende un rango de interrupciones globales (GSI) a partir de
+//! su `gsi_base`. Sus registros se acceden indirectamente: se escribe el
+//! número de registro en IOREGSEL y se lee o escribe IOWIN. Cada GSI tiene
+//! una entrada de redirección de 64 bits (vector, destino, polaridad, disparo).
+//!
+//! `init` deja todas las entradas enmascaradas: mientras el PIC 8259 siga
+//! activo, ningún driver debería desenmascarar una entrada.
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::error::KernelError;
+use crate::acpi::{self, AcpiError, MAX_IO_APICS};
+use crate::mmio::{Field, MmioRegion, ReadWrite, Register};
+
+const IOREGSEL: Register<u32, ReadWrite> = Register::new(0x00);
+const IOWIN: Register<u32, ReadWrite> = Register::new(0x10);
+const MMIO_LEN: u64 = 0x20;
+
+const REG_ID: u8 = 0x00;
+const REG_VERSION: u8 = 0x01;
+const REG_REDIRECTION: u8 = 0x10;
+
+const VERSION_MAX_ENTRY: Field<u32> = Field::new(16, 8);
+
+/// Entradas que entran en un IOREGSEL de 8 bits a partir de `REG_REDIRECTION`.
+const MAX_ENTRIES: u32 = (256 - REG_REDIRECTION as u32) / 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerMode {
+    Edge,
+    Level,
+}
+
+/// Entrada de redirección con entrega fija y destino físico.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Redirection {
+    pub vector: u8,
+    /// ID del APIC local destino.
+    pub destination: u8,
+    pub polarity: Polarity,
+    pub trigger: TriggerMode,
+    pub masked: bool,
+}
+
+impl Redirection {
+    fn encode(&self) -> u64 {
+        let mut value = u64::from(self.vector);
+        if self.polarity == Polarity::ActiveLow {
+            value |= 1 << 13;
+        }
+        if self.trigger == TriggerMode::Level {
+            value |= 1 << 15;
+        }
+        if self.masked {
+            value |= 1 << 16;
+        }
+        value | u64::from(self.destination) << 56
+    }
+
+    fn decode(value: u64) -> Redirection {
+        Redirection {
+            vector: value as u8,
+            destination: (value >> 56) as u8,
+            polarity: if value & (1 << 13) != 0 { Polarity::ActiveLow } else { Polarity::ActiveHigh },
+            trigger: if value & (1 << 15) != 0 { TriggerMode::Level } else { TriggerMode::Edge },
+            masked: value & (1 << 16) != 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoApicError {
+    Acpi(AcpiError),
+    /// No se pudieron mapear sus registros.
+    Map(KernelError),
+    /// Ningún IO-APIC atiende esa GSI.
+    NoSuchGsi(u32),
+    /// La entrada pasa la última de redirección del IO-APIC.
+    NoSuchEntry(u32),
+}
+
+pub struct IoApic {
+    id: u8,
+    gsi_base: u32,
+    entries: u32,
+    regs: MmioRegion,
+}
+
+impl IoApic {
+    fn read(&self, register: u8) -> u32 {
+        self.regs.write(IOREGSEL, u32::from(register));
+        self.regs.read(IOWIN)
+    }
+
+    fn write(&self, register: u8, value: u32) {
+        self.regs.write(IOREGSEL, u32::from(register));
+        self.regs.write(IOWIN, value);
+    }
+
+    fn handles(&self, gsi: u32) -> bool {
+        gsi >= self.gsi_base && gsi < self.gsi_base + self.entries
+    }
+
+    /// El registro bajo de la entrada de redirección de `gsi`.
+    fn redirection_register(&self, gsi: u32) -> Result<u8, IoApicError> {
+        let entry = gsi.checked_sub(self.gsi_base).ok_or(IoApicError::NoSuchGsi(gsi))?;
+        if entry >= self.entries {
+            return Err(IoApicError::NoSuchEntry(entry));
+        }
+        Ok(REG_REDIRECTION + 2 * entry as u8)
+    }
+
+    fn redirection(&self, gsi: u32) -> Result<Redirection, IoApicError> {
+        let register = self.redirection_register(gsi)?;
+        let low = self.read(register);
+        let high = self.read(register + 1);
+        Ok(Redirection::decode(u64::from(high) << 32 | u64::from(low)))
+    }
+
+    fn set_redirection(&self, gsi: u32, entry: &Redirection) -> Result<(), IoApicError> {
+        let register = self.redirection_register(gsi)?;
+        let value = entry.encode();
+        // Se enmascara primero para no entregar una combinación a medio escribir.
+        self.write(register, (value as u32) | 1 << 16);
+        self.write(register + 1, (value >> 32) as u32);
+        self.write(register, value as u32);
+        Ok(())
+    }
+}
+
+static IO_APICS: Mutex<[Option<IoApic>; MAX_IO_APICS]> = Mutex::new([const { None }; MAX_IO_APICS]);
+
+/// Overrides de la MADT, guardados para traducir IRQs ISA.
+static OVERRIDES: Mutex<[Option<acpi::InterruptOverride>; acpi::MAX_OVERRIDES]> =
+    Mutex::new([None; acpi::MAX_OVERRIDES]);
+
+/// Encuentra los IO-APICs en la MADT, los mapea y enmascara todas sus
+/// entradas. Devuelve cuántos encontró. Requiere `memory::init`.
+pub fn init() -> Result<usize, IoApicError> {
+    let madt = acpi::madt().map_err(IoApicError::Acpi)?;
+    let mut found = 0;
+
+    for info in madt.io_apics.iter().flatten() {
+        let base = crate::memory::map_mmio(info.address, MMIO_LEN)
+            .map_err(IoApicError::Map)?;
+        let mut ioapic = IoApic {
+            id: info.id,
+            gsi_base: info.gsi_base,
+            entries: 0,
+            regs: unsafe { MmioRegion::new(base, MMIO_LEN as usize) },
+        };
+        // Más de `MAX_ENTRIES` no se pueden direccionar.
+        ioapic.entries = (VERSION_MAX_ENTRY.get(ioapic.read(REG_VERSION)) + 1).min(MAX_ENTRIES);
+
+        for gsi in ioapic.gsi_base..ioapic.gsi_base + ioapic.entries {
+            let mut entry = ioapic.redirection(gsi)?;
+            entry.masked = true;
+            ioapic.set_redirection(gsi, &entry)?;
+        }
+
+        crate::log_info!(
+            "IO-APIC {} (id registro {:#x}) en {:#x}: GSI {}..{}",
+            ioapic.id,
+            ioapic.read(REG_ID) >> 24,
+            info.address.as_u64(),
+            ioapic.gsi_base,
+            ioapic.gsi_base + ioapic.entries
+        );
+
+        interrupts::without_interrupts(|| {
+            let mut ioapics = IO_APICS.lock();
+            if let Some(slot) = ioapics.iter_mut().find(|s| s.is_none()) {
+                *slot = Some(ioapic);
+                found += 1;
+            }
+        });
+    }
+
+    interrupts::without_interrupts(|| *OVERRIDES.lock() = madt.overrides);
+    Ok(found)
+}
+
+fn with_ioapic<R>(gsi: u32, f: impl FnOnce(&IoApic) -> Result<R, IoApicError>) -> Result<R, IoApicError> {
+    interrupts::without_interrupts(|| {
+        let ioapics = IO_APICS.lock();
+        let ioapic = ioapics
+            .iter()
+            .flatten()
+            .find(|ioapic| ioapic.handles(gsi))
+            .ok_or(IoApicError::NoSuchGsi(gsi))?;
+        f(ioapic)
+    })
+}
+
+pub fn set_redirection(gsi: u32, entry: Redirection) -> Result<(), IoApicError> {
+    with_ioapic(gsi, |ioapic| ioapic.set_redirection(gsi, &entry))
+}
+
+pub fn redirection(gsi: u32) -> Result<Redirection, IoApicError> {
+    with_ioapic(gsi, |ioapic| ioapic.redirection(gsi))
+}
+
+pub fn set_masked(gsi: u32, masked: bool) -> Result<(), IoApicError> {
+    with_ioapic(gsi, |ioapic| {
+        let mut entry = ioapic.redirection(gsi)?;
+        entry.masked = masked;
+        ioapic.set_redirection(gsi, &entry)
+    })
+}
+
+/// GSI, polaridad y disparo de una IRQ ISA, aplicando los overrides de la MADT.
+/// Sin override, las IRQs ISA son identidad, activas en alto y por flanco.
+pub fn isa_irq(irq: u8) -> (u32, Polarity, TriggerMode) {
+    let overrides = interrupts::without_interrupts(|| *OVERRIDES.lock());
+    let Some(o) = overrides.iter().flatten().find(|o| o.isa_irq == irq) else {
+        return (u32::from(irq), Polarity::ActiveHigh, TriggerMode::Edge);
+    };
+
+    // 0b00 = según el bus (ISA: alto, flanco), 0b01 = alto/flanco, 0b11 = bajo/nivel.
+    let polarity = match o.flags & 0b11 {
+        0b11 => Polarity::ActiveLow,
+        _ => Polarity::ActiveHigh,
+    };
+    let trigger = match (o.flags >> 2) & 0b11 {
+        0b11 => TriggerMode::Level,
+        _ => TriggerMode::Edge,
+    };
+    (o.gsi, polarity, trigger)
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_redirection_encoding() {
+    let entry = Redirection {
+        vector: 0x41,
+        destination: 3,
+        polarity: Polarity::ActiveLow,
+        trigger: TriggerMode::Level,
+        masked: true,
+    };
+    assert_eq!(entry.encode(), 0x0300_0000_0001_a041);
+    assert_eq!(Redirection::decode(entry.encode()), entry);
+}
+
+#[test_case]
+fn test_redirection_register_bounds() {
+    // No se accede a los registros: alcanza con una región que no existe.
+    let ioapic = IoApic {
+        id: 0,
+        gsi_base: 24,
+        entries: MAX_ENTRIES,
+        regs: unsafe { MmioRegion::new(x86_64::VirtAddr::zero(), MMIO_LEN as usize) },
+    };
+    assert_eq!(ioapic.redirection_register(24), Ok(REG_REDIRECTION));
+    assert_eq!(ioapic.redirection_register(24 + MAX_ENTRIES - 1), Ok(0xFE));
+    assert_eq!(ioapic.redirection_register(24 + MAX_ENTRIES), Err(IoApicError::NoSuchEntry(MAX_ENTRIES)));
+    assert_eq!(ioapic.redirection_register(23), Err(IoApicError::NoSuchGsi(23)));
+}
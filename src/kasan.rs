@@ -0,0 +1,156 @@
+//! Accesos verificados al heap, al estilo de KASAN.
+//!
+//! Con la feature `kasan` el allocator global mantiene un mapa de sombra con
+//! un bit por cada 8 bytes del heap: prendido mientras el granulo es parte de
+//! una alocación viva. `CheckedPtr` consulta el mapa antes de cada lectura o
+//! escritura y entra en pánico si el acceso toca memoria liberada o que nunca
+//! se alocó. Sin la feature `CheckedPtr` es un puntero común sin costo.
+//!
+//! El mapa es grueso: un granulo compartido por dos alocaciones cuenta como
+//! vivo mientras viva cualquiera de las dos, y también después: al liberar
+//! sólo se apagan los granulos que la alocación ocupaba enteros. Sólo cubre
+//! los primeros `SHADOW_COVERS` bytes del heap; lo que esté fuera del heap
+//! (pila, statics) no se verifica.
+
+use core::marker::PhantomData;
+
+/// Un puntero crudo a `T` cuyos accesos se verifican contra el mapa de sombra.
+#[derive(Debug)]
+pub struct CheckedPtr<T> {
+    ptr: *mut T,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Clone for CheckedPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for CheckedPtr<T> {}
+
+impl<T> CheckedPtr<T> {
+    pub const fn new(ptr: *mut T) -> Self {
+        CheckedPtr { ptr, _marker: PhantomData }
+    }
+
+    pub fn as_ptr(&self) -> *mut T {
+        self.ptr
+    }
+
+    /// El puntero `count` elementos más adelante. La aritmética no se
+    /// verifica; el acceso sí.
+    pub fn add(&self, count: usize) -> Self {
+        CheckedPtr::new(self.ptr.wrapping_add(count))
+    }
+
+    fn check(&self, access: &str) {
+        #[cfg(feature = "kasan")]
+        shadow::check(self.ptr as usize, core::mem::size_of::<T>(), access);
+        #[cfg(not(feature = "kasan"))]
+        let _ = access;
+    }
+
+    /// # Safety
+    ///
+    /// Lo mismo que `ptr::read`: alineado, inicializado y válido. Con `kasan`
+    /// un acceso a heap no alocado entra en pánico en vez de leer basura.
+    pub unsafe fn read(&self) -> T {
+        self.check("lectura");
+        unsafe { self.ptr.read() }
+    }
+
+    /// # Safety
+    ///
+    /// Lo mismo que `ptr::write`.
+    pub unsafe fn write(&self, value: T) {
+        self.check("escritura");
+        unsafe { self.ptr.write(value) }
+    }
+}
+
+#[cfg(feature = "kasan")]
+pub mod shadow {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    use crate::allocator::HEAP_START;
+
+    /// Bytes de heap que cubre el mapa.
+    pub const SHADOW_COVERS: usize = 8 * 1024 * 1024;
+    const GRANULE: usize = 8;
+    const WORDS: usize = SHADOW_COVERS / GRANULE / 64;
+
+    static SHADOW: [AtomicU64; WORDS] = [const { AtomicU64::new(0) }; WORDS];
+
+    /// Granulos que toca `[addr, addr + len)` dentro del área cubierta.
+    pub(super) fn granules(addr: usize, len: usize) -> Option<core::ops::Range<usize>> {
+        let end = addr.checked_add(len)?;
+        if addr < HEAP_START || end > HEAP_START + SHADOW_COVERS {
+            return None;
+        }
+        Some((addr - HEAP_START) / GRANULE..(end - HEAP_START).div_ceil(GRANULE))
+    }
+
+    /// Granulos que `[addr, addr + len)` ocupa enteros.
+    pub(super) fn whole_granules(addr: usize, len: usize) -> Option<core::ops::Range<usize>> {
+        let range = granules(addr, len)?;
+        let first = range.start + usize::from(!(addr - HEAP_START).is_multiple_of(GRANULE));
+        let last = (addr + len - HEAP_START) / GRANULE;
+        Some(first..last.max(first))
+    }
+
+    fn set(range: core::ops::Range<usize>, allocated: bool) {
+        for granule in range {
+            let bit = 1 << (granule % 64);
+            let word = &SHADOW[granule / 64];
+            if allocated {
+                word.fetch_or(bit, Ordering::Relaxed);
+            } else {
+                word.fetch_and(!bit, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Lo llama el allocator global. Al liberar, un granulo que la alocación
+    /// comparte con otra queda prendido.
+    pub(crate) fn mark(addr: usize, len: usize, allocated: bool) {
+        let range = if allocated { granules(addr, len.max(1)) } else { whole_granules(addr, len) };
+        if let Some(range) = range {
+            set(range, allocated);
+        }
+    }
+
+    /// Si todo `[addr, addr + len)` está alocado o fuera del heap cubierto.
+    pub fn is_accessible(addr: usize, len: usize) -> bool {
+        match granules(addr, len.max(1)) {
+            Some(mut range) => range.all(|g| SHADOW[g / 64].load(Ordering::Relaxed) & (1 << (g % 64)) != 0),
+            None => true,
+        }
+    }
+
+    pub(crate) fn check(addr: usize, len: usize, access: &str) {
+        if !is_accessible(addr, len) {
+            panic!("kasan: {} de {} bytes en {:#x}: heap no alocado o liberado", access, len, addr);
+        }
+    }
+}
+
+// ----------------- TESTS -----------------
+
+#[cfg(feature = "kasan")]
+#[test_case]
+fn test_shared_granule_is_not_cleared() {
+    use crate::allocator::HEAP_START;
+
+    // Sólo los rangos, sin tocar el mapa: el heap puede crecer hasta
+    // cualquier dirección cubierta.
+    let a = HEAP_START;
+    let b = a + 12;
+    assert_eq!(shadow::granules(a, 12), Some(0..2));
+    assert_eq!(shadow::granules(b, 12), Some(1..3));
+    // El segundo granulo es de las dos: ninguna lo apaga al liberarse.
+    assert_eq!(shadow::whole_granules(a, 12), Some(0..1));
+    assert_eq!(shadow::whole_granules(b, 12), Some(2..3));
+    assert_eq!(shadow::whole_granules(a + 2, 4), Some(1..1));
+    assert_eq!(shadow::granules(a - 8, 8), None);
+}
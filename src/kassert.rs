@@ -0,0 +1,119 @@
+//! `kassert!` y `kexpect!`: como `assert!` y `expect`, pero el panic incluye
+//! la expresión, el archivo y la línea, la tarea que se estaba polleando y un
+//! backtrace.
+//!
+//! En los tests el panic lo atiende `test_panic_handler`, que marca fallida
+//! sólo la prueba en curso y sigue con las demás.
+
+use core::fmt;
+
+use crate::backtrace::Backtrace;
+
+/// Lo que se puede pasar a `kexpect!`.
+pub trait Expectable {
+    type Output;
+
+    /// El valor, o lo que devuelva `fail` (que no vuelve) con la descripción
+    /// del error.
+    fn or_fail(self, fail: impl FnOnce(&dyn fmt::Debug) -> Self::Output) -> Self::Output;
+}
+
+impl<T> Expectable for Option<T> {
+    type Output = T;
+
+    fn or_fail(self, fail: impl FnOnce(&dyn fmt::Debug) -> T) -> T {
+        match self {
+            Some(value) => value,
+            None => fail(&format_args!("None")),
+        }
+    }
+}
+
+impl<T, E: fmt::Debug> Expectable for Result<T, E> {
+    type Output = T;
+
+    fn or_fail(self, fail: impl FnOnce(&dyn fmt::Debug) -> T) -> T {
+        match self {
+            Ok(value) => value,
+            Err(err) => fail(&err),
+        }
+    }
+}
+
+struct Failure<'a> {
+    expr: &'a str,
+    file: &'a str,
+    line: u32,
+    message: Option<fmt::Arguments<'a>>,
+    backtrace: Backtrace,
+}
+
+impl fmt::Display for Failure<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "`{}` falló en {}:{}", self.expr, self.file, self.line)?;
+        if let Some(message) = self.message {
+            write!(f, ": {}", message)?;
+        }
+        match crate::task::current() {
+            Some(task) => writeln!(f, "\ntarea: {}", task.as_u64())?,
+            None => writeln!(f, "\ntarea: ninguna")?,
+        }
+        writeln!(f, "backtrace:")?;
+        self.backtrace.write(f)
+    }
+}
+
+/// El panic de `kassert!` y `kexpect!`.
+#[cold]
+#[inline(never)]
+pub fn fail(expr: &str, file: &str, line: u32, message: Option<fmt::Arguments>) -> ! {
+    let failure = Failure { expr, file, line, message, backtrace: Backtrace::capture() };
+    panic!("{}", failure)
+}
+
+/// `assert!` con contexto: `kassert!(cond)` o `kassert!(cond, "formato", args...)`.
+#[macro_export]
+macro_rules! kassert {
+    ($cond:expr $(,)?) => {
+        if !$cond {
+            $crate::kassert::fail(stringify!($cond), file!(), line!(), None)
+        }
+    };
+    ($cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            $crate::kassert::fail(stringify!($cond), file!(), line!(), Some(format_args!($($arg)+)))
+        }
+    };
+}
+
+/// Desenvuelve un `Option` o un `Result`, o falla como `kassert!` mostrando
+/// el error: `kexpect!(valor)` o `kexpect!(valor, "formato", args...)`.
+#[macro_export]
+macro_rules! kexpect {
+    ($value:expr $(,)?) => {
+        $crate::kassert::Expectable::or_fail($value, |err| {
+            $crate::kassert::fail(stringify!($value), file!(), line!(), Some(format_args!("{:?}", err)))
+        })
+    };
+    ($value:expr, $($arg:tt)+) => {
+        $crate::kassert::Expectable::or_fail($value, |err| {
+            $crate::kassert::fail(
+                stringify!($value),
+                file!(),
+                line!(),
+                Some(format_args!("{}: {:?}", format_args!($($arg)+), err)),
+            )
+        })
+    };
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_kassert_and_kexpect_pass_through() {
+    let value = core::hint::black_box(2);
+    kassert!(value == 2);
+    kassert!(value > 1, "con mensaje {}", value);
+    assert_eq!(kexpect!(Some(5)), 5);
+    assert_eq!(kexpect!(Ok::<_, ()>(7), "no debería fallar"), 7);
+}
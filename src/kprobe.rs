@@ -0,0 +1,173 @@
+//! Probes dinámicos sobre funciones del kernel, al estilo kprobes.
+//!
+//! `register` reemplaza el primer byte de la instrucción por `int3` (0xCC).
+//! Cuando se ejecuta, el handler de breakpoint llama al callback, restaura
+//! el byte original y vuelve con el flag TF prendido: la CPU ejecuta la
+//! instrucción original y genera un `#DB`, donde se vuelve a poner el `int3`.
+//!
+//! El callback recibe los registros generales tal como estaban al llegar al
+//! `int3`, que guarda el stub de entrada del breakpoint, junto con el stack
+//! frame de la interrupción (RIP, RSP, RFLAGS, CS, SS).
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::structures::idt::InterruptStackFrame;
+use x86_64::VirtAddr;
+
+const MAX_PROBES: usize = 16;
+const INT3: u8 = 0xCC;
+const TRAP_FLAG: u64 = 1 << 8;
+
+pub type ProbeHandler = fn(&Registers);
+
+/// Lo que deja en el stack el stub de entrada del breakpoint: los registros
+/// generales, en el orden inverso al que se apilan, y arriba el frame que
+/// apila la CPU.
+#[repr(C)]
+pub struct Registers {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    pub frame: InterruptStackFrame,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KprobeError {
+    AlreadyProbed,
+    TableFull,
+    NotFound,
+}
+
+#[derive(Clone, Copy)]
+struct Probe {
+    addr: u64,
+    original: u8,
+    handler: ProbeHandler,
+    hits: u64,
+}
+
+static PROBES: Mutex<[Option<Probe>; MAX_PROBES]> = Mutex::new([None; MAX_PROBES]);
+
+/// Dirección del probe que hay que volver a armar en el próximo `#DB` (0 = ninguno).
+static REARM: AtomicU64 = AtomicU64::new(0);
+
+/// Coloca un probe en `addr`.
+///
+/// # Safety
+///
+/// `addr` tiene que ser el comienzo de una instrucción de código del kernel
+/// que no se esté ejecutando en otro contexto mientras se parchea.
+pub unsafe fn register(addr: VirtAddr, handler: ProbeHandler) -> Result<(), KprobeError> {
+    let addr = addr.as_u64();
+    interrupts::without_interrupts(|| {
+        let mut probes = PROBES.lock();
+        if probes.iter().flatten().any(|p| p.addr == addr) {
+            return Err(KprobeError::AlreadyProbed);
+        }
+        let slot = probes
+            .iter_mut()
+            .find(|p| p.is_none())
+            .ok_or(KprobeError::TableFull)?;
+
+        let original = unsafe { core::ptr::read_volatile(addr as *const u8) };
+        *slot = Some(Probe { addr, original, handler, hits: 0 });
+        unsafe { patch(addr, INT3) };
+        Ok(())
+    })
+}
+
+/// Saca el probe de `addr` y restaura la instrucción original.
+pub fn unregister(addr: VirtAddr) -> Result<(), KprobeError> {
+    let addr = addr.as_u64();
+    interrupts::without_interrupts(|| {
+        let mut probes = PROBES.lock();
+        let slot = probes
+            .iter_mut()
+            .find(|p| p.is_some_and(|p| p.addr == addr))
+            .ok_or(KprobeError::NotFound)?;
+
+        let probe = slot.take().unwrap();
+        // Si había un re-armado pendiente, `handle_debug` igual apaga TF y,
+        // como el probe ya no está registrado, no vuelve a poner el `int3`.
+        unsafe { patch(addr, probe.original) };
+        Ok(())
+    })
+}
+
+/// Cantidad de veces que se disparó el probe de `addr`.
+pub fn hits(addr: VirtAddr) -> Option<u64> {
+    let addr = addr.as_u64();
+    interrupts::without_interrupts(|| {
+        PROBES.lock().iter().flatten().find(|p| p.addr == addr).map(|p| p.hits)
+    })
+}
+
+/// Escribe un byte en código del kernel, que está mapeado sólo lectura.
+unsafe fn patch(addr: u64, byte: u8) {
+    use x86_64::registers::control::{Cr0, Cr0Flags};
+
+    interrupts::without_interrupts(|| unsafe {
+        let cr0 = Cr0::read();
+        Cr0::write(cr0 - Cr0Flags::WRITE_PROTECT);
+        core::ptr::write_volatile(addr as *mut u8, byte);
+        Cr0::write(cr0);
+    });
+}
+
+/// Lo llama el handler de breakpoint. Devuelve `false` si el `int3` no es de un probe.
+pub(crate) fn handle_breakpoint(registers: &mut Registers) -> bool {
+    // RIP apunta al byte siguiente al `int3`.
+    let addr = registers.frame.instruction_pointer.as_u64() - 1;
+
+    let probe = {
+        let mut probes = PROBES.lock();
+        match probes.iter_mut().flatten().find(|p| p.addr == addr) {
+            Some(probe) => {
+                probe.hits += 1;
+                *probe
+            }
+            None => return false,
+        }
+    };
+
+    (probe.handler)(registers);
+
+    unsafe {
+        patch(addr, probe.original);
+        REARM.store(addr, Ordering::Relaxed);
+        registers.frame.as_mut().update(|frame| {
+            frame.instruction_pointer = VirtAddr::new(addr);
+            frame.cpu_flags |= TRAP_FLAG;
+        });
+    }
+    true
+}
+
+/// Lo llama el handler de `#DB`. Devuelve `false` si no había un probe por re-armar.
+pub(crate) fn handle_debug(stack_frame: &mut InterruptStackFrame) -> bool {
+    let addr = REARM.swap(0, Ordering::Relaxed);
+    if addr == 0 {
+        return false;
+    }
+
+    if PROBES.lock().iter().flatten().any(|p| p.addr == addr) {
+        unsafe { patch(addr, INT3) };
+    }
+    unsafe {
+        stack_frame.as_mut().update(|frame| frame.cpu_flags &= !TRAP_FLAG);
+    }
+    true
+}
@@ -0,0 +1,94 @@
+//! Duración de los handlers de interrupción, por vector.
+//!
+//! Con la feature `irq-latency`, los handlers de IRQ y el de
+//! `bench::BENCH_VECTOR` leen el TSC al entrar y al salir y lo acumulan acá.
+//! Las mediciones no incluyen la entrada por hardware ni el `iretq`: para eso
+//! está `bench::interrupt_round_trip`, y la diferencia entre ambos es el
+//! costo del cambio de stack (IST) y del guardado de registros.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+struct Slot {
+    count: AtomicU64,
+    total: AtomicU64,
+    min: AtomicU64,
+    max: AtomicU64,
+}
+
+impl Slot {
+    const fn new() -> Slot {
+        Slot {
+            count: AtomicU64::new(0),
+            total: AtomicU64::new(0),
+            min: AtomicU64::new(u64::MAX),
+            max: AtomicU64::new(0),
+        }
+    }
+}
+
+static SLOTS: [Slot; 256] = [const { Slot::new() }; 256];
+
+/// Mínimo, promedio y máximo en ciclos del TSC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Latency {
+    pub count: u64,
+    pub min: u64,
+    pub avg: u64,
+    pub max: u64,
+}
+
+pub(crate) fn record(vector: u8, cycles: u64) {
+    let slot = &SLOTS[usize::from(vector)];
+    slot.count.fetch_add(1, Ordering::Relaxed);
+    slot.total.fetch_add(cycles, Ordering::Relaxed);
+    slot.min.fetch_min(cycles, Ordering::Relaxed);
+    slot.max.fetch_max(cycles, Ordering::Relaxed);
+}
+
+/// `None` si el vector todavía no se midió.
+pub fn latency(vector: u8) -> Option<Latency> {
+    let slot = &SLOTS[usize::from(vector)];
+    let count = slot.count.load(Ordering::Relaxed);
+    if count == 0 {
+        return None;
+    }
+    Some(Latency {
+        count,
+        min: slot.min.load(Ordering::Relaxed),
+        avg: slot.total.load(Ordering::Relaxed) / count,
+        max: slot.max.load(Ordering::Relaxed),
+    })
+}
+
+pub fn reset() {
+    for slot in SLOTS.iter() {
+        slot.count.store(0, Ordering::Relaxed);
+        slot.total.store(0, Ordering::Relaxed);
+        slot.min.store(u64::MAX, Ordering::Relaxed);
+        slot.max.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Tabla de todos los vectores medidos.
+pub fn print() {
+    crate::println!("vector   veces      mín  promedio      máx  (ciclos)");
+    for vector in 0..=255u8 {
+        if let Some(l) = latency(vector) {
+            crate::println!(
+                "{:#04x} {:>9} {:>8} {:>9} {:>8}",
+                vector, l.count, l.min, l.avg, l.max
+            );
+        }
+    }
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_bench_vector_is_measured() {
+    let before = latency(crate::bench::BENCH_VECTOR).map_or(0, |l| l.count);
+    unsafe { core::arch::asm!("int {vector}", vector = const crate::bench::BENCH_VECTOR) };
+    let after = latency(crate::bench::BENCH_VECTOR).unwrap();
+    assert_eq!(after.count, before + 1);
+    assert!(after.min <= after.avg && after.avg <= after.max);
+}
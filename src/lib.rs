@@ -1,11 +1,14 @@
 #![feature(abi_x86_interrupt)]
+#![feature(allocator_api)]
 #![no_std]
 #![cfg_attr(test, no_main)]
 #![feature(custom_test_frameworks)]
 #![test_runner(crate::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 
+use conquer_once::spin::OnceCell;
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 extern crate alloc;
 // ----------------- MODULOS -----------------
@@ -13,25 +16,77 @@ extern crate alloc;
 #[macro_use]
 pub mod serial;
 
+#[macro_use]
+pub mod console;
+
 #[macro_use]
 pub mod vga_buffer;
 
+#[macro_use]
+pub mod log;
+
+pub mod acpi;
+pub mod address_space;
+pub mod apic;
+pub mod backtrace;
+pub mod bench;
+pub mod clocksource;
+pub mod cmos;
+pub mod config;
+pub mod cpu_usage;
+pub mod cpuinfo;
+pub mod device;
+pub mod driver;
+pub mod elf;
+pub mod error;
+pub mod event;
 pub mod gdt;
+pub mod housekeeping;
+pub mod hpet;
+pub mod initrd;
 pub mod interrupts;
+pub mod ioapic;
+pub mod kasan;
+pub mod kassert;
+pub mod kprobe;
+#[cfg(feature = "irq-latency")]
+pub mod latency;
+pub mod lz;
 pub mod memory;
+pub mod metrics;
+pub mod mmio;
+pub mod msi;
+pub mod pci;
+pub mod pit;
+pub mod portio;
+pub mod process;
+pub mod ps2;
+pub mod quota;
 pub mod buddy;
 pub mod slab;
+pub mod stack;
+pub mod state;
+pub mod syscall;
 pub mod allocator;
 pub mod rng;
+pub mod scheduler;
 pub mod task;
+pub mod thread;
+pub mod time;
+pub mod timer_wheel;
+pub mod trace;
+pub mod vma;
+pub mod wait_queue;
+pub mod watchdog;
+pub mod work;
 
 // ----------------- KERNEL RUNTIME -----------------
 
 pub fn init() {
-    gdt::init();
-    interrupts::init_idt();
-    unsafe { interrupts::PICS.lock().initialize() };
-    x86_64::instructions::interrupts::enable();
+    rng::init();
+    initrd::init();
+    driver::init_all();
+    interrupts::enable_hardware();
 }
 
 pub fn hlt_loop() -> ! {
@@ -42,13 +97,13 @@ pub fn hlt_loop() -> ! {
 
 // ----------------- TESTING -----------------
 
-pub trait Testable {
+pub trait Testable: Sync {
     fn run(&self) -> ();
 }
 
 impl<T> Testable for T
 where
-    T: Fn(),
+    T: Fn() + Sync,
 {
     fn run(&self) {
         serial_print!("{}...\t", core::any::type_name::<T>());
@@ -57,19 +112,84 @@ where
     }
 }
 
-pub fn test_runner(tests: &[&dyn Testable]) {
+/// Las pruebas de la corrida en curso, para que `test_panic_handler` pueda
+/// seguir con la siguiente a la que falló.
+static TESTS: spin::Mutex<Option<&'static [&'static dyn Testable]>> = spin::Mutex::new(None);
+static CURRENT_TEST: AtomicUsize = AtomicUsize::new(0);
+static FAILED_TESTS: AtomicUsize = AtomicUsize::new(0);
+
+pub fn test_runner(tests: &'static [&'static dyn Testable]) {
     serial_println!("Ejecutando {} pruebas", tests.len());
-    for test in tests {
+    *TESTS.lock() = Some(tests);
+    run_tests_from(0);
+}
+
+fn run_tests_from(first: usize) -> ! {
+    let tests = TESTS.lock().unwrap_or(&[]);
+    for (index, test) in tests.iter().enumerate().skip(first) {
+        CURRENT_TEST.store(index, Ordering::Relaxed);
         test.run();
     }
-    exit_qemu(QemuExitCode::Success);
+
+    let failed = FAILED_TESTS.load(Ordering::Relaxed);
+    if failed == 0 {
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("{} de {} pruebas fallaron", failed, tests.len());
+        exit_qemu(QemuExitCode::Failed);
+    }
+    hlt_loop();
 }
 
+const RUNNER_STACK_SIZE: usize = 4096 * 32;
+
+/// Donde siguen las pruebas después de un pánico, en vez de encima del frame
+/// de la que falló. Cada pánico vuelve a empezar desde el tope.
+#[repr(align(16))]
+struct RunnerStack(#[allow(dead_code)] [u8; RUNNER_STACK_SIZE]);
+
+static mut RUNNER_STACK: RunnerStack = RunnerStack([0; RUNNER_STACK_SIZE]);
+
+extern "C" fn resume_tests(first: usize) -> ! {
+    // La prueba pudo fallar con interrupciones apagadas.
+    x86_64::instructions::interrupts::enable();
+    run_tests_from(first)
+}
+
+/// Marca fallida la prueba en curso y sigue con las demás, sobre un stack
+/// limpio. Fuera de `test_runner` (un panic al arrancar, por ejemplo) o
+/// dentro de un handler de interrupción, termina la corrida: sin el EOI o el
+/// `iretq` pendiente, las pruebas siguientes verían interrupciones que no
+/// llegan.
+///
+/// Sin unwinding no se libera nada de lo que tenía la prueba: los locks que
+/// sostuviera quedan tomados para las siguientes.
 pub fn test_panic_handler(info: &PanicInfo) -> ! {
+    serial::flush();
     serial_println!("[fallido]\n");
     serial_println!("Error: {}\n", info);
+
+    if TESTS.try_lock().is_some_and(|tests| tests.is_some()) {
+        FAILED_TESTS.fetch_add(1, Ordering::Relaxed);
+        if interrupts::in_interrupt_context() {
+            serial_println!("El pánico fue dentro de un handler de interrupción; no se sigue.");
+        } else {
+            let first = CURRENT_TEST.load(Ordering::Relaxed) + 1;
+            let top = (&raw const RUNNER_STACK) as u64 + RUNNER_STACK_SIZE as u64;
+            unsafe {
+                core::arch::asm!(
+                    "mov rsp, {top}",
+                    "call {resume}",
+                    top = in(reg) top,
+                    resume = sym resume_tests,
+                    in("rdi") first,
+                    options(noreturn),
+                );
+            }
+        }
+    }
     exit_qemu(QemuExitCode::Failed);
-    hlt_loop(); 
+    hlt_loop();
 }
 
 // ----------------- ENTRY POINTS DE TEST -----------------
@@ -104,11 +224,14 @@ pub enum QemuExitCode {
     Failed = 0x11,
 }
 
+static QEMU_EXIT_PORT: OnceCell<portio::PortRegion> = OnceCell::uninit();
+
 pub fn exit_qemu(exit_code: QemuExitCode) {
-    use x86_64::instructions::port::Port;
+    let region = QEMU_EXIT_PORT.get_or_init(|| {
+        portio::claim("qemu-exit", 0xf4, 4).expect("puerto de salida de QEMU ocupado")
+    });
 
     unsafe {
-        let mut port = Port::new(0xf4);
-        port.write(exit_code as u32);
+        region.port::<u32>(0).write(exit_code as u32);
     }
 }
\ No newline at end of file
@@ -0,0 +1,475 @@
+//! Capa de logging del kernel.
+//!
+//! Los mensajes salen por todas las consolas (`println!`) con el nivel como
+//! prefijo, y la fecha adelante una vez que se leyó el RTC. Si el mismo mensaje
+//! se repite seguido se imprime una sola vez, y al llegar uno distinto se
+//! informa cuántas veces se repitió. Para call sites calientes (handlers de
+//! interrupción, el allocator) está `log_rate_limited!`.
+//!
+//! Con la feature `compressed-log`, además de los últimos `HISTORY_LEN`
+//! mensajes se guarda todo el log comprimido con `lz` en un buffer circular
+//! de `ARCHIVE_LEN` bytes, que `dump_archive` descomprime y manda por serie.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU8, AtomicU32, AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+/// Mensajes permitidos por call site dentro de una ventana.
+pub const RATE_LIMIT_BURST: u32 = 5;
+/// Duración de la ventana de rate limiting. Se pasa a ticks al usarla: la
+/// frecuencia del PIT puede cambiar.
+pub const RATE_LIMIT_INTERVAL_MS: u64 = 5000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    Error = 0,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    fn label(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+        }
+    }
+
+    pub fn from_u8(value: u8) -> Option<Level> {
+        Some(match value {
+            0 => Level::Error,
+            1 => Level::Warn,
+            2 => Level::Info,
+            3 => Level::Debug,
+            _ => return None,
+        })
+    }
+}
+
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+pub fn set_level(level: Level) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn level() -> Level {
+    Level::from_u8(MAX_LEVEL.load(Ordering::Relaxed)).unwrap_or(Level::Debug)
+}
+
+// ----------------- SUPRESIÓN DE DUPLICADOS -----------------
+
+struct LastMessage {
+    hash: u64,
+    level: Level,
+    repeats: u64,
+}
+
+static LAST: Mutex<LastMessage> = Mutex::new(LastMessage {
+    hash: 0,
+    level: Level::Info,
+    repeats: 0,
+});
+
+/// FNV-1a sobre la salida formateada, para comparar mensajes sin guardarlos.
+struct MessageHasher(u64);
+
+impl Write for MessageHasher {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x100_0000_01b3);
+        }
+        Ok(())
+    }
+}
+
+fn report_repeats(last: &mut LastMessage) {
+    if last.repeats > 0 {
+        crate::println!(
+            "[{}] último mensaje repetido {} veces",
+            last.level.label(),
+            last.repeats
+        );
+        last.repeats = 0;
+    }
+}
+
+#[doc(hidden)]
+pub fn _log(level: Level, args: fmt::Arguments) {
+    if level > self::level() {
+        return;
+    }
+
+    let mut hasher = MessageHasher(0xcbf2_9ce4_8422_2325);
+    hasher.write_fmt(args).unwrap();
+    let hash = hasher.0 ^ level as u64;
+
+    interrupts::without_interrupts(|| {
+        let mut last = LAST.lock();
+        if last.hash == hash {
+            last.repeats += 1;
+            return;
+        }
+
+        report_repeats(&mut last);
+        last.hash = hash;
+        last.level = level;
+        match crate::time::DateTime::now() {
+            Some(now) => crate::println!("{} [{}] {}", now, level.label(), args),
+            None => crate::println!("[{}] {}", level.label(), args),
+        }
+        record(level, args);
+        #[cfg(feature = "compressed-log")]
+        archive(level, args);
+    });
+}
+
+/// Imprime el contador de repeticiones pendiente, si lo hay.
+pub fn flush() {
+    interrupts::without_interrupts(|| report_repeats(&mut LAST.lock()));
+}
+
+// ----------------- HISTORIAL -----------------
+
+/// Cantidad de mensajes recientes que se conservan para dumps de crash.
+pub const HISTORY_LEN: usize = 16;
+const HISTORY_LINE: usize = 96;
+
+#[derive(Clone, Copy)]
+struct HistoryLine {
+    bytes: [u8; HISTORY_LINE],
+    len: usize,
+}
+
+impl HistoryLine {
+    const EMPTY: HistoryLine = HistoryLine {
+        bytes: [0; HISTORY_LINE],
+        len: 0,
+    };
+
+    fn as_str(&self) -> &str {
+        // Se trunca en un límite de carácter al escribir, así que siempre es UTF-8 válido.
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("<línea inválida>")
+    }
+}
+
+impl Write for HistoryLine {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            let mut buf = [0; 4];
+            let encoded = c.encode_utf8(&mut buf).as_bytes();
+            if self.len + encoded.len() > HISTORY_LINE {
+                return Err(fmt::Error);
+            }
+            self.bytes[self.len..self.len + encoded.len()].copy_from_slice(encoded);
+            self.len += encoded.len();
+        }
+        Ok(())
+    }
+}
+
+struct History {
+    lines: [HistoryLine; HISTORY_LEN],
+    next: usize,
+    count: usize,
+}
+
+static HISTORY: Mutex<History> = Mutex::new(History {
+    lines: [HistoryLine::EMPTY; HISTORY_LEN],
+    next: 0,
+    count: 0,
+});
+
+fn record(level: Level, args: fmt::Arguments) {
+    let mut history = HISTORY.lock();
+    let index = history.next;
+    let line = &mut history.lines[index];
+    line.len = 0;
+    // Un error sólo significa que la línea se truncó.
+    let _ = write!(line, "[{}] {}", level.label(), args);
+    history.next = (index + 1) % HISTORY_LEN;
+    history.count = (history.count + 1).min(HISTORY_LEN);
+}
+
+/// Recorre los últimos mensajes, del más viejo al más nuevo.
+///
+/// Usa `try_lock` para poder llamarse desde handlers de crash: devuelve `false`
+/// si el historial estaba tomado y no se pudo leer.
+pub fn recent(mut f: impl FnMut(&str)) -> bool {
+    let Some(history) = HISTORY.try_lock() else {
+        return false;
+    };
+    let first = (history.next + HISTORY_LEN - history.count) % HISTORY_LEN;
+    for i in 0..history.count {
+        f(history.lines[(first + i) % HISTORY_LEN].as_str());
+    }
+    true
+}
+
+// ----------------- ARCHIVO COMPRIMIDO -----------------
+
+/// Bytes del buffer circular de bloques comprimidos.
+#[cfg(feature = "compressed-log")]
+pub const ARCHIVE_LEN: usize = 256 * 1024;
+/// Texto que se junta antes de comprimirlo como un bloque.
+#[cfg(feature = "compressed-log")]
+const ARCHIVE_BLOCK: usize = 4096;
+
+/// Cada bloque se guarda como un largo de 2 bytes y los datos comprimidos; si
+/// no hay lugar se descartan los bloques más viejos.
+#[cfg(feature = "compressed-log")]
+struct Archive {
+    staging: [u8; ARCHIVE_BLOCK],
+    staged: usize,
+    ring: [u8; ARCHIVE_LEN],
+    /// Comienzo del bloque más viejo y cantidad de bytes ocupados.
+    oldest: usize,
+    used: usize,
+    /// Buffers de trabajo para comprimir y descomprimir.
+    scratch: [u8; crate::lz::max_compressed_len(ARCHIVE_BLOCK)],
+    block: [u8; ARCHIVE_BLOCK],
+    raw_bytes: u64,
+    compressed_bytes: u64,
+}
+
+#[cfg(feature = "compressed-log")]
+static ARCHIVE: Mutex<Archive> = Mutex::new(Archive {
+    staging: [0; ARCHIVE_BLOCK],
+    staged: 0,
+    ring: [0; ARCHIVE_LEN],
+    oldest: 0,
+    used: 0,
+    scratch: [0; crate::lz::max_compressed_len(ARCHIVE_BLOCK)],
+    block: [0; ARCHIVE_BLOCK],
+    raw_bytes: 0,
+    compressed_bytes: 0,
+});
+
+#[cfg(feature = "compressed-log")]
+impl Archive {
+    fn ring_byte(&self, offset: usize) -> u8 {
+        self.ring[(self.oldest + offset) % ARCHIVE_LEN]
+    }
+
+    fn block_len(&self, offset: usize) -> usize {
+        usize::from(u16::from_le_bytes([self.ring_byte(offset), self.ring_byte(offset + 1)]))
+    }
+
+    fn drop_oldest(&mut self) {
+        let len = 2 + self.block_len(0);
+        self.oldest = (self.oldest + len) % ARCHIVE_LEN;
+        self.used -= len;
+    }
+
+    /// Comprime lo acumulado en `staging` y lo agrega al buffer circular.
+    fn seal(&mut self) {
+        if self.staged == 0 {
+            return;
+        }
+        let len = crate::lz::compress(&self.staging[..self.staged], &mut self.scratch)
+            .expect("el buffer de trabajo alcanza para el peor caso");
+        while ARCHIVE_LEN - self.used < 2 + len {
+            self.drop_oldest();
+        }
+
+        let mut end = (self.oldest + self.used) % ARCHIVE_LEN;
+        for &byte in (len as u16).to_le_bytes().iter().chain(&self.scratch[..len]) {
+            self.ring[end] = byte;
+            end = (end + 1) % ARCHIVE_LEN;
+        }
+        self.used += 2 + len;
+        self.raw_bytes += self.staged as u64;
+        self.compressed_bytes += len as u64;
+        self.staged = 0;
+    }
+
+    /// Descomprime cada bloque, del más viejo al más nuevo, y le pasa el texto a `f`.
+    fn for_each_block(&mut self, mut f: impl FnMut(&str)) {
+        let mut offset = 0;
+        while offset < self.used {
+            let len = self.block_len(offset);
+            // El bloque puede cruzar el final del buffer: se copia entero a `scratch`.
+            for i in 0..len {
+                self.scratch[i] = self.ring_byte(offset + 2 + i);
+            }
+            let text = crate::lz::decompress(&self.scratch[..len], &mut self.block)
+                .and_then(|n| core::str::from_utf8(&self.block[..n]).ok())
+                .unwrap_or("<bloque corrupto>\n");
+            f(text);
+            offset += 2 + len;
+        }
+        f(core::str::from_utf8(&self.staging[..self.staged]).unwrap_or(""));
+    }
+}
+
+/// Junta caracteres enteros en `staging`, así cada bloque es UTF-8 válido.
+#[cfg(feature = "compressed-log")]
+impl Write for Archive {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            let mut buf = [0; 4];
+            let encoded = c.encode_utf8(&mut buf).as_bytes();
+            if self.staged + encoded.len() > ARCHIVE_BLOCK {
+                self.seal();
+            }
+            self.staging[self.staged..self.staged + encoded.len()].copy_from_slice(encoded);
+            self.staged += encoded.len();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "compressed-log")]
+fn archive(level: Level, args: fmt::Arguments) {
+    let _ = writeln!(ARCHIVE.lock(), "[{}] {}", level.label(), args);
+}
+
+/// Bytes de texto archivados y lo que ocupan comprimidos, sin contar el
+/// bloque que todavía se está juntando.
+#[cfg(feature = "compressed-log")]
+pub fn archive_stats() -> (u64, u64) {
+    interrupts::without_interrupts(|| {
+        let archive = ARCHIVE.lock();
+        (archive.raw_bytes, archive.compressed_bytes)
+    })
+}
+
+/// Descomprime todo el log archivado y lo manda por serie.
+///
+/// Escribe directo al UART y no por la cola de `serial::enable_async`, que
+/// descartaría la mayor parte de un dump grande.
+#[cfg(feature = "compressed-log")]
+pub fn dump_archive() {
+    interrupts::without_interrupts(|| {
+        let mut serial = crate::serial::SERIAL1.lock();
+        ARCHIVE.lock().for_each_block(|text| {
+            let _ = serial.write_str(text);
+        });
+    });
+}
+
+// ----------------- RATE LIMITING -----------------
+
+/// Estado de rate limiting de un call site; `log_rate_limited!` crea uno estático.
+pub struct RateLimit {
+    window_start: AtomicU64,
+    count: AtomicU32,
+    suppressed: AtomicU32,
+}
+
+impl RateLimit {
+    pub const fn new() -> Self {
+        Self {
+            window_start: AtomicU64::new(0),
+            count: AtomicU32::new(0),
+            suppressed: AtomicU32::new(0),
+        }
+    }
+
+    /// `Some(n)` si el mensaje puede emitirse, donde `n` es la cantidad de
+    /// mensajes descartados desde el último emitido; `None` si hay que descartarlo.
+    pub fn check(&self) -> Option<u32> {
+        let now = crate::time::ticks();
+        let start = self.window_start.load(Ordering::Relaxed);
+        if now.wrapping_sub(start) >= crate::time::ms_to_ticks(RATE_LIMIT_INTERVAL_MS) {
+            self.window_start.store(now, Ordering::Relaxed);
+            self.count.store(0, Ordering::Relaxed);
+        }
+
+        if self.count.fetch_add(1, Ordering::Relaxed) < RATE_LIMIT_BURST {
+            Some(self.suppressed.swap(0, Ordering::Relaxed))
+        } else {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    }
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ----------------- MACROS -----------------
+
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)*) => ($crate::log::_log($level, format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => ($crate::log!($crate::log::Level::Error, $($arg)*));
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)*) => ($crate::log!($crate::log::Level::Warn, $($arg)*));
+}
+
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => ($crate::log!($crate::log::Level::Info, $($arg)*));
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)*) => ($crate::log!($crate::log::Level::Debug, $($arg)*));
+}
+
+/// Como `log!`, pero cada call site emite a lo sumo `RATE_LIMIT_BURST`
+/// mensajes por ventana de `RATE_LIMIT_INTERVAL_MS`.
+#[macro_export]
+macro_rules! log_rate_limited {
+    ($level:expr, $($arg:tt)*) => {{
+        static LIMIT: $crate::log::RateLimit = $crate::log::RateLimit::new();
+        if let Some(suppressed) = LIMIT.check() {
+            if suppressed > 0 {
+                $crate::log!($level, "{} mensajes suprimidos por rate limiting", suppressed);
+            }
+            $crate::log!($level, $($arg)*);
+        }
+    }};
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_rate_limit_burst() {
+    let limit = RateLimit::new();
+    for _ in 0..RATE_LIMIT_BURST {
+        assert_eq!(limit.check(), Some(0));
+    }
+    assert_eq!(limit.check(), None);
+    assert_eq!(limit.check(), None);
+}
+
+#[test_case]
+fn test_duplicate_messages_are_folded() {
+    log_info!("mensaje repetido de test");
+    log_info!("mensaje repetido de test");
+    log_info!("mensaje repetido de test");
+    let repeats = interrupts::without_interrupts(|| LAST.lock().repeats);
+    assert_eq!(repeats, 2);
+    flush();
+}
+
+#[cfg(feature = "compressed-log")]
+#[test_case]
+fn test_archive_keeps_messages() {
+    log_info!("mensaje archivado de test");
+    let mut found = false;
+    interrupts::without_interrupts(|| {
+        let mut archive = ARCHIVE.lock();
+        archive.seal();
+        archive.for_each_block(|text| found |= text.contains("mensaje archivado de test"));
+    });
+    assert!(found);
+    assert!(archive_stats().0 > 0);
+}
@@ -0,0 +1,194 @@
+//! Compresión LZ estilo LZ4, sin heap.
+//!
+//! El formato es el de bloque de LZ4: secuencias de un token (4 bits de largo
+//! de literales, 4 de largo de match menos 4), largos extendidos con bytes de
+//! 255, los literales y un offset de 16 bits hacia atrás. La última secuencia
+//! lleva sólo literales. El compresor es greedy con una tabla hash de 4
+//! bytes: comprime peor que LZ4 de verdad, pero el log se repite mucho.
+
+const MIN_MATCH: usize = 4;
+const MAX_OFFSET: usize = u16::MAX as usize;
+const HASH_BITS: u32 = 10;
+
+/// Tamaño de salida que alcanza para comprimir `len` bytes en el peor caso.
+pub const fn max_compressed_len(len: usize) -> usize {
+    len + len / 255 + 16
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn hash(value: u32) -> usize {
+    (value.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+struct Output<'a> {
+    bytes: &'a mut [u8],
+    len: usize,
+}
+
+impl Output<'_> {
+    fn push(&mut self, byte: u8) -> Option<()> {
+        *self.bytes.get_mut(self.len)? = byte;
+        self.len += 1;
+        Some(())
+    }
+
+    fn extend(&mut self, bytes: &[u8]) -> Option<()> {
+        self.bytes.get_mut(self.len..self.len + bytes.len())?.copy_from_slice(bytes);
+        self.len += bytes.len();
+        Some(())
+    }
+
+    /// Resto de un largo que no entró en los 4 bits del token.
+    fn push_length(&mut self, mut rest: usize) -> Option<()> {
+        while rest >= 255 {
+            self.push(255)?;
+            rest -= 255;
+        }
+        self.push(rest as u8)
+    }
+
+    fn sequence(&mut self, literals: &[u8], matched: Option<(usize, usize)>) -> Option<()> {
+        let match_code = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+        self.push((literals.len().min(15) << 4 | match_code.min(15)) as u8)?;
+        if literals.len() >= 15 {
+            self.push_length(literals.len() - 15)?;
+        }
+        self.extend(literals)?;
+        if let Some((offset, _)) = matched {
+            self.extend(&(offset as u16).to_le_bytes())?;
+            if match_code >= 15 {
+                self.push_length(match_code - 15)?;
+            }
+        }
+        Some(())
+    }
+}
+
+/// Comprime `input` en `output`. Devuelve los bytes escritos, o `None` si no
+/// entraron; con `max_compressed_len(input.len())` bytes siempre entran.
+pub fn compress(input: &[u8], output: &mut [u8]) -> Option<usize> {
+    // Posición + 1 de la última vez que apareció cada hash; 0 = nunca.
+    let mut table = [0u32; 1 << HASH_BITS];
+    let mut out = Output { bytes: output, len: 0 };
+    let mut anchor = 0;
+    let mut i = 0;
+
+    while i + MIN_MATCH <= input.len() {
+        let value = read_u32(input, i);
+        let slot = &mut table[hash(value)];
+        let candidate = *slot as usize;
+        *slot = (i + 1) as u32;
+
+        if candidate != 0 {
+            let start = candidate - 1;
+            if i - start <= MAX_OFFSET && read_u32(input, start) == value {
+                let mut len = MIN_MATCH;
+                while i + len < input.len() && input[start + len] == input[i + len] {
+                    len += 1;
+                }
+                out.sequence(&input[anchor..i], Some((i - start, len)))?;
+                i += len;
+                anchor = i;
+                continue;
+            }
+        }
+        i += 1;
+    }
+
+    out.sequence(&input[anchor..], None)?;
+    Some(out.len)
+}
+
+/// Descomprime `input` en `output`. Devuelve los bytes escritos, o `None` si
+/// la entrada está corrupta o la salida es chica.
+pub fn decompress(input: &[u8], output: &mut [u8]) -> Option<usize> {
+    let mut pos = 0;
+    let mut written = 0;
+    let mut next = || {
+        let byte = *input.get(pos)?;
+        pos += 1;
+        Some(byte)
+    };
+
+    fn length(nibble: u8, next: &mut impl FnMut() -> Option<u8>) -> Option<usize> {
+        let mut len = usize::from(nibble);
+        if nibble == 15 {
+            loop {
+                let byte = next()?;
+                len += usize::from(byte);
+                if byte != 255 {
+                    break;
+                }
+            }
+        }
+        Some(len)
+    }
+
+    loop {
+        let token = next()?;
+        let literals = length(token >> 4, &mut next)?;
+        for _ in 0..literals {
+            *output.get_mut(written)? = next()?;
+            written += 1;
+        }
+
+        let Some(low) = next() else {
+            return Some(written);
+        };
+        let offset = usize::from(u16::from_le_bytes([low, next()?]));
+        if offset == 0 || offset > written {
+            return None;
+        }
+        let len = length(token & 0xF, &mut next)? + MIN_MATCH;
+        // Byte por byte: el match puede solaparse con lo que está copiando.
+        for _ in 0..len {
+            *output.get_mut(written)? = output[written - offset];
+            written += 1;
+        }
+    }
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_round_trip_repetitive_text() {
+    let mut input = [0u8; 1000];
+    for (i, byte) in input.iter_mut().enumerate() {
+        *byte = b"[INFO] tarea 3 lista\n"[i % 21];
+    }
+    let mut compressed = [0u8; max_compressed_len(1000)];
+    let len = compress(&input, &mut compressed).unwrap();
+    assert!(len < 100, "comprimió a {} bytes", len);
+
+    let mut output = [0u8; 1000];
+    assert_eq!(decompress(&compressed[..len], &mut output), Some(1000));
+    assert_eq!(output, input);
+}
+
+#[test_case]
+fn test_round_trip_incompressible() {
+    let mut rng = crate::rng::SimpleRng::new(7);
+    let mut input = [0u8; 600];
+    for byte in input.iter_mut() {
+        *byte = rng.next_range(0, 256) as u8;
+    }
+    let mut compressed = [0u8; max_compressed_len(600)];
+    let len = compress(&input, &mut compressed).unwrap();
+
+    let mut output = [0u8; 600];
+    assert_eq!(decompress(&compressed[..len], &mut output), Some(600));
+    assert_eq!(output, input);
+}
+
+#[test_case]
+fn test_empty_and_corrupt_input() {
+    let mut compressed = [0u8; 16];
+    let len = compress(&[], &mut compressed).unwrap();
+    assert_eq!(decompress(&compressed[..len], &mut [0u8; 4]), Some(0));
+
+    // Un match con offset más allá del comienzo de la salida.
+    assert_eq!(decompress(&[0x10, b'a', 0x05, 0x00], &mut [0u8; 16]), None);
+}
@@ -15,7 +15,8 @@ entry_point!(kernel_main);
 fn kernel_main(boot_info: &'static BootInfo) -> ! {
     use kur_os::memory;
     use kur_os::allocator;
-    use kur_os::task::{Task, executor::Executor, keyboard};
+    use kur_os::serial;
+    use kur_os::task::{Task, TaskPriority, executor::Executor, keyboard};
     use x86_64::VirtAddr;
 
     println!("Hola desde el kernel!");
@@ -27,15 +28,53 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     }
 
     println!("Memoria inicializada correctamente.");
+    kur_os::acpi::discover();
 
     allocator::init_heap().expect("falló la inicialización del heap");
+    kur_os::scheduler::init();
+
+    if let Err(err) = kur_os::ioapic::init() {
+        kur_os::log_warn!("IO-APIC no disponible: {:?}", err);
+    }
+    if let Err(err) = kur_os::hpet::init() {
+        kur_os::log_warn!("HPET no disponible: {:?}", err);
+    }
+    kur_os::clocksource::select();
+    match kur_os::apic::init() {
+        Ok(()) => kur_os::log_info!(
+            "timer del APIC: {} cuentas/s",
+            kur_os::apic::timer::calibrate()
+        ),
+        Err(err) => kur_os::log_warn!("APIC local no disponible: {:?}", err),
+    }
 
     #[cfg(test)]
     test_main();
 
+    // Todavía no hay shell: el listado de `lsdev` se muestra una vez al arrancar.
+    kur_os::device::print_devices();
+
+    for name in kur_os::initrd::autorun() {
+        if let Err(err) = kur_os::process::spawn_program(name) {
+            kur_os::log_warn!("no se pudo arrancar '{}': {}", name, err);
+        }
+    }
+
+    serial::enable_async(4096, serial::Backpressure::DropOldest);
+
     let mut executor = Executor::new();
+    executor.spawn(Task::new(serial::writer_task()));
+    executor.spawn(Task::new(kur_os::work::worker()));
+    executor.spawn(Task::new(kur_os::housekeeping::run()));
+    // Saca el "último mensaje repetido N veces" aunque no llegue otro mensaje.
+    kur_os::housekeeping::register(
+        "log-flush",
+        kur_os::time::ms_to_ticks(5000).max(1),
+        kur_os::log::flush,
+    );
     executor.spawn(Task::new(example_task()));
-    executor.spawn(Task::new(keyboard::print_keypresses()));
+    // Que teclear responda aunque haya tareas de fondo ocupando el executor.
+    executor.spawn(Task::new_with_priority(keyboard::print_keypresses(), TaskPriority::High));
     executor.run();
 }
 
@@ -51,7 +90,9 @@ async fn example_task() {
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    kur_os::serial::flush();
     println!("{}", info);
+    kur_os::serial::flush();
     kur_os::hlt_loop();
 }
 
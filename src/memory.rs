@@ -3,11 +3,14 @@ use x86_64::{
     PhysAddr,
     structures::paging::{
         Page, PhysFrame, Mapper, Size4KiB, FrameAllocator, 
-        OffsetPageTable, PageTable, PageTableFlags, mapper::MapToError
+        OffsetPageTable, PageTable, PageTableFlags,
     }
 };
 
+use crate::error::{KernelError, KernelResult};
+
 use bootloader::bootinfo::MemoryMap;
+use core::sync::atomic::{AtomicU64, Ordering};
 use spin::Mutex;
 
 pub struct BootInfoFrameAllocator {
@@ -54,33 +57,155 @@ unsafe impl FrameAllocator<Size4KiB> for EmptyFrameAllocator {
     }
 }
 
+/// Comienzo de la ventana virtual donde se mapean regiones MMIO.
+pub const MMIO_START: u64 = 0x_5555_0000_0000;
+/// Tamaño de la ventana MMIO.
+pub const MMIO_SIZE: u64 = 0x1000_0000;
+
+/// Comienzo del espacio virtual de usuario. Son entradas del nivel 4 que el
+/// kernel no usa, así que las tablas de abajo se crean sólo para usuario.
+pub const USER_START: u64 = 0x_2000_0000_0000;
+/// Fin (exclusivo) del espacio virtual de usuario.
+pub const USER_END: u64 = 0x_4000_0000_0000;
+
 static MAPPER: Mutex<Option<OffsetPageTable<'static>>> = Mutex::new(None);
+static MMIO_NEXT: Mutex<u64> = Mutex::new(MMIO_START);
 static FRAME_ALLOCATOR: Mutex<Option<BootInfoFrameAllocator>> = Mutex::new(None);
+static FREE_FRAMES: Mutex<FreeFrames> = Mutex::new(FreeFrames { head: None, len: 0 });
+static PHYSICAL_MEMORY_OFFSET: AtomicU64 = AtomicU64::new(0);
+/// Marco de la tabla de nivel 4 del kernel, la activa al arrancar.
+static KERNEL_PAGE_TABLE: AtomicU64 = AtomicU64::new(0);
+
+/// Marcos devueltos con `deallocate_frame`. Cada uno guarda en sus primeros
+/// 8 bytes la dirección del siguiente (`u64::MAX` en el último).
+struct FreeFrames {
+    head: Option<PhysFrame>,
+    len: usize,
+}
 
 pub unsafe fn init(physical_memory_offset: VirtAddr, memory_map: &'static MemoryMap) {
+    use x86_64::registers::control::Cr3;
+
     let level_4_table = unsafe { active_level_4_table(physical_memory_offset) };
-    let mapper = unsafe { OffsetPageTable::new(level_4_table, physical_memory_offset) };
+    let mut mapper = unsafe { OffsetPageTable::new(level_4_table, physical_memory_offset) };
     
-    let frame_allocator = unsafe { BootInfoFrameAllocator::init(memory_map) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(memory_map) };
+
+    PHYSICAL_MEMORY_OFFSET.store(physical_memory_offset.as_u64(), Ordering::Relaxed);
+    KERNEL_PAGE_TABLE.store(Cr3::read().0.start_address().as_u64(), Ordering::Relaxed);
+    share_kernel_windows(&mut mapper, &mut frame_allocator);
 
     *MAPPER.lock() = Some(mapper);
     *FRAME_ALLOCATOR.lock() = Some(frame_allocator);
 }
 
-pub fn map_page(page: Page) -> Result<(), MapToError<Size4KiB>> {
+/// Crea de antemano las tablas de nivel 3 de las ventanas que el kernel
+/// mapea después del arranque (heap, stacks, MMIO).
+///
+/// Un espacio de direcciones de usuario copia la mitad del kernel del nivel 4
+/// al crearse: con estas tablas ya puestas, lo que se mapee después en esas
+/// ventanas lo ven todos sin tener que actualizar cada copia.
+fn share_kernel_windows(mapper: &mut OffsetPageTable, frame_allocator: &mut BootInfoFrameAllocator) {
+    let offset = mapper.phys_offset();
+    let windows = [crate::allocator::HEAP_START as u64, crate::stack::STACKS_START, MMIO_START];
+    for addr in windows {
+        let entry = &mut mapper.level_4_table()[VirtAddr::new(addr).p4_index()];
+        if !entry.is_unused() {
+            continue;
+        }
+        let frame = frame_allocator.allocate_frame().expect("sin marcos para las tablas del kernel");
+        let table: *mut PageTable = (offset + frame.start_address().as_u64()).as_mut_ptr();
+        unsafe { (*table).zero() };
+        entry.set_frame(frame, PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+    }
+}
+
+/// Dónde empieza el mapeo completo de la memoria física. A diferencia de
+/// `phys_to_virt`, no toma el mapper.
+pub fn physical_memory_offset() -> VirtAddr {
+    VirtAddr::new(PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed))
+}
+
+/// La tabla de nivel 4 del kernel. Es la que usan los hilos que no son de
+/// un proceso.
+pub fn kernel_page_table() -> PhysFrame {
+    PhysFrame::containing_address(PhysAddr::new(KERNEL_PAGE_TABLE.load(Ordering::Relaxed)))
+}
+
+/// Carga `page_table` en CR3 si no es la activa. No toma ningún lock: sirve
+/// desde el scheduler.
+///
+/// # Safety
+///
+/// `page_table` tiene que mapear al kernel igual que la actual.
+pub unsafe fn switch_page_table(page_table: PhysFrame) {
+    use x86_64::registers::control::Cr3;
+
+    let (active, flags) = Cr3::read();
+    if active != page_table {
+        unsafe { Cr3::write(page_table, flags) };
+    }
+}
+
+/// Un marco físico libre: uno devuelto con `deallocate_frame` si hay, o
+/// uno nuevo del mapa de memoria. Su contenido es cualquiera.
+pub fn allocate_frame() -> KernelResult<PhysFrame> {
+    if let Some(frame) = pop_free_frame() {
+        return Ok(frame);
+    }
+    let mut frame_allocator_lock = FRAME_ALLOCATOR.lock();
+    let frame_allocator = frame_allocator_lock.as_mut().ok_or(KernelError::NotInitialized)?;
+    frame_allocator.allocate_frame().ok_or(KernelError::OutOfMemory)
+}
+
+fn pop_free_frame() -> Option<PhysFrame> {
+    let offset = PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed);
+    let mut free = FREE_FRAMES.lock();
+    let frame = free.head?;
+    let next = unsafe { *((offset + frame.start_address().as_u64()) as *const u64) };
+    free.head = (next != u64::MAX).then(|| PhysFrame::containing_address(PhysAddr::new(next)));
+    free.len -= 1;
+    Some(frame)
+}
+
+/// Devuelve `frame` para que lo reuse `allocate_frame`.
+///
+/// # Safety
+///
+/// Nadie puede seguir usando `frame`: ni un mapeo ni una tabla de páginas.
+pub unsafe fn deallocate_frame(frame: PhysFrame) {
+    unsafe { push_free_frame(&mut FREE_FRAMES.lock(), frame) };
+}
+
+unsafe fn push_free_frame(free: &mut FreeFrames, frame: PhysFrame) {
+    let offset = PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed);
+    let next = free.head.map_or(u64::MAX, |next| next.start_address().as_u64());
+    unsafe { *((offset + frame.start_address().as_u64()) as *mut u64) = next };
+    free.head = Some(frame);
+    free.len += 1;
+}
+
+/// Marcos devueltos que todavía no se reusaron.
+pub fn free_frames() -> usize {
+    FREE_FRAMES.lock().len
+}
+
+/// Mapea `page` a un marco libre, escribible: uno devuelto si hay, si no
+/// uno nuevo. Si ya estaba mapeada no hace nada.
+pub fn map_page(page: Page) -> KernelResult<()> {
     let mut mapper_lock = MAPPER.lock();
     let mut frame_allocator_lock = FRAME_ALLOCATOR.lock();
 
-    let mapper = mapper_lock.as_mut().expect("Mapper no inicializado");
-    let frame_allocator = frame_allocator_lock.as_mut().expect("FrameAllocator no inicializado");
+    let mapper = mapper_lock.as_mut().ok_or(KernelError::NotInitialized)?;
+    let frame_allocator = frame_allocator_lock.as_mut().ok_or(KernelError::NotInitialized)?;
 
     if mapper.translate_page(page).is_ok() {
         return Ok(());
     }
 
-    let frame = frame_allocator
-        .allocate_frame()
-        .ok_or(MapToError::FrameAllocationFailed)?;
+    let frame = pop_free_frame()
+        .or_else(|| frame_allocator.allocate_frame())
+        .ok_or(KernelError::OutOfMemory)?;
 
     let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
 
@@ -91,6 +216,119 @@ pub fn map_page(page: Page) -> Result<(), MapToError<Size4KiB>> {
     Ok(())
 }
 
+/// Desmapea `page` y devuelve su marco a `allocate_frame`.
+///
+/// No espera: con el mapper o la lista de marcos tomados devuelve
+/// `WouldBlock` sin tocar nada. Así sirve justo después de un cambio de
+/// hilo, cuando el que los tiene tomados puede ser uno que no va a correr.
+///
+/// # Safety
+///
+/// Nadie puede seguir usando la página.
+pub unsafe fn try_unmap_page(page: Page) -> KernelResult<()> {
+    let mut mapper_lock = MAPPER.try_lock().ok_or(KernelError::WouldBlock)?;
+    let mut free = FREE_FRAMES.try_lock().ok_or(KernelError::WouldBlock)?;
+    let mapper = mapper_lock.as_mut().ok_or(KernelError::NotInitialized)?;
+
+    let (frame, flush) = mapper.unmap(page)?;
+    flush.flush();
+    unsafe { push_free_frame(&mut free, frame) };
+    Ok(())
+}
+
+/// Como `map_page`, pero accesible desde ring 3. `page` tiene que estar
+/// entre `USER_START` y `USER_END`.
+pub fn map_user_page(page: Page) -> KernelResult<()> {
+    let addr = page.start_address().as_u64();
+    if !(USER_START..USER_END).contains(&addr) {
+        return Err(KernelError::InvalidArgument);
+    }
+
+    let mut mapper_lock = MAPPER.lock();
+    let mut frame_allocator_lock = FRAME_ALLOCATOR.lock();
+
+    let mapper = mapper_lock.as_mut().ok_or(KernelError::NotInitialized)?;
+    let frame_allocator = frame_allocator_lock.as_mut().ok_or(KernelError::NotInitialized)?;
+
+    if mapper.translate_page(page).is_ok() {
+        return Ok(());
+    }
+
+    let frame = frame_allocator.allocate_frame().ok_or(KernelError::OutOfMemory)?;
+
+    // `map_to` también les pone el bit de usuario a las tablas intermedias:
+    // la CPU lo exige en todos los niveles.
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+
+    unsafe {
+        mapper.map_to(page, frame, flags, frame_allocator)?.flush();
+    };
+
+    Ok(())
+}
+
+/// Dirección virtual por la que se accede a `phys` a través del mapeo
+/// completo de la memoria física que deja el bootloader. Entra en pánico
+/// antes de `init`: sin el offset no hay dirección que devolver.
+pub fn phys_to_virt(phys: PhysAddr) -> VirtAddr {
+    let mapper_lock = MAPPER.lock();
+    let mapper = mapper_lock.as_ref().expect("Mapper no inicializado");
+    mapper.phys_offset() + phys.as_u64()
+}
+
+/// Mapea `size` bytes de registros de un dispositivo en `phys` a una
+/// dirección virtual nueva, sin caché, y la devuelve.
+///
+/// El mapeo de memoria física del bootloader tiene caché habilitada y puede
+/// no cubrir los huecos de MMIO, así que los dispositivos se mapean aparte.
+pub fn map_mmio(phys: PhysAddr, size: u64) -> KernelResult<VirtAddr> {
+    let first = PhysFrame::<Size4KiB>::containing_address(phys);
+    let last = PhysFrame::<Size4KiB>::containing_address(phys + size.max(1) - 1u64);
+    let frames = PhysFrame::range_inclusive(first, last);
+
+    let mut mapper_lock = MAPPER.lock();
+    let mut frame_allocator_lock = FRAME_ALLOCATOR.lock();
+    let mut next = MMIO_NEXT.lock();
+
+    let mapper = mapper_lock.as_mut().ok_or(KernelError::NotInitialized)?;
+    let frame_allocator = frame_allocator_lock.as_mut().ok_or(KernelError::NotInitialized)?;
+
+    let pages = frames.count() as u64;
+    if *next + pages * 4096 > MMIO_START + MMIO_SIZE {
+        // Ventana MMIO agotada.
+        return Err(KernelError::OutOfMemory);
+    }
+    let base = VirtAddr::new(*next);
+    *next += pages * 4096;
+
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE;
+    for (i, frame) in frames.enumerate() {
+        let page = Page::containing_address(base + i as u64 * 4096);
+        unsafe {
+            mapper.map_to(page, frame, flags, frame_allocator)?.flush();
+        }
+    }
+
+    Ok(base + (phys.as_u64() - first.start_address().as_u64()))
+}
+
+/// Para diagnósticos: indica si alguien tiene tomado el mapper.
+pub fn is_locked() -> bool {
+    MAPPER.is_locked()
+}
+
+/// Indica si la página que contiene `addr` está mapeada.
+///
+/// Con el mapper tomado devuelve `WouldBlock` en vez de esperarlo; así puede
+/// usarse desde handlers de excepción sin riesgo de deadlock.
+pub fn is_mapped(addr: VirtAddr) -> KernelResult<bool> {
+    use x86_64::structures::paging::Translate;
+
+    let mapper_lock = MAPPER.try_lock().ok_or(KernelError::WouldBlock)?;
+    let mapper = mapper_lock.as_ref().ok_or(KernelError::NotInitialized)?;
+    Ok(mapper.translate_addr(addr).is_some())
+}
+
 unsafe fn active_level_4_table(physical_memory_offset: VirtAddr)
     -> &'static mut PageTable
 {
@@ -106,13 +344,13 @@ unsafe fn active_level_4_table(physical_memory_offset: VirtAddr)
 }
 
 pub unsafe fn translate_addr(addr: VirtAddr, physical_memory_offset: VirtAddr)
-    -> Option<PhysAddr>
+    -> KernelResult<PhysAddr>
 {
     translate_addr_inner(addr, physical_memory_offset)
 }
 
 fn translate_addr_inner(addr: VirtAddr, physical_memory_offset: VirtAddr)
-    -> Option<PhysAddr>
+    -> KernelResult<PhysAddr>
 {
     use x86_64::structures::paging::page_table::FrameError;
     use x86_64::registers::control::Cr3;
@@ -132,29 +370,26 @@ fn translate_addr_inner(addr: VirtAddr, physical_memory_offset: VirtAddr)
         let entry = &table[index];
         frame = match entry.frame() {
             Ok(frame) => frame,
-            Err(FrameError::FrameNotPresent) => return None,
-            Err(FrameError::HugeFrame) => panic!("páginas grandes no soportadas"),
+            Err(FrameError::FrameNotPresent) => return Err(KernelError::NotMapped),
+            // Páginas grandes.
+            Err(FrameError::HugeFrame) => return Err(KernelError::Unsupported),
         };
     }
 
-    Some(frame.start_address() + u64::from(addr.page_offset()))
+    Ok(frame.start_address() + u64::from(addr.page_offset()))
 }
 
-pub fn create_example_mapping(page: Page) {
+pub fn create_example_mapping(page: Page) -> KernelResult<()> {
+    use x86_64::structures::paging::PageTableFlags as Flags;
+
     let mut mapper_lock = MAPPER.lock();
     let mut frame_allocator_lock = FRAME_ALLOCATOR.lock();
-    
-    if let (Some(mapper), Some(frame_allocator)) = (mapper_lock.as_mut(), frame_allocator_lock.as_mut()) {
-         use x86_64::structures::paging::PageTableFlags as Flags;
+    let mapper = mapper_lock.as_mut().ok_or(KernelError::NotInitialized)?;
+    let frame_allocator = frame_allocator_lock.as_mut().ok_or(KernelError::NotInitialized)?;
 
-        let frame = PhysFrame::containing_address(PhysAddr::new(0xb8000));
-        let flags = Flags::PRESENT | Flags::WRITABLE;
+    let frame = PhysFrame::containing_address(PhysAddr::new(0xb8000));
+    let flags = Flags::PRESENT | Flags::WRITABLE;
 
-        let map_to_result = unsafe {
-            mapper.map_to(page, frame, flags, frame_allocator)
-        };
-        map_to_result.expect("map_to falló").flush();
-    } else {
-        panic!("create_example_mapping llamado antes de memory::init");
-    }
+    unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
+    Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,172 @@
+//! Registro de contadores del kernel.
+//!
+//! Los contadores de los caminos calientes (el allocator, las IRQs, el
+//! executor) se declaran con `register_counter!`: quedan en un `static` del
+//! módulo que los usa y en la sección `kur_metrics` del binario, como los
+//! drivers en `kur_drivers`, así que incrementarlos es un solo atómico, sin
+//! lock ni búsqueda por nombre. Un nombre se registra una sola vez.
+//!
+//! Para lo que no está en un camino caliente, `counter("slab.alloc")`
+//! devuelve el contador con ese nombre, registrado o creado la primera vez
+//! en un pool estático. Ninguno depende del heap, así que el allocator
+//! también puede usarlos. `dump` imprime todos. Los valores sólo crecen:
+//! para algo que sube y baja, como las tareas vivas, alcanza con un atómico
+//! propio.
+
+use core::ops::Deref;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+const MAX_COUNTERS: usize = 64;
+
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    const fn new() -> Self {
+        Counter(AtomicU64::new(0))
+    }
+
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Sube el contador a `value` si es mayor; sirve para registrar picos.
+    pub fn record_max(&self, value: u64) {
+        self.0.fetch_max(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Un contador declarado con `register_counter!`.
+pub struct Registered {
+    name: &'static str,
+    counter: Counter,
+}
+
+impl Registered {
+    pub const fn new(name: &'static str) -> Self {
+        Registered { name, counter: Counter::new() }
+    }
+}
+
+impl Deref for Registered {
+    type Target = Counter;
+
+    fn deref(&self) -> &Counter {
+        &self.counter
+    }
+}
+
+/// Declara un contador con nombre fijo en la sección `kur_metrics`.
+///
+/// ```ignore
+/// crate::register_counter!(HEAP_ALLOC, "heap.alloc");
+///
+/// HEAP_ALLOC.inc();
+/// ```
+#[macro_export]
+macro_rules! register_counter {
+    ($vis:vis $ident:ident, $name:literal) => {
+        #[used]
+        #[unsafe(link_section = "kur_metrics")]
+        $vis static $ident: $crate::metrics::Registered = $crate::metrics::Registered::new($name);
+    };
+}
+
+unsafe extern "C" {
+    static __start_kur_metrics: u8;
+    static __stop_kur_metrics: u8;
+}
+
+fn registered() -> &'static [Registered] {
+    unsafe {
+        let start = (&raw const __start_kur_metrics).cast::<Registered>();
+        let stop = (&raw const __stop_kur_metrics).cast::<Registered>();
+        let len = stop.offset_from(start) as usize;
+        core::slice::from_raw_parts(start, len)
+    }
+}
+
+static NAMES: Mutex<[Option<&'static str>; MAX_COUNTERS]> = Mutex::new([None; MAX_COUNTERS]);
+static COUNTERS: [Counter; MAX_COUNTERS] = [const { Counter::new() }; MAX_COUNTERS];
+
+/// Contador registrado con `name`. Busca con un lock: en call sites
+/// calientes va `register_counter!`.
+pub fn counter(name: &'static str) -> &'static Counter {
+    if let Some(registered) = registered().iter().find(|registered| registered.name == name) {
+        return registered;
+    }
+    interrupts::without_interrupts(|| {
+        let mut names = NAMES.lock();
+        let index = match names.iter().position(|n| *n == Some(name)) {
+            Some(index) => index,
+            None => {
+                let index = names
+                    .iter()
+                    .position(|n| n.is_none())
+                    .expect("pool de métricas lleno");
+                names[index] = Some(name);
+                index
+            }
+        };
+        &COUNTERS[index]
+    })
+}
+
+/// Recorre los contadores: primero los de `register_counter!`, en el orden
+/// en que los dejó el linker, y después los del pool en orden de creación.
+pub fn for_each(mut f: impl FnMut(&'static str, u64)) {
+    for registered in registered() {
+        f(registered.name, registered.get());
+    }
+    let names = interrupts::without_interrupts(|| *NAMES.lock());
+    for (index, name) in names.iter().enumerate() {
+        if let Some(name) = name {
+            f(name, COUNTERS[index].get());
+        }
+    }
+}
+
+pub fn dump() {
+    for_each(|name, value| crate::println!("{:<24} {}", name, value));
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_counter_is_shared_by_name() {
+    let a = counter("test.metrics");
+    a.add(3);
+    counter("test.metrics").inc();
+    assert_eq!(a.get(), 4);
+
+    let mut seen = 0;
+    for_each(|name, value| {
+        if name == "test.metrics" {
+            seen = value;
+        }
+    });
+    assert_eq!(seen, 4);
+}
+
+#[cfg(test)]
+crate::register_counter!(TEST_REGISTERED, "test.metrics.registered");
+
+#[test_case]
+fn test_registered_counter_is_found_by_name() {
+    TEST_REGISTERED.add(2);
+    assert!(core::ptr::eq(counter("test.metrics.registered"), &*TEST_REGISTERED));
+    assert_eq!(counter("test.metrics.registered").get(), 2);
+
+    let mut seen = 0;
+    for_each(|name, _| seen += (name == "test.metrics.registered") as u32);
+    assert_eq!(seen, 1);
+}
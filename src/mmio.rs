@@ -0,0 +1,200 @@
+//! Registros mapeados en memoria.
+//!
+//! Cada dispositivo declara sus registros como constantes `Register` (offset,
+//! ancho y permisos) y sus campos como `Field`. Todo acceso pasa por
+//! `MmioRegion`, que valida que el registro caiga dentro de la región y usa
+//! lecturas/escrituras volátiles. Como la región es sólo una dirección y un
+//! largo, los tests pueden apuntarla a un buffer común en vez de a hardware.
+
+use core::marker::PhantomData;
+use core::ops::{BitAnd, BitOr, Not, Shl, Shr};
+use x86_64::VirtAddr;
+
+/// Anchos de registro soportados.
+pub trait Width:
+    Copy
+    + Eq
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + Not<Output = Self>
+    + Shl<u32, Output = Self>
+    + Shr<u32, Output = Self>
+{
+    const BITS: u32;
+    const ZERO: Self;
+    const ONES: Self;
+}
+
+macro_rules! impl_width {
+    ($($t:ty),*) => {$(
+        impl Width for $t {
+            const BITS: u32 = <$t>::BITS;
+            const ZERO: Self = 0;
+            const ONES: Self = <$t>::MAX;
+        }
+    )*};
+}
+
+impl_width!(u8, u16, u32, u64);
+
+pub trait Readable {}
+pub trait Writable {}
+
+/// Marcadores de permisos de un registro.
+#[derive(Debug, Clone, Copy)]
+pub struct ReadOnly;
+#[derive(Debug, Clone, Copy)]
+pub struct WriteOnly;
+#[derive(Debug, Clone, Copy)]
+pub struct ReadWrite;
+
+impl Readable for ReadOnly {}
+impl Readable for ReadWrite {}
+impl Writable for WriteOnly {}
+impl Writable for ReadWrite {}
+
+/// Registro de ancho `T` en `offset` bytes desde el comienzo de la región.
+#[derive(Debug, Clone, Copy)]
+pub struct Register<T: Width, A> {
+    offset: usize,
+    _marker: PhantomData<(T, A)>,
+}
+
+impl<T: Width, A> Register<T, A> {
+    pub const fn new(offset: usize) -> Self {
+        Register {
+            offset,
+            _marker: PhantomData,
+        }
+    }
+
+    pub const fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+/// Campo de `width` bits a partir del bit `shift` dentro de un registro.
+#[derive(Debug, Clone, Copy)]
+pub struct Field<T: Width> {
+    shift: u32,
+    width: u32,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Width> Field<T> {
+    pub const fn new(shift: u32, width: u32) -> Self {
+        assert!(width > 0 && shift + width <= T::BITS, "campo fuera del registro");
+        Field {
+            shift,
+            width,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Campo de un solo bit.
+    pub const fn bit(shift: u32) -> Self {
+        Self::new(shift, 1)
+    }
+
+    fn mask(&self) -> T {
+        (T::ONES >> (T::BITS - self.width)) << self.shift
+    }
+
+    pub fn get(&self, value: T) -> T {
+        (value & self.mask()) >> self.shift
+    }
+
+    /// Devuelve `value` con el campo reemplazado por `field` (truncado al ancho).
+    pub fn set(&self, value: T, field: T) -> T {
+        (value & !self.mask()) | ((field << self.shift) & self.mask())
+    }
+
+    pub fn is_set(&self, value: T) -> bool {
+        self.get(value) != T::ZERO
+    }
+}
+
+/// Región MMIO de un dispositivo, ya mapeada en el espacio virtual.
+#[derive(Debug)]
+pub struct MmioRegion {
+    base: VirtAddr,
+    len: usize,
+}
+
+impl MmioRegion {
+    /// # Safety
+    ///
+    /// `[base, base + len)` tiene que estar mapeado (sin caché, si es hardware)
+    /// y no ser accedido por otro `MmioRegion` ni referencias de Rust.
+    pub const unsafe fn new(base: VirtAddr, len: usize) -> Self {
+        MmioRegion { base, len }
+    }
+
+    pub fn base(&self) -> VirtAddr {
+        self.base
+    }
+
+    fn pointer<T: Width, A>(&self, reg: Register<T, A>) -> *mut T {
+        let size = core::mem::size_of::<T>();
+        assert!(
+            reg.offset + size <= self.len,
+            "registro {:#x} fuera de la región MMIO ({:#x} bytes)",
+            reg.offset, self.len
+        );
+        assert!(reg.offset.is_multiple_of(size), "registro {:#x} desalineado", reg.offset);
+        (self.base + reg.offset as u64).as_mut_ptr()
+    }
+
+    pub fn read<T: Width, A: Readable>(&self, reg: Register<T, A>) -> T {
+        unsafe { self.pointer(reg).read_volatile() }
+    }
+
+    pub fn write<T: Width, A: Writable>(&self, reg: Register<T, A>, value: T) {
+        unsafe { self.pointer(reg).write_volatile(value) }
+    }
+
+    /// Lectura-modificación-escritura. No es atómica respecto del dispositivo.
+    pub fn modify<T: Width>(&self, reg: Register<T, ReadWrite>, f: impl FnOnce(T) -> T) {
+        let value = self.read(reg);
+        self.write(reg, f(value));
+    }
+
+    pub fn read_field<T: Width, A: Readable>(&self, reg: Register<T, A>, field: Field<T>) -> T {
+        field.get(self.read(reg))
+    }
+
+    pub fn write_field<T: Width>(&self, reg: Register<T, ReadWrite>, field: Field<T>, value: T) {
+        self.modify(reg, |old| field.set(old, value));
+    }
+}
+
+// ----------------- TESTS -----------------
+
+#[cfg(test)]
+const TEST_ID: Register<u32, ReadOnly> = Register::new(0x0);
+#[cfg(test)]
+const TEST_CTRL: Register<u32, ReadWrite> = Register::new(0x4);
+#[cfg(test)]
+const TEST_CTRL_MODE: Field<u32> = Field::new(4, 3);
+#[cfg(test)]
+const TEST_CTRL_ENABLE: Field<u32> = Field::bit(0);
+
+#[test_case]
+fn test_field_get_set() {
+    assert_eq!(TEST_CTRL_MODE.get(0b1101_0000), 0b101);
+    assert_eq!(TEST_CTRL_MODE.set(0xffff_ffff, 0), 0xffff_ff8f);
+    assert_eq!(TEST_CTRL_MODE.set(0, 0xff), 0b111_0000);
+    assert!(TEST_CTRL_ENABLE.is_set(1));
+}
+
+#[test_case]
+fn test_region_against_mock_memory() {
+    let mut mock = [0x1234_5678u32, 0];
+    let region = unsafe { MmioRegion::new(VirtAddr::from_ptr(mock.as_mut_ptr()), 8) };
+
+    assert_eq!(region.read(TEST_ID), 0x1234_5678);
+    region.write_field(TEST_CTRL, TEST_CTRL_MODE, 0b011);
+    region.write_field(TEST_CTRL, TEST_CTRL_ENABLE, 1);
+    assert_eq!(region.read(TEST_CTRL), 0b011_0001);
+    assert_eq!(mock[1], 0b011_0001);
+}
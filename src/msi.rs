@@ -0,0 +1,145 @@
+//! Interrupciones por mensaje (MSI y MSI-X) de dispositivos PCI.
+//!
+//! En vez de levantar una línea IRQ, el dispositivo escribe `data` en la
+//! dirección `address`, que cae en la ventana de 0xFEE0_0000 del APIC local.
+//! La dirección elige el APIC destino y el dato lleva el vector, así que no
+//! pasan ni por el PIC ni por el IO-APIC.
+//!
+//! Los vectores se piden con `interrupts::allocate_vector`; su EOI va al APIC
+//! local, así que todo esto requiere `apic::init`.
+
+use x86_64::PhysAddr;
+
+use crate::error::KernelError;
+use crate::mmio::{MmioRegion, ReadWrite, Register};
+use crate::pci::{PciAddress, COMMAND};
+
+const CAP_MSI: u8 = 0x05;
+const CAP_MSIX: u8 = 0x11;
+
+const MSI_ENABLE: u16 = 1 << 0;
+const MSI_MULTIPLE_ENABLE: u16 = 0b111 << 4;
+const MSI_64BIT: u16 = 1 << 7;
+
+const MSIX_FUNCTION_MASK: u16 = 1 << 14;
+const MSIX_ENABLE: u16 = 1 << 15;
+const MSIX_TABLE_SIZE: u16 = 0x7FF;
+
+/// Bit de COMMAND que deshabilita la línea INTx legada.
+const COMMAND_INTX_DISABLE: u16 = 1 << 10;
+
+const MSIX_ENTRY_LEN: usize = 16;
+const ENTRY_ADDRESS_LOW: Register<u32, ReadWrite> = Register::new(0x0);
+const ENTRY_ADDRESS_HIGH: Register<u32, ReadWrite> = Register::new(0x4);
+const ENTRY_DATA: Register<u32, ReadWrite> = Register::new(0x8);
+const ENTRY_VECTOR_CONTROL: Register<u32, ReadWrite> = Register::new(0xC);
+const ENTRY_MASKED: u32 = 1 << 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MsiError {
+    /// El dispositivo no tiene la capability pedida.
+    NotSupported,
+    /// El índice no está en la tabla MSI-X del dispositivo.
+    NoSuchEntry(u16),
+    /// El BAR de la tabla MSI-X no es de memoria.
+    BadBar(u8),
+    /// No se pudieron mapear sus registros.
+    Map(KernelError),
+}
+
+/// Mensaje con entrega fija, por flanco y destino físico.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Message {
+    pub address: u64,
+    pub data: u32,
+}
+
+impl Message {
+    pub fn new(vector: u8, destination: u8) -> Message {
+        Message {
+            address: 0xFEE0_0000 | u64::from(destination) << 12,
+            data: u32::from(vector),
+        }
+    }
+}
+
+/// Programa la capability MSI de `device` para que entregue `vector` al APIC
+/// `destination`, y apaga su INTx. Se usa un solo mensaje.
+pub fn enable(device: PciAddress, vector: u8, destination: u8) -> Result<(), MsiError> {
+    let cap = device.find_capability(CAP_MSI).ok_or(MsiError::NotSupported)?;
+    let message = Message::new(vector, destination);
+    let control = device.read_u16(cap + 2);
+
+    device.write_u32(cap + 4, message.address as u32);
+    if control & MSI_64BIT != 0 {
+        device.write_u32(cap + 8, (message.address >> 32) as u32);
+        device.write_u16(cap + 0xC, message.data as u16);
+    } else {
+        device.write_u16(cap + 8, message.data as u16);
+    }
+
+    device.write_u16(cap + 2, (control & !MSI_MULTIPLE_ENABLE) | MSI_ENABLE);
+    disable_intx(device);
+    Ok(())
+}
+
+pub fn disable(device: PciAddress) -> Result<(), MsiError> {
+    let cap = device.find_capability(CAP_MSI).ok_or(MsiError::NotSupported)?;
+    device.write_u16(cap + 2, device.read_u16(cap + 2) & !MSI_ENABLE);
+    Ok(())
+}
+
+/// Programa la entrada `entry` de la tabla MSI-X de `device` y la desenmascara.
+///
+/// La tabla vive en uno de los BARs del dispositivo y se mapea cada vez; la
+/// ventana MMIO no se recupera, así que conviene llamarla pocas veces.
+pub fn enable_msix(
+    device: PciAddress,
+    entry: u16,
+    vector: u8,
+    destination: u8,
+) -> Result<(), MsiError> {
+    let cap = device.find_capability(CAP_MSIX).ok_or(MsiError::NotSupported)?;
+    let control = device.read_u16(cap + 2);
+    let entries = (control & MSIX_TABLE_SIZE) + 1;
+    if entry >= entries {
+        return Err(MsiError::NoSuchEntry(entry));
+    }
+
+    // Los 3 bits bajos eligen el BAR; el resto es el offset dentro de él.
+    let table = device.read_u32(cap + 4);
+    let bir = (table & 0b111) as u8;
+    let bar = device.memory_bar(bir).ok_or(MsiError::BadBar(bir))?;
+    let phys = PhysAddr::new(bar + u64::from(table & !0b111));
+    let len = usize::from(entries) * MSIX_ENTRY_LEN;
+    let virt = crate::memory::map_mmio(phys, len as u64).map_err(MsiError::Map)?;
+
+    // Función enmascarada mientras se escriben las entradas.
+    device.write_u16(cap + 2, control | MSIX_ENABLE | MSIX_FUNCTION_MASK);
+
+    let offset = usize::from(entry) * MSIX_ENTRY_LEN;
+    let regs = unsafe { MmioRegion::new(virt + offset as u64, MSIX_ENTRY_LEN) };
+    let message = Message::new(vector, destination);
+    regs.write(ENTRY_VECTOR_CONTROL, ENTRY_MASKED);
+    regs.write(ENTRY_ADDRESS_LOW, message.address as u32);
+    regs.write(ENTRY_ADDRESS_HIGH, (message.address >> 32) as u32);
+    regs.write(ENTRY_DATA, message.data);
+    regs.write(ENTRY_VECTOR_CONTROL, 0);
+
+    device.write_u16(cap + 2, (control | MSIX_ENABLE) & !MSIX_FUNCTION_MASK);
+    disable_intx(device);
+    Ok(())
+}
+
+fn disable_intx(device: PciAddress) {
+    device.write_u16(COMMAND, device.read_u16(COMMAND) | COMMAND_INTX_DISABLE);
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_message_encoding() {
+    let message = Message::new(0x51, 3);
+    assert_eq!(message.address, 0xFEE0_3000);
+    assert_eq!(message.data, 0x51);
+}
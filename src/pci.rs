@@ -0,0 +1,251 @@
+//! Acceso al espacio de configuración PCI por puertos (mecanismo #1).
+//!
+//! Se escribe la dirección (bus, dispositivo, función, registro) en
+//! CONFIG_ADDRESS (0xCF8) y se lee o escribe el valor en CONFIG_DATA (0xCFC).
+//! Alcanza para enumerar el bus y recorrer la lista de capabilities; MSI y
+//! MSI-X se programan a partir de ahí en `msi`. Al inicializarse, el driver
+//! agrega cada función que encuentra al inventario de `device`.
+
+use conquer_once::spin::OnceCell;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::device::{self, Bus, Device, Resource};
+use crate::driver::{Driver, Stage};
+use crate::portio::{self, PortRegion};
+
+const CONFIG_ADDRESS: u16 = 0;
+const CONFIG_DATA: u16 = 4;
+
+pub const VENDOR_ID: u8 = 0x00;
+pub const DEVICE_ID: u8 = 0x02;
+pub const COMMAND: u8 = 0x04;
+pub const STATUS: u8 = 0x06;
+pub const CLASS: u8 = 0x08;
+pub const HEADER_TYPE: u8 = 0x0E;
+pub const BAR0: u8 = 0x10;
+pub const CAPABILITIES: u8 = 0x34;
+pub const INTERRUPT_LINE: u8 = 0x3C;
+
+const STATUS_CAPABILITIES: u16 = 1 << 4;
+const HEADER_MULTIFUNCTION: u8 = 1 << 7;
+
+static PORTS: OnceCell<Mutex<PortRegion>> = OnceCell::uninit();
+
+fn init() {
+    let ports = portio::claim("pci", 0xCF8, 8).expect("puertos de configuración PCI ocupados");
+    PORTS.init_once(|| Mutex::new(ports));
+    for_each_function(|address| device::add(address.device_info()));
+}
+
+crate::register_driver!(PCI_DRIVER, Driver {
+    name: "pci",
+    stage: Stage::Early,
+    depends_on: &[],
+    device: None,
+    probe: Driver::always,
+    init,
+});
+
+/// Función de un dispositivo en el bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PciAddress {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl PciAddress {
+    pub const fn new(bus: u8, device: u8, function: u8) -> Self {
+        PciAddress { bus, device, function }
+    }
+
+    fn config_address(&self, offset: u8) -> u32 {
+        1 << 31
+            | u32::from(self.bus) << 16
+            | u32::from(self.device & 0x1F) << 11
+            | u32::from(self.function & 0x7) << 8
+            | u32::from(offset & 0xFC)
+    }
+
+    pub fn read_u32(&self, offset: u8) -> u32 {
+        let ports = PORTS.try_get().expect("PCI no inicializado");
+        interrupts::without_interrupts(|| {
+            let ports = ports.lock();
+            unsafe {
+                ports.port::<u32>(CONFIG_ADDRESS).write(self.config_address(offset));
+                ports.port::<u32>(CONFIG_DATA).read()
+            }
+        })
+    }
+
+    pub fn write_u32(&self, offset: u8, value: u32) {
+        let ports = PORTS.try_get().expect("PCI no inicializado");
+        interrupts::without_interrupts(|| {
+            let ports = ports.lock();
+            unsafe {
+                ports.port::<u32>(CONFIG_ADDRESS).write(self.config_address(offset));
+                ports.port::<u32>(CONFIG_DATA).write(value);
+            }
+        })
+    }
+
+    pub fn read_u16(&self, offset: u8) -> u16 {
+        (self.read_u32(offset) >> ((offset & 2) * 8)) as u16
+    }
+
+    pub fn write_u16(&self, offset: u8, value: u16) {
+        let shift = (offset & 2) * 8;
+        let old = self.read_u32(offset);
+        let new = (old & !(0xFFFF << shift)) | u32::from(value) << shift;
+        self.write_u32(offset, new);
+    }
+
+    pub fn read_u8(&self, offset: u8) -> u8 {
+        (self.read_u32(offset) >> ((offset & 3) * 8)) as u8
+    }
+
+    pub fn vendor_id(&self) -> u16 {
+        self.read_u16(VENDOR_ID)
+    }
+
+    pub fn device_id(&self) -> u16 {
+        self.read_u16(DEVICE_ID)
+    }
+
+    /// Clase, subclase y prog-if.
+    pub fn class(&self) -> (u8, u8, u8) {
+        let value = self.read_u32(CLASS);
+        ((value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8)
+    }
+
+    /// La función como entrada del inventario, con la IRQ que le asignó el
+    /// firmware si tiene una.
+    pub fn device_info(&self) -> Device {
+        let (class, subclass, _) = self.class();
+        let (id, name) = class_name(class, subclass);
+        let device = Device::new(Bus::Pci, id, name);
+        match self.read_u8(INTERRUPT_LINE) {
+            0 | 0xFF => device,
+            irq => device.with(Resource::Irq(irq)),
+        }
+    }
+
+    pub fn exists(&self) -> bool {
+        self.vendor_id() != 0xFFFF
+    }
+
+    /// Offset de la primera capability con el ID dado.
+    pub fn find_capability(&self, id: u8) -> Option<u8> {
+        if self.read_u16(STATUS) & STATUS_CAPABILITIES == 0 {
+            return None;
+        }
+        let mut offset = self.read_u8(CAPABILITIES) & 0xFC;
+        // La lista podría estar corrupta; no puede haber más de 48 entradas.
+        for _ in 0..48 {
+            if offset == 0 {
+                return None;
+            }
+            if self.read_u8(offset) == id {
+                return Some(offset);
+            }
+            offset = self.read_u8(offset + 1) & 0xFC;
+        }
+        None
+    }
+
+    /// Dirección física de un BAR de memoria, o `None` si es de E/S o no está.
+    pub fn memory_bar(&self, index: u8) -> Option<u64> {
+        let offset = BAR0 + 4 * index;
+        let low = self.read_u32(offset);
+        if low & 1 != 0 {
+            return None;
+        }
+        let high = match (low >> 1) & 0b11 {
+            0b10 => u64::from(self.read_u32(offset + 4)) << 32,
+            _ => 0,
+        };
+        let base = high | u64::from(low & !0xF);
+        (base != 0).then_some(base)
+    }
+}
+
+/// Recorre todas las funciones presentes en el bus, por fuerza bruta.
+pub fn for_each_function(mut f: impl FnMut(PciAddress)) {
+    for bus in 0..=255u8 {
+        for device in 0..32u8 {
+            let first = PciAddress::new(bus, device, 0);
+            if !first.exists() {
+                continue;
+            }
+            f(first);
+
+            if first.read_u8(HEADER_TYPE) & HEADER_MULTIFUNCTION != 0 {
+                for function in 1..8u8 {
+                    let address = PciAddress::new(bus, device, function);
+                    if address.exists() {
+                        f(address);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// ID y nombre de una clase y subclase PCI. El ID sigue la forma de los PNP
+/// (`PCI` y el código de clase) para que un driver pueda asociarse con `bind`.
+fn class_name(class: u8, subclass: u8) -> (&'static str, &'static str) {
+    match (class, subclass) {
+        (0x01, 0x01) => ("PCI0101", "controlador IDE"),
+        (0x01, 0x06) => ("PCI0106", "controlador SATA"),
+        (0x01, _) => ("PCI01", "controlador de almacenamiento"),
+        (0x02, 0x00) => ("PCI0200", "controlador Ethernet"),
+        (0x02, _) => ("PCI02", "controlador de red"),
+        (0x03, 0x00) => ("PCI0300", "controlador VGA"),
+        (0x03, _) => ("PCI03", "controlador de video"),
+        (0x04, _) => ("PCI04", "dispositivo multimedia"),
+        (0x06, 0x00) => ("PCI0600", "puente host"),
+        (0x06, 0x01) => ("PCI0601", "puente ISA"),
+        (0x06, 0x04) => ("PCI0604", "puente PCI-PCI"),
+        (0x06, _) => ("PCI06", "puente"),
+        (0x0C, 0x03) => ("PCI0C03", "controlador USB"),
+        (0x0C, 0x05) => ("PCI0C05", "controlador SMBus"),
+        (0x0C, _) => ("PCI0C", "controlador de bus serie"),
+        _ => ("PCI", "función PCI"),
+    }
+}
+
+/// Listado estilo `lspci`.
+pub fn print_functions() {
+    for_each_function(|address| {
+        let (class, subclass, _) = address.class();
+        crate::println!(
+            "{:02x}:{:02x}.{} {:04x}:{:04x} clase {:02x}{:02x}",
+            address.bus,
+            address.device,
+            address.function,
+            address.vendor_id(),
+            address.device_id(),
+            class,
+            subclass
+        );
+    });
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_host_bridge_is_present() {
+    // QEMU siempre pone el host bridge en 00:00.0 (clase 06, subclase 00).
+    let host = PciAddress::new(0, 0, 0);
+    assert!(host.exists());
+    let (class, subclass, _) = host.class();
+    assert_eq!((class, subclass), (0x06, 0x00));
+}
+
+#[test_case]
+fn test_functions_are_in_the_inventory() {
+    let mut host_bridges = 0;
+    device::for_each(|d| host_bridges += usize::from(d.bus == Bus::Pci && d.id == "PCI0600"));
+    assert_eq!(host_bridges, 1);
+}
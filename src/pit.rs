@@ -0,0 +1,156 @@
+//! PIT 8253/8254 (puertos 0x40-0x43 y el gate en 0x61).
+//!
+//! El canal 0 está cableado a IRQ0 y es el timer del kernel: `set_frequency`
+//! lo reprograma y `time` convierte ticks con la frecuencia vigente. El canal
+//! 2 no interrumpe; su salida se lee en el bit 5 del puerto 0x61, y se usa
+//! como cuenta regresiva de una sola vez (`wait_one_shot`) para calibrar el
+//! timer del APIC y el TSC, porque la frecuencia base del PIT es fija.
+
+use conquer_once::spin::OnceCell;
+use core::sync::atomic::{AtomicU32, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::driver::{Driver, Stage};
+use crate::portio::{self, PortRegion};
+
+/// Frecuencia de entrada de los tres canales.
+pub const BASE_HZ: u64 = 1_193_182;
+/// Cuenta más larga que entra en 16 bits (0 significa 65536).
+const MAX_DIVISOR: u32 = 65536;
+
+const CHANNEL0: u16 = 0;
+const CHANNEL2: u16 = 2;
+const COMMAND: u16 = 3;
+
+// Comando: canal en los bits 6-7, acceso byte bajo y alto (0b11 en 4-5), modo en 1-3.
+const SELECT_CHANNEL0: u8 = 0b00 << 6;
+const SELECT_CHANNEL2: u8 = 0b10 << 6;
+const ACCESS_LOW_HIGH: u8 = 0b11 << 4;
+const MODE_TERMINAL_COUNT: u8 = 0 << 1;
+const MODE_RATE_GENERATOR: u8 = 2 << 1;
+
+// Puerto 0x61: gate del canal 2, parlante y salida del canal 2.
+const GATE2: u8 = 1 << 0;
+const SPEAKER: u8 = 1 << 1;
+const OUT2: u8 = 1 << 5;
+
+struct Ports {
+    pit: PortRegion,
+    control: PortRegion,
+}
+
+static PORTS: OnceCell<Mutex<Ports>> = OnceCell::uninit();
+
+/// Divisor del canal 0. El firmware lo deja en 65536 (~18.2 Hz).
+static DIVISOR: AtomicU32 = AtomicU32::new(MAX_DIVISOR);
+
+fn init() {
+    let ports = Ports {
+        pit: portio::claim("pit", 0x40, 4).expect("puertos del PIT ocupados"),
+        control: portio::claim("pit", 0x61, 1).expect("puerto 0x61 ocupado"),
+    };
+    PORTS.init_once(|| Mutex::new(ports));
+}
+
+crate::register_driver!(PIT_DRIVER, Driver {
+    name: "pit",
+    stage: Stage::Early,
+    depends_on: &[],
+    device: Some("PNP0100"),
+    probe: Driver::always,
+    init,
+});
+
+fn with_ports<R>(f: impl FnOnce(&Ports) -> R) -> R {
+    let ports = PORTS.try_get().expect("PIT no inicializado");
+    interrupts::without_interrupts(|| f(&ports.lock()))
+}
+
+fn divisor_for(hz: u32) -> u32 {
+    assert!(hz > 0, "frecuencia nula");
+    ((BASE_HZ / u64::from(hz)) as u32).clamp(1, MAX_DIVISOR)
+}
+
+/// Programa el canal 0 para interrumpir `hz` veces por segundo. Devuelve la
+/// frecuencia que quedó, que difiere por el redondeo del divisor.
+pub fn set_frequency(hz: u32) -> u32 {
+    let divisor = divisor_for(hz);
+    with_ports(|ports| unsafe {
+        ports
+            .pit
+            .write_only::<u8>(COMMAND)
+            .write(SELECT_CHANNEL0 | ACCESS_LOW_HIGH | MODE_RATE_GENERATOR);
+        let mut channel0 = ports.pit.port::<u8>(CHANNEL0);
+        // 65536 no entra en 16 bits y se escribe como 0.
+        channel0.write(divisor as u8);
+        channel0.write((divisor >> 8) as u8);
+    });
+    DIVISOR.store(divisor, Ordering::Relaxed);
+    frequency()
+}
+
+/// Divisor vigente del canal 0.
+pub fn divisor() -> u32 {
+    DIVISOR.load(Ordering::Relaxed)
+}
+
+/// Frecuencia vigente del canal 0, redondeada a Hz.
+pub fn frequency() -> u32 {
+    (BASE_HZ / u64::from(divisor())) as u32
+}
+
+/// Arranca una cuenta regresiva de `micros` microsegundos en el canal 2.
+/// El máximo es de ~54.9 ms; `one_shot_expired` avisa cuando llega a 0.
+pub fn start_one_shot(micros: u32) {
+    let count = u64::from(micros) * BASE_HZ / 1_000_000;
+    assert!(count < u64::from(MAX_DIVISOR), "cuenta de {} µs demasiado larga", micros);
+    let count = count.max(1) as u16;
+
+    with_ports(|ports| unsafe {
+        let mut control = ports.control.port::<u8>(0);
+        // Gate en bajo y parlante apagado mientras se programa.
+        let saved = control.read();
+        control.write(saved & !(GATE2 | SPEAKER));
+
+        ports
+            .pit
+            .write_only::<u8>(COMMAND)
+            .write(SELECT_CHANNEL2 | ACCESS_LOW_HIGH | MODE_TERMINAL_COUNT);
+        let mut channel2 = ports.pit.port::<u8>(CHANNEL2);
+        channel2.write(count as u8);
+        channel2.write((count >> 8) as u8);
+
+        // Subir el gate arranca la cuenta; OUT2 se prende al llegar a 0.
+        control.write((saved & !SPEAKER) | GATE2);
+    });
+}
+
+pub fn one_shot_expired() -> bool {
+    with_ports(|ports| unsafe { ports.control.port::<u8>(0).read() & OUT2 != 0 })
+}
+
+/// Espera activamente `micros` microsegundos medidos por el canal 2.
+pub fn wait_one_shot(micros: u32) {
+    start_one_shot(micros);
+    while !one_shot_expired() {
+        core::hint::spin_loop();
+    }
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_divisor_for() {
+    assert_eq!(divisor_for(1000), 1193);
+    assert_eq!(divisor_for(1), MAX_DIVISOR);
+    assert_eq!(divisor_for(2_000_000), 1);
+}
+
+#[test_case]
+fn test_one_shot_expires() {
+    start_one_shot(50_000);
+    assert!(!one_shot_expired());
+    wait_one_shot(1000);
+    assert!(one_shot_expired());
+}
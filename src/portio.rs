@@ -0,0 +1,142 @@
+//! Acceso a puertos de E/S con registro de dueños.
+//!
+//! Un driver reclama un rango con `claim` y obtiene un `PortRegion` del que
+//! saca los `Port` que usa. Si el rango se superpone con uno ya reclamado,
+//! `claim` devuelve el dueño actual en vez de dejar que dos drivers se pisen.
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
+use x86_64::structures::port::{PortRead, PortWrite};
+
+const MAX_CLAIMS: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Claim {
+    owner: &'static str,
+    start: u16,
+    len: u16,
+}
+
+impl Claim {
+    fn end(&self) -> u32 {
+        u32::from(self.start) + u32::from(self.len)
+    }
+
+    fn overlaps(&self, other: &Claim) -> bool {
+        u32::from(self.start) < other.end() && u32::from(other.start) < self.end()
+    }
+}
+
+static CLAIMS: Mutex<[Option<Claim>; MAX_CLAIMS]> = Mutex::new([None; MAX_CLAIMS]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortConflict {
+    pub owner: &'static str,
+    pub start: u16,
+    pub len: u16,
+}
+
+/// Rango de puertos reclamado por un driver.
+#[derive(Debug)]
+pub struct PortRegion {
+    owner: &'static str,
+    start: u16,
+    len: u16,
+}
+
+impl PortRegion {
+    pub fn owner(&self) -> &'static str {
+        self.owner
+    }
+
+    pub fn start(&self) -> u16 {
+        self.start
+    }
+
+    fn address(&self, offset: u16) -> u16 {
+        assert!(
+            offset < self.len,
+            "puerto {:#x}+{} fuera del rango de '{}'",
+            self.start, offset, self.owner
+        );
+        self.start + offset
+    }
+
+    pub fn port<T: PortRead + PortWrite>(&self, offset: u16) -> Port<T> {
+        Port::new(self.address(offset))
+    }
+
+    pub fn read_only<T: PortRead>(&self, offset: u16) -> PortReadOnly<T> {
+        PortReadOnly::new(self.address(offset))
+    }
+
+    pub fn write_only<T: PortWrite>(&self, offset: u16) -> PortWriteOnly<T> {
+        PortWriteOnly::new(self.address(offset))
+    }
+}
+
+/// Reclama `len` puertos a partir de `start` a nombre de `owner`.
+pub fn claim(owner: &'static str, start: u16, len: u16) -> Result<PortRegion, PortConflict> {
+    assert!(len > 0, "rango de puertos vacío");
+    let new = Claim { owner, start, len };
+
+    interrupts::without_interrupts(|| {
+        let mut claims = CLAIMS.lock();
+        if let Some(existing) = claims.iter().flatten().find(|c| c.overlaps(&new)) {
+            return Err(PortConflict {
+                owner: existing.owner,
+                start: existing.start,
+                len: existing.len,
+            });
+        }
+
+        let slot = claims
+            .iter_mut()
+            .find(|c| c.is_none())
+            .expect("tabla de puertos llena");
+        *slot = Some(new);
+        Ok(PortRegion { owner, start, len })
+    })
+}
+
+/// Devuelve el rango para que otro driver pueda reclamarlo.
+pub fn release(region: PortRegion) {
+    interrupts::without_interrupts(|| {
+        let mut claims = CLAIMS.lock();
+        for slot in claims.iter_mut() {
+            if slot.is_some_and(|c| c.start == region.start && c.owner == region.owner) {
+                *slot = None;
+            }
+        }
+    });
+}
+
+/// Listado estilo `/proc/ioports`, ordenado por puerto.
+pub fn print_ioports() {
+    let mut claims = interrupts::without_interrupts(|| *CLAIMS.lock());
+    claims.sort_unstable_by_key(|c| c.map_or(u32::MAX, |c| u32::from(c.start)));
+    for claim in claims.iter().flatten() {
+        crate::println!(
+            "{:04x}-{:04x} : {}",
+            claim.start,
+            claim.end() - 1,
+            claim.owner
+        );
+    }
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_claim_conflicts() {
+    let region = claim("test-a", 0x5000, 8).unwrap();
+    assert_eq!(
+        claim("test-b", 0x5004, 2).unwrap_err(),
+        PortConflict { owner: "test-a", start: 0x5000, len: 8 }
+    );
+    assert!(claim("test-b", 0x5008, 2).map(release).is_ok());
+
+    release(region);
+    assert!(claim("test-b", 0x5004, 2).map(release).is_ok());
+}
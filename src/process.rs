@@ -0,0 +1,605 @@
+//! Procesos de usuario.
+//!
+//! Un proceso es un espacio de direcciones propio (`AddressSpace`) más el
+//! hilo del kernel que lo corre. El hilo baja a ring 3 en el punto de entrada
+//! y vuelve al kernel en cada syscall o interrupción, sobre su propio stack:
+//! ese es el stack de kernel del proceso. El scheduler carga la tabla de
+//! nivel 4 del proceso al cambiar a su hilo, así que dos procesos pueden usar
+//! las mismas direcciones sin verse.
+//!
+//! `fork` crea un hijo con una copia entera de la memoria de usuario del
+//! padre (todavía sin copy-on-write) y sus mismos registros: los dos siguen
+//! desde la syscall, el padre con el pid del hijo y el hijo con 0.
+//!
+//! `exec` reemplaza la memoria del proceso actual por un ELF de `initrd` y
+//! sigue en su punto de entrada con un stack nuevo; el pid y el hilo son los
+//! mismos.
+//!
+//! El heap de usuario empieza en la primera página libre después del programa
+//! y termina en el break, que el proceso mueve con `brk` hasta `HEAP_LIMIT`:
+//! las páginas se mapean (en cero) y se liberan a medida que el break cruza
+//! sus bordes. Un `malloc` de usuario se arma sobre eso.
+//!
+//! Entre `HEAP_LIMIT` y el stack van las regiones anónimas de `mmap`, cada
+//! una anotada en la `VmaList` del proceso con sus permisos. Se mapean
+//! enteras al crearlas; sin NX, lo que se puede leer también se puede
+//! ejecutar, y una región `PROT_NONE` sólo reserva las direcciones: tocarla,
+//! o escribir en una sin `PROT_WRITE`, es un fallo de página que termina al
+//! proceso. `fork` copia las regiones y `exec` las suelta.
+//!
+//! Un proceso que termina (`exit`) suelta su espacio de direcciones y queda
+//! zombie, con su código de salida, hasta que alguien lo recoge con `wait`.
+//! Si su padre ya terminó nadie lo va a esperar, y se recoge solo; al
+//! terminar un padre se recogen también sus hijos que ya eran zombies.
+//! Una excepción en ring 3 termina sólo al proceso que la causó, con
+//! `FAULT_EXIT_CODE`.
+
+use alloc::collections::BTreeMap;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::structures::paging::{Page, PageTableFlags};
+use x86_64::VirtAddr;
+
+use crate::address_space::AddressSpace;
+use crate::driver::{Driver, Stage};
+use crate::elf::Elf;
+use crate::error::{KernelError, KernelResult};
+use crate::memory::{USER_END, USER_START};
+use crate::scheduler::DEFAULT_PRIORITY;
+use crate::syscall::{self, SyscallFrame};
+use crate::thread::{self, Thread, ThreadId};
+use crate::vma::{Vma, VmaList, PROT_EXEC, PROT_NONE, PROT_READ, PROT_WRITE};
+use crate::wait_queue::WaitQueue;
+
+/// Donde se carga el código de un proceso.
+pub const CODE_START: u64 = USER_START;
+/// Tope del stack de usuario, que crece hacia abajo.
+pub const USER_STACK_TOP: u64 = USER_END;
+pub const USER_STACK_PAGES: u64 = 4;
+/// El programa y su heap van debajo; lo de arriba, hasta el stack, queda
+/// para otros mapeos.
+pub const HEAP_LIMIT: u64 = USER_START + (USER_END - USER_START) / 2;
+/// Donde van las regiones de `mmap`. Entre el final y el stack queda una
+/// página sin mapear.
+pub const MMAP_AREA: core::ops::Range<u64> = HEAP_LIMIT..USER_STACK_TOP - (USER_STACK_PAGES + 1) * 4096;
+
+/// Como en Linux: devuelve el break que quedó, el de antes si no se pudo
+/// mover. Con 0, sólo lo consulta.
+pub const SYS_BRK: usize = 12;
+/// Sólo memoria anónima y privada (`MAP_PRIVATE | MAP_ANONYMOUS`), sin
+/// `MAP_FIXED`: la dirección es una sugerencia.
+pub const SYS_MMAP: usize = 9;
+pub const SYS_MUNMAP: usize = 11;
+
+pub const MAP_PRIVATE: u64 = 0x02;
+pub const MAP_ANONYMOUS: u64 = 0x20;
+pub const SYS_GETPID: usize = 39;
+pub const SYS_FORK: usize = 57;
+/// Recibe el nombre de la imagen en `initrd` como puntero y largo, sin
+/// argumentos ni entorno.
+pub const SYS_EXECVE: usize = 59;
+pub const SYS_EXIT: usize = 60;
+/// Como `wait4` sin opciones, pero el código de salida es el resultado en
+/// vez de escribirse en `status`.
+pub const SYS_WAIT: usize = 61;
+
+/// Código de salida de un proceso que terminó por una excepción propia (un
+/// acceso inválido, un opcode inválido): el mismo que si le pasara una
+/// dirección inválida a una syscall que no tiene a dónde volver.
+pub const FAULT_EXIT_CODE: i64 = -syscall::EFAULT;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Pid(u64);
+
+impl Pid {
+    fn new() -> Self {
+        static NEXT_PID: AtomicU64 = AtomicU64::new(1);
+        Pid(NEXT_PID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessState {
+    Ready,
+    Running,
+    Blocked,
+    /// Terminó y espera que lo recojan con `wait`.
+    Zombie,
+}
+
+pub struct Process {
+    pid: Pid,
+    name: &'static str,
+    /// Quién lo creó con `fork`.
+    parent: Option<Pid>,
+    /// El que lo corre; su stack es el de kernel del proceso.
+    thread: ThreadId,
+    /// `None` desde que terminó.
+    address_space: Option<AddressSpace>,
+    entry: VirtAddr,
+    /// Los registros con los que arranca un hijo de `fork`, en vez de
+    /// `entry`.
+    resume: Option<SyscallFrame>,
+    /// Comienzo del heap, alineado a página.
+    heap_start: u64,
+    brk: u64,
+    /// Las regiones de `mmap`.
+    vmas: VmaList,
+    exit_code: Option<i64>,
+}
+
+impl Process {
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn thread(&self) -> ThreadId {
+        self.thread
+    }
+
+    pub fn parent(&self) -> Option<Pid> {
+        self.parent
+    }
+
+    /// El final del heap.
+    pub fn brk(&self) -> u64 {
+        self.brk
+    }
+
+    pub fn vmas(&self) -> &VmaList {
+        &self.vmas
+    }
+
+    pub fn state(&self) -> ProcessState {
+        if self.exit_code.is_some() {
+            return ProcessState::Zombie;
+        }
+        match crate::scheduler::thread_state(self.thread) {
+            Some(thread::State::Running) => ProcessState::Running,
+            Some(thread::State::Blocked) => ProcessState::Blocked,
+            // Terminado sin `exit` no hay: el hilo sólo sale de ring 3 por
+            // una syscall o una interrupción.
+            _ => ProcessState::Ready,
+        }
+    }
+}
+
+static PROCESSES: Mutex<BTreeMap<Pid, Process>> = Mutex::new(BTreeMap::new());
+/// Avisa cada vez que termina un proceso.
+static EXITED: WaitQueue = WaitQueue::new();
+
+fn with_processes<R>(f: impl FnOnce(&mut BTreeMap<Pid, Process>) -> R) -> R {
+    interrupts::without_interrupts(|| f(&mut PROCESSES.lock()))
+}
+
+/// Los segmentos de `elf` y su entrada tienen que caer en la parte de
+/// usuario, debajo de `HEAP_LIMIT`.
+fn check_layout(elf: &Elf) -> KernelResult<()> {
+    let fits =
+        |start: u64, len: u64| start >= USER_START && start.checked_add(len).is_some_and(|end| end <= HEAP_LIMIT);
+    if fits(elf.entry(), 1) && elf.segments().all(|segment| fits(segment.vaddr, segment.mem_size)) {
+        Ok(())
+    } else {
+        Err(KernelError::InvalidArgument)
+    }
+}
+
+/// Mapea y copia los segmentos de `elf`, que ya pasó `check_layout`.
+/// Devuelve dónde empieza el heap: la página que sigue al último segmento.
+fn load(address_space: &mut AddressSpace, elf: &Elf) -> KernelResult<u64> {
+    let mut heap_start = USER_START;
+    for segment in elf.segments() {
+        let flags = if segment.is_writable() { PageTableFlags::WRITABLE } else { PageTableFlags::empty() };
+        let first = Page::containing_address(VirtAddr::new(segment.vaddr));
+        let last = Page::containing_address(VirtAddr::new(segment.vaddr + segment.mem_size.max(1) - 1));
+        for page in Page::range_inclusive(first, last) {
+            // Dos segmentos pueden compartir una página: se queda con los
+            // permisos de los dos.
+            match address_space.map(page, flags) {
+                Ok(_) => {}
+                Err(KernelError::AlreadyMapped) => address_space.add_flags(page, flags)?,
+                Err(err) => return Err(err),
+            }
+        }
+        // Lo que sigue a `data` ya está en cero.
+        address_space.write(VirtAddr::new(segment.vaddr), segment.data)?;
+        heap_start = heap_start.max(last.start_address().as_u64() + 4096);
+    }
+    Ok(heap_start)
+}
+
+/// Un stack de `USER_STACK_PAGES` páginas debajo de `USER_STACK_TOP`.
+fn map_stack(address_space: &mut AddressSpace) -> KernelResult<()> {
+    for i in 1..=USER_STACK_PAGES {
+        let page = Page::containing_address(VirtAddr::new(USER_STACK_TOP - i * 4096));
+        address_space.map(page, PageTableFlags::WRITABLE)?;
+    }
+    Ok(())
+}
+
+/// Crea un proceso que corre `code`, cargado tal cual en `CODE_START`, con
+/// un stack de `USER_STACK_PAGES` páginas debajo de `USER_STACK_TOP`.
+pub fn spawn(name: &'static str, code: &[u8]) -> KernelResult<Pid> {
+    let mut address_space = AddressSpace::new()?;
+    let code_pages = (code.len() as u64).div_ceil(4096).max(1);
+    for i in 0..code_pages {
+        let page = Page::containing_address(VirtAddr::new(CODE_START + i * 4096));
+        address_space.map(page, PageTableFlags::empty())?;
+    }
+    address_space.write(VirtAddr::new(CODE_START), code)?;
+    map_stack(&mut address_space)?;
+    let heap_start = CODE_START + code_pages * 4096;
+    start_process(name, address_space, VirtAddr::new(CODE_START), heap_start)
+}
+
+/// Crea un proceso que corre el ELF registrado en `initrd` como `name`.
+pub fn spawn_program(name: &str) -> KernelResult<Pid> {
+    let (name, image) = crate::initrd::find(name).ok_or(KernelError::NotFound)?;
+    let elf = Elf::parse(image)?;
+    check_layout(&elf)?;
+    let mut address_space = AddressSpace::new()?;
+    let heap_start = load(&mut address_space, &elf)?;
+    map_stack(&mut address_space)?;
+    start_process(name, address_space, VirtAddr::new(elf.entry()), heap_start)
+}
+
+fn start_process(name: &'static str, address_space: AddressSpace, entry: VirtAddr, heap_start: u64) -> KernelResult<Pid> {
+    let mut thread = Thread::new(name, DEFAULT_PRIORITY, start)?;
+    thread.page_table = Some(address_space.page_table());
+    let pid = Pid::new();
+    let process = Process {
+        pid,
+        name,
+        parent: None,
+        thread: thread.id,
+        address_space: Some(address_space),
+        entry,
+        resume: None,
+        heap_start,
+        brk: heap_start,
+        vmas: VmaList::new(),
+        exit_code: None,
+    };
+    insert(process, thread)
+}
+
+/// Anota el proceso y pone a correr su hilo. Si el scheduler está lleno,
+/// lo saca y el espacio de direcciones se suelta con él.
+fn insert(process: Process, thread: Thread) -> KernelResult<Pid> {
+    let pid = process.pid;
+    // Antes de que el hilo pueda correr: `start` lo busca acá.
+    with_processes(|processes| processes.insert(pid, process));
+    if let Err(err) = crate::scheduler::add(thread) {
+        drop(with_processes(|processes| processes.remove(&pid)));
+        return Err(err);
+    }
+    Ok(pid)
+}
+
+/// El primer código del hilo de un proceso, ya con su tabla en CR3.
+fn start() {
+    let (entry, resume) =
+        with_current(|process| (process.entry, process.resume.take())).expect("hilo de proceso sin proceso");
+    match resume {
+        Some(frame) => {
+            interrupts::disable();
+            unsafe { syscall::return_to_user(&frame) }
+        }
+        None => unsafe { syscall::enter_user(entry, VirtAddr::new(USER_STACK_TOP)) },
+    }
+}
+
+/// Duplica el proceso actual, que hizo la syscall con `frame`. El hijo
+/// vuelve de la misma syscall con 0; al padre se le devuelve el pid del
+/// hijo. Sin lugar para otro hilo devuelve `WouldBlock`, y sin marcos o
+/// stacks `OutOfMemory`; la copia de la memoria se suelta.
+pub fn fork(frame: &SyscallFrame) -> KernelResult<Pid> {
+    let (name, address_space, parent, heap, vmas) = with_current(|process| {
+        let address_space = process.address_space.as_ref().ok_or(KernelError::NotFound)?.try_clone()?;
+        let heap = (process.heap_start, process.brk);
+        Ok::<_, KernelError>((process.name, address_space, process.pid, heap, process.vmas.clone()))
+    })
+    .ok_or(KernelError::NotFound)??;
+
+    // Si no hay lugar para el hilo, la copia del espacio se suelta acá.
+    let mut thread = Thread::new(name, DEFAULT_PRIORITY, start)?;
+    thread.page_table = Some(address_space.page_table());
+    let child = Process {
+        pid: Pid::new(),
+        name,
+        parent: Some(parent),
+        thread: thread.id,
+        address_space: Some(address_space),
+        entry: VirtAddr::new(CODE_START),
+        resume: Some(SyscallFrame { rax: 0, ..*frame }),
+        heap_start: heap.0,
+        brk: heap.1,
+        vmas,
+        exit_code: None,
+    };
+    insert(child, thread)
+}
+
+/// Mueve el break del proceso actual a `addr`, entre el comienzo del heap y
+/// `HEAP_LIMIT`. Devuelve el break que quedó: si `addr` no sirve o no hay
+/// marcos para crecer, el de antes.
+pub fn brk(addr: u64) -> KernelResult<u64> {
+    with_current(|process| {
+        let address_space = process.address_space.as_mut().ok_or(KernelError::NotFound)?;
+        if !(process.heap_start..=HEAP_LIMIT).contains(&addr) {
+            return Ok(process.brk);
+        }
+        // Mapeadas están las páginas hasta el break, redondeado hacia arriba.
+        let page = |addr: u64| Page::containing_address(VirtAddr::new(addr.next_multiple_of(4096)));
+        let (old_end, new_end) = (page(process.brk), page(addr));
+        for (mapped, page) in Page::range(old_end, new_end).enumerate() {
+            if address_space.map(page, PageTableFlags::WRITABLE).is_err() {
+                for page in Page::range(old_end, old_end + mapped as u64) {
+                    address_space.unmap(page)?;
+                }
+                return Ok(process.brk);
+            }
+        }
+        for page in Page::range(new_end, old_end) {
+            address_space.unmap(page)?;
+        }
+        process.brk = addr;
+        Ok(addr)
+    })
+    .ok_or(KernelError::NotFound)?
+}
+
+fn prot_flags(prot: u32) -> PageTableFlags {
+    if prot & PROT_WRITE != 0 { PageTableFlags::WRITABLE } else { PageTableFlags::empty() }
+}
+
+/// Crea en el proceso actual una región anónima de `len` bytes (redondeado a
+/// páginas) con permisos `prot`, en `hint` si está libre y si no en el
+/// primer hueco de `MMAP_AREA`. La memoria empieza en cero.
+pub fn mmap(hint: u64, len: u64, prot: u32) -> KernelResult<u64> {
+    if len == 0 || prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+        return Err(KernelError::InvalidArgument);
+    }
+    let len = len.checked_next_multiple_of(4096).ok_or(KernelError::OutOfMemory)?;
+    with_current(|process| {
+        let address_space = process.address_space.as_mut().ok_or(KernelError::NotFound)?;
+        let start = process.vmas.find_free(hint, len, MMAP_AREA).ok_or(KernelError::OutOfMemory)?;
+        let vma = Vma { start, end: start + len, prot };
+        if prot != PROT_NONE {
+            let pages = Page::range(
+                Page::containing_address(VirtAddr::new(vma.start)),
+                Page::containing_address(VirtAddr::new(vma.end)),
+            );
+            for (mapped, page) in pages.enumerate() {
+                if let Err(err) = address_space.map(page, prot_flags(prot)) {
+                    for page in Page::range(pages.start, pages.start + mapped as u64) {
+                        address_space.unmap(page)?;
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        process.vmas.insert(vma);
+        Ok(start)
+    })
+    .ok_or(KernelError::NotFound)?
+}
+
+/// Saca `addr..addr + len` de las regiones del proceso actual y libera sus
+/// páginas. Lo que no era de ninguna región queda como estaba.
+pub fn munmap(addr: u64, len: u64) -> KernelResult<()> {
+    let end = len.checked_next_multiple_of(4096).and_then(|len| addr.checked_add(len));
+    let end = match end {
+        Some(end) if len > 0 && addr.is_multiple_of(4096) && addr >= USER_START && end <= USER_END => end,
+        _ => return Err(KernelError::InvalidArgument),
+    };
+    with_current(|process| {
+        let address_space = process.address_space.as_mut().ok_or(KernelError::NotFound)?;
+        for vma in process.vmas.remove(addr, end) {
+            if vma.prot == PROT_NONE {
+                continue;
+            }
+            let first = Page::containing_address(VirtAddr::new(vma.start));
+            for page in Page::range(first, Page::containing_address(VirtAddr::new(vma.end))) {
+                address_space.unmap(page)?;
+            }
+        }
+        Ok(())
+    })
+    .ok_or(KernelError::NotFound)?
+}
+
+fn with_current<R>(f: impl FnOnce(&mut Process) -> R) -> Option<R> {
+    let thread = thread::current()?;
+    with_processes(|processes| processes.values_mut().find(|process| process.thread == thread).map(f))
+}
+
+/// El proceso que está corriendo, si el hilo actual es de uno.
+pub fn current() -> Option<Pid> {
+    with_current(|process| process.pid)
+}
+
+/// Corre `f` sobre `pid`; `None` si no existe o ya se recogió.
+pub fn inspect<R>(pid: Pid, f: impl FnOnce(&Process) -> R) -> Option<R> {
+    with_processes(|processes| processes.get(&pid).map(f))
+}
+
+pub fn state(pid: Pid) -> Option<ProcessState> {
+    inspect(pid, Process::state)
+}
+
+/// Procesos que existen, zombies incluidos.
+pub fn count() -> usize {
+    with_processes(|processes| processes.len())
+}
+
+/// Reemplaza la imagen del proceso actual, que hizo la syscall con `frame`,
+/// por el ELF registrado en `initrd` como `name`. Al volver de la syscall
+/// arranca el programa nuevo, con los registros en cero y el stack vacío.
+///
+/// Si la imagen no sirve, el proceso sigue como estaba. Si falla después de
+/// soltar la memoria vieja (sin marcos para la nueva), no queda a dónde
+/// volver y el proceso termina con `-errno`.
+pub fn exec(frame: &mut SyscallFrame, name: &str) -> KernelResult<()> {
+    let (name, image) = crate::initrd::find(name).ok_or(KernelError::NotFound)?;
+    let elf = Elf::parse(image)?;
+    check_layout(&elf)?;
+    let entry = VirtAddr::new(elf.entry());
+    let loaded = with_current(|process| {
+        let address_space = process.address_space.as_mut().ok_or(KernelError::NotFound)?;
+        address_space.clear();
+        process.vmas = VmaList::new();
+        process.name = name;
+        process.entry = entry;
+        let heap_start = load(address_space, &elf)?;
+        process.heap_start = heap_start;
+        process.brk = heap_start;
+        map_stack(address_space)
+    })
+    .ok_or(KernelError::NotFound)?;
+    if let Err(err) = loaded {
+        exit(-syscall::errno(err));
+    }
+    *frame = SyscallFrame {
+        rip: entry.as_u64(),
+        rsp: USER_STACK_TOP,
+        rflags: syscall::USER_RFLAGS,
+        ..Default::default()
+    };
+    Ok(())
+}
+
+/// Termina el proceso actual con `code`. Su memoria se libera ya; el código
+/// queda hasta que lo recojan con `wait`.
+pub fn exit(code: i64) -> ! {
+    interrupts::disable();
+    let (pid, parent, address_space) = with_current(|process| {
+        process.exit_code = Some(code);
+        (process.pid, process.parent, process.address_space.take())
+    })
+    .expect("process::exit fuera de un proceso");
+    with_processes(|processes| {
+        // Los hijos que ya terminaron no los va a recoger nadie; los que
+        // siguen, al terminar, ven que el padre no está.
+        processes.retain(|_, process| process.parent != Some(pid) || process.exit_code.is_none());
+        let orphan = parent.is_some_and(|parent| processes.get(&parent).is_none_or(|parent| parent.exit_code.is_some()));
+        if orphan {
+            processes.remove(&pid);
+        }
+    });
+    // No se puede soltar la tabla cargada. El hilo no vuelve a correr, así
+    // que el scheduler no la vuelve a poner.
+    unsafe { crate::memory::switch_page_table(crate::memory::kernel_page_table()) };
+    drop(address_space);
+    EXITED.notify_all();
+    thread::exit();
+}
+
+/// Espera que `pid` termine, lo recoge y devuelve su código de salida.
+/// Sólo se puede esperar a un hijo: los de `fork` los recoge el padre, y los
+/// de `spawn` el kernel, desde fuera de un proceso. Con cualquier otro pid
+/// devuelve `NotFound`.
+pub fn wait(pid: Pid) -> KernelResult<i64> {
+    let parent = current();
+    let is_child = |processes: &mut BTreeMap<Pid, Process>| {
+        processes.get(&pid).is_some_and(|process| process.parent == parent)
+    };
+    if !with_processes(is_child) {
+        return Err(KernelError::NotFound);
+    }
+    EXITED.wait_while(|| {
+        with_processes(|processes| processes.get(&pid).is_some_and(|process| process.exit_code.is_none()))
+    });
+    with_processes(|processes| {
+        if !is_child(processes) {
+            return Err(KernelError::NotFound);
+        }
+        let process = processes.remove(&pid).ok_or(KernelError::NotFound)?;
+        Ok(process.exit_code.expect("proceso recogido sin terminar"))
+    })
+}
+
+fn sys_brk(frame: &mut SyscallFrame) -> KernelResult<u64> {
+    brk(frame.arg(0))
+}
+
+fn sys_mmap(frame: &mut SyscallFrame) -> KernelResult<u64> {
+    let (addr, len, prot, flags, offset) = (frame.arg(0), frame.arg(1), frame.arg(2), frame.arg(3), frame.arg(5));
+    // El descriptor (arg 4) no importa con `MAP_ANONYMOUS`.
+    if flags != MAP_PRIVATE | MAP_ANONYMOUS {
+        return Err(KernelError::Unsupported);
+    }
+    if offset != 0 {
+        return Err(KernelError::InvalidArgument);
+    }
+    let prot = u32::try_from(prot).map_err(|_| KernelError::InvalidArgument)?;
+    mmap(addr, len, prot)
+}
+
+fn sys_munmap(frame: &mut SyscallFrame) -> KernelResult<u64> {
+    munmap(frame.arg(0), frame.arg(1)).map(|()| 0)
+}
+
+fn sys_getpid(_: &mut SyscallFrame) -> KernelResult<u64> {
+    current().map(Pid::as_u64).ok_or(KernelError::NotFound)
+}
+
+fn sys_fork(frame: &mut SyscallFrame) -> KernelResult<u64> {
+    fork(frame).map(Pid::as_u64)
+}
+
+/// Largo máximo del nombre que recibe `execve`.
+const MAX_NAME: usize = 64;
+
+fn sys_execve(frame: &mut SyscallFrame) -> KernelResult<u64> {
+    let (addr, len) = (frame.arg(0), frame.arg(1) as usize);
+    if len > MAX_NAME {
+        return Err(KernelError::InvalidArgument);
+    }
+    let addr = VirtAddr::try_new(addr).map_err(|_| KernelError::NotMapped)?;
+    let mut buffer = [0; MAX_NAME];
+    with_current(|process| {
+        let address_space = process.address_space.as_mut().ok_or(KernelError::NotFound)?;
+        address_space.read_user(addr, &mut buffer[..len])
+    })
+    .ok_or(KernelError::NotFound)??;
+    let name = core::str::from_utf8(&buffer[..len]).map_err(|_| KernelError::InvalidArgument)?;
+    exec(frame, name).map(|()| 0)
+}
+
+fn sys_exit(frame: &mut SyscallFrame) -> KernelResult<u64> {
+    exit(frame.arg(0) as i64)
+}
+
+fn sys_wait(frame: &mut SyscallFrame) -> KernelResult<u64> {
+    wait(Pid(frame.arg(0))).map(|code| code as u64)
+}
+
+fn init() {
+    syscall::register(SYS_MMAP, sys_mmap).expect("syscall mmap registrada dos veces");
+    syscall::register(SYS_MUNMAP, sys_munmap).expect("syscall munmap registrada dos veces");
+    syscall::register(SYS_BRK, sys_brk).expect("syscall brk registrada dos veces");
+    syscall::register(SYS_GETPID, sys_getpid).expect("syscall getpid registrada dos veces");
+    syscall::register(SYS_FORK, sys_fork).expect("syscall fork registrada dos veces");
+    syscall::register(SYS_EXECVE, sys_execve).expect("syscall execve registrada dos veces");
+    syscall::register(SYS_EXIT, sys_exit).expect("syscall exit registrada dos veces");
+    syscall::register(SYS_WAIT, sys_wait).expect("syscall wait registrada dos veces");
+}
+
+crate::register_driver!(PROCESS_DRIVER, Driver {
+    name: "process",
+    stage: Stage::Services,
+    depends_on: &["syscall"],
+    device: None,
+    probe: Driver::always,
+    init,
+});
@@ -0,0 +1,355 @@
+//! Controlador PS/2 (8042), puertos 0x60 (datos) y 0x64 (estado/comando).
+//!
+//! `init` hace la secuencia completa antes de habilitar ninguna IRQ: apaga
+//! los dos puertos, vacía el buffer, desactiva la traducción a scancode set 1,
+//! corre el self-test del controlador y el de cada puerto, y resetea e
+//! identifica lo que haya conectado. Por cada dispositivo reconocido agrega
+//! una entrada a `device` (`PNP0303` teclado, `PNP0F13` mouse), a la que se
+//! asocia su driver. Si el controlador o el segundo puerto no existen, o una
+//! prueba falla, lo que no anda simplemente no aparece.
+//!
+//! Como la traducción queda apagada, el teclado habla scancode set 2.
+
+use conquer_once::spin::OnceCell;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::device::{self, Bus, Device, Resource};
+use crate::driver::{Driver, Stage};
+use crate::portio::{self, PortRegion};
+
+// Comandos del controlador (al puerto 0x64).
+const READ_CONFIG: u8 = 0x20;
+const WRITE_CONFIG: u8 = 0x60;
+const DISABLE_PORT2: u8 = 0xA7;
+const ENABLE_PORT2: u8 = 0xA8;
+const TEST_PORT2: u8 = 0xA9;
+const SELF_TEST: u8 = 0xAA;
+const TEST_PORT1: u8 = 0xAB;
+const DISABLE_PORT1: u8 = 0xAD;
+const ENABLE_PORT1: u8 = 0xAE;
+const WRITE_PORT2: u8 = 0xD4;
+
+const SELF_TEST_OK: u8 = 0x55;
+const PORT_TEST_OK: u8 = 0x00;
+
+// Byte de configuración.
+const CONFIG_IRQ1: u8 = 1 << 0;
+const CONFIG_IRQ12: u8 = 1 << 1;
+const CONFIG_PORT2_CLOCK_OFF: u8 = 1 << 5;
+const CONFIG_TRANSLATION: u8 = 1 << 6;
+
+// Comandos y respuestas de los dispositivos.
+const DEVICE_RESET: u8 = 0xFF;
+const DEVICE_IDENTIFY: u8 = 0xF2;
+const DEVICE_ENABLE_SCANNING: u8 = 0xF4;
+const DEVICE_DISABLE_SCANNING: u8 = 0xF5;
+pub(crate) const ACK: u8 = 0xFA;
+const RESEND: u8 = 0xFE;
+const DEVICE_SELF_TEST_OK: u8 = 0xAA;
+
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+/// Vueltas de espera antes de dar por perdida una respuesta.
+const SPIN_LIMIT: u32 = 100_000;
+/// El self-test de un dispositivo tras el reset puede tardar cientos de ms.
+const RESET_SPIN_LIMIT: u32 = 20 * SPIN_LIMIT;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Port {
+    First,
+    Second,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceKind {
+    Keyboard,
+    Mouse,
+    /// Los bytes de identificación, si no se reconocieron.
+    Unknown(u16),
+}
+
+impl DeviceKind {
+    fn from_id(id: &[u8]) -> DeviceKind {
+        match id {
+            // Los teclados AT viejos no mandan ID.
+            [] | [0xAB, ..] => DeviceKind::Keyboard,
+            [0x00] | [0x03] | [0x04] => DeviceKind::Mouse,
+            [a] => DeviceKind::Unknown(u16::from(*a)),
+            [a, b, ..] => DeviceKind::Unknown(u16::from(*a) << 8 | u16::from(*b)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ps2Error {
+    NotInitialized,
+    Timeout,
+    SelfTestFailed(u8),
+    PortTestFailed(Port, u8),
+    /// El dispositivo respondió algo que no es ACK a un comando.
+    NoAck(u8),
+}
+
+struct Ports {
+    data: PortRegion,
+    status: PortRegion,
+}
+
+static PORTS: OnceCell<Mutex<Ports>> = OnceCell::uninit();
+static DEVICES: Mutex<[Option<DeviceKind>; 2]> = Mutex::new([None; 2]);
+
+// ----------------- ACCESO AL CONTROLADOR -----------------
+
+impl Ports {
+    fn wait(&self, mask: u8, set: bool, limit: u32) -> Result<(), Ps2Error> {
+        let mut status = self.status.read_only::<u8>(0);
+        for _ in 0..limit {
+            if (unsafe { status.read() } & mask != 0) == set {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err(Ps2Error::Timeout)
+    }
+
+    fn command(&self, command: u8) -> Result<(), Ps2Error> {
+        self.wait(STATUS_INPUT_FULL, false, SPIN_LIMIT)?;
+        unsafe { self.status.port::<u8>(0).write(command) };
+        Ok(())
+    }
+
+    fn write_data(&self, byte: u8) -> Result<(), Ps2Error> {
+        self.wait(STATUS_INPUT_FULL, false, SPIN_LIMIT)?;
+        unsafe { self.data.port::<u8>(0).write(byte) };
+        Ok(())
+    }
+
+    fn read_data(&self, limit: u32) -> Result<u8, Ps2Error> {
+        self.wait(STATUS_OUTPUT_FULL, true, limit)?;
+        Ok(unsafe { self.data.port::<u8>(0).read() })
+    }
+
+    fn flush(&self) {
+        while self.read_data(SPIN_LIMIT / 100).is_ok() {}
+    }
+
+    fn command_with_reply(&self, command: u8) -> Result<u8, Ps2Error> {
+        self.command(command)?;
+        self.read_data(SPIN_LIMIT)
+    }
+
+    fn config(&self) -> Result<u8, Ps2Error> {
+        self.command_with_reply(READ_CONFIG)
+    }
+
+    fn set_config(&self, config: u8) -> Result<(), Ps2Error> {
+        self.command(WRITE_CONFIG)?;
+        self.write_data(config)
+    }
+
+    fn send(&self, port: Port, byte: u8) -> Result<(), Ps2Error> {
+        if port == Port::Second {
+            self.command(WRITE_PORT2)?;
+        }
+        self.write_data(byte)
+    }
+
+    /// Manda un comando a un dispositivo y espera el ACK, reintentando si
+    /// pide reenvío.
+    fn device_command(&self, port: Port, byte: u8) -> Result<(), Ps2Error> {
+        for _ in 0..3 {
+            self.send(port, byte)?;
+            match self.read_data(SPIN_LIMIT)? {
+                ACK => return Ok(()),
+                RESEND => continue,
+                other => return Err(Ps2Error::NoAck(other)),
+            }
+        }
+        Err(Ps2Error::NoAck(RESEND))
+    }
+
+    fn reset_and_identify(&self, port: Port) -> Result<DeviceKind, Ps2Error> {
+        self.device_command(port, DEVICE_RESET)?;
+        match self.read_data(RESET_SPIN_LIMIT)? {
+            DEVICE_SELF_TEST_OK => {}
+            other => return Err(Ps2Error::SelfTestFailed(other)),
+        }
+        // Un mouse manda su ID después del self-test.
+        self.flush();
+
+        self.device_command(port, DEVICE_DISABLE_SCANNING)?;
+        self.device_command(port, DEVICE_IDENTIFY)?;
+        let mut id = [0u8; 2];
+        let mut len = 0;
+        while len < id.len() {
+            match self.read_data(SPIN_LIMIT / 10) {
+                Ok(byte) => {
+                    id[len] = byte;
+                    len += 1;
+                }
+                Err(_) => break,
+            }
+        }
+        self.device_command(port, DEVICE_ENABLE_SCANNING)?;
+        Ok(DeviceKind::from_id(&id[..len]))
+    }
+}
+
+// ----------------- INICIALIZACIÓN -----------------
+
+/// Sin controlador 8042 el bus flotante devuelve 0xFF en el puerto de estado.
+fn probe() -> bool {
+    match portio::claim("probe", 0x64, 1) {
+        Ok(ports) => {
+            let present = unsafe { ports.read_only::<u8>(0).read() } != 0xFF;
+            portio::release(ports);
+            present
+        }
+        Err(_) => false,
+    }
+}
+
+/// Secuencia de inicialización; devuelve qué hay en cada puerto.
+fn bring_up(ports: &Ports) -> Result<[Option<DeviceKind>; 2], Ps2Error> {
+    ports.command(DISABLE_PORT1)?;
+    ports.command(DISABLE_PORT2)?;
+    ports.flush();
+
+    let mut config = ports.config()?;
+    // Con el segundo puerto deshabilitado, su reloj figura apagado sólo si existe.
+    let maybe_dual = config & CONFIG_PORT2_CLOCK_OFF != 0;
+    config &= !(CONFIG_IRQ1 | CONFIG_IRQ12 | CONFIG_TRANSLATION);
+    ports.set_config(config)?;
+
+    match ports.command_with_reply(SELF_TEST)? {
+        SELF_TEST_OK => {}
+        other => return Err(Ps2Error::SelfTestFailed(other)),
+    }
+    // Algunos controladores vuelven a la configuración de fábrica tras el self-test.
+    ports.set_config(config)?;
+
+    let dual = maybe_dual && {
+        ports.command(ENABLE_PORT2)?;
+        let enabled = ports.config()? & CONFIG_PORT2_CLOCK_OFF == 0;
+        ports.command(DISABLE_PORT2)?;
+        enabled
+    };
+
+    let mut found = [None; 2];
+    for (index, port, test, enable, irq) in [
+        (0, Port::First, TEST_PORT1, ENABLE_PORT1, CONFIG_IRQ1),
+        (1, Port::Second, TEST_PORT2, ENABLE_PORT2, CONFIG_IRQ12),
+    ] {
+        if port == Port::Second && !dual {
+            continue;
+        }
+        let result = ports.command_with_reply(test)?;
+        if result != PORT_TEST_OK {
+            crate::log_warn!("{:?}", Ps2Error::PortTestFailed(port, result));
+            continue;
+        }
+        ports.command(enable)?;
+        match ports.reset_and_identify(port) {
+            Ok(kind) => {
+                found[index] = Some(kind);
+                config |= irq;
+            }
+            // Puerto sano pero sin nada conectado.
+            Err(Ps2Error::Timeout) => {}
+            Err(err) => crate::log_warn!("PS/2 {:?}: {:?}", port, err),
+        }
+    }
+
+    ports.flush();
+    ports.set_config(config)?;
+    Ok(found)
+}
+
+fn init() {
+    let ports = Ports {
+        data: portio::claim("i8042", 0x60, 1).expect("puerto de datos PS/2 ocupado"),
+        status: portio::claim("i8042", 0x64, 1).expect("puerto de estado PS/2 ocupado"),
+    };
+
+    let found = interrupts::without_interrupts(|| bring_up(&ports)).unwrap_or_else(|err| {
+        crate::log_warn!("controlador PS/2 inutilizable: {:?}", err);
+        [None; 2]
+    });
+    PORTS.init_once(|| Mutex::new(ports));
+    interrupts::without_interrupts(|| *DEVICES.lock() = found);
+
+    match found[0] {
+        Some(DeviceKind::Keyboard) => device::add(
+            Device::new(Bus::Ps2, "PNP0303", "Teclado PS/2")
+                .with(Resource::IoPorts { start: 0x60, len: 1 })
+                .with(Resource::IoPorts { start: 0x64, len: 1 })
+                .with(Resource::Irq(1)),
+        ),
+        Some(other) => crate::log_warn!("PS/2: dispositivo inesperado en el primer puerto: {:?}", other),
+        None => {}
+    }
+    match found[1] {
+        Some(DeviceKind::Mouse) => {
+            device::add(Device::new(Bus::Ps2, "PNP0F13", "Mouse PS/2").with(Resource::Irq(12)))
+        }
+        Some(other) => crate::log_info!("PS/2: dispositivo en el segundo puerto: {:?}", other),
+        None => {}
+    }
+}
+
+crate::register_driver!(I8042_DRIVER, Driver {
+    name: "i8042",
+    stage: Stage::Drivers,
+    depends_on: &[],
+    device: None,
+    probe,
+    init,
+});
+
+// ----------------- API -----------------
+
+/// Qué se identificó en `port` al inicializar.
+pub fn device(port: Port) -> Option<DeviceKind> {
+    let index = match port {
+        Port::First => 0,
+        Port::Second => 1,
+    };
+    interrupts::without_interrupts(|| DEVICES.lock()[index])
+}
+
+fn with_ports<R>(f: impl FnOnce(&Ports) -> Result<R, Ps2Error>) -> Result<R, Ps2Error> {
+    let ports = PORTS.try_get().map_err(|_| Ps2Error::NotInitialized)?;
+    interrupts::without_interrupts(|| f(&ports.lock()))
+}
+
+/// Lee el puerto de datos sin mirar el estado. Para el handler de IRQ, que
+/// sabe que hay un byte: lo lee siempre, porque mientras nadie vacíe el
+/// puerto el controlador no genera otra IRQ.
+pub(crate) fn read_data() -> u8 {
+    match PORTS.try_get() {
+        Ok(ports) => interrupts::without_interrupts(|| unsafe { ports.lock().data.port::<u8>(0).read() }),
+        // Antes de `init` el puerto todavía no tiene dueño.
+        Err(_) => unsafe { x86_64::instructions::port::PortReadOnly::<u8>::new(0x60).read() },
+    }
+}
+
+/// Escribe un byte al dispositivo de `port`, sin esperar respuesta.
+pub(crate) fn send(port: Port, byte: u8) -> Result<(), Ps2Error> {
+    with_ports(|ports| ports.send(port, byte))
+}
+
+/// Espera un byte del controlador por polling.
+pub(crate) fn read_polled() -> Result<u8, Ps2Error> {
+    with_ports(|ports| ports.read_data(SPIN_LIMIT))
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_device_kind_from_id() {
+    assert_eq!(DeviceKind::from_id(&[]), DeviceKind::Keyboard);
+    assert_eq!(DeviceKind::from_id(&[0xAB, 0x83]), DeviceKind::Keyboard);
+    assert_eq!(DeviceKind::from_id(&[0x03]), DeviceKind::Mouse);
+    assert_eq!(DeviceKind::from_id(&[0x12, 0x34]), DeviceKind::Unknown(0x1234));
+}
@@ -0,0 +1,171 @@
+//! Cuotas de heap por subsistema.
+//!
+//! Un subsistema que quiere que su memoria se cuente aloca con `Tagged(tag)`,
+//! que implementa `Allocator` sobre el allocator global:
+//! `Vec::new_in(Tagged(Tag::NetBuffers))`, `Box::new_in(x, Tagged(tag))`.
+//! Cada tag lleva los bytes en uso y, si tiene cuota (`set_quota`), una
+//! alocación que la pasaría primero llama al callback de reclamación del tag
+//! (`set_reclaim`, por ejemplo para vaciar una cache) y, si sigue sin entrar,
+//! falla con `AllocError`. Lo que no usa `Tagged` no se cuenta ni se limita.
+
+use alloc::alloc::Global;
+use core::alloc::{AllocError, Allocator, Layout};
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+pub enum Tag {
+    NetBuffers,
+    BlockCache,
+    Tasks,
+}
+
+impl Tag {
+    pub const ALL: [Tag; TAGS] = [Tag::NetBuffers, Tag::BlockCache, Tag::Tasks];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tag::NetBuffers => "net-buffers",
+            Tag::BlockCache => "block-cache",
+            Tag::Tasks => "tasks",
+        }
+    }
+}
+
+const TAGS: usize = 3;
+const NO_QUOTA: usize = usize::MAX;
+
+/// Se llama con los bytes que faltan para que entre una alocación; libera lo
+/// que pueda. No debe alocar con el mismo tag.
+pub type Reclaim = fn(needed: usize);
+
+struct Account {
+    used: AtomicUsize,
+    peak: AtomicUsize,
+    quota: AtomicUsize,
+    failures: AtomicU64,
+}
+
+impl Account {
+    const fn new() -> Self {
+        Account {
+            used: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            quota: AtomicUsize::new(NO_QUOTA),
+            failures: AtomicU64::new(0),
+        }
+    }
+
+    /// Reserva `size` bytes si entran en la cuota. Si no, devuelve cuántos faltan.
+    fn reserve(&self, size: usize) -> Result<(), usize> {
+        let quota = self.quota.load(Ordering::Relaxed);
+        let mut used = self.used.load(Ordering::Relaxed);
+        loop {
+            let wanted = used.saturating_add(size);
+            if wanted > quota {
+                return Err(wanted - quota);
+            }
+            match self.used.compare_exchange_weak(used, wanted, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => {
+                    self.peak.fetch_max(wanted, Ordering::Relaxed);
+                    return Ok(());
+                }
+                Err(current) => used = current,
+            }
+        }
+    }
+
+    fn unreserve(&self, size: usize) {
+        self.used.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+static ACCOUNTS: [Account; TAGS] = [const { Account::new() }; TAGS];
+static RECLAIM: Mutex<[Option<Reclaim>; TAGS]> = Mutex::new([None; TAGS]);
+
+/// Límite de bytes en uso para `tag`; `None` lo quita. No afecta lo que ya
+/// está alocado.
+pub fn set_quota(tag: Tag, quota: Option<usize>) {
+    ACCOUNTS[tag as usize].quota.store(quota.unwrap_or(NO_QUOTA), Ordering::Relaxed);
+}
+
+pub fn set_reclaim(tag: Tag, reclaim: Option<Reclaim>) {
+    interrupts::without_interrupts(|| RECLAIM.lock()[tag as usize] = reclaim);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    pub used: usize,
+    pub peak: usize,
+    pub quota: Option<usize>,
+    /// Alocaciones rechazadas por la cuota.
+    pub failures: u64,
+}
+
+pub fn usage(tag: Tag) -> Usage {
+    let account = &ACCOUNTS[tag as usize];
+    let quota = account.quota.load(Ordering::Relaxed);
+    Usage {
+        used: account.used.load(Ordering::Relaxed),
+        peak: account.peak.load(Ordering::Relaxed),
+        quota: (quota != NO_QUOTA).then_some(quota),
+        failures: account.failures.load(Ordering::Relaxed),
+    }
+}
+
+pub fn print_usage() {
+    crate::println!("TAG           USO       PICO      CUOTA     FALLOS");
+    for tag in Tag::ALL {
+        let usage = usage(tag);
+        match usage.quota {
+            Some(quota) => crate::println!(
+                "{:<13} {:<9} {:<9} {:<9} {}",
+                tag.name(), usage.used, usage.peak, quota, usage.failures
+            ),
+            None => crate::println!(
+                "{:<13} {:<9} {:<9} {:<9} {}",
+                tag.name(), usage.used, usage.peak, "-", usage.failures
+            ),
+        }
+    }
+}
+
+/// Allocator que cuenta lo que aloca contra la cuota de su tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tagged(pub Tag);
+
+impl Tagged {
+    fn account(&self) -> &'static Account {
+        &ACCOUNTS[self.0 as usize]
+    }
+
+    fn reserve(&self, size: usize) -> Result<(), AllocError> {
+        let account = self.account();
+        if let Err(needed) = account.reserve(size) {
+            let reclaim = interrupts::without_interrupts(|| RECLAIM.lock()[self.0 as usize]);
+            if let Some(reclaim) = reclaim {
+                reclaim(needed);
+            }
+            if account.reserve(size).is_err() {
+                account.failures.fetch_add(1, Ordering::Relaxed);
+                return Err(AllocError);
+            }
+        }
+        Ok(())
+    }
+}
+
+unsafe impl Allocator for Tagged {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.reserve(layout.size())?;
+        Global.allocate(layout).inspect_err(|_| self.account().unreserve(layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { Global.deallocate(ptr, layout) };
+        self.account().unreserve(layout.size());
+    }
+}
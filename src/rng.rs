@@ -1,9 +1,19 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+/// La semilla del generador global hasta que `init` la reemplaza.
+pub const DEFAULT_SEED: u64 = 1;
+
+static RNG: Mutex<SimpleRng> = Mutex::new(SimpleRng::new(DEFAULT_SEED));
+static SEED: AtomicU64 = AtomicU64::new(DEFAULT_SEED);
+
 pub struct SimpleRng {
     state: u64,
 }
 
 impl SimpleRng {
-    pub fn new(seed: u64) -> Self {
+    pub const fn new(seed: u64) -> Self {
         Self { state: seed }
     }
 
@@ -16,3 +26,47 @@ impl SimpleRng {
         min + (self.next_u64() % (max - min))
     }
 }
+
+/// Semilla para quien no tenga una fija: el TSC, o una constante con la
+/// feature `deterministic` para que los tests sean reproducibles.
+pub fn boot_seed() -> u64 {
+    if crate::time::is_deterministic() {
+        0x6b75_725f_6f73
+    } else {
+        crate::bench::rdtsc()
+    }
+}
+
+/// Siembra el generador global con `boot_seed`. La llama `kur_os::init`.
+pub fn init() {
+    reseed(boot_seed());
+}
+
+/// Reinicia el generador global con `seed`.
+pub fn reseed(seed: u64) {
+    interrupts::without_interrupts(|| {
+        *RNG.lock() = SimpleRng::new(seed);
+        SEED.store(seed, Ordering::Relaxed);
+    });
+}
+
+/// La última semilla del generador global.
+pub fn seed() -> u64 {
+    SEED.load(Ordering::Relaxed)
+}
+
+/// El siguiente número del generador global.
+pub fn next_u64() -> u64 {
+    interrupts::without_interrupts(|| RNG.lock().next_u64())
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_init_replaces_default_seed() {
+    // El kernel de tests ya pasó por `kur_os::init`.
+    assert_ne!(seed(), DEFAULT_SEED);
+    if crate::time::is_deterministic() {
+        assert_eq!(seed(), boot_seed());
+    }
+}
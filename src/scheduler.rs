@@ -0,0 +1,790 @@
+//! Scheduler round-robin de hilos del kernel.
+//!
+//! Los hilos listos esperan en una cola FIFO por prioridad, de 0 (la más
+//! urgente) a `PRIORITIES - 1`; corre siempre uno de la cola más urgente que
+//! no esté vacía. El que corre tiene una tajada fija de `TIME_SLICE_MS`;
+//! cuando el handler del timer la ve agotada lo manda al final de su cola y
+//! cambia al siguiente. Si no hay nadie listo corre el hilo idle, que hace
+//! `hlt` hasta la próxima interrupción.
+//!
+//! Un lock que bloquea hilos evita la inversión de prioridad con
+//! `inherit_priority`: mientras un hilo urgente espera, el dueño del lock
+//! compite con la prioridad del que espera, hasta `restore_priority`.
+//!
+//! El cambio desde el timer ocurre dentro del handler, sobre el stack del
+//! hilo interrumpido: cuando le vuelve a tocar, `switch` retorna al handler
+//! y el `iretq` lo deja donde estaba. Por eso el EOI se manda antes.
+//!
+//! Un hilo que corre un proceso (`process`) lleva la tabla de nivel 4 del
+//! proceso: al cambiar a él se carga en CR3, y su stack pasa a ser el de
+//! `RSP0` y el de las syscalls. Los demás usan la tabla del kernel.
+//!
+//! El código que llama a `init` (el executor de `main`) pasa a ser el hilo
+//! "main" y compite por la CPU como cualquier otro.
+//!
+//! Un hilo que espera algo que no es la CPU se bloquea en una
+//! `wait_queue::WaitQueue` y no vuelve a ninguna cola hasta que lo despiertan.
+//!
+//! Hay además una clase para trabajo periódico con plazo ("el watchdog cada
+//! 100 ms", "pollear la red cada 10 ms"): `spawn_periodic` crea un hilo que
+//! corre su función una vez por período. El timer wheel lo libera al
+//! comenzar cada período; liberado, le gana a todo hilo round-robin, y entre
+//! periódicos corre el de plazo más cercano (EDF). Una activación que
+//! termina después de su plazo, o que sigue corriendo cuando llega la
+//! siguiente, cuenta como plazo perdido.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::VirtAddr;
+
+use crate::error::{KernelError, KernelResult};
+use crate::thread::{self, State, Thread, ThreadId};
+use crate::timer_wheel;
+
+pub const MAX_THREADS: usize = 32;
+pub const TIME_SLICE_MS: u64 = 20;
+pub const PRIORITIES: usize = 8;
+pub const DEFAULT_PRIORITY: Priority = 4;
+
+/// 0 es la más urgente.
+pub type Priority = u8;
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Class {
+    RoundRobin,
+    Periodic(Periodic),
+}
+
+/// Estado de un hilo periódico. Los tiempos son ticks de `time::ticks`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Periodic {
+    work: fn(),
+    period: u64,
+    /// Plazo relativo al comienzo de cada activación.
+    deadline: u64,
+    /// Plazo absoluto de la activación en curso.
+    due: u64,
+    next_release: u64,
+    /// Liberación que llegó mientras la activación anterior seguía corriendo.
+    queued: Option<u64>,
+    /// La activación en curso ya se contó como perdida.
+    late: bool,
+    activations: u64,
+    missed: u64,
+}
+
+impl Periodic {
+    fn start(&mut self, release: u64) {
+        self.due = release + self.deadline;
+        self.late = false;
+        self.activations += 1;
+    }
+
+    fn miss(&mut self) {
+        if !self.late {
+            self.late = true;
+            self.missed += 1;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineStats {
+    pub activations: u64,
+    pub missed: u64,
+}
+
+/// Por qué se llama a `reschedule`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Switch {
+    /// El hilo cede la CPU: `yield_now`, se bloquea o termina.
+    Voluntary,
+    /// El timer le sacó la CPU: se agotó su tajada o llegó uno más urgente.
+    Preempted,
+}
+
+/// Contadores de `stats`.
+#[derive(Debug, Clone, Copy)]
+struct Counters {
+    voluntary: u64,
+    preempted: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchedulerStats {
+    /// Cambios de hilo desde `init`: `voluntary + preempted`.
+    pub context_switches: u64,
+    pub voluntary: u64,
+    pub preempted: u64,
+    pub threads: Vec<ThreadStats>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThreadStats {
+    pub id: ThreadId,
+    pub name: &'static str,
+    pub state: State,
+    /// Tiempo de CPU acumulado, incluida la tajada en curso.
+    pub run_ns: u64,
+    /// Veces que el scheduler cambió a este hilo.
+    pub switches: u64,
+    /// Máximo de stack usado en bytes; `None` para el hilo de arranque, que
+    /// sigue en el stack del bootloader.
+    pub stack_high_water: Option<usize>,
+}
+
+/// Cola circular de índices en la tabla de hilos.
+struct RunQueue {
+    slots: [usize; MAX_THREADS],
+    head: usize,
+    len: usize,
+}
+
+impl RunQueue {
+    const fn new() -> Self {
+        RunQueue { slots: [0; MAX_THREADS], head: 0, len: 0 }
+    }
+
+    fn push(&mut self, slot: usize) {
+        assert!(self.len < MAX_THREADS, "run queue llena");
+        self.slots[(self.head + self.len) % MAX_THREADS] = slot;
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        if self.len == 0 {
+            return None;
+        }
+        let slot = self.slots[self.head];
+        self.head = (self.head + 1) % MAX_THREADS;
+        self.len -= 1;
+        Some(slot)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Saca `slot` de donde esté, manteniendo el orden del resto.
+    fn remove(&mut self, slot: usize) -> bool {
+        let Some(index) = (0..self.len).find(|i| self.slots[(self.head + i) % MAX_THREADS] == slot) else {
+            return false;
+        };
+        for i in index..self.len - 1 {
+            self.slots[(self.head + i) % MAX_THREADS] = self.slots[(self.head + i + 1) % MAX_THREADS];
+        }
+        self.len -= 1;
+        true
+    }
+}
+
+struct Scheduler {
+    threads: [Option<Thread>; MAX_THREADS],
+    run_queues: [RunQueue; PRIORITIES],
+    current: usize,
+    idle: usize,
+    /// Hilo terminado cuyo stack se libera después del próximo cambio: no se
+    /// puede liberar mientras se corre encima.
+    zombie: Option<usize>,
+    /// Tick en que se agota la tajada del hilo actual.
+    slice_end: u64,
+    /// Desde cuándo corre el hilo actual, en `time::now_ns`.
+    running_since: u64,
+    counters: Counters,
+}
+
+impl Scheduler {
+    const fn new() -> Self {
+        Scheduler {
+            threads: [const { None }; MAX_THREADS],
+            run_queues: [const { RunQueue::new() }; PRIORITIES],
+            current: 0,
+            idle: 0,
+            zombie: None,
+            slice_end: 0,
+            running_since: 0,
+            counters: Counters { voluntary: 0, preempted: 0 },
+        }
+    }
+
+    fn thread(&mut self, slot: usize) -> &mut Thread {
+        self.threads[slot].as_mut().expect("hilo inexistente en la run queue")
+    }
+
+    fn periodic(&mut self, slot: usize) -> Option<&mut Periodic> {
+        match &mut self.threads[slot].as_mut()?.class {
+            Class::Periodic(periodic) => Some(periodic),
+            Class::RoundRobin => None,
+        }
+    }
+
+    /// El periódico listo con el plazo más cercano.
+    fn earliest_deadline(&self) -> Option<usize> {
+        self.threads
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, thread)| match thread {
+                Some(Thread { state: State::Ready, class: Class::Periodic(periodic), .. }) => {
+                    Some((periodic.due, slot))
+                }
+                _ => None,
+            })
+            .min()
+            .map(|(_, slot)| slot)
+    }
+
+    fn has_ready(&self) -> bool {
+        self.run_queues.iter().any(|queue| !queue.is_empty()) || self.earliest_deadline().is_some()
+    }
+
+    /// Pone `slot` al final de la cola de su prioridad y, si es más urgente
+    /// que el que corre, hace que se cambie en el próximo tick.
+    fn enqueue(&mut self, slot: usize) {
+        let priority = self.thread(slot).priority();
+        self.run_queues[usize::from(priority)].push(slot);
+        let current = self.current;
+        if current == self.idle || priority < self.thread(current).priority() {
+            self.slice_end = 0;
+        }
+    }
+
+    /// Cambia la prioridad efectiva de `slot` moviéndolo de cola si espera
+    /// en una.
+    fn reprioritize(&mut self, slot: usize, update: impl FnOnce(&mut Thread)) {
+        let before = self.thread(slot).priority();
+        update(self.thread(slot));
+        let after = self.thread(slot).priority();
+        if before != after && self.run_queues[usize::from(before)].remove(slot) {
+            self.enqueue(slot);
+        }
+    }
+
+    fn slot_of(&self, id: ThreadId) -> Option<usize> {
+        self.threads.iter().position(|thread| thread.as_ref().is_some_and(|thread| thread.id == id))
+    }
+
+    /// `WouldBlock` si ya hay `MAX_THREADS`: se puede volver a probar cuando
+    /// termine alguno.
+    fn insert(&mut self, thread: Thread) -> KernelResult<usize> {
+        let slot = self.threads.iter().position(|t| t.is_none()).ok_or(KernelError::WouldBlock)?;
+        self.threads[slot] = Some(thread);
+        Ok(slot)
+    }
+
+    /// Elige el próximo hilo y deja al actual en la cola (o como zombie).
+    /// Devuelve dónde guardar el stack pointer actual y cuál cargar, o `None`
+    /// si sigue el mismo.
+    fn pick_next(&mut self, reason: Switch) -> Option<(*mut u64, u64)> {
+        let current = self.current;
+        let now = crate::time::now_ns();
+        let ran = now.saturating_sub(self.running_since);
+        self.thread(current).run_ns += ran;
+        self.running_since = now;
+        match self.thread(current).state {
+            State::Running => {
+                self.thread(current).state = State::Ready;
+                // Los periódicos listos no van a la cola: los encuentra
+                // `earliest_deadline`.
+                if current != self.idle && self.periodic(current).is_none() {
+                    let priority = self.thread(current).priority();
+                    self.run_queues[usize::from(priority)].push(current);
+                }
+            }
+            State::Finished => self.zombie = Some(current),
+            State::Ready | State::Blocked => {}
+        }
+        let next = self
+            .earliest_deadline()
+            .or_else(|| self.run_queues.iter_mut().find_map(RunQueue::pop))
+            .unwrap_or(self.idle);
+
+        self.thread(next).state = State::Running;
+        self.current = next;
+        self.slice_end = crate::time::ticks() + slice_ticks();
+        if next == current {
+            return None;
+        }
+        self.thread(next).switches += 1;
+        match reason {
+            Switch::Voluntary => self.counters.voluntary += 1,
+            Switch::Preempted => self.counters.preempted += 1,
+        }
+
+        // El espacio de direcciones cambia antes que el stack: los stacks
+        // están en la mitad del kernel, que es la misma en todos.
+        let next_thread = self.thread(next);
+        if let (Some(_), Some((_, top))) = (next_thread.page_table, next_thread.stack_bounds()) {
+            crate::gdt::set_privilege_stack(VirtAddr::new(top));
+            crate::syscall::set_kernel_stack(VirtAddr::new(top));
+        }
+        let page_table = next_thread.page_table.unwrap_or_else(crate::memory::kernel_page_table);
+        unsafe { crate::memory::switch_page_table(page_table) };
+
+        let old_rsp = &raw mut self.thread(current).rsp;
+        Some((old_rsp, self.thread(next).rsp))
+    }
+}
+
+static SCHEDULER: Mutex<Scheduler> = Mutex::new(Scheduler::new());
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+fn slice_ticks() -> u64 {
+    crate::time::ms_to_ticks(TIME_SLICE_MS).max(1)
+}
+
+/// Convierte el código actual en el hilo "main" y crea el hilo idle. Va
+/// después de `allocator::init_heap`.
+pub fn init() {
+    interrupts::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        assert!(!RUNNING.load(Ordering::Relaxed), "scheduler inicializado dos veces");
+        scheduler.current = scheduler.insert(Thread::boot("main")).expect("sin lugar para el hilo main");
+        let lowest = (PRIORITIES - 1) as Priority;
+        let idle = Thread::new("idle", lowest, idle_loop).expect("sin lugar para el stack del hilo idle");
+        scheduler.idle = scheduler.insert(idle).expect("sin lugar para el hilo idle");
+        scheduler.slice_end = crate::time::ticks() + slice_ticks();
+        scheduler.running_since = crate::time::now_ns();
+        RUNNING.store(true, Ordering::Release);
+    });
+}
+
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::Acquire)
+}
+
+/// Hilos existentes, incluidos main e idle.
+pub fn thread_count() -> usize {
+    interrupts::without_interrupts(|| SCHEDULER.lock().threads.iter().flatten().count())
+}
+
+/// Pone `thread` en su cola. Si no hay lugar, lo suelta y devuelve
+/// `WouldBlock`.
+pub(crate) fn add(thread: Thread) -> KernelResult<ThreadId> {
+    assert!(is_running(), "thread::spawn antes de scheduler::init");
+    interrupts::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let id = thread.id;
+        let slot = scheduler.insert(thread)?;
+        scheduler.enqueue(slot);
+        Ok(id)
+    })
+}
+
+/// Cambia la prioridad propia de `id`. Si heredó una más urgente, sigue con
+/// esa hasta `restore_priority`. Devuelve false si no existe.
+pub fn set_priority(id: ThreadId, priority: Priority) -> bool {
+    assert!(usize::from(priority) < PRIORITIES, "prioridad {} fuera de rango", priority);
+    interrupts::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let Some(slot) = scheduler.slot_of(id) else {
+            return false;
+        };
+        scheduler.reprioritize(slot, |thread| thread.base_priority = priority);
+        true
+    })
+}
+
+/// La prioridad efectiva de `id`.
+pub fn priority(id: ThreadId) -> Option<Priority> {
+    interrupts::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let slot = scheduler.slot_of(id)?;
+        Some(scheduler.thread(slot).priority())
+    })
+}
+
+/// Hook para locks que bloquean: un hilo con prioridad `priority` espera un
+/// lock de `owner`, que compite con ella si es más urgente que la suya.
+pub fn inherit_priority(owner: ThreadId, priority: Priority) {
+    interrupts::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        if let Some(slot) = scheduler.slot_of(owner) {
+            scheduler.reprioritize(slot, |thread| {
+                thread.inherited = Some(thread.inherited.map_or(priority, |old| old.min(priority)));
+            });
+        }
+    });
+}
+
+/// Hook para locks que bloquean: `owner` soltó el lock y vuelve a su
+/// prioridad propia.
+pub fn restore_priority(owner: ThreadId) {
+    interrupts::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        if let Some(slot) = scheduler.slot_of(owner) {
+            scheduler.reprioritize(slot, |thread| thread.inherited = None);
+        }
+    });
+}
+
+/// Crea un hilo que corre `work` cada `period_ms` milisegundos, la primera
+/// vez ya, con plazo `deadline_ms` desde el comienzo de cada período.
+pub fn spawn_periodic(name: &'static str, period_ms: u64, deadline_ms: u64, work: fn()) -> ThreadId {
+    assert!(is_running(), "spawn_periodic antes de scheduler::init");
+    assert!(deadline_ms <= period_ms, "plazo mayor que el período para '{}'", name);
+    let period = crate::time::ms_to_ticks(period_ms).max(1);
+    let deadline = crate::time::ms_to_ticks(deadline_ms).clamp(1, period);
+
+    let mut thread = Thread::new(name, DEFAULT_PRIORITY, periodic_loop).expect("sin lugar para el stack del hilo");
+    let now = crate::time::ticks();
+    let mut periodic = Periodic {
+        work,
+        period,
+        deadline,
+        due: 0,
+        next_release: now + period,
+        queued: None,
+        late: false,
+        activations: 0,
+        missed: 0,
+    };
+    periodic.start(now);
+    thread.class = Class::Periodic(periodic);
+
+    let (id, slot) = interrupts::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let id = thread.id;
+        (id, scheduler.insert(thread).expect("demasiados hilos"))
+    });
+    timer_wheel::add_timer(now + period, release, slot).expect("sin timers para el hilo periódico");
+    id
+}
+
+/// Callback del timer wheel al comenzar un período del hilo en `slot`.
+fn release(slot: usize) {
+    let now = crate::time::ticks();
+    let Some(mut scheduler) = SCHEDULER.try_lock() else {
+        rearm(now + 1, slot);
+        return;
+    };
+    let blocked = scheduler.thread(slot).state == State::Blocked;
+    let Some(periodic) = scheduler.periodic(slot) else {
+        return;
+    };
+    let release_at = periodic.next_release;
+    periodic.next_release += periodic.period;
+    let next_release = periodic.next_release;
+    if blocked {
+        periodic.start(release_at);
+    } else {
+        // La activación anterior sigue: ya perdió su plazo, y si había otra
+        // esperando, esa se saltea.
+        periodic.miss();
+        if periodic.queued.replace(release_at).is_some() {
+            periodic.missed += 1;
+        }
+    }
+    if blocked {
+        scheduler.thread(slot).state = State::Ready;
+        // Que cambie en este mismo tick.
+        scheduler.slice_end = 0;
+    }
+    drop(scheduler);
+    rearm(next_release, slot);
+}
+
+/// Programa la próxima liberación del hilo en `slot`. Sin timers libres el
+/// hilo no se vuelve a liberar: se cuenta como un plazo perdido, salvo que
+/// el scheduler esté tomado, y queda en el log.
+fn rearm(at: u64, slot: usize) {
+    if timer_wheel::add_timer(at, release, slot).is_some() {
+        return;
+    }
+    crate::log_rate_limited!(
+        crate::log::Level::Warn,
+        "sin timers para liberar el hilo periódico del lugar {}",
+        slot
+    );
+    if let Some(periodic) = SCHEDULER.try_lock().as_mut().and_then(|scheduler| scheduler.periodic(slot)) {
+        periodic.missed += 1;
+    }
+}
+
+fn periodic_loop() {
+    loop {
+        let work = interrupts::without_interrupts(|| {
+            let mut scheduler = SCHEDULER.lock();
+            let current = scheduler.current;
+            scheduler.periodic(current).expect("periodic_loop en un hilo no periódico").work
+        });
+        work();
+
+        interrupts::disable();
+        let mut scheduler = SCHEDULER.lock();
+        let current = scheduler.current;
+        let periodic = scheduler.periodic(current).expect("periodic_loop en un hilo no periódico");
+        if crate::time::ticks() > periodic.due {
+            periodic.miss();
+        }
+        let blocked = match periodic.queued.take() {
+            Some(release_at) => {
+                periodic.start(release_at);
+                false
+            }
+            None => true,
+        };
+        if blocked {
+            scheduler.thread(current).state = State::Blocked;
+        }
+        drop(scheduler);
+        if blocked {
+            reschedule(Switch::Voluntary);
+        }
+        interrupts::enable();
+    }
+}
+
+/// Activaciones y plazos perdidos de un hilo periódico.
+pub fn deadline_stats(id: ThreadId) -> Option<DeadlineStats> {
+    interrupts::without_interrupts(|| {
+        let scheduler = SCHEDULER.lock();
+        let slot = scheduler.slot_of(id)?;
+        scheduler.threads[slot].as_ref().and_then(|thread| {
+            match thread.class {
+                Class::Periodic(periodic) => Some(DeadlineStats {
+                    activations: periodic.activations,
+                    missed: periodic.missed,
+                }),
+                Class::RoundRobin => None,
+            }
+        })
+    })
+}
+
+/// El nombre de `id`, sin bloquear: `None` también si el scheduler está
+/// tomado. Para los reportes de los handlers de fallo.
+pub(crate) fn thread_name(id: ThreadId) -> Option<&'static str> {
+    let scheduler = SCHEDULER.try_lock()?;
+    let slot = scheduler.slot_of(id)?;
+    scheduler.threads[slot].as_ref().map(|thread| thread.name)
+}
+
+/// El estado de `id`, si existe.
+pub(crate) fn thread_state(id: ThreadId) -> Option<State> {
+    interrupts::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let slot = scheduler.slot_of(id)?;
+        Some(scheduler.thread(slot).state)
+    })
+}
+
+pub(crate) fn current() -> Option<ThreadId> {
+    if !is_running() {
+        return None;
+    }
+    interrupts::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let current = scheduler.current;
+        Some(scheduler.thread(current).id)
+    })
+}
+
+/// Cambia al próximo hilo listo, si hay. Se llama con las interrupciones
+/// deshabilitadas; vuelve cuando al hilo actual le toca de nuevo.
+fn reschedule(reason: Switch) {
+    // Desde el handler del timer el lock puede estar tomado por el código
+    // interrumpido: en ese caso se cambia en el próximo tick.
+    let Some(mut scheduler) = SCHEDULER.try_lock() else {
+        return;
+    };
+    let Some((old_rsp, new_rsp)) = scheduler.pick_next(reason) else {
+        return;
+    };
+    drop(scheduler);
+    unsafe { thread::switch(old_rsp, new_rsp) };
+    after_switch();
+}
+
+/// Lo que queda pendiente del hilo anterior una vez que se cambió de stack.
+pub(crate) fn after_switch() {
+    let zombie = SCHEDULER.lock().zombie.take();
+    if let Some(slot) = zombie {
+        // Se saca de la tabla con el lock y se libera sin él.
+        let thread = SCHEDULER.lock().threads[slot].take();
+        drop(thread);
+    }
+}
+
+/// Bloquea el hilo actual hasta que lo despierte `unblock`. Se llama con
+/// las interrupciones deshabilitadas, en la misma sección en que se anotó en
+/// lo que lo va a despertar.
+pub(crate) fn block_current() {
+    {
+        let mut scheduler = SCHEDULER.lock();
+        let current = scheduler.current;
+        assert!(current != scheduler.idle, "el hilo idle no se bloquea");
+        scheduler.thread(current).state = State::Blocked;
+    }
+    reschedule(Switch::Voluntary);
+}
+
+/// Pasa `id` a listo si está bloqueado. Sirve desde un handler de
+/// interrupción: si el lock está tomado, se reintenta en el próximo tick.
+pub(crate) fn unblock(id: ThreadId) {
+    interrupts::without_interrupts(|| {
+        let Some(mut scheduler) = SCHEDULER.try_lock() else {
+            timer_wheel::add_timer_after(1, unblock_later, id.as_u64() as usize);
+            return;
+        };
+        let Some(slot) = scheduler.slot_of(id) else {
+            return;
+        };
+        if scheduler.thread(slot).state != State::Blocked {
+            return;
+        }
+        scheduler.thread(slot).state = State::Ready;
+        if scheduler.periodic(slot).is_some() {
+            scheduler.slice_end = 0;
+        } else {
+            scheduler.enqueue(slot);
+        }
+    });
+}
+
+fn unblock_later(id: usize) {
+    unblock(ThreadId::from_u64(id as u64));
+}
+
+pub(crate) fn yield_current() {
+    if is_running() {
+        interrupts::without_interrupts(|| reschedule(Switch::Voluntary));
+    }
+}
+
+pub(crate) fn exit_current() -> ! {
+    interrupts::disable();
+    {
+        let mut scheduler = SCHEDULER.lock();
+        let current = scheduler.current;
+        assert!(current != scheduler.idle, "el hilo idle no termina");
+        scheduler.thread(current).state = State::Finished;
+    }
+    reschedule(Switch::Voluntary);
+    unreachable!("un hilo terminado volvió a correr");
+}
+
+/// Lo llama el handler del timer, ya mandado el EOI.
+pub(crate) fn on_timer_tick(now: u64) {
+    if !is_running() {
+        return;
+    }
+    let expired = SCHEDULER.try_lock().is_some_and(|scheduler| now >= scheduler.slice_end);
+    if expired {
+        reschedule(Switch::Preempted);
+    }
+}
+
+fn idle_loop() {
+    loop {
+        interrupts::disable();
+        let ready = SCHEDULER.lock().has_ready();
+        if ready {
+            reschedule(Switch::Voluntary);
+            interrupts::enable();
+        } else {
+            // `sti; hlt` no deja pasar una interrupción entre las dos.
+            crate::cpu_usage::enable_and_hlt();
+        }
+    }
+}
+
+/// Cambios de contexto y tiempo de CPU de cada hilo.
+pub fn stats() -> SchedulerStats {
+    let mut threads = Vec::with_capacity(MAX_THREADS);
+    let counters = interrupts::without_interrupts(|| {
+        let scheduler = SCHEDULER.lock();
+        let now = crate::time::now_ns();
+        for (slot, thread) in scheduler.threads.iter().enumerate() {
+            let Some(thread) = thread else {
+                continue;
+            };
+            let mut run_ns = thread.run_ns;
+            if slot == scheduler.current && is_running() {
+                run_ns += now.saturating_sub(scheduler.running_since);
+            }
+            threads.push(ThreadStats {
+                id: thread.id,
+                name: thread.name,
+                state: thread.state,
+                run_ns,
+                switches: thread.switches,
+                stack_high_water: thread.stack_high_water(),
+            });
+        }
+        scheduler.counters
+    });
+    SchedulerStats {
+        context_switches: counters.voluntary + counters.preempted,
+        voluntary: counters.voluntary,
+        preempted: counters.preempted,
+        threads,
+    }
+}
+
+pub fn print_threads() {
+    crate::println!("ID   NOMBRE           ESTADO    PRIO   PERÍODO  PERDIDOS  STACK");
+    interrupts::without_interrupts(|| {
+        let scheduler = SCHEDULER.lock();
+        for thread in scheduler.threads.iter().flatten() {
+            let state = match thread.state {
+                State::Ready => "listo",
+                State::Running => "corriendo",
+                State::Blocked => "bloqueado",
+                State::Finished => "terminado",
+            };
+            crate::print!("{:<4} {:<16} {:<9} ", thread.id.as_u64(), thread.name, state);
+            if thread.inherited.is_some() {
+                // Heredada, y entre paréntesis la propia.
+                crate::print!("{}({})   ", thread.priority(), thread.base_priority);
+            } else {
+                crate::print!("{:<6} ", thread.priority());
+            }
+            match thread.class {
+                Class::Periodic(periodic) => crate::print!(
+                    "{:<8} {:<9} ",
+                    crate::time::ticks_to_ms(periodic.period), periodic.missed
+                ),
+                Class::RoundRobin => crate::print!("{:<8} {:<9} ", "-", "-"),
+            }
+            match (thread.stack_bounds(), thread.stack_high_water()) {
+                (Some((base, top)), Some(used)) => {
+                    crate::println!("{:#x}..{:#x} ({} usados)", base, top, used)
+                }
+                _ => crate::println!("-"),
+            }
+        }
+    });
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_run_queue_is_fifo_and_wraps() {
+    let mut queue = RunQueue::new();
+    for round in 0..3 {
+        for slot in 0..MAX_THREADS {
+            queue.push(slot + round);
+        }
+        for slot in 0..MAX_THREADS {
+            assert_eq!(queue.pop(), Some(slot + round));
+        }
+        assert!(queue.is_empty());
+    }
+    assert_eq!(queue.pop(), None);
+}
+
+#[test_case]
+fn test_run_queue_remove_keeps_order() {
+    let mut queue = RunQueue::new();
+    for slot in [3, 1, 4, 5] {
+        queue.push(slot);
+    }
+    assert!(queue.remove(1));
+    assert!(!queue.remove(9));
+    assert_eq!(queue.pop(), Some(3));
+    assert_eq!(queue.pop(), Some(4));
+    assert_eq!(queue.pop(), Some(5));
+    assert!(queue.is_empty());
+}
@@ -1,6 +1,14 @@
 use uart_16550::SerialPort;
 use spin::Mutex;
 use lazy_static::lazy_static;
+use conquer_once::spin::OnceCell;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::task::Poll;
+use crossbeam_queue::ArrayQueue;
+use futures_util::task::AtomicWaker;
+use x86_64::instructions::interrupts;
+use crate::driver::{Driver, Stage};
+use crate::portio::{self, PortRegion};
 
 lazy_static! {
     pub static ref SERIAL1: Mutex<SerialPort> = {
@@ -10,12 +18,147 @@ lazy_static! {
     };
 }
 
+static COM1_PORTS: OnceCell<PortRegion> = OnceCell::uninit();
+
+fn init() {
+    let ports = portio::claim("serial", 0x3F8, 8).expect("puertos de COM1 ocupados");
+    COM1_PORTS.init_once(|| ports);
+    lazy_static::initialize(&SERIAL1);
+}
+
+crate::register_driver!(SERIAL_DRIVER, Driver {
+    name: "serial",
+    stage: Stage::Early,
+    depends_on: &[],
+    device: Some("PNP0501"),
+    probe: Driver::always,
+    init,
+});
+
+/// Acceso al COM1 sin pasar por el lock de `SERIAL1`, para rutas de crash
+/// donde el lock puede haber quedado tomado por el código que falló.
+///
+/// # Safety
+///
+/// Puede intercalar bytes con una escritura en curso; sólo debe usarse
+/// cuando el kernel ya no va a continuar o en diagnósticos como la NMI,
+/// donde eso es preferible a no escribir nada.
+pub unsafe fn emergency_writer() -> SerialPort {
+    unsafe { SerialPort::new(0x3F8) }
+}
+
+// ----------------- ESCRITURA ASINCRÓNICA -----------------
+
+/// Qué hacer cuando la cola de salida está llena.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Vaciar la cola al UART desde el llamador antes de seguir.
+    Block,
+    /// Descartar el byte más viejo de la cola y contarlo en `dropped()`.
+    DropOldest,
+}
+
+/// Bytes que se escriben al UART en cada poll de `writer_task`.
+const DRAIN_CHUNK: usize = 256;
+
+static OUTPUT_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
+static OUTPUT_WAKER: AtomicWaker = AtomicWaker::new();
+static DROP_OLDEST: AtomicBool = AtomicBool::new(false);
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Pasa la salida de consola por una cola de `capacity` bytes que vacía
+/// `writer_task`. Hasta que se llama, todo se escribe sincrónicamente.
+pub fn enable_async(capacity: usize, policy: Backpressure) {
+    DROP_OLDEST.store(policy == Backpressure::DropOldest, Ordering::Relaxed);
+    OUTPUT_QUEUE
+        .try_init_once(|| ArrayQueue::new(capacity))
+        .expect("serial::enable_async solo debería llamarse una vez");
+}
+
+/// Bytes descartados por la política `DropOldest`.
+pub fn dropped() -> u64 {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+/// Escribe texto de consola: lo encola si la salida asincrónica está activa.
+pub fn write_str(s: &str) {
+    use core::fmt::Write;
+
+    let Ok(queue) = OUTPUT_QUEUE.try_get() else {
+        interrupts::without_interrupts(|| {
+            SERIAL1.lock()
+                .write_str(s)
+                .expect("Fallo la impresión por puerto serie");
+        });
+        return;
+    };
+
+    for byte in s.bytes() {
+        while queue.push(byte).is_err() {
+            if DROP_OLDEST.load(Ordering::Relaxed) {
+                if queue.pop().is_some() {
+                    DROPPED.fetch_add(1, Ordering::Relaxed);
+                }
+            } else {
+                drain(queue, usize::MAX);
+            }
+        }
+    }
+    OUTPUT_WAKER.wake();
+}
+
+fn drain(queue: &ArrayQueue<u8>, max: usize) -> usize {
+    interrupts::without_interrupts(|| {
+        let mut port = SERIAL1.lock();
+        let mut written = 0;
+        while written < max {
+            match queue.pop() {
+                Some(byte) => port.send(byte),
+                None => break,
+            }
+            written += 1;
+        }
+        written
+    })
+}
+
+/// Vacía la cola de salida sincrónicamente. Lo llaman los panic handlers.
+pub fn flush() {
+    if let Ok(queue) = OUTPUT_QUEUE.try_get() {
+        drain(queue, usize::MAX);
+    }
+}
+
+/// Tarea que vacía la cola de salida al UART de a `DRAIN_CHUNK` bytes.
+pub async fn writer_task() {
+    let queue = OUTPUT_QUEUE
+        .try_get()
+        .expect("serial::writer_task sin enable_async");
+
+    futures_util::future::poll_fn(|cx| {
+        if drain(queue, DRAIN_CHUNK) == DRAIN_CHUNK {
+            // Quedan bytes: ceder el CPU y volver a la cola de listos.
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
+        OUTPUT_WAKER.register(cx.waker());
+        if !queue.is_empty() {
+            OUTPUT_WAKER.take();
+            cx.waker().wake_by_ref();
+        }
+        Poll::<()>::Pending
+    })
+    .await
+}
+
 #[doc(hidden)]
 pub fn _print(args: core::fmt::Arguments) {
     use core::fmt::Write;
-    use x86_64::instructions::interrupts;
 
     interrupts::without_interrupts(|| {
+        // Lo encolado antes tiene que salir antes que esto.
+        flush();
         SERIAL1.lock()
             .write_fmt(args)
             .expect("Fallo la impresión por puerto serie");
@@ -160,6 +160,13 @@ impl SlabAllocator {
         self.buddy.init(heap_start, heap_size);
     }
 
+    /// Crea un allocator sobre una región prestada; ver `BuddyAllocator::from_region`.
+    pub fn from_region(region: &'static mut [u8]) -> Self {
+        let mut allocator = Self::new();
+        allocator.buddy = BuddyAllocator::from_region(region);
+        allocator
+    }
+
     pub unsafe fn add_memory(&mut self, start: usize, size: usize) {
         self.buddy.add_memory(start, size);
     }
@@ -172,6 +179,12 @@ impl SlabAllocator {
         self.buddy.size()
     }
 
+    /// Bytes libres en el buddy. Los objetos libres dentro de slabs ya
+    /// creados no cuentan.
+    pub fn free_bytes(&self) -> usize {
+        self.buddy.free_bytes()
+    }
+
     pub fn allocate(&mut self, size: usize, align: usize) -> *mut u8 {
         let effective_size = size.max(align);
 
@@ -209,3 +222,66 @@ impl SlabAllocator {
 }
 
 unsafe impl Send for SlabAllocator {}
+
+// ----------------- TESTS -----------------
+
+#[cfg(test)]
+use crate::buddy::test_region;
+
+#[test_case]
+fn test_slab_objects_share_a_page() {
+    let mut slab = SlabAllocator::from_region(test_region!(64 * 1024));
+
+    let a = slab.allocate(32, 8);
+    let b = slab.allocate(32, 8);
+    assert!(!a.is_null() && !b.is_null());
+    assert_ne!(a, b);
+    assert_eq!(a as usize & !(PAGE_SIZE - 1), b as usize & !(PAGE_SIZE - 1));
+    assert_eq!(a as usize % 32, 0);
+}
+
+#[test_case]
+fn test_slab_reuses_freed_object() {
+    let mut slab = SlabAllocator::from_region(test_region!(64 * 1024));
+
+    let a = slab.allocate(100, 8);
+    unsafe { slab.deallocate(a, 100, 8) };
+    let b = slab.allocate(128, 8);
+    assert_eq!(a, b);
+}
+
+#[test_case]
+fn test_slab_size_classes_use_separate_pages() {
+    let mut slab = SlabAllocator::from_region(test_region!(64 * 1024));
+
+    let small = slab.allocate(8, 8);
+    let medium = slab.allocate(256, 8);
+    assert_ne!(small as usize & !(PAGE_SIZE - 1), medium as usize & !(PAGE_SIZE - 1));
+}
+
+#[test_case]
+fn test_slab_grows_to_new_page_when_full() {
+    let mut slab = SlabAllocator::from_region(test_region!(64 * 1024));
+
+    // Con el header del slab, una página de 4 KiB tiene lugar para un solo objeto de 2 KiB.
+    let a = slab.allocate(2048, 8);
+    let b = slab.allocate(2048, 8);
+    assert!(!a.is_null() && !b.is_null());
+    assert_ne!(a as usize & !(PAGE_SIZE - 1), b as usize & !(PAGE_SIZE - 1));
+
+    unsafe {
+        slab.deallocate(a, 2048, 8);
+        slab.deallocate(b, 2048, 8);
+    }
+    assert_eq!(slab.allocate(2048, 8), b);
+}
+
+#[test_case]
+fn test_slab_large_allocations_go_to_buddy() {
+    let mut slab = SlabAllocator::from_region(test_region!(64 * 1024));
+
+    let large = slab.allocate(8192, 8);
+    assert!(!large.is_null());
+    assert_eq!(large as usize % 8192, 0);
+    unsafe { slab.deallocate(large, 8192, 8) };
+}
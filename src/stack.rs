@@ -0,0 +1,138 @@
+//! Stacks de los hilos del kernel, con página de guarda.
+//!
+//! Los stacks no salen del heap: cada uno ocupa un lugar fijo de la ventana
+//! que empieza en `STACKS_START`, y la primera página de cada lugar queda
+//! sin mapear. Un hilo que desborda su stack escribe en esa página y el CPU
+//! avisa con un fallo de página (o con un doble fallo, si lo que no entró fue
+//! el propio frame del fallo) en vez de pisar lo que haya al lado. Los dos
+//! handlers consultan `overflowed_thread` para decir de qué hilo era.
+//!
+//! Al soltarse un stack sus páginas se desmapean y los marcos vuelven a
+//! `memory::allocate_frame`. Si en ese momento el mapper está tomado (el
+//! stack se suelta justo después de un cambio de hilo, y quien lo tiene
+//! puede no correr hasta mucho después), las páginas quedan mapeadas y las
+//! reusa el próximo hilo que tome el lugar.
+//!
+//! Cada stack nuevo se llena con `FILL`; `Stack::high_water` busca desde
+//! abajo la primera palabra que ya no lo tiene para saber cuánto llegó a usar
+//! el hilo. Sirve para elegir `STACK_SIZE` midiendo en vez de adivinar.
+
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::structures::paging::Page;
+use x86_64::VirtAddr;
+
+use crate::error::{KernelError, KernelResult};
+use crate::scheduler::MAX_THREADS;
+use crate::thread::{ThreadId, STACK_SIZE};
+
+/// Comienzo de la ventana de stacks.
+pub const STACKS_START: u64 = 0x_6666_0000_0000;
+pub const GUARD_SIZE: u64 = 4096;
+const SLOT_SIZE: u64 = GUARD_SIZE + STACK_SIZE as u64;
+/// Patrón de un stack sin tocar.
+pub const FILL: u64 = 0x57AC_57AC_57AC_57AC;
+
+/// Id más uno del hilo dueño de cada lugar; 0 si está libre.
+static OWNERS: [AtomicU64; MAX_THREADS] = [const { AtomicU64::new(0) }; MAX_THREADS];
+
+pub(crate) struct Stack {
+    slot: usize,
+}
+
+impl Stack {
+    /// Un stack para `owner`, ya mapeado.
+    pub(crate) fn new(owner: ThreadId) -> KernelResult<Stack> {
+        let slot = OWNERS
+            .iter()
+            .position(|o| o.compare_exchange(0, owner.as_u64() + 1, Ordering::AcqRel, Ordering::Relaxed).is_ok())
+            .ok_or(KernelError::OutOfMemory)?;
+        // Si falla el mapeo, soltarlo libera el lugar.
+        let stack = Stack { slot };
+        let (base, top) = stack.bounds();
+        let first = Page::containing_address(VirtAddr::new(base));
+        let last = Page::containing_address(VirtAddr::new(top - 1));
+        for page in Page::range_inclusive(first, last) {
+            crate::memory::map_page(page)?;
+        }
+        // Todavía no es de ningún hilo: nadie más lo está usando.
+        unsafe { core::slice::from_raw_parts_mut(base as *mut u64, STACK_SIZE / 8).fill(FILL) };
+        Ok(stack)
+    }
+
+    /// Bytes que llegó a usar el hilo: desde el tope hasta la palabra más
+    /// profunda que no tiene `FILL`. Un valor escrito igual al patrón pasa
+    /// por no usado, así que es una cota por debajo.
+    pub(crate) fn high_water(&self) -> usize {
+        let (base, _) = self.bounds();
+        let words = unsafe { core::slice::from_raw_parts(base as *const u64, STACK_SIZE / 8) };
+        let untouched = words.iter().take_while(|&&word| word == FILL).count();
+        STACK_SIZE - untouched * 8
+    }
+
+    /// `[base, top)`, sin la guarda.
+    pub(crate) fn bounds(&self) -> (u64, u64) {
+        let base = STACKS_START + self.slot as u64 * SLOT_SIZE + GUARD_SIZE;
+        (base, base + STACK_SIZE as u64)
+    }
+}
+
+impl Drop for Stack {
+    fn drop(&mut self) {
+        let (base, top) = self.bounds();
+        let first = Page::containing_address(VirtAddr::new(base));
+        let last = Page::containing_address(VirtAddr::new(top - 1));
+        for page in Page::range_inclusive(first, last) {
+            // El hilo ya no corre en este stack. Una página que no se pudo
+            // desmapear, o que nunca se mapeó, queda como está.
+            let _ = unsafe { crate::memory::try_unmap_page(page) };
+        }
+        OWNERS[self.slot].store(0, Ordering::Release);
+    }
+}
+
+/// El hilo cuyo stack se desbordó, para los reportes de los handlers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OverflowedThread {
+    pub id: ThreadId,
+    /// `None` si el scheduler estaba tomado al preguntar.
+    pub name: Option<&'static str>,
+}
+
+impl fmt::Display for OverflowedThread {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.id.as_u64())?;
+        if let Some(name) = self.name {
+            write!(f, " ('{}')", name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Si `addr` cae en la página de guarda de un stack en uso, de quién es. No
+/// bloquea: sirve desde los handlers de fallo.
+pub fn overflowed_thread(addr: u64) -> Option<OverflowedThread> {
+    let offset = addr.checked_sub(STACKS_START)?;
+    let slot = (offset / SLOT_SIZE) as usize;
+    if slot >= MAX_THREADS || offset % SLOT_SIZE >= GUARD_SIZE {
+        return None;
+    }
+    let owner = OWNERS[slot].load(Ordering::Acquire).checked_sub(1)?;
+    let id = ThreadId::from_u64(owner);
+    Some(OverflowedThread { id, name: crate::scheduler::thread_name(id) })
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_only_guard_pages_of_used_slots_match() {
+    let guard = STACKS_START + 3 * SLOT_SIZE;
+    assert_eq!(overflowed_thread(guard), None);
+
+    OWNERS[3].store(41 + 1, Ordering::Relaxed);
+    assert_eq!(overflowed_thread(guard + 8).map(|thread| thread.id), Some(ThreadId::from_u64(41)));
+    // Dentro del stack, no en la guarda.
+    assert_eq!(overflowed_thread(guard + GUARD_SIZE), None);
+    assert_eq!(overflowed_thread(STACKS_START - 1), None);
+    OWNERS[3].store(0, Ordering::Relaxed);
+}
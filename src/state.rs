@@ -0,0 +1,137 @@
+//! Volcado y verificación de invariantes del kernel.
+//!
+//! `dump` escribe en un buffer un registro binario con el estado que importa
+//! para saber si el kernel quedó sano: alocaciones vivas, tareas, hilos,
+//! timers pendientes, y contadores que sólo crecen (ticks, polls,
+//! interrupciones). `verify` compara un registro con el estado actual: los
+//! primeros tienen que coincidir y los contadores no pueden haber bajado.
+//!
+//! Está pensado para iterar rápido sobre escenarios largos con snapshots de
+//! QEMU: se llega al punto de interés, `dump`, `savevm` en el monitor, se
+//! prueba lo que sea, `loadvm`, y `verify` confirma que lo restaurado es
+//! coherente. También sirve sin QEMU, para comprobar que una prueba deja todo
+//! como lo encontró.
+//!
+//! Formato: `MAGIC`, versión (u16), cantidad de campos (u16) y un u64 por
+//! campo, todo little-endian, en el orden de `FIELDS`.
+
+use core::fmt;
+
+use crate::error::KernelError;
+
+const MAGIC: [u8; 4] = *b"KSTA";
+const VERSION: u16 = 1;
+const HEADER_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rule {
+    /// Tiene que volver al mismo valor.
+    Equal,
+    /// Puede haber crecido, nunca bajado.
+    Monotonic,
+}
+
+struct Field {
+    name: &'static str,
+    rule: Rule,
+    read: fn() -> u64,
+}
+
+const FIELDS: [Field; 9] = [
+    Field { name: "heap.live", rule: Rule::Equal, read: || crate::allocator::stats().live },
+    Field { name: "heap.size", rule: Rule::Monotonic, read: || crate::allocator::stats().size as u64 },
+    Field { name: "executor.live_tasks", rule: Rule::Equal, read: || crate::task::executor::live_tasks() as u64 },
+    Field { name: "executor.polls", rule: Rule::Monotonic, read: crate::task::executor::total_polls },
+    Field { name: "scheduler.threads", rule: Rule::Equal, read: || crate::scheduler::thread_count() as u64 },
+    Field { name: "timer_wheel.pending", rule: Rule::Equal, read: || crate::timer_wheel::pending() as u64 },
+    Field { name: "ticks", rule: Rule::Monotonic, read: crate::interrupts::ticks },
+    Field { name: "irq.timer", rule: Rule::Monotonic, read: || crate::interrupts::IRQ_TIMER.get() },
+    Field {
+        name: "irq.spurious",
+        rule: Rule::Monotonic,
+        read: || {
+            let stats = crate::interrupts::stats();
+            stats.spurious_pic1 + stats.spurious_pic2 + stats.spurious_apic
+        },
+    },
+];
+
+/// Bytes que ocupa un volcado.
+pub const DUMP_LEN: usize = HEADER_LEN + FIELDS.len() * 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    /// El buffer es chico para el volcado.
+    BufferTooSmall,
+    /// El buffer no tiene un volcado de esta versión del kernel.
+    BadFormat,
+    Mismatch { field: &'static str, expected: u64, found: u64 },
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::BufferTooSmall => write!(f, "buffer chico para el volcado"),
+            StateError::BadFormat => write!(f, "volcado inválido"),
+            StateError::Mismatch { field, expected, found } => {
+                write!(f, "{}: se esperaba {}, hay {}", field, expected, found)
+            }
+        }
+    }
+}
+
+impl From<StateError> for KernelError {
+    fn from(_: StateError) -> Self {
+        KernelError::InvalidArgument
+    }
+}
+
+/// Escribe el estado actual al comienzo de `buffer`. Devuelve los bytes
+/// escritos (`DUMP_LEN`).
+pub fn dump(buffer: &mut [u8]) -> Result<usize, StateError> {
+    let buffer = buffer.get_mut(..DUMP_LEN).ok_or(StateError::BufferTooSmall)?;
+    buffer[..4].copy_from_slice(&MAGIC);
+    buffer[4..6].copy_from_slice(&VERSION.to_le_bytes());
+    buffer[6..8].copy_from_slice(&(FIELDS.len() as u16).to_le_bytes());
+    for (field, bytes) in FIELDS.iter().zip(buffer[HEADER_LEN..].chunks_exact_mut(8)) {
+        bytes.copy_from_slice(&(field.read)().to_le_bytes());
+    }
+    Ok(DUMP_LEN)
+}
+
+/// Compara el volcado de `buffer` con el estado actual. Devuelve el primer
+/// campo que no cumple su regla.
+pub fn verify(buffer: &[u8]) -> Result<(), StateError> {
+    let buffer = buffer.get(..DUMP_LEN).ok_or(StateError::BadFormat)?;
+    let header_ok = buffer[..4] == MAGIC
+        && buffer[4..6] == VERSION.to_le_bytes()
+        && buffer[6..8] == (FIELDS.len() as u16).to_le_bytes();
+    if !header_ok {
+        return Err(StateError::BadFormat);
+    }
+    for (field, bytes) in FIELDS.iter().zip(buffer[HEADER_LEN..].chunks_exact(8)) {
+        let expected = u64::from_le_bytes(bytes.try_into().unwrap());
+        let found = (field.read)();
+        let ok = match field.rule {
+            Rule::Equal => found == expected,
+            Rule::Monotonic => found >= expected,
+        };
+        if !ok {
+            return Err(StateError::Mismatch { field: field.name, expected, found });
+        }
+    }
+    Ok(())
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_verify_rejects_foreign_buffers() {
+    assert_eq!(dump(&mut [0; 4]), Err(StateError::BufferTooSmall));
+    assert_eq!(verify(&[0; DUMP_LEN]), Err(StateError::BadFormat));
+
+    let mut buffer = [0u8; DUMP_LEN];
+    dump(&mut buffer).unwrap();
+    buffer[4] ^= 0xFF;
+    assert_eq!(verify(&buffer), Err(StateError::BadFormat));
+}
@@ -0,0 +1,342 @@
+//! Entrada al kernel desde ring 3 con `syscall`.
+//!
+//! La convención es la de Linux: el número va en `rax`, los argumentos en
+//! `rdi`, `rsi`, `rdx`, `r10`, `r8` y `r9`, y el resultado vuelve en `rax`,
+//! con los errores como `-errno`. La CPU pisa `rcx` (RIP de vuelta) y `r11`
+//! (RFLAGS); el resto de los registros le vuelve al usuario como estaba.
+//!
+//! `entry` cambia al stack de `RSP0` del TSS, guarda los registros del
+//! usuario en un `SyscallFrame` y llama al handler registrado para el número.
+//! El handler recibe el frame entero, así puede leer los argumentos y también
+//! cambiar a dónde se vuelve.
+//!
+//! El stack es el del hilo que corre el proceso (`set_kernel_stack`, que el
+//! scheduler cambia junto con `RSP0`). `SFMASK` apaga las interrupciones al
+//! entrar y las syscalls corren así de punta a punta; un handler igual puede
+//! bloquearse o terminar el hilo, porque cada proceso tiene su stack.
+
+use core::arch::naked_asm;
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::registers::model_specific::{Efer, EferFlags, LStar, SFMask, Star};
+use x86_64::registers::rflags::RFlags;
+use x86_64::VirtAddr;
+
+use crate::driver::{Driver, Stage};
+use crate::error::{KernelError, KernelResult};
+
+pub const MAX_SYSCALLS: usize = 64;
+/// RFLAGS con el que arranca un programa: interrupciones habilitadas (y el
+/// bit 1, que siempre va en 1).
+pub const USER_RFLAGS: u64 = 0x202;
+/// Primera dirección después de la mitad baja del espacio canónico.
+const LOWER_HALF_END: u64 = 0x8000_0000_0000;
+
+// Los `errno` que puede devolver una syscall, con los valores de Linux.
+pub const ENOENT: i64 = 2;
+pub const EIO: i64 = 5;
+pub const EAGAIN: i64 = 11;
+pub const ENOMEM: i64 = 12;
+pub const EFAULT: i64 = 14;
+pub const EEXIST: i64 = 17;
+pub const EINVAL: i64 = 22;
+pub const ENOSYS: i64 = 38;
+pub const ETIMEDOUT: i64 = 110;
+
+/// Los registros del usuario al entrar, en el orden en que `entry` los
+/// apila (del último al primero).
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct SyscallFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub rbp: u64,
+    pub rbx: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub r10: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    /// El número de syscall.
+    pub rax: u64,
+    /// A dónde vuelve `sysret` (lo guardó la CPU en `rcx`).
+    pub rip: u64,
+    /// RFLAGS del usuario (lo guardó la CPU en `r11`).
+    pub rflags: u64,
+    pub rsp: u64,
+}
+
+impl SyscallFrame {
+    pub fn number(&self) -> u64 {
+        self.rax
+    }
+
+    /// El argumento `index` (de 0 a 5).
+    pub fn arg(&self, index: usize) -> u64 {
+        [self.rdi, self.rsi, self.rdx, self.r10, self.r8, self.r9][index]
+    }
+}
+
+pub type SyscallHandler = fn(&mut SyscallFrame) -> KernelResult<u64>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallError {
+    /// El número no entra en la tabla.
+    OutOfRange(usize),
+    AlreadyRegistered(usize),
+    NotRegistered(usize),
+}
+
+impl fmt::Display for SyscallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyscallError::OutOfRange(number) => write!(f, "syscall {} fuera de rango", number),
+            SyscallError::AlreadyRegistered(number) => write!(f, "syscall {} ya registrada", number),
+            SyscallError::NotRegistered(number) => write!(f, "syscall {} no registrada", number),
+        }
+    }
+}
+
+impl From<SyscallError> for KernelError {
+    fn from(err: SyscallError) -> Self {
+        match err {
+            SyscallError::OutOfRange(_) => KernelError::InvalidArgument,
+            SyscallError::AlreadyRegistered(_) => KernelError::InvalidArgument,
+            SyscallError::NotRegistered(_) => KernelError::NotFound,
+        }
+    }
+}
+
+static HANDLERS: Mutex<[Option<SyscallHandler>; MAX_SYSCALLS]> = Mutex::new([None; MAX_SYSCALLS]);
+
+/// Tope del stack al que cambia `entry`.
+static KERNEL_RSP: AtomicU64 = AtomicU64::new(0);
+/// Donde `entry` deja el RSP del usuario mientras cambia de stack.
+static USER_RSP: AtomicU64 = AtomicU64::new(0);
+
+/// Asocia `handler` al número `number`.
+pub fn register(number: usize, handler: SyscallHandler) -> Result<(), SyscallError> {
+    interrupts::without_interrupts(|| {
+        let mut handlers = HANDLERS.lock();
+        let slot = handlers.get_mut(number).ok_or(SyscallError::OutOfRange(number))?;
+        if slot.is_some() {
+            return Err(SyscallError::AlreadyRegistered(number));
+        }
+        *slot = Some(handler);
+        Ok(())
+    })
+}
+
+pub fn unregister(number: usize) -> Result<(), SyscallError> {
+    interrupts::without_interrupts(|| {
+        let mut handlers = HANDLERS.lock();
+        let slot = handlers.get_mut(number).ok_or(SyscallError::OutOfRange(number))?;
+        slot.take().map(|_| ()).ok_or(SyscallError::NotRegistered(number))
+    })
+}
+
+/// El `errno` con el que le llega `err` al usuario.
+pub fn errno(err: KernelError) -> i64 {
+    match err {
+        KernelError::OutOfMemory => ENOMEM,
+        KernelError::NotMapped => EFAULT,
+        KernelError::AlreadyMapped => EEXIST,
+        KernelError::NotInitialized | KernelError::Unsupported => ENOSYS,
+        KernelError::DeviceError(_) => EIO,
+        KernelError::NotFound => ENOENT,
+        KernelError::InvalidArgument => EINVAL,
+        KernelError::WouldBlock => EAGAIN,
+        KernelError::TimedOut => ETIMEDOUT,
+    }
+}
+
+/// Busca el handler y lo corre; lo que devuelve va a parar a `rax`.
+extern "C" fn dispatch(frame: &mut SyscallFrame) -> u64 {
+    // Se copia para no tener la tabla tomada mientras corre el handler.
+    let handler = usize::try_from(frame.number())
+        .ok()
+        .and_then(|number| HANDLERS.lock().get(number).copied().flatten());
+    let result = match handler {
+        Some(handler) => handler(frame).map_err(errno),
+        None => Err(ENOSYS),
+    };
+    // `sysret` a un RIP fuera de la mitad baja da #GP ya en ring 0 y con el
+    // stack del usuario. Pasa con un `syscall` justo al final de la mitad
+    // baja, o si un handler dejó mal el frame: no hay a dónde volver.
+    if frame.rip >= LOWER_HALF_END {
+        match crate::process::current() {
+            Some(_) => crate::process::exit(-EFAULT),
+            None => panic!("syscall vuelve a {:#x} fuera de un proceso", frame.rip),
+        }
+    }
+    match result {
+        Ok(value) => value,
+        Err(errno) => (-errno) as u64,
+    }
+}
+
+/// El destino de `syscall` (`LSTAR`).
+#[unsafe(naked)]
+unsafe extern "C" fn entry() -> ! {
+    naked_asm!(
+        "mov [rip + {user_rsp}], rsp",
+        "mov rsp, [rip + {kernel_rsp}]",
+        "push qword ptr [rip + {user_rsp}]",
+        "push r11",
+        "push rcx",
+        "push rax",
+        "push rdi",
+        "push rsi",
+        "push rdx",
+        "push r10",
+        "push r8",
+        "push r9",
+        "push rbx",
+        "push rbp",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        // 16 registros: el stack queda alineado a 16 para la llamada.
+        "mov rdi, rsp",
+        "call {dispatch}",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop rbp",
+        "pop rbx",
+        "pop r9",
+        "pop r8",
+        "pop r10",
+        "pop rdx",
+        "pop rsi",
+        "pop rdi",
+        // El número: en `rax` queda el resultado.
+        "add rsp, 8",
+        "pop rcx",
+        "pop r11",
+        "pop rsp",
+        "sysretq",
+        user_rsp = sym USER_RSP,
+        kernel_rsp = sym KERNEL_RSP,
+        dispatch = sym dispatch,
+    );
+}
+
+/// Cambia el stack al que salta `entry`.
+pub(crate) fn set_kernel_stack(top: VirtAddr) {
+    KERNEL_RSP.store(top.as_u64(), Ordering::Relaxed);
+}
+
+/// Baja a ring 3 en `rip` con el stack `rsp`, como si volviera de una
+/// syscall, con las interrupciones habilitadas.
+///
+/// # Safety
+///
+/// `rip` y `rsp` tienen que estar mapeadas con acceso de usuario, y lo que
+/// quede en el stack del kernel no se vuelve a usar.
+pub unsafe fn enter_user(rip: VirtAddr, rsp: VirtAddr) -> ! {
+    interrupts::disable();
+    unsafe {
+        core::arch::asm!(
+            "mov rsp, {rsp}",
+            "sysretq",
+            rsp = in(reg) rsp.as_u64(),
+            in("rcx") rip.as_u64(),
+            in("r11") USER_RFLAGS,
+            options(noreturn),
+        )
+    }
+}
+
+/// Vuelve a ring 3 con los registros de `frame`, como si terminara la
+/// syscall que lo guardó, con `frame.rax` como resultado.
+///
+/// # Safety
+///
+/// Las interrupciones tienen que estar deshabilitadas, el espacio de
+/// direcciones cargado tiene que ser el del frame, y lo que quede en el stack
+/// del kernel no se vuelve a usar.
+#[unsafe(naked)]
+pub unsafe extern "C" fn return_to_user(frame: &SyscallFrame) -> ! {
+    naked_asm!(
+        // El frame hace de stack: se desapila igual que en `entry`.
+        "mov rsp, rdi",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop rbp",
+        "pop rbx",
+        "pop r9",
+        "pop r8",
+        "pop r10",
+        "pop rdx",
+        "pop rsi",
+        "pop rdi",
+        "pop rax",
+        "pop rcx",
+        "pop r11",
+        "pop rsp",
+        "sysretq",
+    );
+}
+
+pub fn init() {
+    let (_, top) = crate::gdt::privilege_stack_bounds();
+    set_kernel_stack(top);
+
+    Star::write(
+        crate::gdt::user_code_selector(),
+        crate::gdt::user_data_selector(),
+        crate::gdt::kernel_code_selector(),
+        crate::gdt::kernel_data_selector(),
+    )
+    .expect("orden de la GDT incompatible con sysret");
+    LStar::write(VirtAddr::from_ptr(entry as *const ()));
+    SFMask::write(RFlags::INTERRUPT_FLAG | RFlags::TRAP_FLAG | RFlags::DIRECTION_FLAG);
+    unsafe { Efer::update(|flags| *flags |= EferFlags::SYSTEM_CALL_EXTENSIONS) };
+}
+
+crate::register_driver!(SYSCALL_DRIVER, Driver {
+    name: "syscall",
+    stage: Stage::Early,
+    depends_on: &["gdt"],
+    device: None,
+    probe: Driver::always,
+    init,
+});
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_dispatch_by_number() {
+    fn add(frame: &mut SyscallFrame) -> KernelResult<u64> {
+        Ok(frame.arg(0) + frame.arg(3))
+    }
+    fn fail(_: &mut SyscallFrame) -> KernelResult<u64> {
+        Err(KernelError::NotMapped)
+    }
+
+    let number = MAX_SYSCALLS - 1;
+    register(number, add).unwrap();
+    assert_eq!(register(number, fail), Err(SyscallError::AlreadyRegistered(number)));
+    assert_eq!(register(MAX_SYSCALLS, add), Err(SyscallError::OutOfRange(MAX_SYSCALLS)));
+
+    let mut frame = SyscallFrame { rax: number as u64, rdi: 40, r10: 2, ..Default::default() };
+    assert_eq!(dispatch(&mut frame), 42);
+
+    unregister(number).unwrap();
+    register(number, fail).unwrap();
+    assert_eq!(dispatch(&mut frame) as i64, -EFAULT);
+    unregister(number).unwrap();
+    assert_eq!(dispatch(&mut frame) as i64, -ENOSYS);
+    frame.rax = u64::MAX;
+    assert_eq!(dispatch(&mut frame) as i64, -ENOSYS);
+}
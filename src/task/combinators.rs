@@ -0,0 +1,142 @@
+//! `join!` y `select!`: esperar varios futures a la vez dentro de una tarea.
+//!
+//! Las dos macros pollean los futures en su propio stack (con `pin!`), sin
+//! alocar ni spawnear tareas, y en el orden en que se escriben. Las dos se
+//! usan dentro de un `async`: ya incluyen el `.await`.
+//!
+//! ```ignore
+//! let (a, b) = task::join!(leer_puerto(), timer::sleep_ms(10));
+//!
+//! let byte = task::select! {
+//!     byte = teclado.next() => byte,
+//!     () = timer::sleep_ms(500) => None,
+//! };
+//! ```
+
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+/// Un future de `join!` y, cuando terminó, su salida.
+#[doc(hidden)]
+pub enum MaybeDone<F: Future> {
+    Pending(F),
+    Done(F::Output),
+    Taken,
+}
+
+impl<F: Future> MaybeDone<F> {
+    pub fn new(future: F) -> Self {
+        MaybeDone::Pending(future)
+    }
+
+    /// Pollea el future si todavía no terminó. Devuelve si ya terminó.
+    pub fn poll_done(mut self: Pin<&mut Self>, context: &mut Context) -> bool {
+        // El future no se mueve: se reemplaza recién cuando ya terminó.
+        let output = match unsafe { self.as_mut().get_unchecked_mut() } {
+            MaybeDone::Pending(future) => match unsafe { Pin::new_unchecked(future) }.poll(context) {
+                Poll::Ready(output) => output,
+                Poll::Pending => return false,
+            },
+            MaybeDone::Done(_) => return true,
+            MaybeDone::Taken => panic!("join! polleado después de terminar"),
+        };
+        self.set(MaybeDone::Done(output));
+        true
+    }
+
+    pub fn take(mut self: Pin<&mut Self>) -> F::Output {
+        match unsafe { core::mem::replace(self.as_mut().get_unchecked_mut(), MaybeDone::Taken) } {
+            MaybeDone::Done(output) => output,
+            _ => panic!("join! sin terminar"),
+        }
+    }
+}
+
+/// Espera a que terminen todos los futures y devuelve sus salidas en una
+/// tupla, en el mismo orden: `let (a, b) = join!(fa, fb);`.
+#[macro_export]
+macro_rules! join {
+    ($($future:expr),+ $(,)?) => {
+        $crate::join!(@futures [] $($future,)+)
+    };
+    // Cada vuelta agrega un `f` propio: la higiene de `macro_rules` los
+    // mantiene distintos.
+    (@futures [$($f:ident = $e:expr,)*] $future:expr, $($rest:expr,)*) => {
+        $crate::join!(@futures [$($f = $e,)* f = $future,] $($rest,)*)
+    };
+    (@futures [$($f:ident = $e:expr,)*]) => {{
+        $( let mut $f = ::core::pin::pin!($crate::task::combinators::MaybeDone::new($e)); )*
+        ::core::future::poll_fn(|context| {
+            let mut done = true;
+            $( done &= $f.as_mut().poll_done(context); )*
+            if done {
+                ::core::task::Poll::Ready(($( $f.as_mut().take(), )*))
+            } else {
+                ::core::task::Poll::Pending
+            }
+        })
+        .await
+    }};
+}
+
+/// Espera al primero de los futures que termine y evalúa su rama; los demás
+/// se sueltan sin terminar. Si varios están listos gana el que está escrito
+/// primero. Si la salida no coincide con el patrón, la rama se descarta y se
+/// sigue esperando a las otras; si no queda ninguna, entra en pánico.
+///
+/// Las ramas se evalúan dentro de un `loop`: un `break` o `continue` sin
+/// etiqueta en una rama se refiere al `select!`, así que para salir de un
+/// bucle de afuera hay que usar una etiqueta.
+///
+/// ```ignore
+/// select! {
+///     patrón = future => expresión,
+///     ...
+/// }
+/// ```
+#[macro_export]
+macro_rules! select {
+    ($($pattern:pat = $future:expr => $body:expr),+ $(,)?) => {
+        $crate::select!(@branches [] $($pattern = $future => $body,)+)
+    };
+    (@branches [$($f:ident $o:ident $d:ident $p:pat = $e:expr => $b:expr,)*]
+        $pattern:pat = $future:expr => $body:expr, $($rest:tt)*) => {
+        $crate::select!(@branches [$($f $o $d $p = $e => $b,)* f o d $pattern = $future => $body,] $($rest)*)
+    };
+    (@branches [$($f:ident $o:ident $d:ident $p:pat = $e:expr => $b:expr,)*]) => {{
+        $( let mut $f = ::core::pin::pin!($e); let mut $o = None; let mut $d = false; )*
+        loop {
+            ::core::future::poll_fn(|context| {
+                $(
+                    // Un future terminado no se vuelve a pollear.
+                    if !$d {
+                        if let ::core::task::Poll::Ready(output) =
+                            ::core::future::Future::poll($f.as_mut(), context)
+                        {
+                            $d = true;
+                            $o = Some(output);
+                            return ::core::task::Poll::Ready(());
+                        }
+                    }
+                )*
+                if true $( && $d )* {
+                    panic!("select!: ninguna rama coincidió con su patrón");
+                }
+                ::core::task::Poll::Pending
+            })
+            .await;
+            // La salida se compara por valor, una sola vez: si no coincide,
+            // la rama queda descartada y se vuelve a esperar a las otras.
+            $(
+                if let Some(output) = $o.take() {
+                    #[allow(unreachable_patterns)]
+                    match output {
+                        $p => break $b,
+                        _ => {}
+                    }
+                }
+            )*
+        }
+    }};
+}
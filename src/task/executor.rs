@@ -1,58 +1,297 @@
-use super::{Task, TaskId};
-use alloc::{collections::BTreeMap, sync::Arc, task::Wake};
+//! Executor de tareas async.
+//!
+//! Las tareas listas esperan en colas por núcleo y por `TaskPriority`. Cada
+//! núcleo saca primero de su propia cola y, si está vacía, le roba una tarea a
+//! la misma prioridad de otro núcleo antes de pasar a la prioridad siguiente.
+//! Una tarea se despierta en la cola del último núcleo que la polleó, así que
+//! lo robado se queda donde corrió.
+//!
+//! Lo que está repartido por núcleo son sólo las colas de ids y el orden en
+//! que se recorren: las tareas en sí (`tasks`) y sus wakers son de un único
+//! `Executor`, que es el que corre todo lo que saca de cualquier cola. Robar
+//! acá es elegir de qué cola sale el próximo id, no pasarle trabajo a otro
+//! núcleo. Para SMP haría falta además un `Executor` por núcleo sobre un
+//! almacén de tareas compartido; las colas, los wakers con su núcleo y
+//! `pop` quedan como están.
+//!
+//! Sin SMP hay un solo núcleo, y las colas de otros núcleos sólo aparecen con
+//! `with_cores` (para probar el orden del robo). Los que usan el executor
+//! (`spawn`, `task::spawn`, los wakers) no dependen de cuántos núcleos haya.
+
+use super::{spawner, JoinHandle, Task, TaskId, TaskPriority, TaskStats};
+use core::future::Future;
+use alloc::{collections::BTreeMap, sync::Arc, task::Wake, vec::Vec};
 use core::task::{Context, Poll, Waker};
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use crossbeam_queue::ArrayQueue;
+use crate::log::Level;
+use crate::watchdog;
+
+/// Plazo del watchdog para cada vuelta del loop.
+const WATCHDOG_DEADLINE_MS: u64 = 5000;
+
+static LIVE_TASKS: AtomicUsize = AtomicUsize::new(0);
+
+crate::register_counter!(POLLS, "executor.polls");
+crate::register_counter!(SPAWNED, "executor.spawned");
+crate::register_counter!(QUEUE_FULL, "executor.queue_full");
+
+/// Tareas spawneadas que todavía no terminaron, en todos los executors.
+pub fn live_tasks() -> usize {
+    LIVE_TASKS.load(Ordering::Relaxed)
+}
+
+/// Polls de tareas hechos desde el arranque.
+pub fn total_polls() -> u64 {
+    POLLS.get()
+}
+
+/// Lugar por omisión en cada cola de tareas listas.
+const QUEUE_CAPACITY: usize = 100;
+
+/// Qué hacer cuando se despierta una tarea y su cola está llena. Las colas
+/// tienen tamaño fijo para que los wakers que se disparan desde una
+/// interrupción no puedan agotar el heap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Descartar el aviso y contarlo en `QueueStats::dropped`. La tarea no
+    /// vuelve a correr hasta que la despierten de nuevo.
+    Drop,
+    Panic,
+    /// Anotar el aviso en el waker y encolarlo cuando haya lugar. No se
+    /// pierde ninguno; las tareas despertadas de más esperan.
+    Backpressure,
+}
+
+/// Ocupación de las colas de tareas listas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueStats {
+    /// Lugar en cada cola.
+    pub capacity: usize,
+    /// Tareas esperando ahora, entre todas las colas.
+    pub queued: usize,
+    /// El máximo que llegó a tener una cola.
+    pub high_water: usize,
+    /// Avisos que encontraron su cola llena.
+    pub overflows: u64,
+    /// Avisos descartados por `Overflow::Drop`.
+    pub dropped: u64,
+    /// Tareas que un núcleo sacó de la cola de otro.
+    pub stolen: u64,
+}
+
+/// Una cola por `TaskPriority`, de la más alta a la más baja.
+type CoreQueues = [ArrayQueue<TaskId>; TaskPriority::COUNT];
+
+/// Las colas de tareas listas de todos los núcleos, compartidas con los
+/// wakers.
+struct ReadyQueues {
+    cores: Vec<CoreQueues>,
+    policy: Overflow,
+    /// Hay avisos anotados por `Overflow::Backpressure` sin encolar.
+    overflowed: AtomicBool,
+    high_water: AtomicUsize,
+    overflows: AtomicU64,
+    dropped: AtomicU64,
+    stolen: AtomicU64,
+}
+
+impl ReadyQueues {
+    fn try_push(&self, waker: &TaskWaker) -> bool {
+        let core = waker.home.load(Ordering::Relaxed);
+        let queue = &self.cores[core][waker.priority.index()];
+        if queue.push(waker.task_id).is_err() {
+            return false;
+        }
+        self.high_water.fetch_max(queue.len(), Ordering::Relaxed);
+        true
+    }
+
+    /// La próxima tarea para `core`: la propia más urgente, o una robada de
+    /// la misma prioridad.
+    fn pop(&self, core: usize) -> Option<TaskId> {
+        let cores = self.cores.len();
+        (0..TaskPriority::COUNT).find_map(|priority| {
+            self.cores[core][priority].pop().or_else(|| {
+                let victim = (1..cores).find_map(|offset| self.cores[(core + offset) % cores][priority].pop());
+                if victim.is_some() {
+                    self.stolen.fetch_add(1, Ordering::Relaxed);
+                }
+                victim
+            })
+        })
+    }
+
+    fn queues(&self) -> impl Iterator<Item = &ArrayQueue<TaskId>> {
+        self.cores.iter().flatten()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queues().all(ArrayQueue::is_empty) && !self.overflowed.load(Ordering::Acquire)
+    }
+
+    /// Encola los avisos que quedaron anotados, mientras haya lugar.
+    fn retry_deferred(&self, wakers: &BTreeMap<TaskId, Arc<TaskWaker>>) {
+        if !self.overflowed.swap(false, Ordering::Acquire) {
+            return;
+        }
+        for waker in wakers.values() {
+            if waker.deferred.swap(false, Ordering::Acquire) && !self.try_push(waker) {
+                waker.defer();
+                return;
+            }
+        }
+    }
+}
 
 pub struct Executor {
     tasks: BTreeMap<TaskId, Task>,
-    task_queue: Arc<ArrayQueue<TaskId>>,
-    waker_cache: BTreeMap<TaskId, Waker>,
+    task_queues: Arc<ReadyQueues>,
+    waker_cache: BTreeMap<TaskId, Arc<TaskWaker>>,
+    /// El núcleo cuyas colas vacía `run`.
+    core: usize,
 }
 
 impl Executor {
     pub fn new() -> Self {
+        Executor::with_capacity(QUEUE_CAPACITY, Overflow::Panic)
+    }
+
+    /// Un executor con `capacity` lugares en cada cola de tareas listas.
+    pub fn with_capacity(capacity: usize, policy: Overflow) -> Self {
+        Executor::with_cores(1, capacity, policy)
+    }
+
+    /// Como `with_capacity`, con colas para `cores` núcleos. Este executor
+    /// hace de núcleo 0: vacía su cola y después las de los demás, y corre
+    /// él mismo todo lo que saca.
+    pub fn with_cores(cores: usize, capacity: usize, policy: Overflow) -> Self {
+        assert!(cores > 0, "executor sin núcleos");
+        // Desde acá `spawn_from_interrupt` ya tiene dónde encolar.
+        spawner::deferred_queue();
         Executor {
             tasks: BTreeMap::new(),
-            task_queue: Arc::new(ArrayQueue::new(100)),
+            task_queues: Arc::new(ReadyQueues {
+                cores: (0..cores).map(|_| core::array::from_fn(|_| ArrayQueue::new(capacity))).collect(),
+                policy,
+                overflowed: AtomicBool::new(false),
+                high_water: AtomicUsize::new(0),
+                overflows: AtomicU64::new(0),
+                dropped: AtomicU64::new(0),
+                stolen: AtomicU64::new(0),
+            }),
             waker_cache: BTreeMap::new(),
+            core: 0,
         }
     }
 
+    /// Encola la tarea como recién despertada: con la cola llena aplica la
+    /// política de `with_capacity`.
     pub fn spawn(&mut self, task: Task) {
-        let task_id = task.id;
-        if self.tasks.insert(task.id, task).is_some() {
-            panic!("tarea con el mismo ID ya existe");
+        self.spawn_on(self.core, task);
+    }
+
+    /// Como `spawn`, en la cola del núcleo `core`.
+    pub fn spawn_on(&mut self, core: usize, task: Task) {
+        assert!(core < self.task_queues.cores.len(), "núcleo {} inexistente", core);
+        insert(&mut self.tasks, &self.task_queues, &mut self.waker_cache, core, task);
+    }
+
+    /// Spawnea `future` y devuelve un handle para esperar su salida.
+    pub fn spawn_with_handle<F>(&mut self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+    {
+        let (task, handle) = Task::with_handle(future);
+        self.spawn(task);
+        handle
+    }
+
+    /// Polls y tiempo de cada tarea que todavía no terminó, por id.
+    pub fn stats(&self) -> Vec<TaskStats> {
+        self.tasks.values().map(Task::stats).collect()
+    }
+
+    pub fn queue_stats(&self) -> QueueStats {
+        let queues = &self.task_queues;
+        QueueStats {
+            capacity: queues.cores[0][0].capacity(),
+            queued: queues.queues().map(ArrayQueue::len).sum(),
+            high_water: queues.high_water.load(Ordering::Relaxed),
+            overflows: queues.overflows.load(Ordering::Relaxed),
+            dropped: queues.dropped.load(Ordering::Relaxed),
+            stolen: queues.stolen.load(Ordering::Relaxed),
         }
-        self.task_queue.push(task_id).expect("cola de tareas llena");
+    }
+
+    /// `stats` por serie, de la tarea que más ocupó al executor a la que
+    /// menos.
+    pub fn print_stats(&self) {
+        let mut stats = self.stats();
+        stats.sort_by_key(|stats| core::cmp::Reverse(stats.busy_ns));
+        crate::serial_println!("TAREA  POLLS     TOTAL (us)  MÁS LARGO (us)");
+        for stats in stats {
+            crate::serial_println!(
+                "{:<6} {:<9} {:<11} {}",
+                stats.id.as_u64(), stats.polls, stats.busy_ns / 1000, stats.longest_ns / 1000
+            );
+        }
+        let queues = self.queue_stats();
+        crate::serial_println!(
+            "colas: {} de {} (máximo {}), {} llenas, {} descartados, {} robados",
+            queues.queued, queues.capacity, queues.high_water, queues.overflows, queues.dropped, queues.stolen
+        );
     }
 
     pub fn run(&mut self) -> ! {
+        let deadline = crate::time::ms_to_ticks(WATCHDOG_DEADLINE_MS).max(1);
+        let watchdog = watchdog::register("executor", deadline);
         loop {
+            watchdog::checkin(watchdog);
             self.run_ready_tasks();
             self.sleep_if_idle();
         }
     }
 
+    /// Corre tareas hasta que no quede ninguna lista, sin dormir. Dentro de
+    /// cada prioridad las tareas se pollean en el orden en que se
+    /// despertaron, así que con el reloj virtual de `time` los tests pueden
+    /// alternar esto con `time::advance` y obtener siempre la misma
+    /// secuencia.
+    pub fn run_until_idle(&mut self) {
+        self.run_ready_tasks();
+    }
+
     fn run_ready_tasks(&mut self) {
         let Self {
             tasks,
-            task_queue,
+            task_queues,
             waker_cache,
+            core,
         } = self;
+        let core = *core;
 
-        while let Some(task_id) = task_queue.pop() {
-            let task = match tasks.get_mut(&task_id) {
-                Some(task) => task,
-                None => continue,
+        loop {
+            // Lo que se spawneó con `task::spawn`, también desde las tareas
+            // que se acaban de pollear.
+            spawner::drain(|task| insert(tasks, task_queues, waker_cache, core, task));
+            task_queues.retry_deferred(waker_cache);
+            let Some(task_id) = task_queues.pop(core) else {
+                break;
+            };
+            let (Some(task), Some(waker)) = (tasks.get_mut(&task_id), waker_cache.get(&task_id)) else {
+                continue;
             };
-            let waker = waker_cache.entry(task_id).or_insert_with(|| {
-                TaskWaker::new_waker(task_id, task_queue.clone())
-            });
-            let mut context = Context::from_waker(waker);
+            // Si era de otro núcleo, ahora es de este.
+            waker.home.store(core, Ordering::Relaxed);
+            let waker = Waker::from(waker.clone());
+            let mut context = Context::from_waker(&waker);
+            POLLS.inc();
             match task.poll(&mut context) {
                 Poll::Ready(()) => {
                     tasks.remove(&task_id);
                     waker_cache.remove(&task_id);
+                    LIVE_TASKS.fetch_sub(1, Ordering::Relaxed);
                 }
                 Poll::Pending => {}
             }
@@ -62,10 +301,10 @@ impl Executor {
     fn sleep_if_idle(&self) {
         use x86_64::instructions::interrupts;
 
-        if self.task_queue.is_empty() {
+        if self.task_queues.is_empty() {
             interrupts::disable();
-            if self.task_queue.is_empty() {
-                interrupts::enable_and_hlt();
+            if self.task_queues.is_empty() && !spawner::has_pending() {
+                crate::cpu_usage::enable_and_hlt();
             } else {
                 interrupts::enable();
             }
@@ -73,21 +312,61 @@ impl Executor {
     }
 }
 
+fn insert(
+    tasks: &mut BTreeMap<TaskId, Task>,
+    task_queues: &Arc<ReadyQueues>,
+    waker_cache: &mut BTreeMap<TaskId, Arc<TaskWaker>>,
+    core: usize,
+    task: Task,
+) {
+    let task_id = task.id;
+    let waker = Arc::new(TaskWaker {
+        task_id,
+        priority: task.priority,
+        home: AtomicUsize::new(core),
+        task_queues: task_queues.clone(),
+        deferred: AtomicBool::new(false),
+    });
+    if tasks.insert(task.id, task).is_some() {
+        panic!("tarea con el mismo ID ya existe");
+    }
+    LIVE_TASKS.fetch_add(1, Ordering::Relaxed);
+    SPAWNED.inc();
+    waker.wake_task();
+    waker_cache.insert(task_id, waker);
+}
+
 struct TaskWaker {
     task_id: TaskId,
-    task_queue: Arc<ArrayQueue<TaskId>>,
+    priority: TaskPriority,
+    /// Núcleo en cuya cola se despierta.
+    home: AtomicUsize,
+    task_queues: Arc<ReadyQueues>,
+    /// Aviso que no entró en la cola (`Overflow::Backpressure`).
+    deferred: AtomicBool,
 }
 
 impl TaskWaker {
-    fn new_waker(task_id: TaskId, task_queue: Arc<ArrayQueue<TaskId>>) -> Waker {
-        Waker::from(Arc::new(TaskWaker {
-            task_id,
-            task_queue,
-        }))
+    fn wake_task(&self) {
+        let queues = &self.task_queues;
+        if queues.try_push(self) {
+            return;
+        }
+        queues.overflows.fetch_add(1, Ordering::Relaxed);
+        QUEUE_FULL.inc();
+        match queues.policy {
+            Overflow::Drop => {
+                queues.dropped.fetch_add(1, Ordering::Relaxed);
+                crate::log_rate_limited!(Level::Warn, "cola de tareas llena; descartando aviso");
+            }
+            Overflow::Panic => panic!("cola de tareas llena"),
+            Overflow::Backpressure => self.defer(),
+        }
     }
 
-    fn wake_task(&self) {
-        self.task_queue.push(self.task_id).expect("cola de tareas llena");
+    fn defer(&self) {
+        self.deferred.store(true, Ordering::Release);
+        self.task_queues.overflowed.store(true, Ordering::Release);
     }
 }
 
@@ -0,0 +1,204 @@
+//! `JoinHandle`: el resultado de una tarea spawneada.
+//!
+//! `Task::with_handle` envuelve el future para que, al terminar, deje su
+//! salida en un lugar compartido con el `JoinHandle` y despierte a quien lo
+//! espera. Si la tarea se suelta sin terminar (se suelta el executor), el
+//! handle devuelve `JoinError::Dropped`.
+//!
+//! Cada tarea tiene además un `CancelToken`, tenga handle o no (ver
+//! `Task::cancel_token`). Cancelarla la despierta, y en su próximo poll
+//! termina sin pollear el future, que el executor suelta junto con la tarea:
+//! un future cancelado no vuelve a correr, pero lo que hace entre dos
+//! `.await` no se interrumpe. El handle devuelve `JoinError::Cancelled`.
+//!
+//! Un pánico dentro de una tarea no llega al handle: el kernel compila con
+//! `panic = "abort"` y el panic handler detiene todo.
+
+use alloc::sync::Arc;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll, Waker};
+use futures_util::task::AtomicWaker;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinError {
+    /// La tarea se soltó antes de terminar.
+    Dropped,
+    /// La tarea se canceló antes de terminar.
+    Cancelled,
+}
+
+enum Slot<T> {
+    Running,
+    Done(T),
+    /// La salida ya se la llevó el handle.
+    Taken,
+    Dropped,
+    Cancelled,
+}
+
+struct Shared<T> {
+    slot: Mutex<Slot<T>>,
+    /// El de quien espera en el handle.
+    waker: AtomicWaker,
+    cancel: CancelToken,
+}
+
+struct Cancel {
+    cancelled: AtomicBool,
+    /// El de la tarea, para que vea la cancelación aunque esté esperando
+    /// otra cosa.
+    task_waker: AtomicWaker,
+}
+
+impl<T> Shared<T> {
+    fn finish(&self, slot: Slot<T>) {
+        interrupts::without_interrupts(|| *self.slot.lock() = slot);
+        self.waker.wake();
+    }
+
+    fn try_take(&self) -> Option<Result<T, JoinError>> {
+        interrupts::without_interrupts(|| {
+            let mut slot = self.slot.lock();
+            match core::mem::replace(&mut *slot, Slot::Taken) {
+                Slot::Done(output) => Some(Ok(output)),
+                Slot::Dropped => {
+                    *slot = Slot::Dropped;
+                    Some(Err(JoinError::Dropped))
+                }
+                Slot::Cancelled => {
+                    *slot = Slot::Cancelled;
+                    Some(Err(JoinError::Cancelled))
+                }
+                Slot::Running => {
+                    *slot = Slot::Running;
+                    None
+                }
+                Slot::Taken => panic!("JoinHandle polleado después de terminar"),
+            }
+        })
+    }
+}
+
+/// Marca la tarea como soltada, o cancelada, si se destruye sin haber
+/// terminado.
+struct DropGuard<T>(Arc<Shared<T>>);
+
+impl<T> Drop for DropGuard<T> {
+    fn drop(&mut self) {
+        let running = interrupts::without_interrupts(|| matches!(*self.0.slot.lock(), Slot::Running));
+        if running {
+            let slot = if self.0.cancel.is_cancelled() { Slot::Cancelled } else { Slot::Dropped };
+            self.0.finish(slot);
+        }
+    }
+}
+
+/// El future de la tarea: el de quien la spawneó, que deja su salida para el
+/// handle. La cancelación la ve `Task::poll`.
+struct Wrapped<F: Future> {
+    future: F,
+    guard: DropGuard<F::Output>,
+}
+
+impl<F: Future> Future for Wrapped<F> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        // `future` no se mueve de lugar.
+        let this = unsafe { self.get_unchecked_mut() };
+        let shared = &this.guard.0;
+        match unsafe { Pin::new_unchecked(&mut this.future) }.poll(context) {
+            Poll::Ready(output) => {
+                shared.finish(Slot::Done(output));
+                Poll::Ready(())
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Envuelve `future` para una tarea cancelada con `cancel`.
+pub(super) fn wrap<F>(future: F, cancel: CancelToken) -> (impl Future<Output = ()>, JoinHandle<F::Output>)
+where
+    F: Future + 'static,
+{
+    let shared = Arc::new(Shared { slot: Mutex::new(Slot::Running), waker: AtomicWaker::new(), cancel });
+    let task = Wrapped { future, guard: DropGuard(shared.clone()) };
+    (task, JoinHandle { shared })
+}
+
+/// Cancela una tarea desde cualquier lado, también desde otra tarea o un
+/// handler de interrupción. Se clona para repartirlo.
+#[derive(Clone)]
+pub struct CancelToken {
+    cancel: Arc<Cancel>,
+}
+
+impl CancelToken {
+    pub(super) fn new() -> CancelToken {
+        CancelToken {
+            cancel: Arc::new(Cancel { cancelled: AtomicBool::new(false), task_waker: AtomicWaker::new() }),
+        }
+    }
+
+    /// Lo llama la tarea en cada poll, para que `cancel` la despierte.
+    pub(super) fn register(&self, waker: &Waker) {
+        self.cancel.task_waker.register(waker);
+    }
+
+    /// Pide que la tarea termine en su próximo poll. Si ya terminó, no hace
+    /// nada.
+    pub fn cancel(&self) {
+        self.cancel.cancelled.store(true, Ordering::Release);
+        self.cancel.task_waker.wake();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.cancelled.load(Ordering::Acquire)
+    }
+}
+
+/// Se resuelve con la salida de la tarea. Soltarlo no afecta a la tarea.
+pub struct JoinHandle<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Si la tarea ya terminó o se soltó.
+    pub fn is_finished(&self) -> bool {
+        interrupts::without_interrupts(|| !matches!(*self.shared.slot.lock(), Slot::Running))
+    }
+
+    /// La salida sin esperar: `None` si la tarea sigue corriendo.
+    pub fn try_join(&mut self) -> Option<Result<T, JoinError>> {
+        self.shared.try_take()
+    }
+
+    /// Cancela la tarea: ver `CancelToken::cancel`.
+    pub fn abort(&self) {
+        self.cancel_token().cancel();
+    }
+
+    pub fn cancel_token(&self) -> CancelToken {
+        self.shared.cancel.clone()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output> {
+        if let Some(result) = self.shared.try_take() {
+            return Poll::Ready(result);
+        }
+        self.shared.waker.register(context.waker());
+        match self.shared.try_take() {
+            Some(result) => Poll::Ready(result),
+            None => Poll::Pending,
+        }
+    }
+}
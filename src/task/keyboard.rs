@@ -2,28 +2,189 @@ use conquer_once::spin::OnceCell;
 use crossbeam_queue::ArrayQueue;
 use core::{
     pin::Pin,
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
     task::{Context, Poll},
 };
 use futures_util::{
     stream::{Stream, StreamExt},
     task::AtomicWaker,
 };
-use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
+use crate::driver::{Driver, Stage};
+use crate::housekeeping::{self, JobId};
+use crate::log::Level;
+use crate::ps2::{self, Ps2Error};
+use pc_keyboard::{
+    layouts, DecodedKey, HandleControl, KeyCode, KeyState, Keyboard, Modifiers, ScancodeSet2,
+};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
 
 static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
 static WAKER: AtomicWaker = AtomicWaker::new();
+/// Teclas ya decodificadas, en UTF-8, para el lado de lectura de la consola.
+static INPUT: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
+
+static READY: AtomicBool = AtomicBool::new(false);
+
+fn init() {
+    READY.store(true, Ordering::Relaxed);
+
+    // Sin puerto serie, el LED de Scroll Lock es la única señal de vida.
+    let mut has_serial = false;
+    crate::device::for_each(|device| has_serial |= device.id == "PNP0501");
+    set_heartbeat(!has_serial);
+}
+
+crate::register_driver!(KEYBOARD_DRIVER, Driver {
+    name: "ps2-keyboard",
+    stage: Stage::Drivers,
+    depends_on: &["pic", "i8042"],
+    device: Some("PNP0303"),
+    probe: Driver::always,
+    init,
+});
+
+/// Lee un scancode del 8042. `None` si el driver no se inicializó o si el
+/// byte es un ACK de un comando, que no es una tecla. El byte se lee igual en
+/// los dos casos, para que el controlador pueda mandar el siguiente.
+pub(crate) fn read_scancode() -> Option<u8> {
+    let byte = ps2::read_data();
+    if !READY.load(Ordering::Relaxed) {
+        return None;
+    }
+    match byte {
+        ps2::ACK => None,
+        scancode => Some(scancode),
+    }
+}
+
+// ----------------- LEDS -----------------
+
+pub const LED_SCROLL_LOCK: u8 = 1 << 0;
+pub const LED_NUM_LOCK: u8 = 1 << 1;
+pub const LED_CAPS_LOCK: u8 = 1 << 2;
+
+const SET_LEDS: u8 = 0xED;
+
+/// Ticks del PIT entre cambios del heartbeat (~1 s).
+const HEARTBEAT_TICKS: u64 = 18;
+
+/// LEDs pedidos por el estado del teclado; el heartbeat se suma encima.
+static LEDS: AtomicU8 = AtomicU8::new(0);
+/// El trabajo periódico que hace titilar el heartbeat, mientras está prendido.
+static HEARTBEAT: Mutex<Option<JobId>> = Mutex::new(None);
+static HEARTBEAT_ON: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedError {
+    NotInitialized,
+    Timeout,
+    NoAck(u8),
+}
+
+impl From<Ps2Error> for LedError {
+    fn from(err: Ps2Error) -> Self {
+        match err {
+            Ps2Error::NoAck(response) => LedError::NoAck(response),
+            Ps2Error::Timeout => LedError::Timeout,
+            _ => LedError::NotInitialized,
+        }
+    }
+}
+
+/// Manda un byte al teclado y espera su ACK por polling. Un scancode que
+/// llegue en el medio se encola como si hubiera llegado por IRQ.
+fn send(byte: u8) -> Result<(), LedError> {
+    ps2::send(ps2::Port::First, byte)?;
+    loop {
+        match ps2::read_polled()? {
+            ps2::ACK => return Ok(()),
+            // Reenviar, falla del self-test o error. 0xF0 no: en el set 2 es
+            // el prefijo de una tecla soltada.
+            response @ 0xFC..=0xFE => return Err(LedError::NoAck(response)),
+            scancode => add_scancode(scancode),
+        }
+    }
+}
+
+fn write_leds(mask: u8) -> Result<(), LedError> {
+    if !READY.load(Ordering::Relaxed) {
+        return Err(LedError::NotInitialized);
+    }
+    // Con interrupciones apagadas los ACK no llegan al handler de IRQ1.
+    interrupts::without_interrupts(|| {
+        send(SET_LEDS)?;
+        send(mask & 0b111)
+    })
+}
+
+fn current_mask() -> u8 {
+    let heartbeat = if HEARTBEAT_ON.load(Ordering::Relaxed) { LED_SCROLL_LOCK } else { 0 };
+    LEDS.load(Ordering::Relaxed) ^ heartbeat
+}
+
+/// Prende los LEDs de `mask` (combinación de `LED_*`) y apaga el resto.
+pub fn set_leds(mask: u8) -> Result<(), LedError> {
+    LEDS.store(mask, Ordering::Relaxed);
+    write_leds(current_mask())
+}
+
+pub fn leds() -> u8 {
+    LEDS.load(Ordering::Relaxed)
+}
+
+/// Hace titilar Scroll Lock como señal de que el kernel sigue vivo. Corre
+/// como tarea periódica de `housekeeping` y no desde el timer: mandar los
+/// LEDs espera el ACK del teclado por polling.
+pub fn set_heartbeat(enabled: bool) {
+    let previous = interrupts::without_interrupts(|| {
+        let mut job = HEARTBEAT.lock();
+        match (enabled, *job) {
+            (true, None) => *job = Some(housekeeping::register("heartbeat", HEARTBEAT_TICKS, heartbeat)),
+            (false, Some(_)) => return job.take(),
+            _ => {}
+        }
+        None
+    });
+    if let Some(job) = previous {
+        housekeeping::unregister(job);
+        if HEARTBEAT_ON.swap(false, Ordering::Relaxed) {
+            let _ = write_leds(current_mask());
+        }
+    }
+}
+
+fn heartbeat() {
+    HEARTBEAT_ON.fetch_xor(true, Ordering::Relaxed);
+    let _ = write_leds(current_mask());
+}
+
+/// LEDs que corresponden al estado del decodificador.
+fn leds_for(modifiers: &Modifiers, scroll_lock: bool) -> u8 {
+    let mut mask = 0;
+    if modifiers.capslock {
+        mask |= LED_CAPS_LOCK;
+    }
+    if modifiers.numlock {
+        mask |= LED_NUM_LOCK;
+    }
+    if scroll_lock {
+        mask |= LED_SCROLL_LOCK;
+    }
+    mask
+}
 
 /// Llamada desde el handler de interrupción del teclado.
 /// Agrega un scancode a la cola y despierta la tarea async.
 pub(crate) fn add_scancode(scancode: u8) {
     if let Ok(queue) = SCANCODE_QUEUE.try_get() {
         if queue.push(scancode).is_err() {
-            crate::println!("ADVERTENCIA: cola de scancodes llena; descartando entrada");
+            crate::log_rate_limited!(Level::Warn, "cola de scancodes llena; descartando entrada");
         } else {
             WAKER.wake();
         }
     } else {
-        crate::println!("ADVERTENCIA: cola de scancodes no inicializada");
+        crate::log_rate_limited!(Level::Warn, "cola de scancodes no inicializada");
     }
 }
 
@@ -64,19 +225,111 @@ impl Stream for ScancodeStream {
     }
 }
 
+/// Lectura no bloqueante de un byte de lo que se tecleó. `None` si no hay
+/// nada o si `print_keypresses` todavía no arrancó.
+pub fn read_byte() -> Option<u8> {
+    INPUT.try_get().ok()?.pop()
+}
+
+fn push_input(character: char) {
+    let Ok(input) = INPUT.try_get() else { return };
+    let mut bytes = [0; 4];
+    for &byte in character.encode_utf8(&mut bytes).as_bytes() {
+        if input.push(byte).is_err() {
+            crate::log_rate_limited!(Level::Warn, "entrada de teclado llena; descartando");
+            return;
+        }
+    }
+}
+
+/// Distribuciones de teclado soportadas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Layout {
+    Us104 = 0,
+    Uk105,
+    De105,
+    Azerty,
+    Dvorak104,
+    Jis109,
+}
+
+impl Layout {
+    pub fn from_u8(value: u8) -> Option<Layout> {
+        Some(match value {
+            0 => Layout::Us104,
+            1 => Layout::Uk105,
+            2 => Layout::De105,
+            3 => Layout::Azerty,
+            4 => Layout::Dvorak104,
+            5 => Layout::Jis109,
+            _ => return None,
+        })
+    }
+
+    fn to_any(self) -> layouts::AnyLayout {
+        use layouts::AnyLayout;
+        match self {
+            Layout::Us104 => AnyLayout::Us104Key(layouts::Us104Key),
+            Layout::Uk105 => AnyLayout::Uk105Key(layouts::Uk105Key),
+            Layout::De105 => AnyLayout::De105Key(layouts::De105Key),
+            Layout::Azerty => AnyLayout::Azerty(layouts::Azerty),
+            Layout::Dvorak104 => AnyLayout::Dvorak104Key(layouts::Dvorak104Key),
+            Layout::Jis109 => AnyLayout::Jis109Key(layouts::Jis109Key),
+        }
+    }
+}
+
+static LAYOUT: AtomicU8 = AtomicU8::new(Layout::Us104 as u8);
+
+/// Cambia la distribución que usa `print_keypresses` a partir de su próximo arranque.
+pub fn set_layout(layout: Layout) {
+    LAYOUT.store(layout as u8, Ordering::Relaxed);
+}
+
+pub fn layout() -> Layout {
+    Layout::from_u8(LAYOUT.load(Ordering::Relaxed)).unwrap_or(Layout::Us104)
+}
+
 pub async fn print_keypresses() {
     let mut scancodes = ScancodeStream::new();
+    INPUT.init_once(|| ArrayQueue::new(256));
     let mut keyboard = Keyboard::new(
-        ScancodeSet1::new(),
-        layouts::Us104Key,
+        // El 8042 queda sin traducción (ver `ps2`): llega el set 2 tal cual.
+        ScancodeSet2::new(),
+        layout().to_any(),
         HandleControl::Ignore,
     );
 
+    // pc-keyboard no lleva la cuenta de Scroll Lock.
+    let mut scroll_lock = false;
+    let _ = set_leds(leds_for(keyboard.get_modifiers(), scroll_lock));
+
     while let Some(scancode) = scancodes.next().await {
         if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
-            if let Some(key) = keyboard.process_keyevent(key_event) {
+            if key_event.code == KeyCode::ScrollLock && key_event.state == KeyState::Down {
+                scroll_lock = !scroll_lock;
+            }
+            let lock_key = matches!(
+                key_event.code,
+                KeyCode::CapsLock | KeyCode::NumpadLock | KeyCode::ScrollLock
+            );
+
+            let decoded = keyboard.process_keyevent(key_event);
+            if lock_key {
+                let mask = leds_for(keyboard.get_modifiers(), scroll_lock);
+                if mask != leds() {
+                    let _ = set_leds(mask);
+                }
+            }
+
+            if let Some(key) = decoded {
+                crate::event::publish(crate::event::Event::KeyPressed(key));
                 match key {
-                    DecodedKey::Unicode(character) => crate::print!("{}", character),
+                    DecodedKey::Unicode(character) => {
+                        push_input(character);
+                        crate::print!("{}", character)
+                    }
                     DecodedKey::RawKey(key) => crate::print!("{:?}", key),
                 }
             }
@@ -6,9 +6,19 @@ use core::{
 };
 use alloc::boxed::Box;
 
+pub use crate::{join, select};
+pub use join::{CancelToken, JoinError, JoinHandle};
+pub use spawner::{spawn, spawn_from_interrupt};
+pub use timer::{timeout, TimedOut};
+
+pub mod combinators;
 pub mod executor;
+mod join;
 pub mod keyboard;
 pub mod simple_executor;
+mod spawner;
+pub mod sync;
+pub mod timer;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct TaskId(u64);
@@ -18,22 +28,146 @@ impl TaskId {
         static NEXT_ID: AtomicU64 = AtomicU64::new(0);
         TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
     }
+
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+const NO_TASK: u64 = u64::MAX;
+static CURRENT: AtomicU64 = AtomicU64::new(NO_TASK);
+
+/// La tarea que se está polleando, en cualquier executor.
+pub fn current() -> Option<TaskId> {
+    match CURRENT.load(Ordering::Relaxed) {
+        NO_TASK => None,
+        id => Some(TaskId(id)),
+    }
+}
+
+/// En qué cola espera una tarea lista. `Executor` pollea siempre las de la
+/// cola más alta que no esté vacía: una tarea `High` que se despierta pasa
+/// antes que todas las `Normal` y `Low` que ya estaban listas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum TaskPriority {
+    High,
+    #[default]
+    Normal,
+    /// Trabajo de fondo: sólo corre cuando no hay nada más listo.
+    Low,
+}
+
+impl TaskPriority {
+    pub const COUNT: usize = 3;
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Cuánto ocupó una tarea al executor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaskStats {
+    pub id: TaskId,
+    pub polls: u64,
+    /// Tiempo total dentro de `poll`.
+    pub busy_ns: u64,
+    /// El poll más largo: una tarea que no cede el executor deja esperando a
+    /// todas las demás ese tiempo.
+    pub longest_ns: u64,
 }
 
 pub struct Task {
     id: TaskId,
     future: Pin<Box<dyn Future<Output = ()>>>,
+    priority: TaskPriority,
+    stats: TaskStats,
+    cancel: CancelToken,
 }
 
 impl Task {
     pub fn new(future: impl Future<Output = ()> + 'static) -> Task {
+        Task::new_with_priority(future, TaskPriority::Normal)
+    }
+
+    pub fn new_with_priority(future: impl Future<Output = ()> + 'static, priority: TaskPriority) -> Task {
+        let mut task = Task::from_pinned(TaskId::new(), Box::pin(future), CancelToken::new());
+        task.priority = priority;
+        task
+    }
+
+    fn from_pinned(id: TaskId, future: Pin<Box<dyn Future<Output = ()>>>, cancel: CancelToken) -> Task {
         Task {
-            id: TaskId::new(),
-            future: Box::pin(future),
+            id,
+            future,
+            priority: TaskPriority::Normal,
+            stats: TaskStats { id, polls: 0, busy_ns: 0, longest_ns: 0 },
+            cancel,
         }
     }
 
+    pub fn priority(&self) -> TaskPriority {
+        self.priority
+    }
+
+    pub fn stats(&self) -> TaskStats {
+        self.stats
+    }
+
+    /// Para cancelar la tarea después de spawnearla.
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    /// Una tarea que corre `future` y un handle para esperar su salida.
+    pub fn with_handle<F>(future: F) -> (Task, JoinHandle<F::Output>)
+    where
+        F: Future + 'static,
+    {
+        let cancel = CancelToken::new();
+        let (future, handle) = join::wrap(future, cancel.clone());
+        (Task::from_pinned(TaskId::new(), Box::pin(future), cancel), handle)
+    }
+
     fn poll(&mut self, context: &mut Context) -> Poll<()> {
-        self.future.as_mut().poll(context)
+        // Cancelada: termina sin pollear el future, que se suelta con la tarea.
+        if self.cancel.is_cancelled() {
+            return Poll::Ready(());
+        }
+        self.cancel.register(context.waker());
+        let previous = CURRENT.swap(self.id.0, Ordering::Relaxed);
+        crate::trace::begin(crate::trace::Point::Task(self.id.0));
+        let start = crate::time::now_ns();
+        let result = self.future.as_mut().poll(context);
+        let elapsed = crate::time::now_ns().saturating_sub(start);
+        crate::trace::end(crate::trace::Point::Task(self.id.0));
+        self.stats.polls += 1;
+        self.stats.busy_ns += elapsed;
+        self.stats.longest_ns = self.stats.longest_ns.max(elapsed);
+        CURRENT.store(previous, Ordering::Relaxed);
+        result
+    }
+}
+
+/// Cede el executor una vez: la tarea vuelve al final de la cola de listas.
+/// Para que un loop largo deje correr a las demás tareas.
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        context.waker().wake_by_ref();
+        Poll::Pending
     }
 }
\ No newline at end of file
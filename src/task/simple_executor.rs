@@ -1,5 +1,6 @@
-use super::Task;
+use super::{JoinHandle, Task};
 use alloc::collections::VecDeque;
+use core::future::Future;
 use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
 
 pub struct SimpleExecutor {
@@ -17,6 +18,16 @@ impl SimpleExecutor {
         self.task_queue.push_back(task)
     }
 
+    /// Spawnea `future` y devuelve un handle para esperar su salida.
+    pub fn spawn_with_handle<F>(&mut self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+    {
+        let (task, handle) = Task::with_handle(future);
+        self.spawn(task);
+        handle
+    }
+
     pub fn run(&mut self) {
         while let Some(mut task) = self.task_queue.pop_front() {
             let waker = dummy_waker();
@@ -0,0 +1,87 @@
+//! Spawn global: encolar tareas en el executor que está corriendo sin tener
+//! un `&mut Executor`.
+//!
+//! `spawn` deja la tarea en una cola global que `Executor` vacía en cada
+//! vuelta, así que sirve desde otra tarea o desde otro hilo. Desde un handler
+//! de interrupción no se puede alocar la tarea; `spawn_from_interrupt` encola
+//! sólo un callback, sin alocar, y el executor lo corre en contexto de tarea,
+//! donde ya puede llamar a `spawn`.
+//!
+//! Las tareas de la cola global tienen que ser `Send`: las puede crear un
+//! hilo distinto del que corre el executor.
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use conquer_once::spin::OnceCell;
+use core::future::Future;
+use core::pin::Pin;
+use crossbeam_queue::ArrayQueue;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use super::{CancelToken, JoinHandle, Task, TaskId};
+use crate::log::Level;
+
+/// Callbacks pendientes de `spawn_from_interrupt`.
+const DEFERRED_CAPACITY: usize = 64;
+
+type SpawnedFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+/// Callback y argumento.
+type Deferred = (fn(usize), usize);
+
+static SPAWNED: Mutex<VecDeque<(TaskId, SpawnedFuture, CancelToken)>> = Mutex::new(VecDeque::new());
+static DEFERRED: OnceCell<ArrayQueue<Deferred>> = OnceCell::uninit();
+
+pub(super) fn deferred_queue() -> &'static ArrayQueue<Deferred> {
+    DEFERRED.get_or_init(|| ArrayQueue::new(DEFERRED_CAPACITY))
+}
+
+/// Encola `future` en el executor que está corriendo y devuelve un handle
+/// para esperar su salida. Corre recién cuando el executor vacía la cola: si
+/// no hay ninguno corriendo, espera.
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send,
+{
+    deferred_queue();
+    let cancel = CancelToken::new();
+    let (future, handle) = super::join::wrap(future, cancel.clone());
+    let future: SpawnedFuture = Box::pin(future);
+    let id = TaskId::new();
+    interrupts::without_interrupts(|| SPAWNED.lock().push_back((id, future, cancel)));
+    handle
+}
+
+/// Desde un handler de interrupción: el executor llama a `callback(arg)` en
+/// contexto de tarea, típicamente para que haga `spawn`. No aloca ni
+/// bloquea. Devuelve false si la cola está llena o todavía no se creó (no se
+/// llamó a `spawn` ni se creó un `Executor`).
+pub fn spawn_from_interrupt(callback: fn(usize), arg: usize) -> bool {
+    let Ok(queue) = DEFERRED.try_get() else {
+        return false;
+    };
+    if queue.push((callback, arg)).is_err() {
+        crate::log_rate_limited!(Level::Warn, "cola de spawn diferido llena; descartando");
+        return false;
+    }
+    true
+}
+
+/// Si hay algo esperando a que el executor lo tome.
+pub(super) fn has_pending() -> bool {
+    DEFERRED.try_get().is_ok_and(|queue| !queue.is_empty())
+        || interrupts::without_interrupts(|| !SPAWNED.lock().is_empty())
+}
+
+/// Corre los callbacks diferidos y entrega las tareas encoladas a `adopt`.
+pub(super) fn drain(mut adopt: impl FnMut(Task)) {
+    if let Ok(queue) = DEFERRED.try_get() {
+        while let Some((callback, arg)) = queue.pop() {
+            callback(arg);
+        }
+    }
+    while let Some((id, future, cancel)) = interrupts::without_interrupts(|| SPAWNED.lock().pop_front()) {
+        adopt(Task::from_pinned(id, future, cancel));
+    }
+}
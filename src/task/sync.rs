@@ -0,0 +1,148 @@
+//! Sincronización entre tareas async.
+//!
+//! Con un `spin::Mutex`, una tarea que lo tiene tomado y hace `.await` deja a
+//! la siguiente que lo pida girando sin devolver nunca el executor, y como el
+//! executor es uno solo la primera no vuelve a correr: deadlock. El `Mutex`
+//! de acá hace esperar con `.await`: `lock()` devuelve un future que queda
+//! pendiente mientras el lock esté tomado, y soltar el guard despierta al
+//! primero de la fila.
+
+use alloc::collections::BTreeMap;
+use core::cell::UnsafeCell;
+use core::future::Future;
+use core::ops::{Deref, DerefMut};
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::task::{Context, Poll, Waker};
+use x86_64::instructions::interrupts;
+
+pub struct Mutex<T> {
+    locked: AtomicBool,
+    /// Los que esperan, en orden de llegada.
+    waiters: spin::Mutex<BTreeMap<u64, Waker>>,
+    next_waiter: AtomicU64,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Mutex {
+            locked: AtomicBool::new(false),
+            waiters: spin::Mutex::new(BTreeMap::new()),
+            next_waiter: AtomicU64::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Espera a tener el lock.
+    pub fn lock(&self) -> Lock<'_, T> {
+        Lock { mutex: self, waiter: None }
+    }
+
+    /// El lock si está libre, sin esperar.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { mutex: self })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    fn wake_first(&self) {
+        let waker = interrupts::without_interrupts(|| self.waiters.lock().values().next().cloned());
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Future de `Mutex::lock`.
+pub struct Lock<'a, T> {
+    mutex: &'a Mutex<T>,
+    /// Lugar en la fila, una vez que tuvo que esperar.
+    waiter: Option<u64>,
+}
+
+impl<'a, T> Lock<'a, T> {
+    fn leave_queue(&mut self) {
+        if let Some(waiter) = self.waiter.take() {
+            interrupts::without_interrupts(|| self.mutex.waiters.lock().remove(&waiter));
+        }
+    }
+}
+
+impl<'a, T> Future for Lock<'a, T> {
+    type Output = MutexGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output> {
+        if let Some(guard) = self.mutex.try_lock() {
+            self.leave_queue();
+            return Poll::Ready(guard);
+        }
+
+        let mutex = self.mutex;
+        let waiter = *self
+            .waiter
+            .get_or_insert_with(|| mutex.next_waiter.fetch_add(1, Ordering::Relaxed));
+        interrupts::without_interrupts(|| {
+            mutex.waiters.lock().insert(waiter, context.waker().clone());
+        });
+
+        // Se pudo haber soltado entre el primer intento y anotarse.
+        match self.mutex.try_lock() {
+            Some(guard) => {
+                self.leave_queue();
+                Poll::Ready(guard)
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Drop for Lock<'_, T> {
+    fn drop(&mut self) {
+        if self.waiter.is_some() {
+            self.leave_queue();
+            // Si lo despertaron para tomar el lock y se soltó sin hacerlo,
+            // el aviso pasa al siguiente.
+            if !self.mutex.locked.load(Ordering::Relaxed) {
+                self.mutex.wake_first();
+            }
+        }
+    }
+}
+
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.locked.store(false, Ordering::Release);
+        self.mutex.wake_first();
+    }
+}
@@ -0,0 +1,160 @@
+//! `sleep` y `timeout` para tareas async.
+//!
+//! Cada `Sleep` pendiente ocupa un lugar de una tabla fija con su plazo y el
+//! waker de la tarea; el hook del timer (`time::on_tick`) despierta a las que
+//! vencieron. La resolución es la del tick del PIT: un `sleep` dura al menos
+//! lo pedido y a lo sumo un tick más.
+//!
+//! `timeout` corre un future junto a un `Sleep` y se queda con el primero que
+//! termine, para que un driver que espera al hardware no quede colgado.
+
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::error::KernelError;
+use crate::log::Level;
+use crate::time::{Duration, Instant};
+
+pub const MAX_SLEEPERS: usize = 64;
+
+struct Sleeper {
+    deadline: Instant,
+    waker: Waker,
+}
+
+static SLEEPERS: Mutex<[Option<Sleeper>; MAX_SLEEPERS]> =
+    Mutex::new([const { None }; MAX_SLEEPERS]);
+
+/// Espera `duration` sin ocupar la CPU.
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(Instant::now() + duration)
+}
+
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep { deadline, slot: None }
+}
+
+/// Future de `sleep`; al soltarlo antes de tiempo se libera su lugar.
+pub struct Sleep {
+    deadline: Instant,
+    slot: Option<usize>,
+}
+
+impl Sleep {
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    fn release(&mut self) {
+        if let Some(index) = self.slot.take() {
+            interrupts::without_interrupts(|| SLEEPERS.lock()[index] = None);
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            self.release();
+            return Poll::Ready(());
+        }
+
+        let deadline = self.deadline;
+        let slot = interrupts::without_interrupts(|| {
+            let mut sleepers = SLEEPERS.lock();
+            let index = match self.slot {
+                Some(index) => index,
+                None => sleepers.iter().position(|slot| slot.is_none())?,
+            };
+            let waker = cx.waker().clone();
+            sleepers[index] = Some(Sleeper { deadline, waker });
+            Some(index)
+        });
+
+        match slot {
+            Some(index) => self.slot = Some(index),
+            None => {
+                // Sin lugar en la tabla: que la tarea vuelva a preguntar.
+                crate::log_rate_limited!(Level::Warn, "tabla de sleep llena; esperando activamente");
+                cx.waker().wake_by_ref();
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// El plazo de `timeout` venció antes de que terminara el future.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimedOut;
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "tiempo de espera agotado")
+    }
+}
+
+impl From<TimedOut> for KernelError {
+    fn from(_: TimedOut) -> Self {
+        KernelError::TimedOut
+    }
+}
+
+/// Espera a `future` a lo sumo `duration`. Si vence el plazo, `future` se
+/// suelta sin terminar.
+pub fn timeout<F: Future>(duration: Duration, future: F) -> Timeout<F> {
+    Timeout { future, sleep: sleep(duration) }
+}
+
+pub struct Timeout<F> {
+    future: F,
+    sleep: Sleep,
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, TimedOut>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // `future` no se mueve de lugar; `Sleep` es `Unpin`.
+        let this = unsafe { self.get_unchecked_mut() };
+        if let Poll::Ready(output) = unsafe { Pin::new_unchecked(&mut this.future) }.poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+        match Pin::new(&mut this.sleep).poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(TimedOut)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Pendientes en la tabla.
+pub fn pending() -> usize {
+    interrupts::without_interrupts(|| SLEEPERS.lock().iter().flatten().count())
+}
+
+/// Lo llama `time::on_tick`: despierta a los que vencieron. El lugar lo
+/// libera el propio `Sleep` cuando lo vuelven a consultar, así que hasta
+/// entonces se lo despierta en cada tick.
+pub(crate) fn on_timer_tick() {
+    let now = Instant::now();
+    // Desde la interrupción: si alguien tiene la tabla, se reintenta en el próximo tick.
+    let Some(sleepers) = SLEEPERS.try_lock() else {
+        return;
+    };
+    for sleeper in sleepers.iter().flatten() {
+        if sleeper.deadline <= now {
+            sleeper.waker.wake_by_ref();
+        }
+    }
+}
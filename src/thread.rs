@@ -0,0 +1,205 @@
+//! Hilos del kernel.
+//!
+//! Cada hilo tiene su propio stack, con una página de guarda debajo (ver
+//! `stack`). Mientras no corre, de él sólo queda el stack pointer guardado: los
+//! registros callee-saved y la dirección de retorno los apiló `switch` en su
+//! stack. Cuál corre y cuándo se cambia lo decide `scheduler`.
+//!
+//! Un hilo nuevo arranca con las interrupciones habilitadas y, si su función
+//! vuelve, termina como si hubiera llamado a `exit`.
+
+use core::arch::naked_asm;
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::structures::paging::PhysFrame;
+
+use crate::scheduler::{Class, Priority, DEFAULT_PRIORITY};
+use crate::error::KernelResult;
+use crate::stack::Stack;
+
+pub const STACK_SIZE: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ThreadId(u64);
+
+impl ThreadId {
+    fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        ThreadId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    pub(crate) fn from_u64(id: u64) -> Self {
+        ThreadId(id)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Ready,
+    Running,
+    /// Esperando algo que no es la CPU: una `WaitQueue`, o un periódico su
+    /// próxima activación.
+    Blocked,
+    Finished,
+}
+
+pub(crate) struct Thread {
+    pub(crate) id: ThreadId,
+    pub(crate) name: &'static str,
+    pub(crate) state: State,
+    /// Stack pointer que dejó `switch`; sólo vale mientras no corre.
+    pub(crate) rsp: u64,
+    pub(crate) class: Class,
+    /// La que se le pidió con `spawn_with_priority` o `set_priority`.
+    pub(crate) base_priority: Priority,
+    /// La heredada de quien espera un lock suyo, si es más urgente.
+    pub(crate) inherited: Option<Priority>,
+    /// Tiempo de CPU acumulado, sin contar la tajada en curso.
+    pub(crate) run_ns: u64,
+    /// Veces que el scheduler cambió a este hilo.
+    pub(crate) switches: u64,
+    /// La tabla de nivel 4 del proceso que corre el hilo; `None` para los
+    /// del kernel, que usan la del kernel.
+    pub(crate) page_table: Option<PhysFrame>,
+    /// `None` para el hilo de arranque, que sigue en el stack del bootloader.
+    stack: Option<Stack>,
+}
+
+impl Thread {
+    /// El código que ya está corriendo cuando se inicializa el scheduler.
+    pub(crate) fn boot(name: &'static str) -> Thread {
+        Thread {
+            id: ThreadId::new(),
+            name,
+            state: State::Running,
+            rsp: 0,
+            class: Class::RoundRobin,
+            base_priority: DEFAULT_PRIORITY,
+            inherited: None,
+            run_ns: 0,
+            switches: 0,
+            page_table: None,
+            stack: None,
+        }
+    }
+
+    /// Un hilo listo para correr `entry`. `OutOfMemory` si no hay lugar
+    /// para su stack.
+    pub(crate) fn new(name: &'static str, priority: Priority, entry: fn()) -> KernelResult<Thread> {
+        let id = ThreadId::new();
+        let stack = Stack::new(id)?;
+        let top = stack.bounds().1 & !0xF;
+        // Lo que desapila `switch` la primera vez: r15, r14, r13, r12 (la
+        // función del hilo), rbx, rbp (cero, para cortar los backtraces) y
+        // la dirección de retorno.
+        let trampoline = trampoline as extern "C" fn() -> !;
+        let frame: [u64; 7] = [0, 0, 0, entry as usize as u64, 0, 0, trampoline as usize as u64];
+        let rsp = top - size_of_val(&frame) as u64;
+        unsafe { (rsp as *mut [u64; 7]).write(frame) };
+        Ok(Thread {
+            id,
+            name,
+            state: State::Ready,
+            rsp,
+            class: Class::RoundRobin,
+            base_priority: priority,
+            inherited: None,
+            run_ns: 0,
+            switches: 0,
+            page_table: None,
+            stack: Some(stack),
+        })
+    }
+
+    /// La prioridad con la que compite: la más urgente entre la propia y la
+    /// heredada.
+    pub(crate) fn priority(&self) -> Priority {
+        self.inherited.map_or(self.base_priority, |inherited| inherited.min(self.base_priority))
+    }
+
+    /// `[base, top)` del stack propio, si tiene.
+    pub(crate) fn stack_bounds(&self) -> Option<(u64, u64)> {
+        self.stack.as_ref().map(Stack::bounds)
+    }
+
+    /// Máximo de stack propio usado hasta ahora, si tiene.
+    pub(crate) fn stack_high_water(&self) -> Option<usize> {
+        self.stack.as_ref().map(Stack::high_water)
+    }
+}
+
+/// Apila los registros callee-saved, guarda el stack pointer en `*old_rsp` y
+/// sigue en el stack `new_rsp`, desapilando lo que dejó ahí un `switch`
+/// anterior (o `Thread::new`).
+///
+/// # Safety
+///
+/// `new_rsp` tiene que ser el stack de un hilo suspendido, y las
+/// interrupciones tienen que estar deshabilitadas.
+#[unsafe(naked)]
+pub(crate) unsafe extern "C" fn switch(old_rsp: *mut u64, new_rsp: u64) {
+    naked_asm!(
+        "push rbp",
+        "push rbx",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        "mov [rdi], rsp",
+        "mov rsp, rsi",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop rbx",
+        "pop rbp",
+        "ret",
+    );
+}
+
+/// Primera instrucción de un hilo nuevo: `switch` "vuelve" acá con la
+/// función del hilo en r12.
+#[unsafe(naked)]
+extern "C" fn trampoline() -> ! {
+    naked_asm!("mov rdi, r12", "call {start}", "ud2", start = sym start);
+}
+
+extern "C" fn start(entry: usize) -> ! {
+    let entry: fn() = unsafe { core::mem::transmute(entry) };
+    crate::scheduler::after_switch();
+    x86_64::instructions::interrupts::enable();
+    entry();
+    exit();
+}
+
+/// Crea un hilo que corre `entry` con `DEFAULT_PRIORITY` y lo pone al final
+/// de su cola.
+pub fn spawn(name: &'static str, entry: fn()) -> ThreadId {
+    spawn_with_priority(name, DEFAULT_PRIORITY, entry)
+}
+
+/// Como `spawn`, con prioridad `priority` (0 es la más urgente).
+pub fn spawn_with_priority(name: &'static str, priority: Priority, entry: fn()) -> ThreadId {
+    assert!(usize::from(priority) < crate::scheduler::PRIORITIES, "prioridad {} fuera de rango", priority);
+    let thread = Thread::new(name, priority, entry).expect("sin lugar para el stack del hilo");
+    crate::scheduler::add(thread).expect("demasiados hilos")
+}
+
+/// El hilo que está corriendo, si ya hay scheduler.
+pub fn current() -> Option<ThreadId> {
+    crate::scheduler::current()
+}
+
+/// Cede la CPU: el hilo pasa al final de la cola de su prioridad y sigue
+/// cuando le vuelva a tocar. Si no hay otro listo, vuelve enseguida.
+pub fn yield_now() {
+    crate::scheduler::yield_current();
+}
+
+/// Termina el hilo actual. Su stack se libera después del próximo cambio.
+pub fn exit() -> ! {
+    crate::scheduler::exit_current()
+}
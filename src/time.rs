@@ -0,0 +1,520 @@
+//! Reloj del kernel, en ticks del timer.
+//!
+//! Todo lo que mide plazos (el watchdog, el rate limiting del log) lee
+//! `ticks()` en vez del contador del handler del PIT. Normalmente es lo
+//! mismo, pero con la feature `deterministic` el reloj es virtual: sólo avanza
+//! cuando un test llama a `advance`, y los hooks de cada tick corren en ese
+//! momento y no desde la interrupción. Así un test con plazos da siempre el
+//! mismo resultado, sin importar lo rápido que corra QEMU.
+//!
+//! `interrupts::ticks()` sigue contando las interrupciones reales del PIT en
+//! los dos modos.
+//!
+//! Para medir intervalos cortos está `now_ns`, que lee la fuente de reloj que
+//! eligió `clocksource` (normalmente el TSC). La frecuencia del TSC se toma
+//! de CPUID (hoja 0x15) si la CPU la informa, o se mide contra la cuenta
+//! regresiva del PIT; lo hace el driver `tsc` al arrancar.
+//!
+//! Encima de eso, `Instant` y `Duration` (la de `core`) al estilo de `std`,
+//! y `uptime`. Antes de calibrar el TSC tienen la resolución de los ticks.
+//!
+//! La hora real sale del RTC: el driver `rtc` la lee una vez al arrancar y
+//! desde ahí `unix_timestamp` la sigue con `uptime`. `DateTime` la convierte
+//! a fecha (UTC, que es lo que se asume que guarda el RTC).
+
+use core::arch::x86_64::__cpuid;
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+pub use core::time::Duration;
+use x86_64::VirtAddr;
+
+use crate::driver::{Driver, Stage};
+
+/// Ticks que entran en `ms` milisegundos a la frecuencia vigente del PIT
+/// (`pit::set_frequency`), redondeando para abajo.
+pub fn ms_to_ticks(ms: u64) -> u64 {
+    ms * crate::pit::BASE_HZ / (u64::from(crate::pit::divisor()) * 1000)
+}
+
+pub fn ticks_to_ms(ticks: u64) -> u64 {
+    ticks * u64::from(crate::pit::divisor()) * 1000 / crate::pit::BASE_HZ
+}
+
+/// Milisegundos virtuales transcurridos.
+#[cfg(feature = "deterministic")]
+static VIRTUAL_MS: AtomicU64 = AtomicU64::new(0);
+
+pub fn ticks() -> u64 {
+    #[cfg(feature = "deterministic")]
+    return ms_to_ticks(VIRTUAL_MS.load(Ordering::Relaxed));
+    #[cfg(not(feature = "deterministic"))]
+    crate::interrupts::ticks()
+}
+
+/// Si el reloj es virtual.
+pub const fn is_deterministic() -> bool {
+    cfg!(feature = "deterministic")
+}
+
+/// Trabajo periódico de cada tick. Lo llama el handler del timer con el RIP
+/// interrumpido, o `advance` con una dirección nula en modo determinístico.
+pub(crate) fn on_tick(now: u64, interrupted_rip: VirtAddr) {
+    crate::watchdog::on_timer_tick(now, interrupted_rip);
+    crate::task::timer::on_timer_tick();
+    crate::timer_wheel::on_timer_tick(now);
+    crate::cpu_usage::on_timer_tick(now);
+}
+
+/// Adelanta el reloj virtual `ms` milisegundos y corre los hooks de cada
+/// tick que pase. Sólo existe con la feature `deterministic`.
+#[cfg(feature = "deterministic")]
+pub fn advance(ms: u64) {
+    let before = ticks();
+    VIRTUAL_MS.fetch_add(ms, Ordering::Relaxed);
+    for now in before + 1..=ticks() {
+        on_tick(now, VirtAddr::zero());
+    }
+}
+
+/// Espera a que pasen `n` ticks: con `hlt` si el reloj es real, adelantándolo
+/// si es virtual.
+pub fn sleep_ticks(n: u64) {
+    #[cfg(feature = "deterministic")]
+    {
+        let target = ticks() + n;
+        while ticks() < target {
+            advance(1);
+        }
+    }
+    #[cfg(not(feature = "deterministic"))]
+    {
+        let target = ticks() + n;
+        while ticks() < target {
+            x86_64::instructions::hlt();
+        }
+    }
+}
+
+// ----------------- TSC -----------------
+
+const CALIBRATION_US: u32 = 10_000;
+
+/// Ciclos del TSC por segundo; 0 = sin calibrar.
+static TSC_HZ: AtomicU64 = AtomicU64::new(0);
+
+/// Frecuencia del TSC según CPUID 0x15 (cristal × numerador / denominador),
+/// si la CPU informa los tres valores.
+fn tsc_hz_from_cpuid() -> Option<u64> {
+    if unsafe { __cpuid(0).eax } < 0x15 {
+        return None;
+    }
+    let leaf = unsafe { __cpuid(0x15) };
+    let (denominator, numerator, crystal_hz) = (leaf.eax, leaf.ebx, leaf.ecx);
+    if denominator == 0 || numerator == 0 || crystal_hz == 0 {
+        return None;
+    }
+    Some(u64::from(crystal_hz) * u64::from(numerator) / u64::from(denominator))
+}
+
+/// Si el TSC avanza a ritmo constante aunque cambie la frecuencia de la CPU.
+pub(crate) fn tsc_invariant() -> bool {
+    unsafe { __cpuid(0x8000_0000).eax >= 0x8000_0007 && __cpuid(0x8000_0007).edx & (1 << 8) != 0 }
+}
+
+fn tsc_hz_from_pit() -> u64 {
+    let cycles = x86_64::instructions::interrupts::without_interrupts(|| {
+        let start = crate::bench::rdtsc();
+        crate::pit::wait_one_shot(CALIBRATION_US);
+        crate::bench::rdtsc() - start
+    });
+    cycles * 1_000_000 / u64::from(CALIBRATION_US)
+}
+
+/// Determina la frecuencia del TSC.
+pub fn calibrate_tsc() -> u64 {
+    let hz = tsc_hz_from_cpuid().unwrap_or_else(tsc_hz_from_pit);
+    TSC_HZ.store(hz, Ordering::Relaxed);
+    hz
+}
+
+fn init_tsc() {
+    let hz = calibrate_tsc();
+    crate::log_info!("TSC: {} kHz", hz / 1000);
+    if !tsc_invariant() {
+        crate::log_warn!("TSC no invariante: now_ns puede desviarse si cambia la frecuencia");
+    }
+    crate::clocksource::select();
+}
+
+crate::register_driver!(TSC_DRIVER, Driver {
+    name: "tsc",
+    stage: Stage::Drivers,
+    depends_on: &["pit"],
+    device: None,
+    probe: Driver::always,
+    init: init_tsc,
+});
+
+/// Ciclos del TSC por segundo, o 0 si todavía no se calibró.
+pub fn tsc_frequency() -> u64 {
+    TSC_HZ.load(Ordering::Relaxed)
+}
+
+pub(crate) fn cycles_to_ns(cycles: u64, hz: u64) -> u64 {
+    (u128::from(cycles) * 1_000_000_000 / u128::from(hz)) as u64
+}
+
+/// Nanosegundos monótonos desde el arranque, según `clocksource`.
+///
+/// Con la feature `deterministic` es el reloj virtual de `advance`.
+pub fn now_ns() -> u64 {
+    #[cfg(feature = "deterministic")]
+    return VIRTUAL_MS.load(Ordering::Relaxed) * 1_000_000;
+    #[cfg(not(feature = "deterministic"))]
+    crate::clocksource::now_ns()
+}
+
+// ----------------- INSTANT -----------------
+
+/// Tiempo que lleva el kernel andando.
+pub fn uptime() -> Duration {
+    Duration::from_nanos(now_ns())
+}
+
+/// Un punto del reloj monótono, para medir cuánto pasó entre dos momentos.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant {
+    ns: u64,
+}
+
+impl Instant {
+    pub fn now() -> Instant {
+        Instant { ns: now_ns() }
+    }
+
+    /// Lo que pasó desde `earlier`, o cero si `earlier` es posterior.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_nanos(self.ns.saturating_sub(earlier.ns))
+    }
+
+    pub fn checked_duration_since(&self, earlier: Instant) -> Option<Duration> {
+        self.ns.checked_sub(earlier.ns).map(Duration::from_nanos)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+        let ns = u64::try_from(duration.as_nanos()).ok()?;
+        Some(Instant { ns: self.ns.checked_add(ns)? })
+    }
+
+    pub fn checked_sub(&self, duration: Duration) -> Option<Instant> {
+        let ns = u64::try_from(duration.as_nanos()).ok()?;
+        Some(Instant { ns: self.ns.checked_sub(ns)? })
+    }
+
+    /// Nanosegundos desde el origen del reloj.
+    pub fn as_nanos(&self) -> u64 {
+        self.ns
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        self.checked_add(duration).expect("desborde al sumar a un Instant")
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, duration: Duration) {
+        *self = *self + duration;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, duration: Duration) -> Instant {
+        self.checked_sub(duration).expect("desborde al restar a un Instant")
+    }
+}
+
+impl SubAssign<Duration> for Instant {
+    fn sub_assign(&mut self, duration: Duration) {
+        *self = *self - duration;
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+// ----------------- HORA REAL -----------------
+
+// Registros del RTC en el CMOS.
+const RTC_SECONDS: u8 = 0x00;
+const RTC_MINUTES: u8 = 0x02;
+const RTC_HOURS: u8 = 0x04;
+const RTC_DAY: u8 = 0x07;
+const RTC_MONTH: u8 = 0x08;
+const RTC_YEAR: u8 = 0x09;
+/// No es estándar, pero es donde lo pone casi todo firmware (y QEMU).
+const RTC_CENTURY: u8 = 0x32;
+const RTC_STATUS_A: u8 = 0x0A;
+const RTC_STATUS_B: u8 = 0x0B;
+
+const STATUS_A_UPDATING: u8 = 1 << 7;
+const STATUS_B_24H: u8 = 1 << 1;
+const STATUS_B_BINARY: u8 = 1 << 2;
+const HOUR_PM: u8 = 1 << 7;
+
+/// Segundos Unix al momento en que `uptime` era cero.
+static BOOT_UNIX: AtomicU64 = AtomicU64::new(0);
+static RTC_SYNCED: AtomicBool = AtomicBool::new(false);
+
+/// Fecha y hora en UTC, con resolución de segundos.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+/// Días desde 1970-01-01 (algoritmo `days_from_civil` de H. Hinnant).
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+impl DateTime {
+    pub fn from_unix(seconds: u64) -> DateTime {
+        let days = (seconds / 86_400) as i64 + 719_468;
+        let of_day = seconds % 86_400;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days - era * 146_097;
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u8;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        DateTime {
+            year: year as u16,
+            month,
+            day,
+            hour: (of_day / 3600) as u8,
+            minute: (of_day / 60 % 60) as u8,
+            second: (of_day % 60) as u8,
+        }
+    }
+
+    /// Segundos Unix; las fechas anteriores a 1970 dan 0.
+    pub fn to_unix(&self) -> u64 {
+        let days = days_from_civil(i64::from(self.year), u32::from(self.month), u32::from(self.day));
+        let seconds = days * 86_400
+            + i64::from(self.hour) * 3600
+            + i64::from(self.minute) * 60
+            + i64::from(self.second);
+        seconds.max(0) as u64
+    }
+
+    /// La hora actual, si ya se leyó el RTC.
+    pub fn now() -> Option<DateTime> {
+        unix_timestamp().map(DateTime::from_unix)
+    }
+}
+
+/// `AAAA-MM-DD HH:MM:SS`.
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+fn bcd_to_binary(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0F)
+}
+
+/// Convierte los registros crudos del RTC según el formato del status B.
+fn decode_rtc(raw: [u8; 7], status_b: u8) -> DateTime {
+    let [second, minute, hour, day, month, year, century] = raw;
+    let decode = |value: u8| {
+        if status_b & STATUS_B_BINARY != 0 { value } else { bcd_to_binary(value) }
+    };
+
+    let pm = hour & HOUR_PM != 0;
+    let mut hour = decode(hour & !HOUR_PM);
+    if status_b & STATUS_B_24H == 0 {
+        hour = match (hour, pm) {
+            (12, false) => 0,
+            (12, true) => 12,
+            (hour, true) => hour + 12,
+            (hour, false) => hour,
+        };
+    }
+
+    let century = match decode(century) {
+        century @ 19..=21 => u16::from(century),
+        _ => 20,
+    };
+    DateTime {
+        year: century * 100 + u16::from(decode(year)),
+        month: decode(month),
+        day: decode(day),
+        hour,
+        minute: decode(minute),
+        second: decode(second),
+    }
+}
+
+fn read_rtc_raw() -> [u8; 7] {
+    while crate::cmos::read(RTC_STATUS_A) & STATUS_A_UPDATING != 0 {
+        core::hint::spin_loop();
+    }
+    [RTC_SECONDS, RTC_MINUTES, RTC_HOURS, RTC_DAY, RTC_MONTH, RTC_YEAR, RTC_CENTURY]
+        .map(crate::cmos::read)
+}
+
+/// Lee la fecha del RTC. Repite hasta que dos lecturas seguidas coincidan,
+/// para no mezclar valores de antes y después de una actualización.
+pub fn read_rtc() -> DateTime {
+    let mut raw = read_rtc_raw();
+    loop {
+        let again = read_rtc_raw();
+        if again == raw {
+            break;
+        }
+        raw = again;
+    }
+    decode_rtc(raw, crate::cmos::read(RTC_STATUS_B))
+}
+
+fn init_rtc() {
+    let now = read_rtc();
+    BOOT_UNIX.store(now.to_unix().saturating_sub(uptime().as_secs()), Ordering::Relaxed);
+    RTC_SYNCED.store(true, Ordering::Relaxed);
+    crate::log_info!("RTC: {} UTC", now);
+}
+
+crate::register_driver!(RTC_DRIVER, Driver {
+    name: "rtc",
+    stage: Stage::Drivers,
+    // Después del TSC, para que `uptime` ya tenga buena resolución. El
+    // dispositivo PNP0B00 es de `cmos`.
+    depends_on: &["cmos", "tsc"],
+    device: None,
+    probe: Driver::always,
+    init: init_rtc,
+});
+
+/// Segundos desde 1970-01-01 UTC, o `None` si todavía no se leyó el RTC.
+pub fn unix_timestamp() -> Option<u64> {
+    if !RTC_SYNCED.load(Ordering::Relaxed) {
+        return None;
+    }
+    Some(BOOT_UNIX.load(Ordering::Relaxed) + uptime().as_secs())
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_tick_conversion() {
+    // Con el divisor que deja el firmware (~18.2 Hz).
+    assert_eq!(ms_to_ticks(1000), 18);
+    assert_eq!(ms_to_ticks(54), 0);
+    assert_eq!(ms_to_ticks(55), 1);
+    assert_eq!(ticks_to_ms(91), 4998);
+}
+
+#[test_case]
+fn test_cycles_to_ns() {
+    assert_eq!(cycles_to_ns(3_000_000_000, 3_000_000_000), 1_000_000_000);
+    // Sin desbordar con contadores grandes.
+    assert_eq!(cycles_to_ns(u64::MAX / 2, 4_000_000_000), 2_305_843_009_213_693_951);
+}
+
+#[cfg(not(feature = "deterministic"))]
+#[test_case]
+fn test_now_ns_is_monotonic_and_plausible() {
+    let start = now_ns();
+    crate::pit::wait_one_shot(10_000);
+    let elapsed = now_ns() - start;
+    // 10 ms, con margen amplio para la emulación.
+    assert!(elapsed > 5_000_000 && elapsed < 50_000_000, "{} ns", elapsed);
+}
+
+#[cfg(feature = "deterministic")]
+#[test_case]
+fn test_virtual_clock_only_moves_on_advance() {
+    let start = VIRTUAL_MS.load(Ordering::Relaxed);
+    let before = ticks();
+    for _ in 0..100_000 {
+        core::hint::spin_loop();
+    }
+    assert_eq!(ticks(), before);
+    advance(1000);
+    assert_eq!(ticks(), ms_to_ticks(start + 1000));
+}
+
+#[test_case]
+fn test_instant_arithmetic() {
+    let start = Instant { ns: 1_000 };
+    let later = start + Duration::from_micros(5);
+    assert_eq!(later.as_nanos(), 6_000);
+    assert_eq!(later - start, Duration::from_micros(5));
+    assert_eq!(start - later, Duration::ZERO);
+    assert_eq!(start.checked_duration_since(later), None);
+    assert_eq!(start.checked_sub(Duration::from_micros(2)), None);
+    assert_eq!(later - Duration::from_nanos(6_000), Instant { ns: 0 });
+}
+
+#[test_case]
+fn test_instant_is_monotonic() {
+    let start = Instant::now();
+    let uptime = uptime();
+    assert!(Instant::now() >= start);
+    assert!(uptime >= Duration::from_nanos(start.as_nanos()));
+}
+
+#[test_case]
+fn test_unix_date_conversion() {
+    let epoch = DateTime { year: 1970, month: 1, day: 1, hour: 0, minute: 0, second: 0 };
+    assert_eq!(DateTime::from_unix(0), epoch);
+    let date = DateTime::from_unix(1_700_000_000);
+    assert_eq!(date, DateTime { year: 2023, month: 11, day: 14, hour: 22, minute: 13, second: 20 });
+    assert_eq!(date.to_unix(), 1_700_000_000);
+    // 29 de febrero de un bisiesto.
+    let leap = DateTime { year: 2024, month: 2, day: 29, hour: 12, minute: 0, second: 0 };
+    assert_eq!(DateTime::from_unix(leap.to_unix()), leap);
+}
+
+#[test_case]
+fn test_decode_rtc() {
+    // BCD, 12 horas: 11:05:09 PM del 31/12/99, siglo 20.
+    let date = decode_rtc([0x09, 0x05, 0x11 | HOUR_PM, 0x31, 0x12, 0x99, 0x20], 0);
+    assert_eq!(date, DateTime { year: 2099, month: 12, day: 31, hour: 23, minute: 5, second: 9 });
+    // Binario, 24 horas, sin registro de siglo.
+    let date = decode_rtc([9, 5, 0, 1, 2, 26, 0], STATUS_B_BINARY | STATUS_B_24H);
+    assert_eq!(date, DateTime { year: 2026, month: 2, day: 1, hour: 0, minute: 5, second: 9 });
+}
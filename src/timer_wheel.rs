@@ -0,0 +1,298 @@
+//! Timers del kernel: callbacks que corren cuando `time::ticks()` llega a un
+//! plazo.
+//!
+//! Es una rueda jerárquica como la de Linux: `LEVELS` niveles de 64 ranuras,
+//! donde el nivel `l` agrupa los plazos de a 64^l ticks. Agregar y cancelar
+//! es O(1) (listas doblemente enlazadas dentro de un pool fijo de nodos);
+//! cuando el nivel 0 da la vuelta, la ranura que toca del nivel siguiente se
+//! reparte hacia abajo. Los plazos más lejanos que el alcance de la rueda
+//! (64^LEVELS ticks) esperan en el último nivel y se reubican al bajar.
+//!
+//! Los callbacks corren desde la interrupción del timer (o desde
+//! `time::advance` con el reloj virtual), con la rueda ya liberada: pueden
+//! agregar o cancelar timers, pero no bloquear.
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+pub const MAX_TIMERS: usize = 4096;
+const LEVELS: usize = 4;
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+/// Distancia máxima que la rueda distingue.
+const RANGE: u64 = 1 << (SLOT_BITS * LEVELS as u32);
+const NIL: u16 = u16::MAX;
+
+pub type Callback = fn(usize);
+
+/// Identifica un timer agregado; deja de valer cuando corre o se cancela.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerId {
+    index: u16,
+    generation: u32,
+}
+
+#[derive(Clone, Copy)]
+struct Node {
+    deadline: u64,
+    callback: Option<Callback>,
+    arg: usize,
+    prev: u16,
+    next: u16,
+    /// Ranura donde está (nivel * SLOTS + índice), o NIL si está libre.
+    slot: u16,
+    generation: u32,
+}
+
+const FREE_NODE: Node = Node {
+    deadline: 0,
+    callback: None,
+    arg: 0,
+    prev: NIL,
+    next: NIL,
+    slot: NIL,
+    generation: 0,
+};
+
+struct Wheel<const N: usize> {
+    /// Último tick procesado.
+    now: u64,
+    heads: [u16; LEVELS * SLOTS],
+    nodes: [Node; N],
+    /// Lista de nodos libres, enlazada por `next`.
+    free: u16,
+    len: usize,
+}
+
+impl<const N: usize> Wheel<N> {
+    const fn new() -> Self {
+        assert!(N < NIL as usize);
+        let mut nodes = [FREE_NODE; N];
+        let mut i = 0;
+        while i + 1 < N {
+            nodes[i].next = (i + 1) as u16;
+            i += 1;
+        }
+        Wheel {
+            now: 0,
+            heads: [NIL; LEVELS * SLOTS],
+            nodes,
+            free: if N == 0 { NIL } else { 0 },
+            len: 0,
+        }
+    }
+
+    fn slot_for(&self, deadline: u64) -> usize {
+        if deadline <= self.now {
+            // Vencido: a la ranura actual, que se revisa en la próxima pasada.
+            return (self.now % SLOTS as u64) as usize;
+        }
+        let target = deadline.min(self.now + RANGE - 1);
+        let delta = target - self.now;
+        let mut level = 0;
+        while delta >= 1 << (SLOT_BITS * (level as u32 + 1)) {
+            level += 1;
+        }
+        level * SLOTS + ((target >> (SLOT_BITS * level as u32)) % SLOTS as u64) as usize
+    }
+
+    fn link(&mut self, index: u16) {
+        let slot = self.slot_for(self.nodes[index as usize].deadline);
+        let head = self.heads[slot];
+        let node = &mut self.nodes[index as usize];
+        node.slot = slot as u16;
+        node.prev = NIL;
+        node.next = head;
+        if head != NIL {
+            self.nodes[head as usize].prev = index;
+        }
+        self.heads[slot] = index;
+    }
+
+    fn unlink(&mut self, index: u16) {
+        let Node { prev, next, slot, .. } = self.nodes[index as usize];
+        if prev == NIL {
+            self.heads[slot as usize] = next;
+        } else {
+            self.nodes[prev as usize].next = next;
+        }
+        if next != NIL {
+            self.nodes[next as usize].prev = prev;
+        }
+    }
+
+    fn insert(&mut self, deadline: u64, callback: Callback, arg: usize) -> Option<TimerId> {
+        let index = self.free;
+        if index == NIL {
+            return None;
+        }
+        let node = &mut self.nodes[index as usize];
+        self.free = node.next;
+        node.deadline = deadline;
+        node.callback = Some(callback);
+        node.arg = arg;
+        let generation = node.generation;
+        self.link(index);
+        self.len += 1;
+        Some(TimerId { index, generation })
+    }
+
+    fn release(&mut self, index: u16) -> (Callback, usize) {
+        self.unlink(index);
+        let free = self.free;
+        let node = &mut self.nodes[index as usize];
+        let fired = (node.callback.take().unwrap(), node.arg);
+        node.slot = NIL;
+        node.generation = node.generation.wrapping_add(1);
+        node.next = free;
+        self.free = index;
+        self.len -= 1;
+        fired
+    }
+
+    fn cancel(&mut self, id: TimerId) -> bool {
+        let node = &self.nodes[id.index as usize];
+        if node.slot == NIL || node.generation != id.generation {
+            return false;
+        }
+        self.release(id.index);
+        true
+    }
+
+    /// Reubica los timers de una ranura según lo que falta para su plazo.
+    fn cascade(&mut self, slot: usize) {
+        let mut index = core::mem::replace(&mut self.heads[slot], NIL);
+        while index != NIL {
+            let next = self.nodes[index as usize].next;
+            self.link(index);
+            index = next;
+        }
+    }
+
+    fn tick(&mut self) {
+        self.now += 1;
+        // El nivel `l` se reparte cada vez que los de abajo dan la vuelta.
+        let mut due = 1;
+        while due < LEVELS && self.now.is_multiple_of(1 << (SLOT_BITS * due as u32)) {
+            due += 1;
+        }
+        // Del más alto al más bajo, así lo que baja dos niveles de una vez no
+        // se vuelve a repartir.
+        for level in (1..due).rev() {
+            let index = (self.now >> (SLOT_BITS * level as u32)) % SLOTS as u64;
+            self.cascade(level * SLOTS + index as usize);
+        }
+    }
+
+    /// Avanza hasta `now` y saca el próximo timer vencido. Se llama hasta que
+    /// devuelva `None`.
+    fn next_expired(&mut self, now: u64) -> Option<(Callback, usize)> {
+        loop {
+            let slot = (self.now % SLOTS as u64) as usize;
+            let mut index = self.heads[slot];
+            while index != NIL {
+                let next = self.nodes[index as usize].next;
+                if self.nodes[index as usize].deadline <= self.now {
+                    return Some(self.release(index));
+                }
+                // Un plazo más allá del alcance que quedó acá al bajar.
+                self.unlink(index);
+                self.link(index);
+                index = next;
+            }
+            if self.now >= now {
+                return None;
+            }
+            self.tick();
+        }
+    }
+}
+
+static WHEEL: Mutex<Wheel<MAX_TIMERS>> = Mutex::new(Wheel::new());
+
+/// Programa `callback(arg)` para cuando `time::ticks()` llegue a `deadline`.
+/// Un plazo ya pasado corre en el próximo tick. `None` si hay `MAX_TIMERS`
+/// pendientes.
+pub fn add_timer(deadline: u64, callback: Callback, arg: usize) -> Option<TimerId> {
+    interrupts::without_interrupts(|| WHEEL.lock().insert(deadline, callback, arg))
+}
+
+/// Como `add_timer`, a `delay` ticks de ahora.
+pub fn add_timer_after(delay: u64, callback: Callback, arg: usize) -> Option<TimerId> {
+    add_timer(crate::time::ticks() + delay, callback, arg)
+}
+
+/// Cancela un timer. `false` si ya corrió o ya estaba cancelado.
+pub fn cancel(id: TimerId) -> bool {
+    interrupts::without_interrupts(|| WHEEL.lock().cancel(id))
+}
+
+/// Timers pendientes.
+pub fn pending() -> usize {
+    interrupts::without_interrupts(|| WHEEL.lock().len)
+}
+
+/// Lo llama `time::on_tick`: corre los timers vencidos hasta `now`.
+pub(crate) fn on_timer_tick(now: u64) {
+    loop {
+        // Si el lock está tomado se sigue en el próximo tick; la rueda
+        // recuerda hasta dónde llegó.
+        let Some(mut wheel) = WHEEL.try_lock() else {
+            return;
+        };
+        let Some((callback, arg)) = wheel.next_expired(now) else {
+            return;
+        };
+        drop(wheel);
+        callback(arg);
+    }
+}
+
+// ----------------- TESTS -----------------
+
+/// Saca todo lo vencido hasta `now`: (arg, tick en que corrió).
+#[cfg(test)]
+fn collect<const N: usize>(wheel: &mut Wheel<N>, now: u64, fired: &mut [(usize, u64)]) -> usize {
+    let mut count = 0;
+    while let Some((_, arg)) = wheel.next_expired(now) {
+        fired[count] = (arg, wheel.now);
+        count += 1;
+    }
+    count
+}
+
+#[test_case]
+fn test_timers_fire_on_their_tick_across_levels() {
+    fn nop(_: usize) {}
+    let mut wheel = Wheel::<16>::new();
+    // Uno por nivel, y uno más allá del alcance.
+    for deadline in [RANGE + 5, 70_000, 5_000, 100, 3] {
+        wheel.insert(deadline, nop, deadline as usize).unwrap();
+    }
+    let mut fired = [(0, 0); 16];
+    assert_eq!(collect(&mut wheel, RANGE + 10, &mut fired), 5);
+    for (i, deadline) in [3, 100, 5_000, 70_000, RANGE + 5].into_iter().enumerate() {
+        assert_eq!(fired[i], (deadline as usize, deadline));
+    }
+    assert_eq!(wheel.len, 0);
+}
+
+#[test_case]
+fn test_cancel_and_reuse() {
+    fn nop(_: usize) {}
+    let mut wheel = Wheel::<2>::new();
+    let first = wheel.insert(10, nop, 1).unwrap();
+    wheel.insert(20, nop, 2).unwrap();
+    assert!(wheel.insert(30, nop, 3).is_none());
+
+    assert!(wheel.cancel(first));
+    assert!(!wheel.cancel(first));
+    // El nodo se reusa con otra generación: el id viejo no lo cancela.
+    let third = wheel.insert(5, nop, 3).unwrap();
+    assert!(!wheel.cancel(first));
+
+    let mut fired = [(0, 0); 2];
+    assert_eq!(collect(&mut wheel, 25, &mut fired), 2);
+    assert_eq!(fired, [(3, 5), (2, 20)]);
+    assert!(!wheel.cancel(third));
+}
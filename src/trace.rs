@@ -0,0 +1,246 @@
+//! Tracepoints del kernel, exportables al formato de trazas de Chrome.
+//!
+//! Con el trazado activo (`enable`) cada poll de tarea y cada handler de
+//! interrupción medido anota su comienzo y su fin en un ring buffer binario de
+//! `CAPACITY` registros; lleno, lo nuevo pisa lo más viejo. `write_chrome_json`
+//! lo convierte al JSON `trace_event` que abren tal cual `chrome://tracing` y
+//! ui.perfetto.dev, con una fila para las tareas y otra para las
+//! interrupciones. `dump_chrome_json` lo manda por serie: se copia del log a
+//! un `.json` y no hace falta ninguna herramienta propia del lado del host.
+//!
+//! Anotar no aloca ni bloquea: sirve desde un handler de interrupción.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+pub const CAPACITY: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Point {
+    /// Poll de la tarea con este `TaskId`.
+    Task(u64),
+    /// Handler del vector.
+    Irq(u8),
+}
+
+impl Point {
+    /// Fila (`tid`) de la traza.
+    fn row(self) -> usize {
+        match self {
+            Point::Task(_) => 0,
+            Point::Irq(_) => 1,
+        }
+    }
+}
+
+const ROWS: [&str; 2] = ["tareas", "interrupciones"];
+
+#[derive(Debug, Clone, Copy)]
+struct Record {
+    ns: u64,
+    point: Point,
+    begin: bool,
+}
+
+struct Ring {
+    records: [Record; CAPACITY],
+    next: usize,
+    len: usize,
+}
+
+impl Ring {
+    fn push(&mut self, record: Record) {
+        self.records[self.next] = record;
+        self.next = (self.next + 1) % CAPACITY;
+        if self.len < CAPACITY {
+            self.len += 1;
+        } else {
+            OVERWRITTEN.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// El registro `i`, del más viejo al más nuevo.
+    fn get(&self, i: usize) -> Option<Record> {
+        (i < self.len).then(|| self.records[(self.next + CAPACITY - self.len + i) % CAPACITY])
+    }
+}
+
+const EMPTY: Record = Record { ns: 0, point: Point::Irq(0), begin: false };
+
+static RING: Mutex<Ring> = Mutex::new(Ring { records: [EMPTY; CAPACITY], next: 0, len: 0 });
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static OVERWRITTEN: AtomicU64 = AtomicU64::new(0);
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Descarta lo anotado.
+pub fn clear() {
+    interrupts::without_interrupts(|| {
+        let mut ring = RING.lock();
+        ring.next = 0;
+        ring.len = 0;
+    });
+    OVERWRITTEN.store(0, Ordering::Relaxed);
+}
+
+/// Registros pisados por falta de lugar desde el último `clear`.
+pub fn overwritten() -> u64 {
+    OVERWRITTEN.load(Ordering::Relaxed)
+}
+
+pub fn begin(point: Point) {
+    record(point, true);
+}
+
+pub fn end(point: Point) {
+    record(point, false);
+}
+
+fn record(point: Point, begin: bool) {
+    if !is_enabled() {
+        return;
+    }
+    let ns = crate::time::now_ns();
+    interrupts::without_interrupts(|| {
+        // Sólo falla si se anota desde una interrupción durante un `clear`:
+        // ese registro se pierde.
+        if let Some(mut ring) = RING.try_lock() {
+            ring.push(Record { ns, point, begin });
+        }
+    });
+}
+
+fn write_event(out: &mut impl Write, record: &Record) -> fmt::Result {
+    let phase = if record.begin { "B" } else { "E" };
+    let (category, row) = match record.point {
+        Point::Task(id) => {
+            write!(out, ",\n{{\"name\":\"tarea {}\"", id)?;
+            ("task", record.point.row())
+        }
+        Point::Irq(vector) => {
+            write!(out, ",\n{{\"name\":\"irq {:#04x}\"", vector)?;
+            ("irq", record.point.row())
+        }
+    };
+    // `ts` va en microsegundos.
+    write!(
+        out,
+        ",\"cat\":\"{}\",\"ph\":\"{}\",\"ts\":{}.{:03},\"pid\":1,\"tid\":{}}}",
+        category, phase, record.ns / 1000, record.ns % 1000, row + 1
+    )
+}
+
+/// Escribe lo anotado como JSON `trace_event`. Pausa el trazado mientras
+/// tanto. Los fines cuyo comienzo ya se pisó se omiten.
+pub fn write_chrome_json(out: &mut impl Write) -> fmt::Result {
+    let was_enabled = ENABLED.swap(false, Ordering::Relaxed);
+    let result = (|| {
+        write!(out, "{{\"displayTimeUnit\":\"ns\",\"traceEvents\":[")?;
+        for (row, name) in ROWS.iter().enumerate() {
+            if row > 0 {
+                write!(out, ",")?;
+            }
+            write!(
+                out,
+                "\n{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":\"{}\"}}}}",
+                row + 1, name
+            )?;
+        }
+
+        let mut depth = [0u32; ROWS.len()];
+        let mut i = 0;
+        // Un registro por vez para no tener las interrupciones apagadas
+        // mientras sale todo por serie.
+        while let Some(record) = interrupts::without_interrupts(|| RING.lock().get(i)) {
+            i += 1;
+            let depth = &mut depth[record.point.row()];
+            if record.begin {
+                *depth += 1;
+            } else if *depth == 0 {
+                continue;
+            } else {
+                *depth -= 1;
+            }
+            write_event(out, &record)?;
+        }
+        writeln!(out, "\n]}}")
+    })();
+    ENABLED.store(was_enabled, Ordering::Relaxed);
+    result
+}
+
+struct SerialOut;
+
+impl Write for SerialOut {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        crate::serial_print!("{}", s);
+        Ok(())
+    }
+}
+
+/// `write_chrome_json` por serie.
+pub fn dump_chrome_json() {
+    let _ = write_chrome_json(&mut SerialOut);
+}
+
+// ----------------- TESTS -----------------
+
+#[cfg(test)]
+struct Buffer {
+    bytes: [u8; 4096],
+    len: usize,
+}
+
+#[cfg(test)]
+impl Buffer {
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap()
+    }
+}
+
+#[cfg(test)]
+impl Write for Buffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // Lo que no entra se descarta.
+        let n = s.len().min(self.bytes.len() - self.len);
+        self.bytes[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+#[test_case]
+fn test_chrome_json_pairs_events() {
+    clear();
+    enable();
+    // Un fin sin comienzo, como si el comienzo se hubiera pisado.
+    end(Point::Task(6));
+    begin(Point::Task(7));
+    begin(Point::Irq(0x21));
+    end(Point::Irq(0x21));
+    end(Point::Task(7));
+    disable();
+
+    let mut buffer = Buffer { bytes: [0; 4096], len: 0 };
+    write_chrome_json(&mut buffer).unwrap();
+    let json = buffer.as_str();
+    assert!(json.starts_with("{\"displayTimeUnit\":\"ns\",\"traceEvents\":["));
+    assert!(json.trim_end().ends_with("]}"));
+    assert!(json.contains("\"args\":{\"name\":\"tareas\"}"));
+    assert!(json.contains("{\"name\":\"tarea 7\",\"cat\":\"task\",\"ph\":\"B\""));
+    assert!(json.contains("{\"name\":\"irq 0x21\",\"cat\":\"irq\",\"ph\":\"E\""));
+    assert!(!json.contains("tarea 6"));
+    clear();
+}
@@ -27,10 +27,10 @@ pub enum Color {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(transparent)]
-struct ColorCode(u8);
+pub struct ColorCode(u8);
 
 impl ColorCode {
-    const fn new(foreground: Color, background: Color) -> ColorCode {
+    pub const fn new(foreground: Color, background: Color) -> ColorCode {
         ColorCode((background as u8) << 4 | (foreground as u8))
     }
 }
@@ -38,23 +38,103 @@ impl ColorCode {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
-struct ScreenChar {
-    ascii_character: u8,
-    color_code: ColorCode,
+pub struct ScreenChar {
+    pub ascii_character: u8,
+    pub color_code: ColorCode,
+}
+
+/// Destino de escritura del `Writer`: la memoria de video real o un mock.
+pub trait TextBuffer {
+    fn read(&self, row: usize, col: usize) -> ScreenChar;
+    fn write(&mut self, row: usize, col: usize, character: ScreenChar);
+}
+
+impl<T: TextBuffer + ?Sized> TextBuffer for &mut T {
+    fn read(&self, row: usize, col: usize) -> ScreenChar {
+        (**self).read(row, col)
+    }
+
+    fn write(&mut self, row: usize, col: usize, character: ScreenChar) {
+        (**self).write(row, col, character)
+    }
 }
 
+/// Buffer VGA mapeado en 0xb8000; cada acceso es volátil.
 #[repr(transparent)]
-struct Buffer {
+pub struct Buffer {
     chars: [[Volatile<ScreenChar>; BUFFER_WIDTH]; BUFFER_HEIGHT],
 }
 
-pub struct Writer {
+impl TextBuffer for Buffer {
+    fn read(&self, row: usize, col: usize) -> ScreenChar {
+        self.chars[row][col].read()
+    }
+
+    fn write(&mut self, row: usize, col: usize, character: ScreenChar) {
+        self.chars[row][col].write(character);
+    }
+}
+
+/// Buffer en memoria común, para testear el `Writer` sin tocar la pantalla.
+pub struct MemoryBuffer {
+    pub chars: [[ScreenChar; BUFFER_WIDTH]; BUFFER_HEIGHT],
+}
+
+impl MemoryBuffer {
+    pub const fn new() -> Self {
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: ColorCode::new(Color::White, Color::Black),
+        };
+        MemoryBuffer {
+            chars: [[blank; BUFFER_WIDTH]; BUFFER_HEIGHT],
+        }
+    }
+
+    /// Texto de una fila, sin los espacios finales.
+    pub fn row_text(&self, row: usize, out: &mut [u8; BUFFER_WIDTH]) -> usize {
+        for (col, c) in self.chars[row].iter().enumerate() {
+            out[col] = c.ascii_character;
+        }
+        out.iter().rposition(|&b| b != b' ').map_or(0, |last| last + 1)
+    }
+}
+
+impl Default for MemoryBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextBuffer for MemoryBuffer {
+    fn read(&self, row: usize, col: usize) -> ScreenChar {
+        self.chars[row][col]
+    }
+
+    fn write(&mut self, row: usize, col: usize, character: ScreenChar) {
+        self.chars[row][col] = character;
+    }
+}
+
+pub struct Writer<B: TextBuffer = &'static mut Buffer> {
     column_position: usize,
     color_code: ColorCode,
-    buffer: &'static mut Buffer,
+    buffer: B,
 }
 
-impl Writer {
+impl<B: TextBuffer> Writer<B> {
+    pub fn new(buffer: B, foreground: Color, background: Color) -> Self {
+        Writer {
+            column_position: 0,
+            color_code: ColorCode::new(foreground, background),
+            buffer,
+        }
+    }
+
+    pub fn buffer(&self) -> &B {
+        &self.buffer
+    }
+
     pub fn write_byte(&mut self, byte: u8) {
         match byte {
             b'\n' => self.new_line(),
@@ -67,7 +147,7 @@ impl Writer {
                 let col = self.column_position;
 
                 let color_code = self.color_code;
-                self.buffer.chars[row][col].write(ScreenChar {
+                self.buffer.write(row, col, ScreenChar {
                     ascii_character: byte,
                     color_code,
                 });
@@ -79,8 +159,8 @@ impl Writer {
     fn new_line(&mut self) {
         for row in 1..BUFFER_HEIGHT {
             for col in 0..BUFFER_WIDTH {
-                let character = self.buffer.chars[row][col].read();
-                self.buffer.chars[row - 1][col].write(character);
+                let character = self.buffer.read(row, col);
+                self.buffer.write(row - 1, col, character);
             }
         }
         self.clear_row(BUFFER_HEIGHT - 1);
@@ -93,10 +173,15 @@ impl Writer {
             color_code: self.color_code,
         };
         for col in 0..BUFFER_WIDTH {
-            self.buffer.chars[row][col].write(blank);
+            self.buffer.write(row, col, blank);
         }
     }
 
+    /// Posición del cursor como `(columna, fila)`; siempre se escribe en la última fila.
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.column_position, BUFFER_HEIGHT - 1)
+    }
+
     pub fn write_string(&mut self, s: &str) {
         for byte in s.bytes() {
             match byte {
@@ -109,7 +194,7 @@ impl Writer {
 
 use core::fmt;
 
-impl fmt::Write for Writer {
+impl<B: TextBuffer> fmt::Write for Writer<B> {
     fn write_str(&mut self, s: &str) -> fmt::Result {
         self.write_string(s);
         Ok(())
@@ -120,32 +205,11 @@ use spin::Mutex;
 use lazy_static::lazy_static;
 
 lazy_static! {
-    pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer {
-        column_position: 0,
-        color_code: ColorCode::new(Color::Yellow, Color::Black),
-        buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
-    });
-}
-
-#[macro_export]
-macro_rules! print {
-    ($($arg:tt)*) => ($crate::vga_buffer::_print(format_args!($($arg)*)));
-}
-
-#[macro_export]
-macro_rules! println {
-    () => ($crate::print!("\n"));
-    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
-}
-
-#[doc(hidden)]
-pub fn _print(args: fmt::Arguments) {
-    use core::fmt::Write;
-    use x86_64::instructions::interrupts;
-
-    interrupts::without_interrupts(|| {
-        WRITER.lock().write_fmt(args).unwrap();
-    });
+    pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer::new(
+        unsafe { &mut *(0xb8000 as *mut Buffer) },
+        Color::Yellow,
+        Color::Black,
+    ));
 }
 
 // ----------------- TESTS -----------------
@@ -174,8 +238,69 @@ fn test_println_output() {
         let mut writer = WRITER.lock();
         writeln!(writer, "\n{}", s).expect("writeln falló");
         for (i, c) in s.chars().enumerate() {
-            let screen_char = writer.buffer.chars[BUFFER_HEIGHT - 2][i].read();
+            let screen_char = writer.buffer.read(BUFFER_HEIGHT - 2, i);
             assert_eq!(char::from(screen_char.ascii_character), c);
         }
     });
-}
\ No newline at end of file
+}
+
+#[test_case]
+fn test_mock_write_last_row() {
+    let mut writer = Writer::new(MemoryBuffer::new(), Color::Yellow, Color::Black);
+    writer.write_string("hola");
+
+    let mut row = [0; BUFFER_WIDTH];
+    let len = writer.buffer().row_text(BUFFER_HEIGHT - 1, &mut row);
+    assert_eq!(&row[..len], b"hola");
+    assert_eq!(writer.cursor(), (4, BUFFER_HEIGHT - 1));
+    assert_eq!(
+        writer.buffer().read(BUFFER_HEIGHT - 1, 0).color_code,
+        ColorCode::new(Color::Yellow, Color::Black)
+    );
+}
+
+#[test_case]
+fn test_mock_scrolling() {
+    let mut writer = Writer::new(MemoryBuffer::new(), Color::Yellow, Color::Black);
+    writer.write_string("primera\nsegunda\n");
+
+    let mut row = [0; BUFFER_WIDTH];
+    let len = writer.buffer().row_text(BUFFER_HEIGHT - 3, &mut row);
+    assert_eq!(&row[..len], b"primera");
+    let len = writer.buffer().row_text(BUFFER_HEIGHT - 2, &mut row);
+    assert_eq!(&row[..len], b"segunda");
+    assert_eq!(writer.buffer().row_text(BUFFER_HEIGHT - 1, &mut row), 0);
+
+    for _ in 0..BUFFER_HEIGHT {
+        writer.write_byte(b'\n');
+    }
+    for r in 0..BUFFER_HEIGHT {
+        assert_eq!(writer.buffer().row_text(r, &mut row), 0);
+    }
+}
+
+#[test_case]
+fn test_mock_line_wrapping() {
+    let mut writer = Writer::new(MemoryBuffer::new(), Color::Yellow, Color::Black);
+    for _ in 0..BUFFER_WIDTH {
+        writer.write_byte(b'a');
+    }
+    writer.write_byte(b'b');
+
+    let mut row = [0; BUFFER_WIDTH];
+    assert_eq!(writer.buffer().row_text(BUFFER_HEIGHT - 2, &mut row), BUFFER_WIDTH);
+    assert!(row.iter().all(|&b| b == b'a'));
+    let len = writer.buffer().row_text(BUFFER_HEIGHT - 1, &mut row);
+    assert_eq!(&row[..len], b"b");
+}
+
+#[test_case]
+fn test_mock_non_printable_mapping() {
+    let mut writer = Writer::new(MemoryBuffer::new(), Color::Yellow, Color::Black);
+    // Todo lo que no es ASCII imprimible se muestra como ■ (0xfe en CP437).
+    writer.write_string("a\tñ");
+
+    let mut row = [0; BUFFER_WIDTH];
+    let len = writer.buffer().row_text(BUFFER_HEIGHT - 1, &mut row);
+    assert_eq!(&row[..len], &[b'a', 0xfe, 0xfe, 0xfe]);
+}
@@ -0,0 +1,149 @@
+//! Regiones de memoria virtual de un proceso (VMAs).
+//!
+//! `VmaList` anota qué rangos de usuario ocupa cada región de `mmap` y con
+//! qué permisos. No toca las tablas de páginas: `process` mapea y desmapea, y
+//! la lista sabe buscar un hueco y sacar un rango partiendo las regiones que
+//! corta, como hace `munmap`.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Permisos de una región, los de `mmap`.
+pub const PROT_NONE: u32 = 0;
+pub const PROT_READ: u32 = 1;
+pub const PROT_WRITE: u32 = 2;
+pub const PROT_EXEC: u32 = 4;
+
+/// `start..end`, alineados a página.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Vma {
+    pub start: u64,
+    pub end: u64,
+    pub prot: u32,
+}
+
+impl Vma {
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+/// Las regiones, ordenadas por comienzo y sin solaparse.
+#[derive(Debug, Clone, Default)]
+pub struct VmaList {
+    regions: BTreeMap<u64, Vma>,
+}
+
+impl VmaList {
+    pub const fn new() -> Self {
+        VmaList { regions: BTreeMap::new() }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Vma> {
+        self.regions.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// La región que contiene `addr`.
+    pub fn find(&self, addr: u64) -> Option<&Vma> {
+        self.regions.range(..=addr).next_back().map(|(_, vma)| vma).filter(|vma| vma.end > addr)
+    }
+
+    /// Si alguna región toca `start..end`.
+    pub fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.regions.range(..end).next_back().is_some_and(|(_, vma)| vma.end > start)
+    }
+
+    /// Dónde entran `len` bytes dentro de `range`: en `hint` si está libre,
+    /// si no en el primer hueco.
+    pub fn find_free(&self, hint: u64, len: u64, range: Range<u64>) -> Option<u64> {
+        let fits = |start: u64| start.checked_add(len).filter(|&end| start >= range.start && end <= range.end);
+        if hint.is_multiple_of(4096) && fits(hint).is_some_and(|end| !self.overlaps(hint, end)) {
+            return Some(hint);
+        }
+        let mut candidate = range.start;
+        for vma in self.regions.values() {
+            if vma.end <= candidate {
+                continue;
+            }
+            if vma.start >= candidate.checked_add(len)? {
+                break;
+            }
+            candidate = vma.end;
+        }
+        fits(candidate).map(|_| candidate)
+    }
+
+    /// Agrega `vma`, que no tiene que solaparse con ninguna.
+    pub fn insert(&mut self, vma: Vma) {
+        assert!(!vma.is_empty() && !self.overlaps(vma.start, vma.end), "región {:#x?} solapada o vacía", vma);
+        self.regions.insert(vma.start, vma);
+    }
+
+    /// Saca `start..end` de las regiones que lo tocan; las que quedan a
+    /// medias se parten. Devuelve los pedazos que se sacaron.
+    pub fn remove(&mut self, start: u64, end: u64) -> Vec<Vma> {
+        let touched: Vec<Vma> =
+            self.regions.range(..end).rev().map(|(_, vma)| *vma).take_while(|vma| vma.end > start).collect();
+        let mut removed = Vec::new();
+        for vma in touched {
+            self.regions.remove(&vma.start);
+            if vma.start < start {
+                self.regions.insert(vma.start, Vma { end: start, ..vma });
+            }
+            if vma.end > end {
+                self.regions.insert(end, Vma { start: end, ..vma });
+            }
+            removed.push(Vma { start: vma.start.max(start), end: vma.end.min(end), prot: vma.prot });
+        }
+        removed
+    }
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_find_free_uses_hint_or_first_gap() {
+    let mut vmas = VmaList::new();
+    vmas.insert(Vma { start: 0x1000, end: 0x3000, prot: PROT_READ });
+    vmas.insert(Vma { start: 0x4000, end: 0x5000, prot: PROT_READ });
+
+    assert_eq!(vmas.find_free(0x8000, 0x1000, 0x1000..0x10000), Some(0x8000));
+    // El hint está ocupado o desalineado: primer hueco.
+    assert_eq!(vmas.find_free(0x2000, 0x1000, 0x1000..0x10000), Some(0x3000));
+    assert_eq!(vmas.find_free(0x8800, 0x2000, 0x1000..0x10000), Some(0x5000));
+    assert_eq!(vmas.find_free(0, 0x10000, 0x1000..0x10000), None);
+    assert_eq!(vmas.find(0x4800).map(|vma| vma.start), Some(0x4000));
+    assert!(vmas.find(0x3000).is_none());
+}
+
+#[test_case]
+fn test_remove_splits_regions() {
+    let mut vmas = VmaList::new();
+    vmas.insert(Vma { start: 0x1000, end: 0x5000, prot: PROT_READ | PROT_WRITE });
+    vmas.insert(Vma { start: 0x6000, end: 0x8000, prot: PROT_READ });
+
+    let removed = vmas.remove(0x2000, 0x7000);
+    assert_eq!(removed.len(), 2);
+    assert!(removed.contains(&Vma { start: 0x2000, end: 0x5000, prot: PROT_READ | PROT_WRITE }));
+    assert!(removed.contains(&Vma { start: 0x6000, end: 0x7000, prot: PROT_READ }));
+
+    let left: Vec<Vma> = vmas.iter().copied().collect();
+    assert_eq!(left, [
+        Vma { start: 0x1000, end: 0x2000, prot: PROT_READ | PROT_WRITE },
+        Vma { start: 0x7000, end: 0x8000, prot: PROT_READ },
+    ]);
+    assert!(vmas.remove(0x3000, 0x6000).is_empty());
+}
@@ -0,0 +1,99 @@
+//! Colas de espera para hilos.
+//!
+//! Un hilo que espera algo (datos de un dispositivo, que otro hilo termine
+//! una parte) se anota en una `WaitQueue` y se bloquea: no vuelve a correr
+//! hasta que alguien llame a `notify_one` o `notify_all`. Con un lock y una
+//! condición arman una variable de condición:
+//!
+//! ```ignore
+//! static DATA_READY: WaitQueue = WaitQueue::new();
+//!
+//! // El hilo del driver.
+//! DATA_READY.wait_while(|| BUFFER.lock().is_empty());
+//!
+//! // El handler de interrupción.
+//! BUFFER.lock().push(byte);
+//! DATA_READY.notify_one();
+//! ```
+//!
+//! Revisar la condición y bloquearse ocurre con las interrupciones
+//! deshabilitadas, así que un aviso no se puede colar en el medio y perderse.
+//! Avisar no aloca ni bloquea: sirve desde un handler de interrupción.
+
+use alloc::collections::VecDeque;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::thread::ThreadId;
+
+pub struct WaitQueue {
+    /// Hilos bloqueados, en orden de llegada.
+    waiters: Mutex<VecDeque<ThreadId>>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        WaitQueue { waiters: Mutex::new(VecDeque::new()) }
+    }
+
+    /// Bloquea el hilo actual hasta el próximo aviso.
+    pub fn wait(&self) {
+        interrupts::without_interrupts(|| self.block());
+    }
+
+    /// Bloquea el hilo actual mientras `condition` sea verdadera. Se evalúa
+    /// con las interrupciones deshabilitadas, al entrar y después de cada
+    /// aviso.
+    pub fn wait_while(&self, mut condition: impl FnMut() -> bool) {
+        interrupts::without_interrupts(|| {
+            while condition() {
+                self.block();
+            }
+        });
+    }
+
+    fn block(&self) {
+        let Some(current) = crate::thread::current() else {
+            panic!("WaitQueue::wait antes de scheduler::init");
+        };
+        self.waiters.lock().push_back(current);
+        crate::scheduler::block_current();
+    }
+
+    /// Despierta al que espera hace más tiempo. Devuelve false si no había
+    /// nadie.
+    pub fn notify_one(&self) -> bool {
+        let waiter = interrupts::without_interrupts(|| self.waiters.lock().pop_front());
+        match waiter {
+            Some(id) => {
+                crate::scheduler::unblock(id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Despierta a todos. Devuelve cuántos eran.
+    pub fn notify_all(&self) -> usize {
+        let mut woken = 0;
+        while self.notify_one() {
+            woken += 1;
+        }
+        woken
+    }
+
+    /// Hilos esperando.
+    pub fn len(&self) -> usize {
+        interrupts::without_interrupts(|| self.waiters.lock().len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,182 @@
+//! Watchdog de subsistemas.
+//!
+//! Cada subsistema que corre en un loop (el executor, y más adelante la
+//! shell o el poller de red) se registra con un plazo en ticks y llama a
+//! `checkin` en cada vuelta. El handler del timer revisa los plazos y, si
+//! alguno venció, vuelca un diagnóstico por serie una vez por atasco: la
+//! tarea que se estaba polleando y el backtrace del código interrumpido, que
+//! es el que no suelta la CPU.
+//!
+//! Un loop con interrupciones apagadas no deja correr al timer, así que no se
+//! detecta mientras dura; lo que sí se ve es el hueco cuando vuelven, y se
+//! informa si pasa de `IRQS_OFF_LIMIT_NS`. Para un kernel colgado del todo
+//! queda la NMI (`nmi` en el monitor de QEMU), que vuelca su propio reporte.
+
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::VirtAddr;
+
+const MAX_WATCHED: usize = 8;
+/// Hueco entre dos ticks a partir del cual se avisa que las interrupciones
+/// estuvieron apagadas.
+const IRQS_OFF_LIMIT_NS: u64 = 1_000_000_000;
+
+/// `time::now_ns` en el tick anterior.
+static LAST_TICK_NS: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchdogId(usize);
+
+#[derive(Clone, Copy)]
+struct Watched {
+    name: &'static str,
+    deadline: u64,
+    last_checkin: u64,
+    reported: bool,
+}
+
+static WATCHED: Mutex<[Option<Watched>; MAX_WATCHED]> = Mutex::new([None; MAX_WATCHED]);
+
+/// Empieza a vigilar un subsistema que tiene que hacer check-in cada `deadline` ticks.
+pub fn register(name: &'static str, deadline: u64) -> WatchdogId {
+    interrupts::without_interrupts(|| {
+        let mut watched = WATCHED.lock();
+        let index = watched
+            .iter()
+            .position(|w| w.is_none())
+            .expect("demasiados subsistemas vigilados");
+        watched[index] = Some(Watched {
+            name,
+            deadline,
+            last_checkin: crate::time::ticks(),
+            reported: false,
+        });
+        WatchdogId(index)
+    })
+}
+
+pub fn unregister(id: WatchdogId) {
+    interrupts::without_interrupts(|| WATCHED.lock()[id.0] = None);
+}
+
+pub fn checkin(id: WatchdogId) {
+    interrupts::without_interrupts(|| {
+        if let Some(w) = WATCHED.lock()[id.0].as_mut() {
+            w.last_checkin = crate::time::ticks();
+            w.reported = false;
+        }
+    });
+}
+
+/// Indica si el subsistema no hizo check-in dentro de su plazo.
+pub fn stalled(id: WatchdogId) -> bool {
+    interrupts::without_interrupts(|| WATCHED.lock()[id.0].is_some_and(|w| w.reported))
+}
+
+/// Lo llama `time::on_tick` con el RIP del código interrumpido.
+pub(crate) fn on_timer_tick(now: u64, interrupted_rip: VirtAddr) {
+    check_irqs_off_gap(interrupted_rip);
+
+    // Si el lock está tomado se revisa en el próximo tick.
+    let Some(mut watched) = WATCHED.try_lock() else {
+        return;
+    };
+
+    for w in watched.iter_mut().flatten() {
+        if !w.reported && now.wrapping_sub(w.last_checkin) > w.deadline {
+            w.reported = true;
+            report_stall(w, now, interrupted_rip);
+        }
+    }
+}
+
+/// Nombre de un lock global y cómo consultar si está tomado.
+type LockProbe = (&'static str, fn() -> bool);
+
+/// Locks globales cuyo estado se informa en el diagnóstico.
+const LOCKS: [LockProbe; 5] = [
+    ("vga::WRITER", || crate::vga_buffer::WRITER.is_locked()),
+    ("serial::SERIAL1", || crate::serial::SERIAL1.is_locked()),
+    ("console::CONSOLES", crate::console::is_locked),
+    ("memory::MAPPER", crate::memory::is_locked),
+    ("allocator::ALLOCATOR", crate::allocator::is_locked),
+];
+
+/// Con el reloj virtual los saltos de `advance` no son huecos, y con el PIT
+/// como fuente el hueco no se ve.
+fn check_irqs_off_gap(interrupted_rip: VirtAddr) {
+    if crate::time::is_deterministic() || crate::clocksource::current().name() == "pit" {
+        return;
+    }
+    let now_ns = crate::time::now_ns();
+    let last = LAST_TICK_NS.swap(now_ns, Ordering::Relaxed);
+    let gap = now_ns.saturating_sub(last);
+    if last != 0 && gap > IRQS_OFF_LIMIT_NS {
+        let mut out = unsafe { crate::serial::emergency_writer() };
+        let _ = writeln!(
+            out,
+            "WATCHDOG: interrupciones apagadas ~{} ms; volvieron en {:#x}",
+            gap / 1_000_000,
+            interrupted_rip.as_u64()
+        );
+    }
+}
+
+fn report_stall(w: &Watched, now: u64, interrupted_rip: VirtAddr) {
+    // El subsistema trabado puede tener tomado el lock de la consola.
+    let mut out = unsafe { crate::serial::emergency_writer() };
+
+    let _ = writeln!(
+        out,
+        "WATCHDOG: '{}' sin check-in hace {} ticks (límite {})",
+        w.name,
+        now.wrapping_sub(w.last_checkin),
+        w.deadline
+    );
+    let _ = writeln!(out, "  RIP interrumpido: {:#x}", interrupted_rip.as_u64());
+    let _ = writeln!(
+        out,
+        "  Tareas vivas: {}, polls totales: {}",
+        crate::task::executor::live_tasks(),
+        crate::task::executor::total_polls()
+    );
+    match crate::task::current() {
+        Some(task) => {
+            let _ = writeln!(out, "  Tarea en poll: {}", task.as_u64());
+        }
+        None => {
+            let _ = writeln!(out, "  Sin tarea en poll (trabado fuera del executor)");
+        }
+    }
+    // Desde el handler del timer la cadena de RBP sigue en el código
+    // interrumpido: el frame del handler guarda su RBP y, encima, su RIP.
+    let _ = writeln!(out, "  Backtrace:");
+    let _ = crate::backtrace::Backtrace::capture().write(&mut out);
+
+    let _ = write!(out, "  Locks tomados:");
+    let mut any = false;
+    for (name, is_locked) in LOCKS {
+        if is_locked() {
+            let _ = write!(out, " {}", name);
+            any = true;
+        }
+    }
+    let _ = writeln!(out, "{}", if any { "" } else { " ninguno" });
+}
+
+// ----------------- TESTS -----------------
+
+#[test_case]
+fn test_watchdog_detects_missing_checkin() {
+    let id = register("test", 1);
+    assert!(!stalled(id));
+
+    crate::time::sleep_ticks(3);
+    assert!(stalled(id));
+
+    checkin(id);
+    assert!(!stalled(id));
+    unregister(id);
+}
@@ -0,0 +1,146 @@
+//! Trabajo diferido: closures que corren en contexto de tarea después de un
+//! plazo.
+//!
+//! `schedule_after` guarda la closure y programa un timer en `timer_wheel`.
+//! Cuando vence, el callback (desde la interrupción) sólo encola el id; la
+//! closure la corre `worker`, una tarea async que tiene que estar spawneada
+//! en el executor. Así la closure puede alocar, tomar locks o usar `print!`
+//! sin las restricciones de un handler de interrupción.
+//!
+//! La closure queda en el heap detrás de un `kasan::CheckedPtr`: con la
+//! feature `kasan`, correr o cancelar un trabajo ya liberado entra en pánico
+//! en vez de saltar a basura.
+
+use alloc::alloc::{dealloc, Layout};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use conquer_once::spin::OnceCell;
+use core::future::poll_fn;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::task::Poll;
+use crossbeam_queue::ArrayQueue;
+use futures_util::task::AtomicWaker;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::kasan::CheckedPtr;
+use crate::log::Level;
+use crate::timer_wheel::{self, TimerId};
+
+/// Vencidos esperando a `worker`.
+const READY_CAPACITY: usize = 256;
+
+type Work = Box<dyn FnOnce() + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct WorkId(u64);
+
+struct Pending {
+    timer: TimerId,
+    work: CheckedPtr<Work>,
+}
+
+// La closure es `Send` y sólo se toca con `PENDING` tomado o después de
+// sacarla del mapa.
+unsafe impl Send for Pending {}
+
+/// Saca la closure del heap y libera su lugar.
+fn take(work: CheckedPtr<Work>) -> Work {
+    unsafe {
+        let taken = work.read();
+        dealloc(work.as_ptr().cast(), Layout::new::<Work>());
+        taken
+    }
+}
+
+static PENDING: Mutex<BTreeMap<WorkId, Pending>> = Mutex::new(BTreeMap::new());
+static READY: OnceCell<ArrayQueue<WorkId>> = OnceCell::uninit();
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+fn ready_queue() -> &'static ArrayQueue<WorkId> {
+    READY.get_or_init(|| ArrayQueue::new(READY_CAPACITY))
+}
+
+/// Corre `work` en `worker` dentro de al menos `ms` milisegundos. `None` si
+/// la rueda de timers está llena.
+pub fn schedule_after(ms: u64, work: impl FnOnce() + Send + 'static) -> Option<WorkId> {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    let id = WorkId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    ready_queue();
+    let work = CheckedPtr::new(Box::into_raw(Box::new(Box::new(work) as Work)));
+
+    interrupts::without_interrupts(|| {
+        // Un tick más: el actual ya está en curso.
+        let deadline = crate::time::ticks() + crate::time::ms_to_ticks(ms) + 1;
+        match timer_wheel::add_timer(deadline, on_expired, id.0 as usize) {
+            Some(timer) => {
+                PENDING.lock().insert(id, Pending { timer, work });
+                Some(id)
+            }
+            None => {
+                drop(take(work));
+                None
+            }
+        }
+    })
+}
+
+/// Cancela un trabajo que todavía no corrió. `false` si ya corrió o está por
+/// correr.
+pub fn cancel(id: WorkId) -> bool {
+    interrupts::without_interrupts(|| {
+        let mut pending = PENDING.lock();
+        match pending.get(&id) {
+            Some(entry) if timer_wheel::cancel(entry.timer) => {
+                if let Some(entry) = pending.remove(&id) {
+                    drop(take(entry.work));
+                }
+                true
+            }
+            _ => false,
+        }
+    })
+}
+
+/// Trabajos programados que todavía no corrieron.
+pub fn pending() -> usize {
+    interrupts::without_interrupts(|| PENDING.lock().len())
+}
+
+fn on_expired(id: usize) {
+    let Ok(queue) = READY.try_get() else {
+        return;
+    };
+    if queue.push(WorkId(id as u64)).is_err() {
+        // No se puede liberar la closure desde la interrupción: queda en
+        // `PENDING` y no corre nunca.
+        crate::log_rate_limited!(Level::Warn, "cola de trabajo diferido llena; descartando");
+    }
+    WAKER.wake();
+}
+
+/// Corre los trabajos a medida que vencen. Nunca termina.
+pub async fn worker() {
+    let queue = ready_queue();
+    loop {
+        let id = poll_fn(|cx| {
+            if let Some(id) = queue.pop() {
+                return Poll::Ready(id);
+            }
+            WAKER.register(cx.waker());
+            match queue.pop() {
+                Some(id) => {
+                    WAKER.take();
+                    Poll::Ready(id)
+                }
+                None => Poll::Pending,
+            }
+        })
+        .await;
+
+        let entry = interrupts::without_interrupts(|| PENDING.lock().remove(&id));
+        if let Some(entry) = entry {
+            take(entry.work)();
+        }
+    }
+}
@@ -0,0 +1,63 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kur_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use kur_os::apic::{self, timer};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use kur_os::memory;
+    use x86_64::VirtAddr;
+
+    kur_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    unsafe {
+        memory::init(phys_mem_offset, &boot_info.memory_map);
+    }
+    apic::init().expect("falló la inicialización del APIC local");
+
+    test_main();
+    kur_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    kur_os::test_panic_handler(info)
+}
+
+fn wait_pit_ticks(n: u64) {
+    let start = kur_os::interrupts::ticks();
+    while kur_os::interrupts::ticks() < start + n {
+        x86_64::instructions::hlt();
+    }
+}
+
+#[test_case]
+fn test_calibration_is_plausible() {
+    // Bus de 1 MHz a 10 GHz, dividido por 16.
+    let frequency = timer::calibrate();
+    assert!(frequency > 1_000_000 / 16 && frequency < 10_000_000_000 / 16);
+}
+
+#[test_case]
+fn test_periodic_timer_fires() {
+    let before = timer::fired();
+    timer::set_periodic(1000);
+    // 5 ticks del PIT son ~275 ms: a 1 kHz tendría que haber cientos.
+    wait_pit_ticks(5);
+    timer::stop();
+    assert!(timer::fired() - before > 100);
+}
+
+#[test_case]
+fn test_one_shot_fires_once() {
+    let before = timer::fired();
+    timer::one_shot(1000);
+    wait_pit_ticks(2);
+    assert_eq!(timer::fired() - before, 1);
+}
@@ -33,6 +33,21 @@ fn panic(info: &PanicInfo) -> ! {
     kur_os::test_panic_handler(info)
 }
 
+/// Corre `executor` hasta que `done` dé true, dejando pasar un tick entre
+/// vueltas: con `hlt`, o adelantando el reloj virtual con la feature
+/// `deterministic`. Falla con `what` si no termina en 5 s.
+fn run_until(executor: &mut kur_os::task::executor::Executor, what: &str, done: impl Fn() -> bool) {
+    use kur_os::time::{Duration, Instant};
+
+    let start = Instant::now();
+    executor.run_until_idle();
+    while !done() {
+        assert!(start.elapsed() < Duration::from_secs(5), "{}", what);
+        kur_os::time::sleep_ticks(1);
+        executor.run_until_idle();
+    }
+}
+
 #[test_case]
 fn test_simple_executor_runs_task() {
     use core::sync::atomic::{AtomicBool, Ordering};
@@ -104,3 +119,448 @@ fn test_async_value_propagation() {
 
     assert_eq!(RESULT.load(Ordering::SeqCst), 42);
 }
+
+#[test_case]
+fn test_sleep_wakes_after_deadline() {
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use kur_os::task::executor::Executor;
+    use kur_os::task::timer;
+    use kur_os::time::{Duration, Instant};
+
+    static WOKE: AtomicBool = AtomicBool::new(false);
+
+    let start = Instant::now();
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(async {
+        timer::sleep(Duration::from_millis(200)).await;
+        WOKE.store(true, Ordering::SeqCst);
+    }));
+
+    executor.run_until_idle();
+    assert!(!WOKE.load(Ordering::SeqCst));
+    assert_eq!(timer::pending(), 1);
+
+    run_until(&mut executor, "el sleep nunca despertó", || WOKE.load(Ordering::SeqCst));
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    assert_eq!(timer::pending(), 0);
+}
+
+#[test_case]
+fn test_schedule_after_runs_in_worker() {
+    use core::sync::atomic::{AtomicU32, Ordering};
+    use kur_os::task::executor::Executor;
+    use kur_os::time::{Duration, Instant};
+    use kur_os::work;
+
+    static RAN: AtomicU32 = AtomicU32::new(0);
+
+    let start = Instant::now();
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(work::worker()));
+    work::schedule_after(100, || {
+        RAN.fetch_add(1, Ordering::SeqCst);
+    })
+    .unwrap();
+    let cancelled = work::schedule_after(100, || {
+        RAN.fetch_add(10, Ordering::SeqCst);
+    })
+    .unwrap();
+    assert!(work::cancel(cancelled));
+    assert_eq!(work::pending(), 1);
+
+    run_until(&mut executor, "el trabajo nunca corrió", || work::pending() == 0);
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    assert_eq!(RAN.load(Ordering::SeqCst), 1);
+}
+
+#[test_case]
+fn test_housekeeping_runs_periodically() {
+    use core::sync::atomic::{AtomicU32, Ordering};
+    use kur_os::housekeeping;
+    use kur_os::task::executor::Executor;
+
+    static RUNS: AtomicU32 = AtomicU32::new(0);
+    fn job() {
+        RUNS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(housekeeping::run()));
+    let id = housekeeping::register("test", 1, job);
+
+    executor.run_until_idle();
+    assert_eq!(RUNS.load(Ordering::SeqCst), 0);
+    run_until(&mut executor, "la tarea periódica no corrió", || housekeeping::runs(id) >= 3);
+    housekeeping::unregister(id);
+    assert!(RUNS.load(Ordering::SeqCst) >= 3);
+}
+
+#[test_case]
+fn test_yield_now_interleaves_tasks() {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    // Cada paso agrega un dígito: 1 para la primera tarea, 2 para la segunda.
+    static ORDER: AtomicU64 = AtomicU64::new(0);
+
+    fn step(digit: u64) {
+        let order = ORDER.load(Ordering::SeqCst);
+        ORDER.store(order * 10 + digit, Ordering::SeqCst);
+    }
+
+    async fn steps(digit: u64) {
+        for _ in 0..3 {
+            step(digit);
+            kur_os::task::yield_now().await;
+        }
+    }
+
+    let mut executor = SimpleExecutor::new();
+    executor.spawn(Task::new(steps(1)));
+    executor.spawn(Task::new(steps(2)));
+    executor.run();
+
+    assert_eq!(ORDER.load(Ordering::SeqCst), 121212);
+}
+
+#[test_case]
+fn test_join_handle_returns_output() {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    static JOINED: AtomicU64 = AtomicU64::new(0);
+
+    async fn answer() -> u64 {
+        kur_os::task::yield_now().await;
+        42
+    }
+
+    let mut executor = SimpleExecutor::new();
+    let handle = executor.spawn_with_handle(answer());
+    executor.spawn(Task::new(async move {
+        JOINED.store(handle.await.unwrap(), Ordering::SeqCst);
+    }));
+    executor.run();
+
+    assert_eq!(JOINED.load(Ordering::SeqCst), 42);
+}
+
+#[test_case]
+fn test_join_handle_reports_dropped_task() {
+    use kur_os::task::JoinError;
+
+    let (task, mut handle) = Task::with_handle(async { 7u8 });
+    assert!(!handle.is_finished());
+    assert_eq!(handle.try_join(), None);
+    drop(task);
+    assert!(handle.is_finished());
+    assert_eq!(handle.try_join(), Some(Err(JoinError::Dropped)));
+}
+
+#[test_case]
+fn test_async_mutex_held_across_await() {
+    use alloc::sync::Arc;
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use kur_os::task::sync::Mutex;
+
+    static TOTAL: AtomicU64 = AtomicU64::new(0);
+
+    // Cada tarea cede el executor con el lock tomado: con un spin::Mutex la
+    // otra giraría para siempre.
+    async fn add(counter: Arc<Mutex<u64>>) {
+        for _ in 0..3 {
+            let mut value = counter.lock().await;
+            let before = *value;
+            kur_os::task::yield_now().await;
+            *value = before + 1;
+        }
+        TOTAL.store(*counter.lock().await, Ordering::SeqCst);
+    }
+
+    let counter = Arc::new(Mutex::new(0));
+    let mut executor = SimpleExecutor::new();
+    executor.spawn(Task::new(add(counter.clone())));
+    executor.spawn(Task::new(add(counter.clone())));
+    executor.run();
+
+    assert_eq!(TOTAL.load(Ordering::SeqCst), 6);
+    assert!(counter.try_lock().is_some());
+}
+
+#[test_case]
+fn test_global_spawn_runs_on_executor() {
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use kur_os::task::executor::Executor;
+
+    static DEFERRED: AtomicU64 = AtomicU64::new(0);
+
+    fn from_interrupt(arg: usize) {
+        kur_os::task::spawn(async move {
+            DEFERRED.store(arg as u64, Ordering::SeqCst);
+        });
+    }
+
+    let mut executor = Executor::new();
+    // Una tarea que spawnea otra y espera su salida.
+    let mut outer = kur_os::task::spawn(async {
+        kur_os::task::spawn(async { 20 }).await.unwrap() + 1
+    });
+    assert!(kur_os::task::spawn_from_interrupt(from_interrupt, 7));
+    executor.run_until_idle();
+
+    assert_eq!(outer.try_join(), Some(Ok(21)));
+    assert_eq!(DEFERRED.load(Ordering::SeqCst), 7);
+}
+
+#[test_case]
+fn test_executor_stats_count_polls() {
+    use kur_os::task::executor::Executor;
+
+    async fn yields_twice() {
+        kur_os::task::yield_now().await;
+        kur_os::task::yield_now().await;
+    }
+
+    let mut executor = Executor::new();
+    let pending = Task::new(core::future::pending::<()>());
+    let pending_id = pending.stats().id;
+    executor.spawn(Task::new(yields_twice()));
+    executor.spawn(pending);
+    executor.run_until_idle();
+
+    // La que terminó ya no aparece; la que sigue se polleó una vez.
+    let stats = executor.stats();
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats[0].id, pending_id);
+    assert_eq!(stats[0].polls, 1);
+    assert!(stats[0].longest_ns <= stats[0].busy_ns);
+    executor.print_stats();
+}
+
+#[test_case]
+fn test_high_priority_tasks_poll_first() {
+    use alloc::vec::Vec;
+    use kur_os::task::{executor::Executor, TaskPriority};
+    use spin::Mutex;
+
+    static ORDER: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+    async fn record(name: &'static str) {
+        ORDER.lock().push(name);
+        kur_os::task::yield_now().await;
+        ORDER.lock().push(name);
+    }
+
+    let mut executor = Executor::new();
+    executor.spawn(Task::new_with_priority(record("low"), TaskPriority::Low));
+    executor.spawn(Task::new(record("normal")));
+    executor.spawn(Task::new_with_priority(record("high"), TaskPriority::High));
+    executor.run_until_idle();
+
+    // Al ceder, cada una vuelve a su cola: la alta termina antes de que
+    // empiecen las demás.
+    assert_eq!(*ORDER.lock(), ["high", "high", "normal", "normal", "low", "low"]);
+}
+
+#[test_case]
+fn test_full_queue_applies_overflow_policy() {
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use kur_os::task::executor::{Executor, Overflow};
+
+    static RAN: AtomicU64 = AtomicU64::new(0);
+
+    async fn count() {
+        RAN.fetch_add(1, Ordering::SeqCst);
+    }
+
+    // Con lugar para dos, la tercera tarea espera a que se vacíe la cola.
+    let mut executor = Executor::with_capacity(2, Overflow::Backpressure);
+    for _ in 0..3 {
+        executor.spawn(Task::new(count()));
+    }
+    let stats = executor.queue_stats();
+    assert_eq!((stats.capacity, stats.queued, stats.high_water), (2, 2, 2));
+    assert_eq!((stats.overflows, stats.dropped), (1, 0));
+    executor.run_until_idle();
+    assert_eq!(RAN.load(Ordering::SeqCst), 3);
+
+    // Con `Drop` el aviso se pierde: la tarea queda sin correr.
+    RAN.store(0, Ordering::SeqCst);
+    let mut executor = Executor::with_capacity(2, Overflow::Drop);
+    for _ in 0..3 {
+        executor.spawn(Task::new(count()));
+    }
+    executor.run_until_idle();
+    assert_eq!(RAN.load(Ordering::SeqCst), 2);
+    assert_eq!(executor.queue_stats().dropped, 1);
+}
+
+#[test_case]
+fn test_join_and_select_macros() {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    static RESULT: AtomicU64 = AtomicU64::new(0);
+
+    async fn after_yields(n: u64) -> u64 {
+        for _ in 0..n {
+            kur_os::task::yield_now().await;
+        }
+        n
+    }
+
+    async fn run() {
+        let (a, b, c) = kur_os::task::join!(after_yields(3), after_yields(1), async { 10 });
+        assert_eq!((a, b, c), (3, 1, 10));
+
+        // Gana el que termina primero aunque esté escrito después.
+        let winner = kur_os::task::select! {
+            slow = after_yields(5) => slow * 100,
+            fast = after_yields(2) => fast,
+            () = core::future::pending::<()>() => 0,
+        };
+
+        // La rama que termina primero no coincide: se sigue con la otra.
+        let skipped = kur_os::task::select! {
+            Some(never) = async { None::<u64> } => never * 1000,
+            late = after_yields(3) => late,
+        };
+        assert_eq!(skipped, 3);
+
+        // El patrón se compara por valor: admite bindings `mut`.
+        let bumped = kur_os::task::select! {
+            Some(mut byte) = async { Some(2u64) } => {
+                byte += 1;
+                byte
+            },
+            () = core::future::pending::<()>() => 0,
+        };
+        assert_eq!(bumped, 3);
+        RESULT.store(a + b + c + winner, Ordering::SeqCst);
+    }
+
+    let mut executor = SimpleExecutor::new();
+    executor.spawn(Task::new(run()));
+    executor.run();
+    assert_eq!(RESULT.load(Ordering::SeqCst), 16);
+}
+
+#[test_case]
+fn test_timeout_gives_up_on_hung_future() {
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use kur_os::task::executor::Executor;
+    use kur_os::task::{timeout, TimedOut};
+    use kur_os::time::{Duration, Instant};
+
+    // 1: terminó a tiempo, 2: venció el plazo.
+    static FAST: AtomicU64 = AtomicU64::new(0);
+    static HUNG: AtomicU64 = AtomicU64::new(0);
+
+    let start = Instant::now();
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(async {
+        let result = timeout(Duration::from_millis(500), async { 7 }).await;
+        FAST.store(if result == Ok(7) { 1 } else { 2 }, Ordering::SeqCst);
+    }));
+    executor.spawn(Task::new(async {
+        let result = timeout(Duration::from_millis(50), core::future::pending::<()>()).await;
+        HUNG.store(if result == Err(TimedOut) { 2 } else { 1 }, Ordering::SeqCst);
+    }));
+
+    run_until(&mut executor, "el timeout nunca venció", || HUNG.load(Ordering::SeqCst) != 0);
+    assert_eq!(FAST.load(Ordering::SeqCst), 1);
+    assert_eq!(HUNG.load(Ordering::SeqCst), 2);
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    assert_eq!(kur_os::task::timer::pending(), 0);
+}
+
+#[test_case]
+fn test_abort_drops_task_at_next_poll() {
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use kur_os::task::executor::Executor;
+    use kur_os::task::JoinError;
+
+    static POLLS: AtomicU64 = AtomicU64::new(0);
+    static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+    struct OnDrop;
+
+    impl Drop for OnDrop {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let mut executor = Executor::new();
+    let mut handle = executor.spawn_with_handle(async {
+        let _guard = OnDrop;
+        loop {
+            POLLS.fetch_add(1, Ordering::SeqCst);
+            // Espera algo que nunca llega: sólo la cancelación la despierta.
+            core::future::pending::<()>().await;
+        }
+    });
+    executor.run_until_idle();
+    assert_eq!(POLLS.load(Ordering::SeqCst), 1);
+
+    let token = handle.cancel_token();
+    assert!(!token.is_cancelled());
+    handle.abort();
+    assert!(token.is_cancelled());
+    executor.run_until_idle();
+
+    assert_eq!(POLLS.load(Ordering::SeqCst), 1);
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 1);
+    assert_eq!(handle.try_join(), Some(Err(JoinError::Cancelled)));
+    assert!(executor.stats().is_empty());
+}
+
+#[test_case]
+fn test_task_without_handle_can_be_cancelled() {
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use kur_os::task::executor::Executor;
+
+    static POLLS: AtomicU64 = AtomicU64::new(0);
+
+    let mut executor = Executor::new();
+    let task = Task::new(async {
+        loop {
+            POLLS.fetch_add(1, Ordering::SeqCst);
+            core::future::pending::<()>().await;
+        }
+    });
+    let token = task.cancel_token();
+    executor.spawn(task);
+    executor.run_until_idle();
+    assert_eq!(POLLS.load(Ordering::SeqCst), 1);
+
+    token.cancel();
+    executor.run_until_idle();
+    assert_eq!(POLLS.load(Ordering::SeqCst), 1);
+    assert!(executor.stats().is_empty());
+}
+
+#[test_case]
+fn test_idle_core_steals_ready_tasks() {
+    use alloc::vec::Vec;
+    use kur_os::task::executor::{Executor, Overflow};
+    use kur_os::task::TaskPriority;
+    use spin::Mutex;
+
+    static ORDER: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+    async fn record(n: u32) {
+        ORDER.lock().push(n);
+        kur_os::task::yield_now().await;
+        ORDER.lock().push(n);
+    }
+
+    // Todo está en la cola del núcleo 1: el 0 lo roba, respetando la
+    // prioridad entre colas de núcleos distintos.
+    let mut executor = Executor::with_cores(2, 8, Overflow::Panic);
+    executor.spawn_on(1, Task::new(record(1)));
+    executor.spawn_on(1, Task::new_with_priority(record(2), TaskPriority::High));
+    executor.spawn(Task::new_with_priority(record(3), TaskPriority::Low));
+    executor.run_until_idle();
+
+    assert_eq!(*ORDER.lock(), [2, 2, 1, 1, 3, 3]);
+    // Robadas una vez cada una; al despertar ya estaban en el núcleo 0.
+    assert_eq!(executor.queue_stats().stolen, 2);
+}
@@ -0,0 +1,44 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kur_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use kur_os::bench;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use kur_os::allocator;
+    use kur_os::memory;
+    use x86_64::VirtAddr;
+
+    kur_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    unsafe {
+        memory::init(phys_mem_offset, &boot_info.memory_map);
+    }
+    allocator::init_heap().expect("falló la inicialización del heap");
+
+    test_main();
+    kur_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    kur_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn test_memory_latency_is_measured() {
+    let sample = bench::memory_latency(16 << 10);
+    assert!(sample.min > 0 && sample.min <= sample.median && sample.median <= sample.max);
+}
+
+#[test_case]
+fn test_interrupt_round_trip_returns() {
+    let sample = bench::interrupt_round_trip();
+    assert!(sample.min > 0);
+}
@@ -0,0 +1,88 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kur_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use kur_os::event::{self, Event, MAX_SUBSCRIBERS};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use kur_os::allocator;
+    use kur_os::memory;
+    use x86_64::VirtAddr;
+
+    kur_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    unsafe {
+        memory::init(phys_mem_offset, &boot_info.memory_map);
+    }
+    allocator::init_heap().expect("falló la inicialización del heap");
+
+    test_main();
+    kur_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    kur_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn test_every_subscriber_receives_events() {
+    let a = event::subscribe(4).unwrap();
+    let b = event::subscribe(4).unwrap();
+    event::publish(Event::DeviceAdded("disco"));
+    event::publish(Event::LinkUp("eth0"));
+
+    for subscription in [&a, &b] {
+        assert_eq!(subscription.try_next(), Some(Event::DeviceAdded("disco")));
+        assert_eq!(subscription.try_next(), Some(Event::LinkUp("eth0")));
+        assert_eq!(subscription.try_next(), None);
+    }
+}
+
+#[test_case]
+fn test_full_queue_drops_events() {
+    let subscription = event::subscribe(1).unwrap();
+    event::publish(Event::LinkUp("eth0"));
+    event::publish(Event::LinkUp("eth1"));
+    assert_eq!(subscription.dropped(), 1);
+    assert_eq!(subscription.try_next(), Some(Event::LinkUp("eth0")));
+}
+
+#[test_case]
+fn test_dropping_subscription_frees_slot() {
+    let all: alloc::vec::Vec<_> = (0..MAX_SUBSCRIBERS)
+        .map(|_| event::subscribe(1).unwrap())
+        .collect();
+    assert!(event::subscribe(1).is_none());
+    drop(all);
+    assert!(event::subscribe(1).is_some());
+}
+
+#[test_case]
+fn test_stream_wakes_awaiting_task() {
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use futures_util::StreamExt;
+    use kur_os::task::{simple_executor::SimpleExecutor, Task};
+
+    static RECEIVED: AtomicBool = AtomicBool::new(false);
+
+    let mut subscription = event::subscribe(4).unwrap();
+    event::publish(Event::DeviceAdded("rtc"));
+
+    let mut executor = SimpleExecutor::new();
+    executor.spawn(Task::new(async move {
+        let event = subscription.next().await;
+        RECEIVED.store(event == Some(Event::DeviceAdded("rtc")), Ordering::SeqCst);
+    }));
+    executor.run();
+
+    assert!(RECEIVED.load(Ordering::SeqCst));
+}
@@ -1,6 +1,7 @@
 #![no_std]
 #![no_main]
 #![feature(custom_test_frameworks)]
+#![feature(allocator_api)]
 #![test_runner(kur_os::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 
@@ -89,3 +90,81 @@ fn fragmentation() {
 
     assert_eq!(larger_blocks.len(), 500);
 }
+
+#[test_case]
+fn test_tagged_quota_and_reclaim() {
+    use alloc::vec::Vec;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use kur_os::quota::{self, Tag, Tagged};
+
+    static RECLAIMED: AtomicUsize = AtomicUsize::new(0);
+    fn reclaim(needed: usize) {
+        RECLAIMED.store(needed, Ordering::SeqCst);
+    }
+
+    let tag = Tag::BlockCache;
+    quota::set_quota(tag, Some(1024));
+    quota::set_reclaim(tag, Some(reclaim));
+
+    let mut buffer: Vec<u8, Tagged> = Vec::with_capacity_in(1000, Tagged(tag));
+    buffer.push(1);
+    assert_eq!(quota::usage(tag).used, 1000);
+
+    // No entra: se llama a la reclamación, que no libera nada, y falla.
+    let mut other: Vec<u8, Tagged> = Vec::new_in(Tagged(tag));
+    assert!(other.try_reserve_exact(100).is_err());
+    assert_eq!(RECLAIMED.load(Ordering::SeqCst), 76);
+    assert_eq!(quota::usage(tag).failures, 1);
+
+    drop(buffer);
+    assert!(other.try_reserve_exact(100).is_ok());
+    assert_eq!(quota::usage(tag).used, 100);
+    assert_eq!(quota::usage(tag).peak, 1000);
+
+    drop(other);
+    quota::set_quota(tag, None);
+    quota::set_reclaim(tag, None);
+    assert_eq!(quota::usage(tag).used, 0);
+}
+
+#[test_case]
+fn test_checked_ptr_tracks_heap() {
+    use kur_os::kasan::CheckedPtr;
+
+    let mut value = Box::new(7u64);
+    let ptr = CheckedPtr::new(&mut *value as *mut u64);
+    unsafe { ptr.write(8) };
+    assert_eq!(unsafe { ptr.read() }, 8);
+
+    #[cfg(feature = "kasan")]
+    {
+        use kur_os::kasan::shadow;
+        let addr = ptr.as_ptr() as usize;
+        assert!(shadow::is_accessible(addr, 8));
+        drop(value);
+        assert!(!shadow::is_accessible(addr, 8));
+        // Fuera del heap no se verifica.
+        let local = 0u64;
+        assert!(shadow::is_accessible(&local as *const u64 as usize, 8));
+    }
+}
+
+#[test_case]
+fn test_state_verify_catches_leak() {
+    use kur_os::state::{self, StateError, DUMP_LEN};
+
+    let mut snapshot = [0u8; DUMP_LEN];
+    state::dump(&mut snapshot).unwrap();
+
+    let temporary = Box::new([0u8; 64]);
+    drop(temporary);
+    assert_eq!(state::verify(&snapshot), Ok(()));
+
+    let leaked = Box::new(1u32);
+    match state::verify(&snapshot) {
+        Err(StateError::Mismatch { field: "heap.live", expected, found }) => assert_eq!(found, expected + 1),
+        other => panic!("se esperaba heap.live distinto: {:?}", other),
+    }
+    drop(leaked);
+    assert_eq!(state::verify(&snapshot), Ok(()));
+}
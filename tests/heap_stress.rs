@@ -9,6 +9,7 @@ extern crate alloc;
 use bootloader::{entry_point, BootInfo};
 use core::panic::PanicInfo;
 use kur_os::task::{Task, simple_executor::SimpleExecutor};
+use kur_os::metrics::{self, Counter};
 use kur_os::rng::SimpleRng;
 use alloc::vec::Vec;
 
@@ -36,52 +37,50 @@ fn panic(info: &PanicInfo) -> ! {
 }
 
 struct StressStats {
-    allocs: u64,
-    deallocs: u64,
-    bytes_allocated: u64,
-    bytes_freed: u64,
-    peak_objects: usize,
+    allocs: &'static Counter,
+    deallocs: &'static Counter,
+    bytes_allocated: &'static Counter,
+    bytes_freed: &'static Counter,
+    peak_objects: &'static Counter,
 }
 
 impl StressStats {
     fn new() -> Self {
         Self {
-            allocs: 0,
-            deallocs: 0,
-            bytes_allocated: 0,
-            bytes_freed: 0,
-            peak_objects: 0,
+            allocs: metrics::counter("heap_stress.allocs"),
+            deallocs: metrics::counter("heap_stress.deallocs"),
+            bytes_allocated: metrics::counter("heap_stress.bytes_allocated"),
+            bytes_freed: metrics::counter("heap_stress.bytes_freed"),
+            peak_objects: metrics::counter("heap_stress.peak_objects"),
         }
     }
 
-    fn record_alloc(&mut self, size: usize, live_count: usize) {
-        self.allocs += 1;
-        self.bytes_allocated += size as u64;
-        if live_count > self.peak_objects {
-            self.peak_objects = live_count;
-        }
+    fn record_alloc(&self, size: usize, live_count: usize) {
+        self.allocs.inc();
+        self.bytes_allocated.add(size as u64);
+        self.peak_objects.record_max(live_count as u64);
+    }
+
+    fn record_dealloc(&self, size: usize) {
+        self.deallocs.inc();
+        self.bytes_freed.add(size as u64);
     }
 
-    fn record_dealloc(&mut self, size: usize) {
-        self.deallocs += 1;
-        self.bytes_freed += size as u64;
+    fn bytes_in_use(&self) -> u64 {
+        self.bytes_allocated.get() - self.bytes_freed.get()
     }
 
     fn print_summary(&self) {
         kur_os::serial_println!("=== Heap Stress Test — Resultados ===");
-        kur_os::serial_println!("  Asignaciones:      {}", self.allocs);
-        kur_os::serial_println!("  Liberaciones:      {}", self.deallocs);
-        kur_os::serial_println!("  Bytes asignados:   {}", self.bytes_allocated);
-        kur_os::serial_println!("  Bytes liberados:   {}", self.bytes_freed);
-        kur_os::serial_println!("  Bytes en uso:      {}", self.bytes_allocated - self.bytes_freed);
-        kur_os::serial_println!("  Pico de objetos:   {}", self.peak_objects);
+        metrics::for_each(|name, value| kur_os::serial_println!("  {:<32} {}", name, value));
+        kur_os::serial_println!("  Bytes en uso:      {}", self.bytes_in_use());
     }
 }
 
 async fn heap_stress_test() {
     let mut rng = SimpleRng::new(42);
     let mut storage: Vec<Vec<u8>> = Vec::new();
-    let mut stats = StressStats::new();
+    let stats = StressStats::new();
 
     kur_os::serial_println!("Iniciando Stress Test del Heap...");
 
@@ -106,8 +105,9 @@ async fn heap_stress_test() {
                 "  Iteración {}: {} objetos en vuelo, {} bytes asignados",
                 i,
                 storage.len(),
-                stats.bytes_allocated - stats.bytes_freed
+                stats.bytes_in_use()
             );
+            kur_os::task::yield_now().await;
         }
     }
 
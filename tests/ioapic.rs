@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kur_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use kur_os::ioapic::{self, Polarity, Redirection, TriggerMode};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use kur_os::memory;
+    use x86_64::VirtAddr;
+
+    kur_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    unsafe {
+        memory::init(phys_mem_offset, &boot_info.memory_map);
+    }
+    let found = ioapic::init().expect("falló la inicialización del IO-APIC");
+    assert!(found > 0, "QEMU siempre emula un IO-APIC");
+
+    test_main();
+    kur_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    kur_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn test_entries_start_masked() {
+    for gsi in 0..16 {
+        assert!(ioapic::redirection(gsi).unwrap().masked);
+    }
+}
+
+#[test_case]
+fn test_redirection_roundtrip() {
+    // GSI 23 no está conectado a nada en QEMU.
+    let entry = Redirection {
+        vector: 0x50,
+        destination: 0,
+        polarity: Polarity::ActiveLow,
+        trigger: TriggerMode::Level,
+        masked: true,
+    };
+    ioapic::set_redirection(23, entry).unwrap();
+    assert_eq!(ioapic::redirection(23).unwrap(), entry);
+}
+
+#[test_case]
+fn test_isa_timer_override() {
+    // QEMU redirige la IRQ 0 del PIT a la GSI 2.
+    let (gsi, _, _) = ioapic::isa_irq(0);
+    assert_eq!(gsi, 2);
+}
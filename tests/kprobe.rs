@@ -0,0 +1,82 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kur_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU64, Ordering};
+use kur_os::kprobe;
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    kur_os::init();
+    test_main();
+    kur_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    kur_os::test_panic_handler(info)
+}
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static LAST_RIP: AtomicU64 = AtomicU64::new(0);
+
+fn count_hit(registers: &kprobe::Registers) {
+    HITS.fetch_add(1, Ordering::SeqCst);
+    LAST_RIP.store(registers.frame.instruction_pointer.as_u64(), Ordering::SeqCst);
+}
+
+static LAST_RDI: AtomicU64 = AtomicU64::new(0);
+
+fn record_argument(registers: &kprobe::Registers) {
+    LAST_RDI.store(registers.rdi, Ordering::SeqCst);
+}
+
+#[inline(never)]
+fn probed(x: u64) -> u64 {
+    core::hint::black_box(x) * 2
+}
+
+#[test_case]
+fn test_probe_fires_and_resumes() {
+    let addr = VirtAddr::new(probed as fn(u64) -> u64 as usize as u64);
+    unsafe { kprobe::register(addr, count_hit).expect("no se pudo registrar el probe") };
+
+    assert_eq!(probed(3), 6);
+    assert_eq!(probed(5), 10);
+    assert_eq!(HITS.load(Ordering::SeqCst), 2);
+    assert_eq!(kprobe::hits(addr), Some(2));
+    assert_eq!(LAST_RIP.load(Ordering::SeqCst), addr.as_u64() + 1);
+
+    kprobe::unregister(addr).expect("no se pudo sacar el probe");
+    assert_eq!(probed(7), 14);
+    assert_eq!(HITS.load(Ordering::SeqCst), 2);
+}
+
+#[test_case]
+fn test_probe_registration_errors() {
+    let addr = VirtAddr::new(probed as fn(u64) -> u64 as usize as u64);
+    unsafe {
+        kprobe::register(addr, count_hit).unwrap();
+        assert_eq!(kprobe::register(addr, count_hit), Err(kprobe::KprobeError::AlreadyProbed));
+    }
+    kprobe::unregister(addr).unwrap();
+    assert_eq!(kprobe::unregister(addr), Err(kprobe::KprobeError::NotFound));
+}
+
+#[test_case]
+fn test_probe_sees_registers() {
+    let addr = VirtAddr::new(probed as fn(u64) -> u64 as usize as u64);
+    unsafe { kprobe::register(addr, record_argument).unwrap() };
+
+    // El primer argumento llega en `rdi`, y el probe está en la primera instrucción.
+    assert_eq!(probed(0x1234), 0x2468);
+    assert_eq!(LAST_RDI.load(Ordering::SeqCst), 0x1234);
+
+    kprobe::unregister(addr).unwrap();
+}
@@ -0,0 +1,434 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kur_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use kur_os::process::{self, ProcessState};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use kur_os::allocator;
+    use kur_os::memory;
+    use x86_64::VirtAddr;
+
+    kur_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    unsafe {
+        memory::init(phys_mem_offset, &boot_info.memory_map);
+    }
+    allocator::init_heap().expect("falló la inicialización del heap");
+    kur_os::scheduler::init();
+
+    test_main();
+    kur_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    kur_os::test_panic_handler(info)
+}
+
+/// `exit(code)`.
+const fn exit_with(code: u8) -> [u8; 14] {
+    [
+        0xBF, code, 0, 0, 0, // mov edi, code
+        0xB8, 60, 0, 0, 0, // mov eax, SYS_EXIT
+        0x0F, 0x05, // syscall
+        0xEB, 0xFE, // jmp $
+    ]
+}
+
+/// `exit(getpid())`.
+const EXIT_WITH_PID: [u8; 18] = [
+    0xB8, 39, 0, 0, 0, // mov eax, SYS_GETPID
+    0x0F, 0x05, // syscall
+    0x89, 0xC7, // mov edi, eax
+    0xB8, 60, 0, 0, 0, // mov eax, SYS_EXIT
+    0x0F, 0x05, // syscall
+    0xEB, 0xFE, // jmp $
+];
+
+/// Guarda 1 en el stack y hace `fork`. El hijo pisa el valor con 2 y sale
+/// con 5; el padre espera al hijo y sale con `10 * 5 + ` lo que ve en su
+/// stack.
+const FORK_AND_WAIT: [u8; 73] = [
+    0x48, 0xC7, 0x44, 0x24, 0xF0, 1, 0, 0, 0, // mov qword [rsp - 16], 1
+    0xB8, 57, 0, 0, 0, // mov eax, SYS_FORK
+    0x0F, 0x05, // syscall
+    0x48, 0x85, 0xC0, // test rax, rax
+    0x75, 21, // jnz padre
+    // hijo:
+    0x48, 0xC7, 0x44, 0x24, 0xF0, 2, 0, 0, 0, // mov qword [rsp - 16], 2
+    0xBF, 5, 0, 0, 0, // mov edi, 5
+    0xB8, 60, 0, 0, 0, // mov eax, SYS_EXIT
+    0x0F, 0x05, // syscall
+    // padre:
+    0x48, 0x89, 0xC7, // mov rdi, rax
+    0xB8, 61, 0, 0, 0, // mov eax, SYS_WAIT
+    0x0F, 0x05, // syscall
+    0x48, 0x6B, 0xC0, 10, // imul rax, rax, 10
+    0x48, 0x03, 0x44, 0x24, 0xF0, // add rax, [rsp - 16]
+    0x48, 0x89, 0xC7, // mov rdi, rax
+    0xB8, 60, 0, 0, 0, // mov eax, SYS_EXIT
+    0x0F, 0x05, // syscall
+    0xEB, 0xFE, // jmp $
+];
+
+/// Un `malloc` mínimo sobre `brk`: pide dos páginas, guarda 40 al final de
+/// la segunda y sale con eso más las páginas que creció el heap.
+const BRK_MALLOC: [u8; 63] = [
+    0x31, 0xFF, // xor edi, edi
+    0xB8, 12, 0, 0, 0, // mov eax, SYS_BRK
+    0x0F, 0x05, // syscall
+    0x48, 0x89, 0xC3, // mov rbx, rax
+    0x48, 0x8D, 0xB8, 0x00, 0x20, 0, 0, // lea rdi, [rax + 0x2000]
+    0xB8, 12, 0, 0, 0, // mov eax, SYS_BRK
+    0x0F, 0x05, // syscall
+    0x48, 0x29, 0xD8, // sub rax, rbx
+    0x48, 0xC1, 0xE8, 12, // shr rax, 12
+    0x48, 0xC7, 0x83, 0xF8, 0x1F, 0, 0, 40, 0, 0, 0, // mov qword [rbx + 0x1FF8], 40
+    0x48, 0x03, 0x83, 0xF8, 0x1F, 0, 0, // add rax, [rbx + 0x1FF8]
+    0x48, 0x89, 0xC7, // mov rdi, rax
+    0xB8, 60, 0, 0, 0, // mov eax, SYS_EXIT
+    0x0F, 0x05, // syscall
+    0xEB, 0xFE, // jmp $
+];
+
+/// `mmap` de dos páginas, guarda 42 al final, `munmap` y sale con lo que
+/// leyó más lo que devolvió `munmap`.
+const MMAP_AND_UNMAP: [u8; 86] = [
+    0x31, 0xFF, // xor edi, edi
+    0xBE, 0x00, 0x20, 0, 0, // mov esi, 0x2000
+    0xBA, 3, 0, 0, 0, // mov edx, PROT_READ | PROT_WRITE
+    0x41, 0xBA, 0x22, 0, 0, 0, // mov r10d, MAP_PRIVATE | MAP_ANONYMOUS
+    0x49, 0xC7, 0xC0, 0xFF, 0xFF, 0xFF, 0xFF, // mov r8, -1
+    0x45, 0x31, 0xC9, // xor r9d, r9d
+    0xB8, 9, 0, 0, 0, // mov eax, SYS_MMAP
+    0x0F, 0x05, // syscall
+    0x48, 0x89, 0xC3, // mov rbx, rax
+    0x48, 0xC7, 0x83, 0xF8, 0x1F, 0, 0, 42, 0, 0, 0, // mov qword [rbx + 0x1FF8], 42
+    0x4C, 0x8B, 0xA3, 0xF8, 0x1F, 0, 0, // mov r12, [rbx + 0x1FF8]
+    0x48, 0x89, 0xDF, // mov rdi, rbx
+    0xBE, 0x00, 0x20, 0, 0, // mov esi, 0x2000
+    0xB8, 11, 0, 0, 0, // mov eax, SYS_MUNMAP
+    0x0F, 0x05, // syscall
+    0x4C, 0x01, 0xE0, // add rax, r12
+    0x48, 0x89, 0xC7, // mov rdi, rax
+    0xB8, 60, 0, 0, 0, // mov eax, SYS_EXIT
+    0x0F, 0x05, // syscall
+    0xEB, 0xFE, // jmp $
+];
+
+#[test_case]
+fn test_address_spaces_are_isolated() {
+    use kur_os::address_space::AddressSpace;
+    use x86_64::structures::paging::{Page, PageTableFlags};
+    use x86_64::VirtAddr;
+
+    let addr = VirtAddr::new(process::CODE_START);
+    let page = Page::containing_address(addr);
+    let mut first = AddressSpace::new().unwrap();
+    let mut second = AddressSpace::new().unwrap();
+    first.map(page, PageTableFlags::WRITABLE).unwrap();
+    second.map(page, PageTableFlags::empty()).unwrap();
+    first.write(addr, b"uno").unwrap();
+    second.write(addr, b"dos").unwrap();
+
+    let (first_phys, first_flags) = first.translate(addr).unwrap();
+    let (second_phys, second_flags) = second.translate(addr).unwrap();
+    assert_ne!(first_phys, second_phys);
+    assert!(first_flags.contains(PageTableFlags::USER_ACCESSIBLE | PageTableFlags::WRITABLE));
+    assert!(!second_flags.contains(PageTableFlags::WRITABLE));
+    // La tabla del kernel no se enteró.
+    assert_eq!(kur_os::memory::is_mapped(addr), Ok(false));
+    // Y lo del kernel se ve desde los dos.
+    let kernel = VirtAddr::from_ptr(main as *const ());
+    assert!(first.translate(kernel).is_some());
+
+    assert_eq!(first.map(page, PageTableFlags::empty()), Err(kur_os::error::KernelError::AlreadyMapped));
+    second.add_flags(page, PageTableFlags::WRITABLE).unwrap();
+    let (phys, flags) = second.translate(addr).unwrap();
+    assert_eq!(phys, second_phys);
+    assert!(flags.contains(PageTableFlags::USER_ACCESSIBLE | PageTableFlags::WRITABLE));
+    assert_eq!(
+        first.map(Page::containing_address(VirtAddr::new(0x1000)), PageTableFlags::empty()),
+        Err(kur_os::error::KernelError::InvalidArgument)
+    );
+
+    // La página, las tablas de niveles 3 a 1 y la de nivel 4.
+    let free = kur_os::memory::free_frames();
+    drop(first);
+    assert_eq!(kur_os::memory::free_frames(), free + 5);
+    second.unmap(page).unwrap();
+    assert!(second.translate(addr).is_none());
+}
+
+#[test_case]
+fn test_processes_share_addresses_but_not_memory() {
+    let first = process::spawn("uno", &exit_with(7)).unwrap();
+    let second = process::spawn("dos", &exit_with(9)).unwrap();
+    assert_ne!(first, second);
+    assert_eq!(process::wait(second), Ok(9));
+    assert_eq!(process::wait(first), Ok(7));
+    assert_eq!(process::state(first), None);
+    assert_eq!(process::wait(first), Err(kur_os::error::KernelError::NotFound));
+}
+
+#[test_case]
+fn test_fork_copies_memory_and_returns_twice() {
+    let pid = process::spawn("fork", &FORK_AND_WAIT).unwrap();
+    assert_eq!(process::wait(pid), Ok(51));
+    // El padre ya recogió al hijo.
+    assert_eq!(process::count(), 0);
+}
+
+#[test_case]
+fn test_only_the_parent_can_wait() {
+    let other = process::spawn("otro", &exit_with(7)).unwrap();
+    let pid = (other.as_u64() as u32).to_le_bytes();
+    // `exit(wait(other))`: `other` no es su hijo.
+    let code = [
+        0xBF, pid[0], pid[1], pid[2], pid[3], // mov edi, other
+        0xB8, 61, 0, 0, 0, // mov eax, SYS_WAIT
+        0x0F, 0x05, // syscall
+        0x48, 0x89, 0xC7, // mov rdi, rax
+        0xB8, 60, 0, 0, 0, // mov eax, SYS_EXIT
+        0x0F, 0x05, // syscall
+        0xEB, 0xFE, // jmp $
+    ];
+    let stranger = process::spawn("extraño", &code).unwrap();
+    assert_eq!(process::wait(stranger), Ok(-2));
+    assert_eq!(process::wait(other), Ok(7));
+}
+
+#[test_case]
+fn test_exited_process_is_zombie_until_waited() {
+    let pid = process::spawn("getpid", &EXIT_WITH_PID).unwrap();
+    let deadline = kur_os::interrupts::ticks() + 100;
+    while process::state(pid) != Some(ProcessState::Zombie) && kur_os::interrupts::ticks() < deadline {
+        x86_64::instructions::hlt();
+    }
+    assert_eq!(process::state(pid), Some(ProcessState::Zombie));
+    assert_eq!(process::inspect(pid, |process| process.name()), Some("getpid"));
+    assert_eq!(process::wait(pid), Ok(pid.as_u64() as i64));
+    assert_eq!(process::count(), 0);
+}
+
+/// Un ejecutable con un único segmento, `code`, que empieza en `vaddr` y es
+/// la entrada.
+fn elf_image(vaddr: u64, code: &[u8]) -> &'static [u8] {
+    use alloc::vec::Vec;
+    use kur_os::elf::{PF_R, PF_X};
+
+    let mut image = Vec::new();
+    image.extend_from_slice(b"\x7FELF\x02\x01\x01");
+    image.resize(16, 0);
+    image.extend_from_slice(&2u16.to_le_bytes()); // ET_EXEC
+    image.extend_from_slice(&0x3Eu16.to_le_bytes()); // x86_64
+    image.extend_from_slice(&1u32.to_le_bytes());
+    image.extend_from_slice(&vaddr.to_le_bytes()); // entrada
+    image.extend_from_slice(&64u64.to_le_bytes()); // encabezados de programa
+    image.resize(54, 0);
+    image.extend_from_slice(&56u16.to_le_bytes());
+    image.extend_from_slice(&1u16.to_le_bytes());
+    image.resize(64, 0);
+
+    image.extend_from_slice(&1u32.to_le_bytes()); // PT_LOAD
+    image.extend_from_slice(&(PF_R | PF_X).to_le_bytes());
+    image.extend_from_slice(&120u64.to_le_bytes()); // offset
+    image.extend_from_slice(&vaddr.to_le_bytes());
+    image.extend_from_slice(&vaddr.to_le_bytes());
+    image.extend_from_slice(&(code.len() as u64).to_le_bytes());
+    image.extend_from_slice(&(code.len() as u64).to_le_bytes());
+    image.extend_from_slice(&4096u64.to_le_bytes());
+    image.extend_from_slice(code);
+    image.leak()
+}
+
+/// `execve(name)`; si vuelve, sale con lo que devolvió.
+fn exec_stub(name: &str) -> alloc::vec::Vec<u8> {
+    let mut code = alloc::vec![
+        0x48, 0x8D, 0x3D, 24, 0, 0, 0, // lea rdi, [rip + name]
+        0xBE, name.len() as u8, 0, 0, 0, // mov esi, len
+        0xB8, 59, 0, 0, 0, // mov eax, SYS_EXECVE
+        0x0F, 0x05, // syscall
+        0x48, 0x89, 0xC7, // mov rdi, rax
+        0xB8, 60, 0, 0, 0, // mov eax, SYS_EXIT
+        0x0F, 0x05, // syscall
+        0xEB, 0xFE, // jmp $
+    ];
+    code.extend_from_slice(name.as_bytes());
+    code
+}
+
+#[test_case]
+fn test_exec_replaces_the_image() {
+    kur_os::initrd::register("exit42", elf_image(0x2000_0100_0000, &exit_with(42))).unwrap();
+
+    let pid = process::spawn("exec", &exec_stub("exit42")).unwrap();
+    assert_eq!(process::wait(pid), Ok(42));
+
+    // Si la imagen no existe, `execve` vuelve con el error.
+    let pid = process::spawn("exec", &exec_stub("nada")).unwrap();
+    assert_eq!(process::wait(pid), Ok(-2));
+
+    let pid = process::spawn_program("exit42").unwrap();
+    assert_eq!(process::inspect(pid, |process| process.name()), Some("exit42"));
+    assert_eq!(process::wait(pid), Ok(42));
+    assert_eq!(process::count(), 0);
+}
+
+#[test_case]
+fn test_brk_grows_the_heap() {
+    let pid = process::spawn("brk", &BRK_MALLOC).unwrap();
+    let deadline = kur_os::interrupts::ticks() + 100;
+    while process::state(pid) != Some(ProcessState::Zombie) && kur_os::interrupts::ticks() < deadline {
+        x86_64::instructions::hlt();
+    }
+    // El heap empieza en la página que sigue al código.
+    assert_eq!(process::inspect(pid, |process| process.brk()), Some(process::CODE_START + 0x3000));
+    assert_eq!(process::wait(pid), Ok(42));
+}
+
+#[test_case]
+fn test_mmap_and_munmap_anonymous_memory() {
+    let pid = process::spawn("mmap", &MMAP_AND_UNMAP).unwrap();
+    let deadline = kur_os::interrupts::ticks() + 100;
+    while process::state(pid) != Some(ProcessState::Zombie) && kur_os::interrupts::ticks() < deadline {
+        x86_64::instructions::hlt();
+    }
+    // `munmap` sacó la región de la lista.
+    assert_eq!(process::inspect(pid, |process| process.vmas().is_empty()), Some(true));
+    assert_eq!(process::wait(pid), Ok(42));
+}
+
+#[test_case]
+fn test_user_fault_kills_only_the_process() {
+    // `ud2`, y una escritura a la dirección 0, que no está mapeada.
+    const INVALID_OPCODE: [u8; 2] = [0x0F, 0x0B];
+    const WILD_POINTER: [u8; 14] = [
+        0x48, 0xC7, 0x04, 0x25, 0, 0, 0, 0, 1, 0, 0, 0, // mov qword [0], 1
+        0xEB, 0xFE, // jmp $
+    ];
+
+    let first = process::spawn("ud2", &INVALID_OPCODE).unwrap();
+    let second = process::spawn("wild", &WILD_POINTER).unwrap();
+    assert_eq!(process::wait(first), Ok(process::FAULT_EXIT_CODE));
+    assert_eq!(process::wait(second), Ok(process::FAULT_EXIT_CODE));
+    assert_ne!(process::FAULT_EXIT_CODE, 0);
+
+    // El kernel sigue corriendo procesos.
+    let pid = process::spawn("despues", &exit_with(3)).unwrap();
+    assert_eq!(process::wait(pid), Ok(3));
+    assert_eq!(process::count(), 0);
+}
+
+/// `mmap` de una página con `prot` y una escritura en ella; si sobrevive,
+/// sale con 0.
+const fn write_mapped(prot: u8) -> [u8; 56] {
+    [
+        0x31, 0xFF, // xor edi, edi
+        0xBE, 0x00, 0x10, 0, 0, // mov esi, 0x1000
+        0xBA, prot, 0, 0, 0, // mov edx, prot
+        0x41, 0xBA, 0x22, 0, 0, 0, // mov r10d, MAP_PRIVATE | MAP_ANONYMOUS
+        0x49, 0xC7, 0xC0, 0xFF, 0xFF, 0xFF, 0xFF, // mov r8, -1
+        0x45, 0x31, 0xC9, // xor r9d, r9d
+        0xB8, 9, 0, 0, 0, // mov eax, SYS_MMAP
+        0x0F, 0x05, // syscall
+        0x48, 0xC7, 0x00, 1, 0, 0, 0, // mov qword [rax], 1
+        0xBF, 0, 0, 0, 0, // mov edi, 0
+        0xB8, 60, 0, 0, 0, // mov eax, SYS_EXIT
+        0x0F, 0x05, // syscall
+        0xEB, 0xFE, // jmp $
+    ]
+}
+
+#[test_case]
+fn test_mmap_protection_kills_only_the_process() {
+    use kur_os::vma::{PROT_NONE, PROT_READ, PROT_WRITE};
+
+    let none = process::spawn("prot_none", &write_mapped(PROT_NONE as u8)).unwrap();
+    let read = process::spawn("prot_read", &write_mapped(PROT_READ as u8)).unwrap();
+    let write = process::spawn("prot_write", &write_mapped((PROT_READ | PROT_WRITE) as u8)).unwrap();
+    assert_eq!(process::wait(none), Ok(process::FAULT_EXIT_CODE));
+    assert_eq!(process::wait(read), Ok(process::FAULT_EXIT_CODE));
+    assert_eq!(process::wait(write), Ok(0));
+    assert_eq!(process::count(), 0);
+}
+
+#[test_case]
+fn test_fork_fails_when_the_scheduler_is_full() {
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use kur_os::scheduler::{self, MAX_THREADS};
+
+    static HOLD: AtomicBool = AtomicBool::new(true);
+
+    fn hold() {
+        while HOLD.load(Ordering::SeqCst) {
+            kur_os::thread::yield_now();
+        }
+    }
+
+    // `exit(fork())`.
+    const FORK_ONCE: [u8; 19] = [
+        0xB8, 57, 0, 0, 0, // mov eax, SYS_FORK
+        0x0F, 0x05, // syscall
+        0x48, 0x89, 0xC7, // mov rdi, rax
+        0xB8, 60, 0, 0, 0, // mov eax, SYS_EXIT
+        0x0F, 0x05, // syscall
+        0xEB, 0xFE, // jmp $
+    ];
+
+    // Queda lugar para el proceso pero no para su hijo.
+    HOLD.store(true, Ordering::SeqCst);
+    let holders: alloc::vec::Vec<_> =
+        (scheduler::thread_count()..MAX_THREADS - 1).map(|_| kur_os::thread::spawn("hold", hold)).collect();
+
+    let pid = process::spawn("fork", &FORK_ONCE).unwrap();
+    assert_eq!(process::wait(pid), Ok(-kur_os::syscall::EAGAIN));
+    assert_eq!(process::count(), 0);
+
+    HOLD.store(false, Ordering::SeqCst);
+    while scheduler::stats().threads.iter().any(|thread| holders.contains(&thread.id)) {
+        kur_os::thread::yield_now();
+    }
+}
+
+/// `fork`; la rama indicada por `jump` (0x75 `jnz`: el hijo, 0x74 `jz`: el
+/// padre) da unas vueltas antes de salir con 0. La otra sale enseguida.
+const fn fork_and_spin(jump: u8) -> [u8; 32] {
+    [
+        0xB8, 57, 0, 0, 0, // mov eax, SYS_FORK
+        0x0F, 0x05, // syscall
+        0x48, 0x85, 0xC0, // test rax, rax
+        jump, 9, // jnz/jz salir
+        0xB9, 0, 0, 0, 1, // mov ecx, 0x0100_0000
+        0xFF, 0xC9, // dec ecx
+        0x75, 0xFC, // jnz -4
+        // salir:
+        0x31, 0xFF, // xor edi, edi
+        0xB8, 60, 0, 0, 0, // mov eax, SYS_EXIT
+        0x0F, 0x05, // syscall
+        0xEB, 0xFE, // jmp $
+    ]
+}
+
+#[test_case]
+fn test_orphans_are_reaped() {
+    // El padre termina antes que el hijo, y después al revés.
+    for jump in [0x75, 0x74] {
+        let pid = process::spawn("huerfano", &fork_and_spin(jump)).unwrap();
+        assert_eq!(process::wait(pid), Ok(0));
+        let deadline = kur_os::interrupts::ticks() + 100;
+        while process::count() != 0 && kur_os::interrupts::ticks() < deadline {
+            x86_64::instructions::hlt();
+        }
+        assert_eq!(process::count(), 0);
+    }
+}
@@ -0,0 +1,303 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kur_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use kur_os::allocator;
+    use kur_os::memory;
+    use x86_64::VirtAddr;
+
+    kur_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    unsafe {
+        memory::init(phys_mem_offset, &boot_info.memory_map);
+    }
+    allocator::init_heap().expect("falló la inicialización del heap");
+    kur_os::scheduler::init();
+
+    test_main();
+    kur_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    kur_os::test_panic_handler(info)
+}
+
+/// Espera con `hlt` hasta que `done` devuelva true o pasen `ticks` ticks.
+fn wait_until(ticks: u64, done: impl Fn() -> bool) -> bool {
+    let deadline = kur_os::interrupts::ticks() + ticks;
+    while !done() && kur_os::interrupts::ticks() < deadline {
+        x86_64::instructions::hlt();
+    }
+    done()
+}
+
+/// Espera que el hilo `id` termine.
+fn join(id: kur_os::thread::ThreadId) -> bool {
+    use kur_os::thread::State;
+
+    wait_until(100, || {
+        kur_os::scheduler::stats()
+            .threads
+            .iter()
+            .all(|thread| thread.id != id || thread.state == State::Finished)
+    })
+}
+
+#[test_case]
+fn test_spawned_thread_runs_and_exits() {
+    static RAN: AtomicU64 = AtomicU64::new(0);
+
+    fn body() {
+        RAN.fetch_add(1, Ordering::SeqCst);
+    }
+
+    let id = kur_os::thread::spawn("once", body);
+    assert_ne!(Some(id), kur_os::thread::current());
+    assert!(wait_until(100, || RAN.load(Ordering::SeqCst) == 1));
+}
+
+#[test_case]
+fn test_busy_threads_share_the_cpu() {
+    static SPIN: AtomicBool = AtomicBool::new(true);
+    static A: AtomicU64 = AtomicU64::new(0);
+    static B: AtomicU64 = AtomicU64::new(0);
+
+    fn spin_a() {
+        while SPIN.load(Ordering::Relaxed) {
+            A.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn spin_b() {
+        while SPIN.load(Ordering::Relaxed) {
+            B.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // Ninguno cede la CPU: sólo avanzan los dos si el timer los alterna.
+    let a = kur_os::thread::spawn("spin-a", spin_a);
+    let b = kur_os::thread::spawn("spin-b", spin_b);
+    let shared = wait_until(200, || A.load(Ordering::Relaxed) > 0 && B.load(Ordering::Relaxed) > 0);
+    // Que no sigan girando en las pruebas siguientes.
+    SPIN.store(false, Ordering::Relaxed);
+    assert!(join(a) && join(b));
+    assert!(shared);
+}
+
+#[test_case]
+fn test_periodic_thread_runs_every_period() {
+    static RUNS: AtomicU64 = AtomicU64::new(0);
+
+    fn tick() {
+        RUNS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    let id = kur_os::scheduler::spawn_periodic("periodic", 100, 100, tick);
+    let period = kur_os::time::ms_to_ticks(100).max(1);
+    assert!(wait_until(period * 10, || RUNS.load(Ordering::SeqCst) >= 3));
+    let stats = kur_os::scheduler::deadline_stats(id).unwrap();
+    assert!(stats.activations >= 3);
+    assert_eq!(stats.missed, 0);
+}
+
+#[test_case]
+fn test_overrun_counts_missed_deadline() {
+    static FIRST: AtomicU64 = AtomicU64::new(0);
+
+    // Sólo la primera activación se pasa: un periódico que se pasa siempre
+    // no deja correr a ningún hilo round-robin, este test incluido.
+    fn overrun() {
+        if FIRST.fetch_add(1, Ordering::SeqCst) == 0 {
+            let period = kur_os::time::ms_to_ticks(100).max(1);
+            let until = kur_os::time::ticks() + 2 * period;
+            while kur_os::time::ticks() < until {}
+        }
+    }
+
+    let id = kur_os::scheduler::spawn_periodic("overrun", 100, 50, overrun);
+    let period = kur_os::time::ms_to_ticks(100).max(1);
+    assert!(wait_until(period * 10, || kur_os::scheduler::deadline_stats(id).unwrap().missed >= 1));
+    assert_eq!(kur_os::scheduler::deadline_stats(kur_os::thread::current().unwrap()), None);
+}
+
+#[test_case]
+fn test_urgent_thread_preempts() {
+    static RAN: AtomicU64 = AtomicU64::new(0);
+
+    fn urgent() {
+        RAN.store(1, Ordering::SeqCst);
+    }
+
+    kur_os::thread::spawn_with_priority("urgent", 0, urgent);
+    // Sin `hlt`: sólo corre si el timer le saca la CPU a este hilo.
+    let deadline = kur_os::interrupts::ticks() + 100;
+    while RAN.load(Ordering::SeqCst) == 0 && kur_os::interrupts::ticks() < deadline {}
+    assert_eq!(RAN.load(Ordering::SeqCst), 1);
+}
+
+#[test_case]
+fn test_priority_changes_and_inheritance() {
+    use kur_os::scheduler::{self, DEFAULT_PRIORITY};
+
+    let me = kur_os::thread::current().unwrap();
+    assert_eq!(scheduler::priority(me), Some(DEFAULT_PRIORITY));
+
+    // Siempre más urgente que los hilos que siguen girando de los tests
+    // anteriores: con menos, este no volvería a correr.
+    scheduler::set_priority(me, 3);
+    assert_eq!(scheduler::priority(me), Some(3));
+
+    // Un hilo de prioridad 1 espera un lock nuestro.
+    scheduler::inherit_priority(me, 1);
+    assert_eq!(scheduler::priority(me), Some(1));
+    // Cambiar la propia no toca la heredada.
+    scheduler::set_priority(me, 2);
+    assert_eq!(scheduler::priority(me), Some(1));
+
+    scheduler::restore_priority(me);
+    assert_eq!(scheduler::priority(me), Some(2));
+    scheduler::set_priority(me, DEFAULT_PRIORITY);
+}
+
+#[test_case]
+fn test_yield_now_lets_others_run() {
+    static RAN: AtomicU64 = AtomicU64::new(0);
+
+    fn body() {
+        RAN.store(1, Ordering::SeqCst);
+    }
+
+    kur_os::thread::spawn("yielded-to", body);
+    // Sin `hlt` ni esperar al timer: cada vuelta le da la CPU al siguiente.
+    for _ in 0..100 {
+        if RAN.load(Ordering::SeqCst) == 1 {
+            break;
+        }
+        kur_os::thread::yield_now();
+    }
+    assert_eq!(RAN.load(Ordering::SeqCst), 1);
+}
+
+#[test_case]
+fn test_wait_queue_blocks_until_notified() {
+    use kur_os::wait_queue::WaitQueue;
+
+    static QUEUE: WaitQueue = WaitQueue::new();
+    static READY: AtomicU64 = AtomicU64::new(0);
+    static WOKEN: AtomicU64 = AtomicU64::new(0);
+
+    fn waiter() {
+        QUEUE.wait_while(|| READY.load(Ordering::SeqCst) == 0);
+        WOKEN.fetch_add(1, Ordering::SeqCst);
+    }
+
+    kur_os::thread::spawn("waiter-a", waiter);
+    kur_os::thread::spawn("waiter-b", waiter);
+    assert!(wait_until(100, || QUEUE.len() == 2));
+
+    // Un aviso sin que se cumpla la condición: vuelve a bloquearse.
+    assert!(QUEUE.notify_one());
+    assert!(wait_until(100, || QUEUE.len() == 2));
+    assert_eq!(WOKEN.load(Ordering::SeqCst), 0);
+
+    READY.store(1, Ordering::SeqCst);
+    assert_eq!(QUEUE.notify_all(), 2);
+    assert!(wait_until(100, || WOKEN.load(Ordering::SeqCst) == 2));
+    assert!(!QUEUE.notify_one());
+}
+
+#[test_case]
+fn test_stats_count_switches_and_run_time() {
+    use kur_os::scheduler;
+
+    static SPIN: AtomicBool = AtomicBool::new(true);
+
+    fn spin() {
+        while SPIN.load(Ordering::Relaxed) {
+            core::hint::spin_loop();
+        }
+    }
+
+    let me = kur_os::thread::current().unwrap();
+    let before = scheduler::stats();
+
+    // Con otro hilo listo, ceder cambia de hilo.
+    let spinner = kur_os::thread::spawn("spin", spin);
+    kur_os::thread::yield_now();
+    // Sin `hlt`: el timer tiene que sacarle la CPU a este hilo.
+    let slice = kur_os::time::ms_to_ticks(scheduler::TIME_SLICE_MS).max(1);
+    let deadline = kur_os::interrupts::ticks() + 3 * slice;
+    while kur_os::interrupts::ticks() < deadline {}
+    let after = scheduler::stats();
+    SPIN.store(false, Ordering::Relaxed);
+    assert!(join(spinner));
+
+    assert!(after.voluntary > before.voluntary);
+    assert!(after.preempted > before.preempted);
+    assert_eq!(after.context_switches, after.voluntary + after.preempted);
+    let run_ns = |stats: &scheduler::SchedulerStats| {
+        stats.threads.iter().find(|thread| thread.id == me).unwrap().run_ns
+    };
+    assert!(run_ns(&after) > run_ns(&before));
+}
+
+#[test_case]
+fn test_stack_high_water_tracks_deepest_use() {
+    use kur_os::wait_queue::WaitQueue;
+
+    static DONE: WaitQueue = WaitQueue::new();
+    static RELEASE: AtomicU64 = AtomicU64::new(0);
+    static USED: AtomicU64 = AtomicU64::new(0);
+
+    fn deep() {
+        let mut buffer = [0u8; 4096];
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        core::hint::black_box(&mut buffer);
+        USED.store(1, Ordering::SeqCst);
+        DONE.wait_while(|| RELEASE.load(Ordering::SeqCst) == 0);
+    }
+
+    let id = kur_os::thread::spawn("deep", deep);
+    assert!(wait_until(100, || USED.load(Ordering::SeqCst) == 1));
+    let stats = kur_os::scheduler::stats();
+    let thread = stats.threads.iter().find(|thread| thread.id == id).unwrap();
+    let used = thread.stack_high_water.unwrap();
+    assert!((4096..kur_os::thread::STACK_SIZE).contains(&used), "{} bytes", used);
+
+    RELEASE.store(1, Ordering::SeqCst);
+    DONE.notify_all();
+}
+
+#[test_case]
+fn test_finished_thread_returns_its_stack_frames() {
+    use kur_os::thread::STACK_SIZE;
+
+    static HOLD: AtomicBool = AtomicBool::new(true);
+
+    fn body() {
+        while HOLD.load(Ordering::SeqCst) {
+            kur_os::thread::yield_now();
+        }
+    }
+
+    let id = kur_os::thread::spawn("frames", body);
+    let running = kur_os::memory::free_frames();
+    HOLD.store(false, Ordering::SeqCst);
+    // Sale de la tabla recién cuando se suelta, con su stack.
+    assert!(wait_until(100, || kur_os::scheduler::stats().threads.iter().all(|thread| thread.id != id)));
+    assert!(kur_os::memory::free_frames() >= running + STACK_SIZE / 4096);
+}
@@ -0,0 +1,63 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kur_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use kur_os::serial::{self, Backpressure};
+use x86_64::instructions::interrupts::without_interrupts;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use kur_os::allocator;
+    use kur_os::memory;
+    use x86_64::VirtAddr;
+
+    kur_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    unsafe {
+        memory::init(phys_mem_offset, &boot_info.memory_map);
+    }
+    allocator::init_heap().expect("falló la inicialización del heap");
+
+    serial::enable_async(16, Backpressure::DropOldest);
+
+    test_main();
+    kur_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    kur_os::test_panic_handler(info)
+}
+
+// Con interrupciones deshabilitadas para que la salida del timer no entre en la cola.
+
+#[test_case]
+fn test_drop_oldest_counts_discarded_bytes() {
+    without_interrupts(|| {
+        serial::flush();
+        let before = serial::dropped();
+        // 32 bytes en una cola de 16: los 16 más viejos se descartan.
+        serial::write_str("0123456789abcdef0123456789abcdef");
+        assert_eq!(serial::dropped() - before, 16);
+        serial::flush();
+    });
+}
+
+#[test_case]
+fn test_flush_empties_queue() {
+    without_interrupts(|| {
+        let before = serial::dropped();
+        serial::write_str("0123456789");
+        serial::flush();
+        serial::write_str("0123456789");
+        serial::flush();
+        assert_eq!(serial::dropped(), before);
+    });
+}
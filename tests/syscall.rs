@@ -0,0 +1,159 @@
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU64, Ordering};
+use kur_os::error::{KernelError, KernelResult};
+use kur_os::syscall::{self, SyscallFrame};
+use kur_os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+use lazy_static::lazy_static;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.general_protection_fault.set_handler_fn(test_gpf_handler);
+        idt.page_fault.set_handler_fn(test_page_fault_handler);
+        idt.invalid_opcode.set_handler_fn(test_invalid_opcode_handler);
+        unsafe {
+            idt.double_fault
+                .set_handler_fn(test_double_fault_handler)
+                .set_stack_index(kur_os::gdt::DOUBLE_FAULT_IST_INDEX);
+        }
+        idt
+    };
+}
+
+const RECORD: u32 = 1;
+const ADD: u32 = 2;
+const FINISH: u32 = 3;
+const UNKNOWN: u32 = 63;
+
+const fn le(value: u32) -> [u8; 4] {
+    value.to_le_bytes()
+}
+
+/// Una syscall sin handler, otra que anota lo que devolvió, una suma y la
+/// última con el resultado de la suma.
+const STUB: [u8; 46] = {
+    let [u0, u1, u2, u3] = le(UNKNOWN);
+    let [r0, r1, r2, r3] = le(RECORD);
+    let [a0, a1, a2, a3] = le(ADD);
+    let [f0, f1, f2, f3] = le(FINISH);
+    [
+        0xB8, u0, u1, u2, u3, // mov eax, UNKNOWN
+        0x0F, 0x05, // syscall
+        0x48, 0x89, 0xC7, // mov rdi, rax
+        0xB8, r0, r1, r2, r3, // mov eax, RECORD
+        0x0F, 0x05, // syscall
+        0xB8, a0, a1, a2, a3, // mov eax, ADD
+        0xBF, 11, 0, 0, 0, // mov edi, 11
+        0xBE, 22, 0, 0, 0, // mov esi, 22
+        0x0F, 0x05, // syscall
+        0x48, 0x89, 0xC7, // mov rdi, rax
+        0xB8, f0, f1, f2, f3, // mov eax, FINISH
+        0x0F, 0x05, // syscall
+        0xEB, 0xFE, // jmp $
+    ]
+};
+
+const CODE: u64 = kur_os::memory::USER_START;
+const STACK: u64 = kur_os::memory::USER_START + 0x10_0000;
+
+/// Lo que anotó `RECORD`.
+static RECORDED: AtomicU64 = AtomicU64::new(0);
+
+fn record(frame: &mut SyscallFrame) -> KernelResult<u64> {
+    RECORDED.store(frame.arg(0), Ordering::SeqCst);
+    Ok(0)
+}
+
+fn add(frame: &mut SyscallFrame) -> KernelResult<u64> {
+    if frame.rsp != STACK || !(CODE..CODE + STUB.len() as u64).contains(&frame.rip) {
+        return Err(KernelError::InvalidArgument);
+    }
+    Ok(frame.arg(0) + frame.arg(1))
+}
+
+fn finish(frame: &mut SyscallFrame) -> KernelResult<u64> {
+    let unknown = RECORDED.load(Ordering::SeqCst) as i64;
+    if unknown == -syscall::ENOSYS && frame.arg(0) == 33 {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]");
+        serial_println!("desconocida: {}, suma: {}", unknown, frame.arg(0) as i64);
+        exit_qemu(QemuExitCode::Failed);
+    }
+    kur_os::hlt_loop();
+}
+
+extern "x86-interrupt" fn test_gpf_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+    serial_println!("[failed]");
+    serial_println!("#GP {:#x}\n{:#?}", error_code, stack_frame);
+    exit_qemu(QemuExitCode::Failed);
+    kur_os::hlt_loop();
+}
+
+extern "x86-interrupt" fn test_page_fault_handler(stack_frame: InterruptStackFrame, error_code: PageFaultErrorCode) {
+    serial_println!("[failed]");
+    serial_println!("#PF {:?} en {:?}\n{:#?}", error_code, x86_64::registers::control::Cr2::read(), stack_frame);
+    exit_qemu(QemuExitCode::Failed);
+    kur_os::hlt_loop();
+}
+
+extern "x86-interrupt" fn test_invalid_opcode_handler(stack_frame: InterruptStackFrame) {
+    serial_println!("[failed]");
+    serial_println!("#UD\n{:#?}", stack_frame);
+    exit_qemu(QemuExitCode::Failed);
+    kur_os::hlt_loop();
+}
+
+extern "x86-interrupt" fn test_double_fault_handler(stack_frame: InterruptStackFrame, _error_code: u64) -> ! {
+    serial_println!("[failed]");
+    serial_println!("#DF\n{:#?}", stack_frame);
+    exit_qemu(QemuExitCode::Failed);
+    kur_os::hlt_loop();
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use kur_os::memory;
+    use x86_64::instructions::port::Port;
+    use x86_64::structures::paging::Page;
+    use x86_64::VirtAddr;
+
+    serial_print!("syscall::from_ring3...\t");
+
+    // Sin PIC remapeado el timer llegaría como vector 8: se enmascara todo.
+    unsafe {
+        Port::<u8>::new(0x21).write(0xFF);
+        Port::<u8>::new(0xA1).write(0xFF);
+    }
+    kur_os::gdt::init();
+    syscall::init();
+    TEST_IDT.load();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    unsafe {
+        memory::init(phys_mem_offset, &boot_info.memory_map);
+    }
+
+    syscall::register(RECORD as usize, record).unwrap();
+    syscall::register(ADD as usize, add).unwrap();
+    syscall::register(FINISH as usize, finish).unwrap();
+
+    memory::map_user_page(Page::containing_address(VirtAddr::new(CODE))).expect("no se pudo mapear el código");
+    memory::map_user_page(Page::containing_address(VirtAddr::new(STACK - 1))).expect("no se pudo mapear el stack");
+    unsafe {
+        core::ptr::copy_nonoverlapping(STUB.as_ptr(), CODE as *mut u8, STUB.len());
+        syscall::enter_user(VirtAddr::new(CODE), VirtAddr::new(STACK));
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    kur_os::test_panic_handler(info)
+}
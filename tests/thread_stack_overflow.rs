@@ -0,0 +1,81 @@
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU64, Ordering};
+use kur_os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+use lazy_static::lazy_static;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        unsafe {
+            idt.double_fault
+                .set_handler_fn(test_double_fault_handler)
+                .set_stack_index(kur_os::gdt::DOUBLE_FAULT_IST_INDEX);
+        }
+        idt
+    };
+}
+
+static OVERFLOWING: AtomicU64 = AtomicU64::new(u64::MAX);
+
+extern "x86-interrupt" fn test_double_fault_handler(_stack_frame: InterruptStackFrame, _error_code: u64) -> ! {
+    let cr2 = x86_64::registers::control::Cr2::read_raw();
+    match kur_os::stack::overflowed_thread(cr2) {
+        Some(thread) if thread.id.as_u64() == OVERFLOWING.load(Ordering::SeqCst) => {
+            serial_println!("[ok]");
+            exit_qemu(QemuExitCode::Success);
+        }
+        _ => {
+            serial_println!("[failed]");
+            serial_println!("CR2 {:#x} fuera de la guarda del hilo", cr2);
+            exit_qemu(QemuExitCode::Failed);
+        }
+    }
+    kur_os::hlt_loop();
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use kur_os::{allocator, memory};
+    use x86_64::instructions::port::Port;
+    use x86_64::VirtAddr;
+
+    serial_print!("thread_stack_overflow::guard_page...\t");
+
+    // Sin PIC remapeado el timer llegaría como vector 8: se enmascara todo.
+    unsafe {
+        Port::<u8>::new(0x21).write(0xFF);
+        Port::<u8>::new(0xA1).write(0xFF);
+    }
+    kur_os::gdt::init();
+    TEST_IDT.load();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    unsafe {
+        memory::init(phys_mem_offset, &boot_info.memory_map);
+    }
+    allocator::init_heap().expect("falló la inicialización del heap");
+    kur_os::scheduler::init();
+
+    let id = kur_os::thread::spawn("overflow", stack_overflow);
+    OVERFLOWING.store(id.as_u64(), Ordering::SeqCst);
+    kur_os::thread::yield_now();
+
+    panic!("el hilo que desborda su stack volvió");
+}
+
+#[allow(unconditional_recursion)]
+fn stack_overflow() {
+    stack_overflow();
+    volatile::Volatile::new(0).read();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    kur_os::test_panic_handler(info)
+}
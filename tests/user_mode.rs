@@ -0,0 +1,129 @@
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use kur_os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+use lazy_static::lazy_static;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+use x86_64::PrivilegeLevel;
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        // Sin IST: tiene que correr en el stack de `RSP0`.
+        idt.breakpoint
+            .set_handler_fn(test_breakpoint_handler)
+            .set_privilege_level(PrivilegeLevel::Ring3);
+        idt.general_protection_fault.set_handler_fn(test_gpf_handler);
+        idt.page_fault.set_handler_fn(test_page_fault_handler);
+        unsafe {
+            idt.double_fault
+                .set_handler_fn(test_double_fault_handler)
+                .set_stack_index(kur_os::gdt::DOUBLE_FAULT_IST_INDEX);
+        }
+        idt
+    };
+}
+
+/// `int3` y `jmp` sobre sí mismo.
+const STUB: [u8; 3] = [0xCC, 0xEB, 0xFE];
+
+const CODE: u64 = kur_os::memory::USER_START;
+const STACK: u64 = kur_os::memory::USER_START + 0x10_0000;
+
+extern "x86-interrupt" fn test_breakpoint_handler(stack_frame: InterruptStackFrame) {
+    let cs = x86_64::structures::gdt::SegmentSelector(stack_frame.code_segment as u16);
+    let rsp: u64;
+    unsafe { core::arch::asm!("mov {}, rsp", out(reg) rsp, options(nomem, nostack)) };
+    let (rsp0_base, rsp0_top) = kur_os::gdt::privilege_stack_bounds();
+    if cs.rpl() == PrivilegeLevel::Ring3
+        && stack_frame.instruction_pointer.as_u64() == CODE + 1
+        && (rsp0_base.as_u64()..rsp0_top.as_u64()).contains(&rsp)
+    {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]");
+        serial_println!("{:#?}", stack_frame);
+        exit_qemu(QemuExitCode::Failed);
+    }
+    kur_os::hlt_loop();
+}
+
+extern "x86-interrupt" fn test_gpf_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+    serial_println!("[failed]");
+    serial_println!("#GP {:#x}\n{:#?}", error_code, stack_frame);
+    exit_qemu(QemuExitCode::Failed);
+    kur_os::hlt_loop();
+}
+
+extern "x86-interrupt" fn test_page_fault_handler(stack_frame: InterruptStackFrame, error_code: PageFaultErrorCode) {
+    serial_println!("[failed]");
+    serial_println!("#PF {:?} en {:?}\n{:#?}", error_code, x86_64::registers::control::Cr2::read(), stack_frame);
+    exit_qemu(QemuExitCode::Failed);
+    kur_os::hlt_loop();
+}
+
+extern "x86-interrupt" fn test_double_fault_handler(stack_frame: InterruptStackFrame, _error_code: u64) -> ! {
+    serial_println!("[failed]");
+    serial_println!("#DF\n{:#?}", stack_frame);
+    exit_qemu(QemuExitCode::Failed);
+    kur_os::hlt_loop();
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use kur_os::memory;
+    use x86_64::instructions::port::Port;
+    use x86_64::structures::paging::Page;
+    use x86_64::VirtAddr;
+
+    serial_print!("user_mode::iretq_to_ring3...\t");
+
+    // Sin PIC remapeado el timer llegaría como vector 8: se enmascara todo.
+    unsafe {
+        Port::<u8>::new(0x21).write(0xFF);
+        Port::<u8>::new(0xA1).write(0xFF);
+    }
+    kur_os::gdt::init();
+    TEST_IDT.load();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    unsafe {
+        memory::init(phys_mem_offset, &boot_info.memory_map);
+    }
+
+    memory::map_user_page(Page::containing_address(VirtAddr::new(CODE))).expect("no se pudo mapear el código");
+    memory::map_user_page(Page::containing_address(VirtAddr::new(STACK - 1))).expect("no se pudo mapear el stack");
+    unsafe {
+        core::ptr::copy_nonoverlapping(STUB.as_ptr(), CODE as *mut u8, STUB.len());
+    }
+
+    let cs = kur_os::gdt::user_code_selector().0 as u64;
+    let ss = kur_os::gdt::user_data_selector().0 as u64;
+    // Con IF apagado: sólo se vuelve por el `int3`.
+    let rflags = 0x2u64;
+    unsafe {
+        core::arch::asm!(
+            "push {ss}",
+            "push {rsp}",
+            "push {rflags}",
+            "push {cs}",
+            "push {rip}",
+            "iretq",
+            ss = in(reg) ss,
+            rsp = in(reg) STACK,
+            rflags = in(reg) rflags,
+            cs = in(reg) cs,
+            rip = in(reg) CODE,
+            options(noreturn),
+        );
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    kur_os::test_panic_handler(info)
+}